@@ -15,3 +15,6 @@ curl -i --location --request POST $TEST_URL --header 'Content-Type: application/
 echo "Checking account status (calling a query)"
 curl -i --location $TEST_URL
 echo
+echo "Listing accounts"
+curl -i --location "localhost:3030/account?limit=10"
+echo
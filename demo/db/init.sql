@@ -7,14 +7,16 @@ CREATE TABLE events
     event_version  text                         NOT NULL,
     payload        json                         NOT NULL,
     metadata       json                         NOT NULL,
+    committed_at   timestamptz                  NOT NULL DEFAULT now(),
     PRIMARY KEY (aggregate_type, aggregate_id, sequence)
 );
 
 CREATE TABLE account_query
 (
-    view_id text                        NOT NULL,
-    version           bigint CHECK (version >= 0) NOT NULL,
-    payload           json                        NOT NULL,
+    view_id           text                              NOT NULL,
+    version           bigint CHECK (version >= 0)       NOT NULL,
+    last_sequence     bigint CHECK (last_sequence >= 0) NOT NULL DEFAULT 0,
+    payload           json                              NOT NULL,
     PRIMARY KEY (view_id)
 );
 
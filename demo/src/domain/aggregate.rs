@@ -115,7 +115,8 @@ mod aggregate_tests {
     use async_trait::async_trait;
     use std::sync::Mutex;
 
-    use cqrs_es::test::TestFramework;
+    use cqrs_es::test::{EventSchemaTestFramework, PropertyTestFramework, TestFramework};
+    use proptest::prelude::*;
 
     use crate::domain::aggregate::BankAccount;
     use crate::domain::commands::BankAccountCommand;
@@ -283,6 +284,71 @@ mod aggregate_tests {
             .then_expect_error_message("funds not available");
     }
 
+    #[test]
+    fn test_balance_never_negative() {
+        let commands = prop_oneof![
+            (1..500).prop_map(|amount| BankAccountCommand::DepositMoney {
+                amount: f64::from(amount)
+            }),
+            (1..500).prop_map(|amount| BankAccountCommand::WithdrawMoney {
+                amount: f64::from(amount),
+                atm_id: "ATM34f1ba3c".to_string()
+            }),
+            (1..500).prop_map(|amount| BankAccountCommand::WriteCheck {
+                check_number: "1170".to_string(),
+                amount: f64::from(amount)
+            }),
+        ];
+        let services = BankAccountServices::new(Box::new(ApprovingBankAccountServices));
+        PropertyTestFramework::<BankAccount>::with(services)
+            .with_invariant("balance is never negative", |account| {
+                account.balance >= 0_f64
+            })
+            .check(commands);
+    }
+
+    // Stored events must remain readable as the events change, intentional changes are
+    // recorded with `CQRS_BLESS_EVENT_SCHEMAS=1 cargo test`.
+    #[test]
+    fn test_event_schemas() {
+        EventSchemaTestFramework::<BankAccount>::with_directory("event_schemas").verify(vec![
+            BankAccountEvent::AccountOpened {
+                account_id: "ACCT-0565".to_string(),
+            },
+            BankAccountEvent::CustomerDepositedMoney {
+                amount: 200.0,
+                balance: 200.0,
+            },
+            BankAccountEvent::CustomerWithdrewCash {
+                amount: 40.0,
+                balance: 160.0,
+            },
+            BankAccountEvent::CustomerWroteCheck {
+                check_number: "1170".to_string(),
+                amount: 60.0,
+                balance: 100.0,
+            },
+        ]);
+    }
+
+    // Approves every atm withdrawal and check.
+    pub struct ApprovingBankAccountServices;
+
+    #[async_trait]
+    impl BankAccountApi for ApprovingBankAccountServices {
+        async fn atm_withdrawal(&self, _atm_id: &str, _amount: f64) -> Result<(), AtmError> {
+            Ok(())
+        }
+
+        async fn validate_check(
+            &self,
+            _account_id: &str,
+            _check_number: &str,
+        ) -> Result<(), CheckingError> {
+            Ok(())
+        }
+    }
+
     pub struct MockBankAccountServices {
         atm_withdrawal_response: Mutex<Option<Result<(), AtmError>>>,
         validate_check_response: Mutex<Option<Result<(), CheckingError>>>,
@@ -1,6 +1,6 @@
 use axum::routing::get;
 use axum::Router;
-use cqrs_demo::route_handler::{command_handler, query_handler};
+use cqrs_demo::route_handler::{command_handler, list_handler, query_handler};
 use cqrs_demo::state::new_application_state;
 use std::net::SocketAddr;
 
@@ -11,6 +11,7 @@ async fn main() {
     // For this example a single logical endpoint is used and the HTTP method
     // distinguishes whether the call is a command or a query.
     let router = Router::new()
+        .route("/account", get(list_handler))
         .route(
             "/account/{account_id}",
             get(query_handler).post(command_handler),
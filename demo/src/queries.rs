@@ -89,3 +89,39 @@ impl View<BankAccount> for BankAccountView {
         }
     }
 }
+
+#[cfg(test)]
+mod view_tests {
+    use cqrs_es::test::ViewTestFramework;
+
+    use crate::domain::aggregate::BankAccount;
+    use crate::domain::events::BankAccountEvent;
+    use crate::queries::BankAccountView;
+
+    type AccountViewTestFramework = ViewTestFramework<BankAccountView, BankAccount>;
+
+    #[test]
+    fn test_check_written() {
+        AccountViewTestFramework::with_aggregate_id("account-1")
+            .given(vec![
+                BankAccountEvent::AccountOpened {
+                    account_id: "account-1".to_string(),
+                },
+                BankAccountEvent::CustomerDepositedMoney {
+                    amount: 200.0,
+                    balance: 200.0,
+                },
+            ])
+            .when(vec![BankAccountEvent::CustomerWroteCheck {
+                check_number: "1170".to_string(),
+                amount: 150.0,
+                balance: 50.0,
+            }])
+            .then_expect_view_matching(|view| {
+                view.account_id.as_deref() == Some("account-1")
+                    && view.balance == 50.0
+                    && view.written_checks == vec!["1170".to_string()]
+                    && view.ledger.len() == 2
+            });
+    }
+}
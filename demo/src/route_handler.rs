@@ -1,10 +1,15 @@
 use crate::command_extractor::CommandExtractor;
+use crate::queries::BankAccountView;
 use crate::state::ApplicationState;
-use axum::extract::{Path, State};
+use axum::extract::{Path, Query, State};
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
 use axum::Json;
-use cqrs_es::persist::ViewRepository;
+use cqrs_es::persist::{ListableViewRepository, ViewListQuery, ViewRepository};
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_PAGE_SIZE: usize = 20;
+const MAX_PAGE_SIZE: usize = 100;
 
 // Serves as our query endpoint to respond with the materialized `BankAccountView`
 // for the requested account.
@@ -22,6 +27,46 @@ pub async fn query_handler(
     }
 }
 
+#[derive(Debug, Deserialize)]
+pub struct ListParams {
+    cursor: Option<String>,
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AccountList {
+    accounts: Vec<BankAccountView>,
+    next_cursor: Option<String>,
+}
+
+// Serves as our list endpoint, responding with a page of `BankAccountView`s.
+// The `next_cursor` in the response can be passed as the `cursor` parameter to
+// request the following page.
+pub async fn list_handler(
+    Query(params): Query<ListParams>,
+    State(state): State<ApplicationState>,
+) -> Response {
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let query = ViewListQuery::new(limit).after(params.cursor);
+    match state.account_query.list_views(&query).await {
+        Ok(page) => {
+            let accounts = page.views.into_iter().map(|(_, view)| view).collect();
+            let account_list = AccountList {
+                accounts,
+                next_cursor: page.next_cursor,
+            };
+            (StatusCode::OK, Json(account_list)).into_response()
+        }
+        Err(err) => {
+            println!("Error: {err:#?}\n");
+            (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response()
+        }
+    }
+}
+
 // Serves as our command endpoint to make changes in a `BankAccount` aggregate.
 pub async fn command_handler(
     Path(account_id): Path<String>,
@@ -7,6 +7,10 @@ aws dynamodb create-table \
   --attribute-definitions \
         AttributeName=AggregateTypeAndId,AttributeType=S \
         AttributeName=AggregateIdSequence,AttributeType=N \
+        AttributeName=AggregateType,AttributeType=S \
+        AttributeName=CommittedAt,AttributeType=N \
+  --global-secondary-indexes \
+        '[{"IndexName":"AggregateTypeCommittedAt","KeySchema":[{"AttributeName":"AggregateType","KeyType":"HASH"},{"AttributeName":"CommittedAt","KeyType":"RANGE"}],"Projection":{"ProjectionType":"INCLUDE","NonKeyAttributes":["AggregateId"]}}]' \
   --billing-mode PAY_PER_REQUEST \
   --endpoint-url http://localhost:8000
 
@@ -27,3 +31,21 @@ aws dynamodb create-table \
         AttributeName=ViewId,AttributeType=S \
   --billing-mode PAY_PER_REQUEST \
   --endpoint-url http://localhost:8000
+
+aws dynamodb create-table \
+  --table-name TestViewShadowTable \
+      --key-schema \
+        AttributeName=ViewId,KeyType=HASH \
+  --attribute-definitions \
+        AttributeName=ViewId,AttributeType=S \
+  --billing-mode PAY_PER_REQUEST \
+  --endpoint-url http://localhost:8000
+
+aws dynamodb create-table \
+  --table-name ConformanceViewTable \
+      --key-schema \
+        AttributeName=ViewId,KeyType=HASH \
+  --attribute-definitions \
+        AttributeName=ViewId,AttributeType=S \
+  --billing-mode PAY_PER_REQUEST \
+  --endpoint-url http://localhost:8000
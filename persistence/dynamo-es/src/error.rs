@@ -1,4 +1,5 @@
 use aws_sdk_dynamodb::error::{BuildError, SdkError};
+use aws_sdk_dynamodb::operation::batch_get_item::BatchGetItemError;
 use aws_sdk_dynamodb::operation::query::QueryError;
 use aws_sdk_dynamodb::operation::scan::ScanError;
 use aws_sdk_dynamodb::operation::transact_write_items::TransactWriteItemsError;
@@ -68,6 +69,12 @@ impl From<SdkError<QueryError>> for DynamoAggregateError {
     }
 }
 
+impl From<SdkError<BatchGetItemError>> for DynamoAggregateError {
+    fn from(error: SdkError<BatchGetItemError>) -> Self {
+        unknown_error(error)
+    }
+}
+
 impl From<BuildError> for DynamoAggregateError {
     fn from(error: BuildError) -> Self {
         Self::UnknownError(Box::new(error))
@@ -1,4 +1,5 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use aws_sdk_dynamodb::operation::query::builders::QueryFluentBuilder;
 use aws_sdk_dynamodb::operation::query::QueryOutput;
@@ -7,7 +8,8 @@ use aws_sdk_dynamodb::primitives::Blob;
 use aws_sdk_dynamodb::types::{AttributeValue, Put, TransactWriteItem};
 use aws_sdk_dynamodb::Client;
 use cqrs_es::persist::{
-    PersistedEventRepository, PersistenceError, ReplayStream, SerializedEvent, SerializedSnapshot,
+    PersistedEventRepository, PersistenceError, ReplayFeed, ReplayFilter, ReplayStream,
+    SerializedEvent, SerializedSnapshot,
 };
 use cqrs_es::Aggregate;
 use serde_json::Value;
@@ -19,6 +21,10 @@ const DEFAULT_EVENT_TABLE: &str = "Events";
 const DEFAULT_SNAPSHOT_TABLE: &str = "Snapshots";
 
 const DEFAULT_STREAMING_CHANNEL_SIZE: usize = 200;
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+// Subscriptions look back this far beyond the previous poll, allowing for clock skew between
+// writers and for the eventual consistency of scans.
+const SUBSCRIPTION_LOOKBACK: Duration = Duration::from_secs(10);
 
 /// An event repository relying on DynamoDb for persistence.
 pub struct DynamoEventRepository {
@@ -26,6 +32,8 @@ pub struct DynamoEventRepository {
     event_table: String,
     snapshot_table: String,
     stream_channel_size: usize,
+    poll_interval: Duration,
+    subscription_index: Option<String>,
 }
 
 impl DynamoEventRepository {
@@ -57,10 +65,55 @@ impl DynamoEventRepository {
     /// ```
     pub fn with_streaming_channel_size(self, stream_channel_size: usize) -> Self {
         Self {
-            client: self.client,
-            event_table: self.event_table,
-            snapshot_table: self.snapshot_table,
             stream_channel_size,
+            ..self
+        }
+    }
+    /// Configures how often a subscription polls for newly committed events, the default is to
+    /// poll every second.
+    ///
+    /// _Note: without a subscription index each poll scans the entire events table, see
+    /// `with_subscription_index`._
+    ///
+    /// _Example: poll for new events every 200 milliseconds._
+    /// ```
+    /// use std::time::Duration;
+    /// use aws_sdk_dynamodb::Client;
+    /// use dynamo_es::DynamoEventRepository;
+    ///
+    /// fn configure_repo(client: Client) -> DynamoEventRepository {
+    ///     let store = DynamoEventRepository::new(client);
+    ///     store.with_subscription_poll_interval(Duration::from_millis(200))
+    /// }
+    /// ```
+    pub fn with_subscription_poll_interval(self, poll_interval: Duration) -> Self {
+        Self {
+            poll_interval,
+            ..self
+        }
+    }
+    /// Configures subscriptions to find newly committed events using a global secondary index
+    /// of the events table with `AggregateType` as the partition key and `CommittedAt` as the
+    /// sort key, the index must project the `AggregateId` attribute.
+    ///
+    /// Without this index every poll of a subscription scans the entire events table, which is
+    /// only suitable for development and small tables. Subscriptions still scan the table once
+    /// to catch up on the events committed before subscribing.
+    ///
+    /// _Example: find new events using the "AggregateTypeCommittedAt" index._
+    /// ```
+    /// use aws_sdk_dynamodb::Client;
+    /// use dynamo_es::DynamoEventRepository;
+    ///
+    /// fn configure_repo(client: Client) -> DynamoEventRepository {
+    ///     let store = DynamoEventRepository::new(client);
+    ///     store.with_subscription_index("AggregateTypeCommittedAt")
+    /// }
+    /// ```
+    pub fn with_subscription_index(self, index_name: &str) -> Self {
+        Self {
+            subscription_index: Some(index_name.to_string()),
+            ..self
         }
     }
     /// Configures a `DynamoEventRepository` to use the provided table names.
@@ -77,7 +130,11 @@ impl DynamoEventRepository {
     /// }
     /// ```
     pub fn with_tables(self, event_table: &str, snapshot_table: &str) -> Self {
-        Self::use_table_names(self.client, event_table, snapshot_table)
+        Self {
+            event_table: event_table.to_string(),
+            snapshot_table: snapshot_table.to_string(),
+            ..self
+        }
     }
 
     fn use_table_names(client: Client, event_table: &str, snapshot_table: &str) -> Self {
@@ -86,6 +143,8 @@ impl DynamoEventRepository {
             event_table: event_table.to_string(),
             snapshot_table: snapshot_table.to_string(),
             stream_channel_size: DEFAULT_STREAMING_CHANNEL_SIZE,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            subscription_index: None,
         }
     }
 
@@ -107,6 +166,7 @@ impl DynamoEventRepository {
     ) -> (Vec<TransactWriteItem>, usize) {
         let mut current_sequence: usize = 0;
         let mut transactions: Vec<TransactWriteItem> = Vec::default();
+        let committed_at = epoch_millis(SystemTime::now());
         for event in events {
             current_sequence = event.sequence;
             let aggregate_type_and_id =
@@ -120,6 +180,7 @@ impl DynamoEventRepository {
             let payload = AttributeValue::B(Blob::new(payload_blob));
             let metadata_blob = serde_json::to_vec(&event.metadata).unwrap();
             let metadata = AttributeValue::B(Blob::new(metadata_blob));
+            let committed_at = AttributeValue::N(committed_at.to_string());
 
             let put = Put::builder()
                 .table_name(table_name)
@@ -131,6 +192,7 @@ impl DynamoEventRepository {
                 .item("EventType", event_type)
                 .item("Payload", payload)
                 .item("Metadata", metadata)
+                .item("CommittedAt", committed_at)
                 .condition_expression("attribute_not_exists( AggregateIdSequence )")
                 .build()
                 .unwrap();
@@ -229,6 +291,72 @@ impl DynamoEventRepository {
         Ok(output)
     }
 
+    // Sequences are part of the key condition when querying a single aggregate instance.
+    fn create_filtered_query(
+        &self,
+        aggregate_type: &str,
+        aggregate_id: &str,
+        filter: &ReplayFilter,
+    ) -> QueryFluentBuilder {
+        let mut key_condition = "#agg_type_id = :agg_type_id".to_string();
+        let mut values = Vec::new();
+        match (filter.min_sequence, filter.max_sequence) {
+            (Some(min), Some(max)) => {
+                key_condition.push_str(" AND #sequence BETWEEN :min_sequence AND :max_sequence");
+                values.push((":min_sequence", min));
+                values.push((":max_sequence", max));
+            }
+            (Some(min), None) => {
+                key_condition.push_str(" AND #sequence >= :min_sequence");
+                values.push((":min_sequence", min));
+            }
+            (None, Some(max)) => {
+                key_condition.push_str(" AND #sequence <= :max_sequence");
+                values.push((":max_sequence", max));
+            }
+            (None, None) => {}
+        }
+        let mut query = self
+            .create_query(&self.event_table, aggregate_type, aggregate_id)
+            .key_condition_expression(key_condition)
+            .limit(self.stream_channel_size as i32);
+        if !values.is_empty() {
+            query = query.expression_attribute_names("#sequence", "AggregateIdSequence");
+        }
+        for (name, value) in values {
+            query = query.expression_attribute_values(name, AttributeValue::N(value.to_string()));
+        }
+        let (conditions, values) = filter_conditions(filter, false);
+        if !conditions.is_empty() {
+            query = query.filter_expression(conditions.join(" AND "));
+        }
+        values.into_iter().fold(query, |query, (name, value)| {
+            query.expression_attribute_values(name, value)
+        })
+    }
+
+    fn create_filtered_scan(
+        &self,
+        aggregate_type: &str,
+        filter: &ReplayFilter,
+    ) -> ScanFluentBuilder {
+        let (mut conditions, values) = filter_conditions(filter, true);
+        conditions.insert(0, "AggregateType = :aggregate_type".to_string());
+        let scan = self
+            .client
+            .scan()
+            .table_name(&self.event_table)
+            .limit(self.stream_channel_size as i32)
+            .filter_expression(conditions.join(" AND "))
+            .expression_attribute_values(
+                ":aggregate_type",
+                AttributeValue::S(aggregate_type.to_string()),
+            );
+        values.into_iter().fold(scan, |scan, (name, value)| {
+            scan.expression_attribute_values(name, value)
+        })
+    }
+
     fn create_query(
         &self,
         table: &str,
@@ -248,6 +376,54 @@ impl DynamoEventRepository {
     }
 }
 
+// Builds the filter expression conditions and their values for the filter criteria that are
+// not part of a key condition.
+fn filter_conditions(
+    filter: &ReplayFilter,
+    include_sequences: bool,
+) -> (Vec<String>, Vec<(String, AttributeValue)>) {
+    let mut conditions = Vec::new();
+    let mut values = Vec::new();
+    if !filter.event_types.is_empty() {
+        let mut names = Vec::with_capacity(filter.event_types.len());
+        for (i, event_type) in filter.event_types.iter().enumerate() {
+            let name = format!(":event_type_{i}");
+            values.push((name.clone(), AttributeValue::S(event_type.clone())));
+            names.push(name);
+        }
+        conditions.push(format!("EventType IN ({})", names.join(", ")));
+    }
+    if include_sequences {
+        if let Some(min_sequence) = filter.min_sequence {
+            conditions.push("AggregateIdSequence >= :min_sequence".to_string());
+            let value = AttributeValue::N(min_sequence.to_string());
+            values.push((":min_sequence".to_string(), value));
+        }
+        if let Some(max_sequence) = filter.max_sequence {
+            conditions.push("AggregateIdSequence <= :max_sequence".to_string());
+            let value = AttributeValue::N(max_sequence.to_string());
+            values.push((":max_sequence".to_string(), value));
+        }
+    }
+    if let Some(committed_from) = filter.committed_from {
+        conditions.push("CommittedAt >= :committed_from".to_string());
+        let value = AttributeValue::N(epoch_millis(committed_from).to_string());
+        values.push((":committed_from".to_string(), value));
+    }
+    if let Some(committed_to) = filter.committed_to {
+        conditions.push("CommittedAt < :committed_to".to_string());
+        let value = AttributeValue::N(epoch_millis(committed_to).to_string());
+        values.push((":committed_to".to_string(), value));
+    }
+    (conditions, values)
+}
+
+fn epoch_millis(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis())
+        .unwrap_or_default()
+}
+
 fn serialized_event(
     entry: HashMap<String, AttributeValue>,
 ) -> Result<SerializedEvent, DynamoAggregateError> {
@@ -348,44 +524,253 @@ impl PersistedEventRepository for DynamoEventRepository {
             .limit(self.stream_channel_size as i32);
         Ok(stream_all_events(scan, self.stream_channel_size))
     }
+
+    // Aggregate instances are queried in turn, otherwise the events table is scanned.
+    async fn stream_filtered_events<A: Aggregate>(
+        &self,
+        filter: &ReplayFilter,
+    ) -> Result<ReplayStream, PersistenceError> {
+        if filter.aggregate_ids.is_empty() {
+            let scan = self.create_filtered_scan(A::TYPE, filter);
+            return Ok(stream_all_events(scan, self.stream_channel_size));
+        }
+        let queries = filter
+            .aggregate_ids
+            .iter()
+            .map(|aggregate_id| self.create_filtered_query(A::TYPE, aggregate_id, filter))
+            .collect();
+        Ok(stream_queries(queries, self.stream_channel_size))
+    }
+
+    // The events table, or the subscription index if configured, is polled for aggregate
+    // instances with newly committed events, the new events are then read with a consistent
+    // query so that they are always in sequence.
+    async fn subscribe_all_events<A: Aggregate>(&self) -> Result<ReplayStream, PersistenceError> {
+        let subscription = EventSubscription {
+            client: self.client.clone(),
+            event_table: self.event_table.clone(),
+            subscription_index: self.subscription_index.clone(),
+            aggregate_type: A::TYPE,
+            poll_interval: self.poll_interval,
+            page_size: self.stream_channel_size as i32,
+            last_sequences: HashMap::new(),
+        };
+        let (feed, stream) = ReplayStream::new(self.stream_channel_size);
+        tokio::spawn(subscription.follow(feed));
+        Ok(stream)
+    }
+}
+
+struct EventSubscription {
+    client: Client,
+    event_table: String,
+    subscription_index: Option<String>,
+    aggregate_type: &'static str,
+    poll_interval: Duration,
+    page_size: i32,
+    last_sequences: HashMap<String, usize>,
+}
+
+impl EventSubscription {
+    async fn follow(mut self, mut feed: ReplayFeed) {
+        // the first poll catches up on all committed events
+        let mut committed_since = None;
+        loop {
+            let poll_started = epoch_millis(SystemTime::now());
+            match self.poll(&mut feed, committed_since).await {
+                Ok(true) => {}
+                Ok(false) => return,
+                Err(err) => {
+                    let _ = feed.push(Err(err.into())).await;
+                    return;
+                }
+            }
+            committed_since = Some(poll_started.saturating_sub(SUBSCRIPTION_LOOKBACK.as_millis()));
+            tokio::select! {
+                () = feed.closed() => return,
+                () = tokio::time::sleep(self.poll_interval) => {}
+            }
+        }
+    }
+
+    // Pushes any new events, returns false if the subscription has been closed.
+    async fn poll(
+        &mut self,
+        feed: &mut ReplayFeed,
+        committed_since: Option<u128>,
+    ) -> Result<bool, DynamoAggregateError> {
+        for aggregate_id in self.changed_aggregates(committed_since).await? {
+            if !self.push_events(feed, &aggregate_id).await? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    async fn changed_aggregates(
+        &self,
+        committed_since: Option<u128>,
+    ) -> Result<Vec<String>, DynamoAggregateError> {
+        let mut changed = Vec::new();
+        let mut found = HashSet::new();
+        let mut last_evaluated_key = None;
+        loop {
+            let (items, next_key) = match (&self.subscription_index, committed_since) {
+                (Some(index_name), Some(committed_since)) => {
+                    let output = self
+                        .committed_query(index_name, committed_since)
+                        .set_exclusive_start_key(last_evaluated_key)
+                        .send()
+                        .await?;
+                    (output.items, output.last_evaluated_key)
+                }
+                _ => {
+                    let output = self
+                        .committed_scan(committed_since)
+                        .set_exclusive_start_key(last_evaluated_key)
+                        .send()
+                        .await?;
+                    (output.items, output.last_evaluated_key)
+                }
+            };
+            for entry in items.unwrap_or_default() {
+                let aggregate_id = att_as_string(&entry, "AggregateId")?;
+                let sequence = att_as_number(&entry, "AggregateIdSequence")?;
+                if sequence > self.last_sequence(&aggregate_id)
+                    && found.insert(aggregate_id.clone())
+                {
+                    changed.push(aggregate_id);
+                }
+            }
+            last_evaluated_key = next_key;
+            if last_evaluated_key.is_none() {
+                return Ok(changed);
+            }
+        }
+    }
+
+    fn committed_query(&self, index_name: &str, committed_since: u128) -> QueryFluentBuilder {
+        self.client
+            .query()
+            .table_name(&self.event_table)
+            .index_name(index_name)
+            .limit(self.page_size)
+            .key_condition_expression(
+                "AggregateType = :aggregate_type AND CommittedAt >= :committed_since",
+            )
+            .expression_attribute_values(
+                ":aggregate_type",
+                AttributeValue::S(self.aggregate_type.to_string()),
+            )
+            .expression_attribute_values(
+                ":committed_since",
+                AttributeValue::N(committed_since.to_string()),
+            )
+    }
+
+    fn committed_scan(&self, committed_since: Option<u128>) -> ScanFluentBuilder {
+        let scan = self
+            .client
+            .scan()
+            .table_name(&self.event_table)
+            .limit(self.page_size)
+            .expression_attribute_values(
+                ":aggregate_type",
+                AttributeValue::S(self.aggregate_type.to_string()),
+            );
+        match committed_since {
+            None => scan.filter_expression("AggregateType = :aggregate_type"),
+            Some(committed_since) => scan
+                .filter_expression(
+                    "AggregateType = :aggregate_type AND CommittedAt >= :committed_since",
+                )
+                .expression_attribute_values(
+                    ":committed_since",
+                    AttributeValue::N(committed_since.to_string()),
+                ),
+        }
+    }
+
+    async fn push_events(
+        &mut self,
+        feed: &mut ReplayFeed,
+        aggregate_id: &str,
+    ) -> Result<bool, DynamoAggregateError> {
+        let base_query = self
+            .client
+            .query()
+            .table_name(&self.event_table)
+            .consistent_read(true)
+            .limit(self.page_size)
+            .key_condition_expression("#agg_type_id = :agg_type_id AND #sequence > :sequence")
+            .expression_attribute_names("#agg_type_id", "AggregateTypeAndId")
+            .expression_attribute_names("#sequence", "AggregateIdSequence")
+            .expression_attribute_values(
+                ":agg_type_id",
+                AttributeValue::S(format!("{}:{}", self.aggregate_type, aggregate_id)),
+            )
+            .expression_attribute_values(
+                ":sequence",
+                AttributeValue::N(self.last_sequence(aggregate_id).to_string()),
+            );
+        let mut last_evaluated_key = None;
+        loop {
+            let output = base_query
+                .clone()
+                .set_exclusive_start_key(last_evaluated_key)
+                .send()
+                .await?;
+            for entry in output.items.unwrap_or_default() {
+                let event = serialized_event(entry)?;
+                self.last_sequences
+                    .insert(event.aggregate_id.clone(), event.sequence);
+                if feed.push(Ok(event)).await.is_err() {
+                    return Ok(false);
+                }
+            }
+            last_evaluated_key = output.last_evaluated_key;
+            if last_evaluated_key.is_none() {
+                return Ok(true);
+            }
+        }
+    }
+
+    fn last_sequence(&self, aggregate_id: &str) -> usize {
+        self.last_sequences
+            .get(aggregate_id)
+            .copied()
+            .unwrap_or_default()
+    }
 }
 
-// TODO: combine these two methods
 fn stream_events(base_query: QueryFluentBuilder, channel_size: usize) -> ReplayStream {
+    stream_queries(vec![base_query], channel_size)
+}
+
+fn stream_queries(queries: Vec<QueryFluentBuilder>, channel_size: usize) -> ReplayStream {
     let (mut feed, stream) = ReplayStream::new(channel_size);
     tokio::spawn(async move {
-        let mut last_evaluated_key: Option<HashMap<String, AttributeValue>> = None;
-        loop {
-            let query = match &last_evaluated_key {
-                None => base_query.clone(),
-                Some(last) => last.iter().fold(base_query.clone(), |query, (key, value)| {
-                    query.exclusive_start_key(key.to_string(), value.to_owned())
-                }),
-            };
-            match query.send().await {
-                Ok(query_output) => {
-                    last_evaluated_key = query_output.last_evaluated_key;
-                    if let Some(entries) = query_output.items {
-                        for entry in entries {
-                            let Ok(event) = serialized_event(entry) else {
-                                return;
-                            };
-                            if feed.push(Ok(event)).await.is_err() {
-                                //         TODO: in the unlikely event of a broken channel this error should be reported.
-                                return;
-                            }
+        for base_query in queries {
+            let mut last_evaluated_key: Option<HashMap<String, AttributeValue>> = None;
+            loop {
+                let query = base_query
+                    .clone()
+                    .set_exclusive_start_key(last_evaluated_key);
+                match query.send().await {
+                    Ok(query_output) => {
+                        last_evaluated_key = query_output.last_evaluated_key;
+                        if !push_entries(&mut feed, query_output.items).await {
+                            return;
                         }
                     }
+                    Err(err) => return push_error(feed, err.into()).await,
                 }
-                Err(err) => {
-                    let err: DynamoAggregateError = err.into();
-                    if feed.push(Err(err.into())).await.is_err() {}
+                if last_evaluated_key.is_none() {
+                    break;
                 }
             }
-            if last_evaluated_key.is_none() {
-                return;
-            }
         }
+        feed.finish();
     });
     stream
 }
@@ -394,237 +779,200 @@ fn stream_all_events(base_query: ScanFluentBuilder, channel_size: usize) -> Repl
     tokio::spawn(async move {
         let mut last_evaluated_key: Option<HashMap<String, AttributeValue>> = None;
         loop {
-            let query = match &last_evaluated_key {
-                None => base_query.clone(),
-                Some(last) => last.iter().fold(base_query.clone(), |query, (key, value)| {
-                    query.exclusive_start_key(key.to_string(), value.to_owned())
-                }),
-            };
+            let query = base_query
+                .clone()
+                .set_exclusive_start_key(last_evaluated_key);
             match query.send().await {
                 Ok(query_output) => {
                     last_evaluated_key = query_output.last_evaluated_key;
-                    if let Some(entries) = query_output.items {
-                        for entry in entries {
-                            let Ok(event) = serialized_event(entry) else {
-                                return;
-                            };
-                            if feed.push(Ok(event)).await.is_err() {
-                                //         TODO: in the unlikely event of a broken channel this error should be reported.
-                                return;
-                            }
-                        }
+                    if !push_entries(&mut feed, query_output.items).await {
+                        return;
                     }
                 }
-                Err(err) => {
-                    let err: DynamoAggregateError = err.into();
-                    if feed.push(Err(err.into())).await.is_err() {}
-                }
+                Err(err) => return push_error(feed, err.into()).await,
             }
             if last_evaluated_key.is_none() {
-                return;
+                return feed.finish();
             }
         }
     });
     stream
 }
 
+// Pushes a page of items onto the feed, returns false if streaming should stop.
+async fn push_entries(
+    feed: &mut ReplayFeed,
+    entries: Option<Vec<HashMap<String, AttributeValue>>>,
+) -> bool {
+    for entry in entries.into_iter().flatten() {
+        let event = serialized_event(entry).map_err(Into::into);
+        let failed = event.is_err();
+        // the stream has been dropped if the push fails, so there is no one to report to
+        if feed.push(event).await.is_err() || failed {
+            return false;
+        }
+    }
+    true
+}
+
+// Pushes an error onto the feed, ending the stream.
+async fn push_error(mut feed: ReplayFeed, err: DynamoAggregateError) {
+    let _ = feed.push(Err(err.into())).await;
+}
+
 #[cfg(test)]
 mod test {
-    use cqrs_es::persist::PersistedEventRepository;
+    use std::time::{Duration, SystemTime};
+
+    use cqrs_es::persist::{PersistedEventRepository, ReplayFilter, SerializedEvent};
+    use serde_json::json;
 
-    use crate::error::DynamoAggregateError;
+    use crate::event_repository::filter_conditions;
     use crate::testing::tests::{
-        snapshot_context, test_dynamodb_client, test_event_envelope, Created, SomethingElse,
-        TestAggregate, TestEvent, Tested,
+        test_dynamodb_client, test_event_envelope, Created, TestAggregate, TestEvent, Tested,
     };
     use crate::DynamoEventRepository;
 
     #[tokio::test]
-    async fn event_repositories() {
+    async fn replay_stream_reports_errors() {
         let client = test_dynamodb_client().await;
-        let id = uuid::Uuid::new_v4().to_string();
-        let event_repo = DynamoEventRepository::new(client.clone()).with_streaming_channel_size(1);
-        let events = event_repo.get_events::<TestAggregate>(&id).await.unwrap();
-        assert!(events.is_empty());
+        let event_repo =
+            DynamoEventRepository::new(client).with_tables("missing_events", "missing_snapshots");
+        let mut stream = event_repo
+            .stream_all_events::<TestAggregate>()
+            .await
+            .unwrap();
+        assert!(stream.next_serialized().await.unwrap().is_err());
+        assert!(stream.next_serialized().await.is_none());
+        assert!(!stream.is_complete());
+    }
 
+    #[tokio::test]
+    async fn filtered_replay_stream() {
+        let client = test_dynamodb_client().await;
+        let id = uuid::Uuid::new_v4().to_string();
+        let event_repo = DynamoEventRepository::new(client.clone());
+        let started = SystemTime::now() - Duration::from_secs(60);
+        // streamed events are deserialized, which requires metadata to be present
+        let event = |sequence, event| SerializedEvent {
+            metadata: json!({}),
+            ..test_event_envelope(&id, sequence, event)
+        };
         event_repo
             .insert_events(&[
-                test_event_envelope(&id, 1, TestEvent::Created(Created { id: id.clone() })),
-                test_event_envelope(
-                    &id,
+                event(1, TestEvent::Created(Created { id: id.clone() })),
+                event(
                     2,
                     TestEvent::Tested(Tested {
-                        test_name: "a test was run".to_string(),
+                        test_name: "first test".to_string(),
                     }),
                 ),
-            ])
-            .await
-            .unwrap();
-        let events = event_repo.get_events::<TestAggregate>(&id).await.unwrap();
-        assert_eq!(2, events.len());
-        events.iter().for_each(|e| assert_eq!(&id, &e.aggregate_id));
-
-        // Optimistic lock error
-        let result = event_repo
-            .insert_events(&[
-                test_event_envelope(
-                    &id,
+                event(
                     3,
-                    TestEvent::SomethingElse(SomethingElse {
-                        description: "this should not persist".to_string(),
-                    }),
-                ),
-                test_event_envelope(
-                    &id,
-                    2,
-                    TestEvent::SomethingElse(SomethingElse {
-                        description: "bad sequence number".to_string(),
+                    TestEvent::Tested(Tested {
+                        test_name: "second test".to_string(),
                     }),
                 ),
             ])
             .await
-            .unwrap_err();
-        match result {
-            DynamoAggregateError::OptimisticLock => {}
-            _ => panic!("invalid error result found during insert: {result}"),
-        }
-
-        let events = event_repo.get_events::<TestAggregate>(&id).await.unwrap();
-        assert_eq!(2, events.len());
-
-        let events = event_repo
-            .get_last_events::<TestAggregate>(&id, 1)
-            .await
             .unwrap();
-        assert_eq!(1, events.len());
 
-        verify_replay_stream(&id, event_repo).await;
+        let filter = ReplayFilter::default()
+            .with_aggregate_ids(vec![id.clone()])
+            .with_event_types(vec!["Tested".to_string()])
+            .with_sequences(1..=2);
+        let sequences = stream_sequences(&event_repo, &filter).await;
+        assert_eq!(vec![2], sequences);
+
+        let filter = ReplayFilter::default()
+            .with_aggregate_ids(vec![id.clone()])
+            .committed_between(started, SystemTime::now() + Duration::from_secs(60));
+        let sequences = stream_sequences(&event_repo, &filter).await;
+        assert_eq!(vec![1, 2, 3], sequences);
+
+        let filter = ReplayFilter::default()
+            .with_aggregate_ids(vec![id.clone()])
+            .committed_between(started - Duration::from_secs(60), started);
+        let sequences = stream_sequences(&event_repo, &filter).await;
+        assert!(sequences.is_empty());
     }
 
-    async fn verify_replay_stream(id: &str, event_repo: DynamoEventRepository) {
-        let mut stream = event_repo.stream_events::<TestAggregate>(id).await.unwrap();
-        let mut found_in_stream = 0;
-        while (stream.next::<TestAggregate>(&[]).await).is_some() {
-            found_in_stream += 1;
-        }
-        assert_eq!(found_in_stream, 2);
-
+    #[tokio::test]
+    async fn subscription_follows_committed_events() {
+        let client = test_dynamodb_client().await;
+        let id = uuid::Uuid::new_v4().to_string();
+        let event_repo = DynamoEventRepository::new(client.clone())
+            .with_subscription_poll_interval(Duration::from_millis(100))
+            .with_subscription_index("AggregateTypeCommittedAt");
+        let event = |sequence| SerializedEvent {
+            metadata: json!({}),
+            ..test_event_envelope(
+                &id,
+                sequence,
+                TestEvent::Tested(Tested {
+                    test_name: format!("test {sequence}"),
+                }),
+            )
+        };
+        event_repo.insert_events(&[event(1)]).await.unwrap();
         let mut stream = event_repo
-            .stream_all_events::<TestAggregate>()
+            .subscribe_all_events::<TestAggregate>()
             .await
             .unwrap();
-        let mut found_in_stream = 0;
-        while (stream.next::<TestAggregate>(&[]).await).is_some() {
-            found_in_stream += 1;
+        event_repo
+            .insert_events(&[event(2), event(3)])
+            .await
+            .unwrap();
+
+        // the committed event is replayed, followed by the live events
+        let mut sequences = Vec::new();
+        while sequences.len() < 3 {
+            let next = tokio::time::timeout(Duration::from_secs(5), stream.next_serialized())
+                .await
+                .expect("timed out waiting for subscribed events")
+                .unwrap()
+                .unwrap();
+            if next.aggregate_id == id {
+                sequences.push(next.sequence);
+            }
         }
-        assert!(found_in_stream >= 2);
+        assert_eq!(vec![1, 2, 3], sequences);
     }
 
-    #[tokio::test]
-    async fn snapshot_repositories() {
-        let client = test_dynamodb_client().await;
-        let id = uuid::Uuid::new_v4().to_string();
-        let repo = DynamoEventRepository::new(client.clone());
-        let snapshot = repo.get_snapshot::<TestAggregate>(&id).await.unwrap();
-        assert_eq!(None, snapshot);
-
-        let test_description = "some test snapshot here".to_string();
-        let test_tests = vec!["testA".to_string(), "testB".to_string()];
-        repo.update_snapshot::<TestAggregate>(
-            serde_json::to_value(TestAggregate {
-                id: id.clone(),
-                description: test_description.clone(),
-                tests: test_tests.clone(),
-            })
-            .unwrap(),
-            id.clone(),
-            1,
-            &[],
-        )
-        .await
-        .unwrap();
-
-        let snapshot = repo.get_snapshot::<TestAggregate>(&id).await.unwrap();
+    #[test]
+    fn test_filter_conditions() {
+        let filter = ReplayFilter::default()
+            .with_event_types(vec!["Created".to_string(), "Tested".to_string()])
+            .with_sequences(2..=4)
+            .committed_between(SystemTime::UNIX_EPOCH, SystemTime::UNIX_EPOCH);
+        let (conditions, values) = filter_conditions(&filter, false);
         assert_eq!(
-            Some(snapshot_context(
-                id.clone(),
-                0,
-                1,
-                serde_json::to_value(TestAggregate {
-                    id: id.clone(),
-                    description: test_description.clone(),
-                    tests: test_tests.clone(),
-                })
-                .unwrap(),
-            )),
-            snapshot
+            vec![
+                "EventType IN (:event_type_0, :event_type_1)",
+                "CommittedAt >= :committed_from",
+                "CommittedAt < :committed_to",
+            ],
+            conditions
         );
+        assert_eq!(4, values.len());
 
-        // sequence iterated, does update
-        repo.update_snapshot::<TestAggregate>(
-            serde_json::to_value(TestAggregate {
-                id: id.clone(),
-                description: "a test description that should be saved".to_string(),
-                tests: test_tests.clone(),
-            })
-            .unwrap(),
-            id.clone(),
-            2,
-            &[],
-        )
-        .await
-        .unwrap();
-
-        let snapshot = repo.get_snapshot::<TestAggregate>(&id).await.unwrap();
-        assert_eq!(
-            Some(snapshot_context(
-                id.clone(),
-                0,
-                2,
-                serde_json::to_value(TestAggregate {
-                    id: id.clone(),
-                    description: "a test description that should be saved".to_string(),
-                    tests: test_tests.clone(),
-                })
-                .unwrap(),
-            )),
-            snapshot
-        );
+        let (conditions, values) = filter_conditions(&filter, true);
+        assert_eq!(5, conditions.len());
+        assert_eq!(6, values.len());
+    }
 
-        // sequence out of order or not iterated, does not update
-        let result = repo
-            .update_snapshot::<TestAggregate>(
-                serde_json::to_value(TestAggregate {
-                    id: id.clone(),
-                    description: "a test description that should not be saved".to_string(),
-                    tests: test_tests.clone(),
-                })
-                .unwrap(),
-                id.clone(),
-                2,
-                &[],
-            )
+    async fn stream_sequences(
+        event_repo: &DynamoEventRepository,
+        filter: &ReplayFilter,
+    ) -> Vec<usize> {
+        let mut stream = event_repo
+            .stream_filtered_events::<TestAggregate>(filter)
             .await
-            .unwrap_err();
-        match result {
-            DynamoAggregateError::OptimisticLock => {}
-            _ => panic!("invalid error result found during insert: {result}"),
+            .unwrap();
+        let mut sequences = Vec::new();
+        while let Some(event) = stream.next::<TestAggregate>(&[]).await {
+            sequences.push(event.unwrap().sequence);
         }
-
-        let snapshot = repo.get_snapshot::<TestAggregate>(&id).await.unwrap();
-        assert_eq!(
-            Some(snapshot_context(
-                id.clone(),
-                0,
-                2,
-                serde_json::to_value(TestAggregate {
-                    id: id.clone(),
-                    description: "a test description that should be saved".to_string(),
-                    tests: test_tests.clone(),
-                })
-                .unwrap(),
-            )),
-            snapshot
-        );
+        sequences
     }
 }
@@ -7,10 +7,7 @@ pub(crate) mod tests {
     use aws_sdk_dynamodb::config::{Credentials, Region};
     use aws_sdk_dynamodb::Client;
     use cqrs_es::event_sink::EventSink;
-    use cqrs_es::persist::{
-        GenericQuery, PersistedEventRepository, PersistedEventStore, SerializedEvent,
-        SerializedSnapshot,
-    };
+    use cqrs_es::persist::{GenericQuery, PersistedEventStore, SerializedEvent};
     use cqrs_es::{Aggregate, DomainEvent, EventEnvelope, EventStore, View};
     use serde::{Deserialize, Serialize};
     use serde_json::Value;
@@ -149,20 +146,6 @@ pub(crate) mod tests {
         }
     }
 
-    pub(crate) fn snapshot_context(
-        aggregate_id: String,
-        current_sequence: usize,
-        current_snapshot: usize,
-        aggregate: Value,
-    ) -> SerializedSnapshot {
-        SerializedSnapshot {
-            aggregate_id,
-            aggregate,
-            current_sequence,
-            current_snapshot,
-        }
-    }
-
     #[tokio::test]
     async fn commit_and_load_events() {
         let client = test_dynamodb_client().await;
@@ -202,52 +185,4 @@ pub(crate) mod tests {
             .unwrap();
         assert_eq!(3, event_store.load_events(id.as_str()).await.unwrap().len());
     }
-
-    #[tokio::test]
-    async fn event_repositories() {
-        let client = test_dynamodb_client().await;
-        let id = uuid::Uuid::new_v4().to_string();
-        let event_repo = DynamoEventRepository::new(client.clone());
-        let events = event_repo.get_events::<TestAggregate>(&id).await.unwrap();
-        assert!(events.is_empty());
-
-        event_repo
-            .insert_events(&[
-                test_event_envelope(&id, 1, TestEvent::Created(Created { id: id.clone() })),
-                test_event_envelope(
-                    &id,
-                    2,
-                    TestEvent::Tested(Tested {
-                        test_name: "a test was run".to_string(),
-                    }),
-                ),
-            ])
-            .await
-            .unwrap();
-        let events = event_repo.get_events::<TestAggregate>(&id).await.unwrap();
-        assert_eq!(2, events.len());
-        events.iter().for_each(|e| assert_eq!(&id, &e.aggregate_id));
-
-        event_repo
-            .insert_events(&[
-                test_event_envelope(
-                    &id,
-                    3,
-                    TestEvent::SomethingElse(SomethingElse {
-                        description: "this should not persist".to_string(),
-                    }),
-                ),
-                test_event_envelope(
-                    &id,
-                    2,
-                    TestEvent::SomethingElse(SomethingElse {
-                        description: "bad sequence number".to_string(),
-                    }),
-                ),
-            ])
-            .await
-            .unwrap_err();
-        let events = event_repo.get_events::<TestAggregate>(&id).await.unwrap();
-        assert_eq!(2, events.len());
-    }
 }
@@ -1,16 +1,36 @@
+use std::collections::HashMap;
 use std::marker::PhantomData;
+use std::sync::RwLock;
 
 use aws_sdk_dynamodb::primitives::Blob;
-use aws_sdk_dynamodb::types::{AttributeValue, Put, TransactWriteItem};
-use cqrs_es::persist::{PersistenceError, ViewContext, ViewRepository};
+use aws_sdk_dynamodb::types::{
+    AttributeValue, ConditionCheck, Delete, KeysAndAttributes, Put, Select, TransactWriteItem,
+};
+use cqrs_es::persist::{
+    ListableViewRepository, PersistenceError, ShadowViewRepository, ViewContext, ViewFilter,
+    ViewListQuery, ViewPage, ViewRepository,
+};
 use cqrs_es::{Aggregate, View};
 
-use crate::helpers::{att_as_number, att_as_value, commit_transactions, load_dynamo_view};
+use crate::error::DynamoAggregateError;
+use crate::helpers::{
+    att_as_number, att_as_string, att_as_value, commit_transactions, load_dynamo_view,
+};
+
+// The maximum number of items in a single BatchGetItem and TransactWriteItems request.
+const BATCH_GET_SIZE: usize = 100;
+const TRANSACT_WRITE_SIZE: usize = 100;
+// The view id of the item, held in the view table, that names the table holding the live views
+// once a shadow table has been promoted.
+const ACTIVE_TABLE_VIEW_ID: &str = "#active-view-table";
 
 /// A DynamoDb backed view repository for use in backing a `GenericQuery`.
 pub struct DynamoViewRepository<V, A> {
     _phantom: PhantomData<(V, A)>,
     view_name: String,
+    shadow_name: Option<String>,
+    // the name of the live table, once read from the active table item
+    live_table: RwLock<Option<String>>,
     client: aws_sdk_dynamodb::client::Client,
 }
 
@@ -38,9 +58,166 @@ where
         Self {
             _phantom: PhantomData,
             view_name: view_name.to_string(),
+            shadow_name: None,
+            live_table: RwLock::default(),
             client,
         }
     }
+
+    /// Configures a shadow table that views will be rebuilt into during a blue/green rebuild.
+    /// The shadow table must have the same key schema as the view table.
+    ///
+    /// DynamoDb tables cannot be renamed, so the name of the table holding the live views is
+    /// kept in an item of the view table and promoting the shadow table updates this item.
+    /// The item is read once and the table name cached. Writes to the views are only committed
+    /// while the cached table is still live, so a repository in another process follows a
+    /// promotion at its next write, which fails with an optimistic lock error and is retried
+    /// against the new table. Until then loads read the previously live table, use `promoted`
+    /// to follow a promotion made elsewhere immediately.
+    ///
+    /// ```
+    /// # use cqrs_es::doc::MyAggregate;
+    /// # use cqrs_es::persist::doc::MyView;
+    /// use aws_sdk_dynamodb::Client;
+    /// use dynamo_es::DynamoViewRepository;
+    ///
+    /// fn configure_view_repo(client: Client) -> DynamoViewRepository<MyView,MyAggregate> {
+    ///     DynamoViewRepository::new("my_view_table", client)
+    ///         .with_shadow_table("my_view_table_shadow")
+    /// }
+    /// ```
+    pub fn with_shadow_table(self, shadow_name: &str) -> Self {
+        Self {
+            shadow_name: Some(shadow_name.to_string()),
+            ..self
+        }
+    }
+
+    /// The name of the table holding the live views.
+    pub async fn live_table(&self) -> Result<String, PersistenceError> {
+        Ok(self.live_table_name().await?)
+    }
+
+    /// Drops the cached name of the live table so that it is read again by the next operation,
+    /// e.g., once the shadow table has been promoted by another process.
+    pub fn promoted(&self) {
+        *self.live_table.write().unwrap() = None;
+    }
+
+    async fn live_table_name(&self) -> Result<String, DynamoAggregateError> {
+        if self.shadow_name.is_none() {
+            return Ok(self.view_name.clone());
+        }
+        if let Some(live_table) = self.live_table.read().unwrap().clone() {
+            return Ok(live_table);
+        }
+        let output = self
+            .client
+            .query()
+            .table_name(&self.view_name)
+            .consistent_read(true)
+            .key_condition_expression("ViewId = :view_id")
+            .expression_attribute_values(
+                ":view_id",
+                AttributeValue::S(ACTIVE_TABLE_VIEW_ID.to_string()),
+            )
+            .send()
+            .await?;
+        let live_table = match output.items.unwrap_or_default().first() {
+            Some(item) => att_as_string(item, "ActiveTable")?,
+            None => self.view_name.clone(),
+        };
+        *self.live_table.write().unwrap() = Some(live_table.clone());
+        Ok(live_table)
+    }
+
+    // With a shadow table configured the transaction also checks that the table written to is
+    // still live, a conflict drops the cached table name so that retries follow a promotion.
+    async fn commit_to_live_table(
+        &self,
+        live_table: &str,
+        mut transactions: Vec<TransactWriteItem>,
+    ) -> Result<(), DynamoAggregateError> {
+        if self.shadow_name.is_some() {
+            let check = ConditionCheck::builder()
+                .table_name(&self.view_name)
+                .key(
+                    "ViewId",
+                    AttributeValue::S(ACTIVE_TABLE_VIEW_ID.to_string()),
+                )
+                .condition_expression(
+                    "attribute_not_exists(ActiveTable) OR ActiveTable = :live_table",
+                )
+                .expression_attribute_values(
+                    ":live_table",
+                    AttributeValue::S(live_table.to_string()),
+                )
+                .build()?;
+            transactions.push(TransactWriteItem::builder().condition_check(check).build());
+        }
+        let result = self
+            .client
+            .transact_write_items()
+            .set_transact_items(Some(transactions))
+            .send()
+            .await;
+        if let Err(err) = result {
+            let err = DynamoAggregateError::from(err);
+            if let DynamoAggregateError::OptimisticLock = err {
+                self.promoted();
+            }
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    // The shadow location is whichever of the two tables is not currently live.
+    fn shadow_table_name(&self, live_table: &str) -> Result<String, PersistenceError> {
+        let Some(shadow_name) = &self.shadow_name else {
+            return Err(PersistenceError::UnknownError(
+                "no shadow table configured for this view".into(),
+            ));
+        };
+        match live_table == self.view_name {
+            true => Ok(shadow_name.clone()),
+            false => Ok(self.view_name.clone()),
+        }
+    }
+
+    async fn clear_table(&self, table_name: &str) -> Result<(), DynamoAggregateError> {
+        let mut last_evaluated_key: Option<HashMap<String, AttributeValue>> = None;
+        loop {
+            let scan_output = self
+                .client
+                .scan()
+                .table_name(table_name)
+                .projection_expression("ViewId")
+                .filter_expression("ViewId <> :active_table_view_id")
+                .expression_attribute_values(
+                    ":active_table_view_id",
+                    AttributeValue::S(ACTIVE_TABLE_VIEW_ID.to_string()),
+                )
+                .set_exclusive_start_key(last_evaluated_key)
+                .send()
+                .await?;
+            let items = scan_output.items.unwrap_or_default();
+            for chunk in items.chunks(25) {
+                let mut transactions = Vec::with_capacity(chunk.len());
+                for item in chunk {
+                    let delete = Delete::builder()
+                        .table_name(table_name)
+                        .set_key(Some(item.clone()))
+                        .build()?;
+                    transactions.push(TransactWriteItem::builder().delete(delete).build());
+                }
+                commit_transactions(&self.client, transactions).await?;
+            }
+            last_evaluated_key = scan_output.last_evaluated_key;
+            if last_evaluated_key.is_none() {
+                return Ok(());
+            }
+        }
+    }
 }
 
 impl<V, A> ViewRepository<V, A> for DynamoViewRepository<V, A>
@@ -49,7 +226,8 @@ where
     A: Aggregate,
 {
     async fn load(&self, view_id: &str) -> Result<Option<V>, PersistenceError> {
-        let query_result = load_dynamo_view(&self.client, &self.view_name, view_id).await?;
+        let table_name = self.live_table_name().await?;
+        let query_result = load_dynamo_view(&self.client, &table_name, view_id).await?;
         let query_items = match query_result.items {
             None => return Ok(None),
             Some(items) => items,
@@ -67,7 +245,8 @@ where
         &self,
         view_id: &str,
     ) -> Result<Option<(V, ViewContext)>, PersistenceError> {
-        let query_result = load_dynamo_view(&self.client, &self.view_name, view_id).await?;
+        let table_name = self.live_table_name().await?;
+        let query_result = load_dynamo_view(&self.client, &table_name, view_id).await?;
         let query_items = match query_result.items {
             None => return Ok(None),
             Some(items) => items,
@@ -81,9 +260,15 @@ where
             Some(item) => item,
         };
         let version = att_as_number(query_item, "ViewVersion")?;
+        // views stored before sequences were tracked will not have a `LastSequence`
+        let last_sequence = match query_item.contains_key("LastSequence") {
+            true => att_as_number(query_item, "LastSequence")?,
+            false => 0,
+        };
         let payload = att_as_value(query_item, "Payload")?;
         let view: V = serde_json::from_value(payload)?;
-        let context = ViewContext::new(view_id.to_string(), version as i64);
+        let context =
+            ViewContext::new(view_id.to_string(), version as i64).with_last_sequence(last_sequence);
         Ok(Some((view, context)))
     }
 
@@ -91,31 +276,303 @@ where
         let view_id = AttributeValue::S(String::from(&context.view_instance_id));
         let expected_view_version = AttributeValue::N(context.version.to_string());
         let view_version = AttributeValue::N((context.version + 1).to_string());
+        let last_sequence = AttributeValue::N(context.last_sequence.to_string());
         let payload_blob = serde_json::to_vec(&view).unwrap();
         let payload = AttributeValue::B(Blob::new(payload_blob));
+        let table_name = self.live_table_name().await?;
         let transaction = TransactWriteItem::builder()
             .put(Put::builder()
-                .table_name(&self.view_name)
+                .table_name(&table_name)
                 .item("ViewId", view_id)
                 .item("ViewVersion", view_version)
+                .item("LastSequence", last_sequence)
                 .item("Payload", payload)
                 .condition_expression("attribute_not_exists(ViewVersion) OR (ViewVersion  = :expected_view_version)")
                 .expression_attribute_values(":expected_view_version", expected_view_version)
                 .build()
                 .map_err(|e|PersistenceError::UnknownError(Box::new(e)))?)
             .build();
-        commit_transactions(&self.client, vec![transaction]).await?;
+        self.commit_to_live_table(&table_name, vec![transaction])
+            .await?;
+        Ok(())
+    }
+
+    async fn load_all_with_context(
+        &self,
+        view_ids: &[String],
+    ) -> Result<Vec<(V, ViewContext)>, PersistenceError> {
+        let table_name = self.live_table_name().await?;
+        let mut views = Vec::with_capacity(view_ids.len());
+        for chunk in view_ids.chunks(BATCH_GET_SIZE) {
+            let keys = chunk
+                .iter()
+                .map(|view_id| {
+                    HashMap::from([("ViewId".to_string(), AttributeValue::S(view_id.clone()))])
+                })
+                .collect();
+            let mut request_items = Some(HashMap::from([(
+                table_name.clone(),
+                KeysAndAttributes::builder()
+                    .set_keys(Some(keys))
+                    .build()
+                    .map_err(DynamoAggregateError::from)?,
+            )]));
+            while let Some(items) = request_items.filter(|items| !items.is_empty()) {
+                let output = self
+                    .client
+                    .batch_get_item()
+                    .set_request_items(Some(items))
+                    .send()
+                    .await
+                    .map_err(DynamoAggregateError::from)?;
+                let found = output
+                    .responses
+                    .and_then(|mut responses| responses.remove(&table_name))
+                    .unwrap_or_default();
+                for item in found {
+                    let view_id = att_as_string(&item, "ViewId")?;
+                    let version = att_as_number(&item, "ViewVersion")?;
+                    let last_sequence = match item.contains_key("LastSequence") {
+                        true => att_as_number(&item, "LastSequence")?,
+                        false => 0,
+                    };
+                    let view: V = serde_json::from_value(att_as_value(&item, "Payload")?)?;
+                    let context =
+                        ViewContext::new(view_id, version as i64).with_last_sequence(last_sequence);
+                    views.push((view, context));
+                }
+                request_items = output.unprocessed_keys;
+            }
+        }
+        Ok(views)
+    }
+
+    // Views are written in transactions of up to 99 views, leaving room for the check that the
+    // table is still live, each view is only written if it has not been modified since it was
+    // loaded. A conflict cancels only the transaction holding the conflicting view, views in
+    // earlier transactions will already have been written.
+    async fn update_all(&self, views: Vec<(V, ViewContext)>) -> Result<(), PersistenceError> {
+        let table_name = self.live_table_name().await?;
+        for chunk in views.chunks(TRANSACT_WRITE_SIZE - 1) {
+            let mut transactions = Vec::with_capacity(chunk.len());
+            for (view, context) in chunk {
+                let payload = serde_json::to_vec(view).map_err(DynamoAggregateError::from)?;
+                let put = Put::builder()
+                    .table_name(&table_name)
+                    .item("ViewId", AttributeValue::S(context.view_instance_id.clone()))
+                    .item(
+                        "ViewVersion",
+                        AttributeValue::N((context.version + 1).to_string()),
+                    )
+                    .item(
+                        "LastSequence",
+                        AttributeValue::N(context.last_sequence.to_string()),
+                    )
+                    .item("Payload", AttributeValue::B(Blob::new(payload)))
+                    .condition_expression(
+                        "attribute_not_exists(ViewVersion) OR (ViewVersion = :expected_view_version)",
+                    )
+                    .expression_attribute_values(
+                        ":expected_view_version",
+                        AttributeValue::N(context.version.to_string()),
+                    )
+                    .build()
+                    .map_err(DynamoAggregateError::from)?;
+                transactions.push(TransactWriteItem::builder().put(put).build());
+            }
+            self.commit_to_live_table(&table_name, transactions).await?;
+        }
+        Ok(())
+    }
+
+    async fn delete_view(&self, context: ViewContext) -> Result<(), PersistenceError> {
+        let view_id = AttributeValue::S(context.view_instance_id);
+        let expected_view_version = AttributeValue::N(context.version.to_string());
+        let table_name = self.live_table_name().await?;
+        let transaction = TransactWriteItem::builder()
+            .delete(
+                Delete::builder()
+                    .table_name(&table_name)
+                    .key("ViewId", view_id)
+                    .condition_expression("ViewVersion = :expected_view_version")
+                    .expression_attribute_values(":expected_view_version", expected_view_version)
+                    .build()
+                    .map_err(|e| PersistenceError::UnknownError(Box::new(e)))?,
+            )
+            .build();
+        self.commit_to_live_table(&table_name, vec![transaction])
+            .await?;
         Ok(())
     }
+
+    async fn clear_all(&self) -> Result<(), PersistenceError> {
+        let table_name = self.live_table_name().await?;
+        self.clear_table(&table_name).await?;
+        Ok(())
+    }
+}
+
+// Payloads are stored as binary so DynamoDb cannot filter on their fields, views are instead
+// filtered as they are scanned and the view id of the last view returned is used as the cursor.
+impl<V, A> ListableViewRepository<V, A> for DynamoViewRepository<V, A>
+where
+    V: View<A>,
+    A: Aggregate,
+{
+    async fn list_views(&self, query: &ViewListQuery) -> Result<ViewPage<V>, PersistenceError> {
+        let table_name = self.live_table_name().await?;
+        let mut views = Vec::with_capacity(query.page_size());
+        let mut exclusive_start_key = query.cursor.as_ref().map(|cursor| {
+            HashMap::from([("ViewId".to_string(), AttributeValue::S(cursor.clone()))])
+        });
+        loop {
+            let scan_output = self
+                .client
+                .scan()
+                .table_name(&table_name)
+                .filter_expression("ViewId <> :active_table_view_id")
+                .expression_attribute_values(
+                    ":active_table_view_id",
+                    AttributeValue::S(ACTIVE_TABLE_VIEW_ID.to_string()),
+                )
+                .set_exclusive_start_key(exclusive_start_key)
+                .send()
+                .await
+                .map_err(DynamoAggregateError::from)?;
+            let items = scan_output.items.unwrap_or_default();
+            let items_remaining = push_matching_views(&mut views, items, query)?;
+            exclusive_start_key = scan_output.last_evaluated_key;
+            if views.len() == query.page_size() {
+                let has_more = items_remaining || exclusive_start_key.is_some();
+                let next_cursor = match has_more {
+                    true => views.last().map(|(view_id, _)| view_id.clone()),
+                    false => None,
+                };
+                return Ok(ViewPage { views, next_cursor });
+            }
+            if exclusive_start_key.is_none() {
+                return Ok(ViewPage {
+                    views,
+                    next_cursor: None,
+                });
+            }
+        }
+    }
+
+    async fn count_views(&self, filters: &[ViewFilter]) -> Result<u64, PersistenceError> {
+        let table_name = self.live_table_name().await?;
+        let mut count = 0;
+        let mut exclusive_start_key: Option<HashMap<String, AttributeValue>> = None;
+        loop {
+            let scan = self
+                .client
+                .scan()
+                .table_name(&table_name)
+                .filter_expression("ViewId <> :active_table_view_id")
+                .expression_attribute_values(
+                    ":active_table_view_id",
+                    AttributeValue::S(ACTIVE_TABLE_VIEW_ID.to_string()),
+                )
+                .set_exclusive_start_key(exclusive_start_key);
+            let scan = match filters.is_empty() {
+                true => scan.select(Select::Count),
+                false => scan.projection_expression("Payload"),
+            };
+            let scan_output = scan.send().await.map_err(DynamoAggregateError::from)?;
+            if filters.is_empty() {
+                count += scan_output.count as u64;
+            } else {
+                for item in scan_output.items.unwrap_or_default() {
+                    let payload = att_as_value(&item, "Payload")?;
+                    if filters.iter().all(|filter| filter.matches(&payload)) {
+                        count += 1;
+                    }
+                }
+            }
+            exclusive_start_key = scan_output.last_evaluated_key;
+            if exclusive_start_key.is_none() {
+                return Ok(count);
+            }
+        }
+    }
+}
+
+impl<V, A> ShadowViewRepository<V, A> for DynamoViewRepository<V, A>
+where
+    V: View<A>,
+    A: Aggregate,
+{
+    type Shadow = Self;
+
+    async fn prepare_shadow(&self) -> Result<Self::Shadow, PersistenceError> {
+        let live_table = self.live_table_name().await?;
+        let shadow = Self::new(&self.shadow_table_name(&live_table)?, self.client.clone());
+        shadow.clear_all().await?;
+        Ok(shadow)
+    }
+
+    // The active table item is only replaced if it still names the table that was live, so
+    // concurrent promotions cannot both succeed.
+    async fn promote_shadow(&self) -> Result<(), PersistenceError> {
+        let live_table = self.live_table_name().await?;
+        let shadow_table = self.shadow_table_name(&live_table)?;
+        let promoted_table = shadow_table.clone();
+        let put = Put::builder()
+            .table_name(&self.view_name)
+            .item(
+                "ViewId",
+                AttributeValue::S(ACTIVE_TABLE_VIEW_ID.to_string()),
+            )
+            .item("ActiveTable", AttributeValue::S(shadow_table))
+            .condition_expression("attribute_not_exists(ActiveTable) OR ActiveTable = :live_table")
+            .expression_attribute_values(":live_table", AttributeValue::S(live_table))
+            .build()
+            .map_err(DynamoAggregateError::from)?;
+        let transaction = TransactWriteItem::builder().put(put).build();
+        let result = commit_transactions(&self.client, vec![transaction]).await;
+        *self.live_table.write().unwrap() = result.is_ok().then_some(promoted_table);
+        Ok(result?)
+    }
+}
+
+// Adds the views matching the query from a page of scanned items until the limit is reached,
+// returns whether any items were left unread.
+fn push_matching_views<V: View<A>, A: Aggregate>(
+    views: &mut Vec<(String, V)>,
+    items: Vec<HashMap<String, AttributeValue>>,
+    query: &ViewListQuery,
+) -> Result<bool, DynamoAggregateError> {
+    let mut items = items.into_iter();
+    while views.len() < query.page_size() {
+        let Some(item) = items.next() else {
+            return Ok(false);
+        };
+        let payload = att_as_value(&item, "Payload")?;
+        if !query.filters.iter().all(|filter| filter.matches(&payload)) {
+            continue;
+        }
+        let view_id = att_as_string(&item, "ViewId")?;
+        views.push((view_id, serde_json::from_value(payload)?));
+    }
+    Ok(items.len() > 0)
 }
 
 #[cfg(test)]
 mod test {
-    use cqrs_es::persist::{ViewContext, ViewRepository};
+    use cqrs_es::persist::{
+        ListableViewRepository, PersistenceError, ShadowViewRepository, ViewContext, ViewFilter,
+        ViewListQuery, ViewRepository,
+    };
+
+    use std::collections::HashMap;
+
+    use aws_sdk_dynamodb::primitives::Blob;
+    use aws_sdk_dynamodb::types::AttributeValue;
 
     use crate::testing::tests::{
         test_dynamodb_client, Created, TestAggregate, TestEvent, TestView,
     };
+    use crate::view_repository::push_matching_views;
     use crate::DynamoViewRepository;
 
     #[tokio::test]
@@ -131,15 +588,15 @@ mod test {
                 id: "just a test event for this view".to_string(),
             })],
         };
-        repo.update_view(view.clone(), ViewContext::new(test_view_id.to_string(), 0))
-            .await
-            .unwrap();
+        let context = ViewContext::new(test_view_id.to_string(), 0).with_last_sequence(3);
+        repo.update_view(view.clone(), context).await.unwrap();
         let (found, context) = repo
             .load_with_context(&test_view_id)
             .await
             .unwrap()
             .unwrap();
         assert_eq!(found, view);
+        assert_eq!(3, context.last_sequence);
 
         let updated_view = TestView {
             events: vec![TestEvent::Created(Created {
@@ -154,4 +611,209 @@ mod test {
 
         assert_eq!(found, updated_view);
     }
+
+    #[tokio::test]
+    async fn test_shadow_view_repository() {
+        let repo = DynamoViewRepository::<TestView, TestAggregate>::new(
+            "TestViewTable",
+            test_dynamodb_client().await,
+        )
+        .with_shadow_table("TestViewShadowTable");
+        let test_view_id = uuid::Uuid::new_v4().to_string();
+
+        let view = TestView {
+            events: vec![TestEvent::Created(Created {
+                id: "a rebuilt view".to_string(),
+            })],
+        };
+        let shadow = repo.prepare_shadow().await.unwrap();
+        shadow
+            .update_view(view.clone(), ViewContext::new(test_view_id.to_string(), 0))
+            .await
+            .unwrap();
+        assert_eq!(None, repo.load(&test_view_id).await.unwrap());
+
+        repo.promote_shadow().await.unwrap();
+        assert_eq!("TestViewShadowTable", repo.live_table().await.unwrap());
+        let found = repo.load(&test_view_id).await.unwrap().unwrap();
+        assert_eq!(found, view);
+
+        // a repository configured with the same tables follows the promotion
+        let other_repo = DynamoViewRepository::<TestView, TestAggregate>::new(
+            "TestViewTable",
+            test_dynamodb_client().await,
+        )
+        .with_shadow_table("TestViewShadowTable");
+        let found = other_repo.load(&test_view_id).await.unwrap().unwrap();
+        assert_eq!(found, view);
+
+        // promoting again switches back to the original table, the other repository follows once
+        // a write to the table it last read fails
+        repo.prepare_shadow().await.unwrap();
+        repo.promote_shadow().await.unwrap();
+        assert_eq!("TestViewTable", repo.live_table().await.unwrap());
+        assert_eq!(
+            "TestViewShadowTable",
+            other_repo.live_table().await.unwrap()
+        );
+        let result = other_repo
+            .update_view(view.clone(), ViewContext::new(test_view_id.to_string(), 1))
+            .await;
+        assert!(matches!(result, Err(PersistenceError::OptimisticLockError)));
+        assert_eq!("TestViewTable", other_repo.live_table().await.unwrap());
+
+        repo.prepare_shadow().await.unwrap();
+        repo.promote_shadow().await.unwrap();
+        other_repo.promoted();
+        assert_eq!(
+            "TestViewShadowTable",
+            other_repo.live_table().await.unwrap()
+        );
+
+        // leave the original table live
+        repo.prepare_shadow().await.unwrap();
+        repo.promote_shadow().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_list_views() {
+        let repo = DynamoViewRepository::<TestView, TestAggregate>::new(
+            "TestViewTable",
+            test_dynamodb_client().await,
+        );
+        let marker = uuid::Uuid::new_v4().to_string();
+        for _ in 0..3 {
+            let view = TestView {
+                events: vec![TestEvent::Created(Created { id: marker.clone() })],
+            };
+            let view_id = uuid::Uuid::new_v4().to_string();
+            repo.update_view(view, ViewContext::new(view_id, 0))
+                .await
+                .unwrap();
+        }
+        let filter = ViewFilter::equals("events.0.Created.id", marker.as_str());
+
+        let count = repo
+            .count_views(std::slice::from_ref(&filter))
+            .await
+            .unwrap();
+        assert_eq!(3, count);
+
+        let query = ViewListQuery::new(2).with_filter(filter);
+        let first_page = repo.list_views(&query).await.unwrap();
+        assert_eq!(2, first_page.views.len());
+
+        let query = query.after(first_page.next_cursor);
+        let second_page = repo.list_views(&query).await.unwrap();
+        assert_eq!(1, second_page.views.len());
+        assert_eq!(None, second_page.next_cursor);
+    }
+
+    #[tokio::test]
+    async fn test_delete_view() {
+        let repo = DynamoViewRepository::<TestView, TestAggregate>::new(
+            "TestViewTable",
+            test_dynamodb_client().await,
+        );
+        let test_view_id = uuid::Uuid::new_v4().to_string();
+
+        let view = TestView {
+            events: vec![TestEvent::Created(Created {
+                id: "a view to delete".to_string(),
+            })],
+        };
+        repo.update_view(view, ViewContext::new(test_view_id.to_string(), 0))
+            .await
+            .unwrap();
+        let stale_context = ViewContext::new(test_view_id.to_string(), 0);
+        let result = repo.delete_view(stale_context).await;
+        assert!(matches!(result, Err(PersistenceError::OptimisticLockError)));
+
+        let (_, context) = repo
+            .load_with_context(&test_view_id)
+            .await
+            .unwrap()
+            .unwrap();
+        repo.delete_view(context).await.unwrap();
+        assert_eq!(None, repo.load(&test_view_id).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_update_all() {
+        let repo = DynamoViewRepository::<TestView, TestAggregate>::new(
+            "TestViewTable",
+            test_dynamodb_client().await,
+        );
+        let existing_id = uuid::Uuid::new_v4().to_string();
+        let new_id = uuid::Uuid::new_v4().to_string();
+        let view = |id: &str| TestView {
+            events: vec![TestEvent::Created(Created { id: id.to_string() })],
+        };
+        repo.update_view(view("existing"), ViewContext::new(existing_id.clone(), 0))
+            .await
+            .unwrap();
+
+        let view_ids = vec![existing_id.clone(), new_id.clone()];
+        let loaded = repo.load_all_with_context(&view_ids).await.unwrap();
+        assert_eq!(1, loaded.len());
+        let (_, existing_context) = loaded.into_iter().next().unwrap();
+        repo.update_all(vec![
+            (view("updated"), existing_context.with_last_sequence(2)),
+            (view("new"), ViewContext::new(new_id.clone(), 0)),
+        ])
+        .await
+        .unwrap();
+        let (found, context) = repo.load_with_context(&existing_id).await.unwrap().unwrap();
+        assert_eq!(view("updated"), found);
+        assert_eq!((2, 2), (context.version, context.last_sequence));
+        assert_eq!(Some(view("new")), repo.load(&new_id).await.unwrap());
+
+        // a stale view cancels the whole transaction
+        let other_id = uuid::Uuid::new_v4().to_string();
+        let result = repo
+            .update_all(vec![
+                (view("other"), ViewContext::new(other_id.clone(), 0)),
+                (view("stale"), ViewContext::new(existing_id.clone(), 1)),
+            ])
+            .await;
+        assert!(matches!(result, Err(PersistenceError::OptimisticLockError)));
+        assert_eq!(
+            Some(view("updated")),
+            repo.load(&existing_id).await.unwrap()
+        );
+        assert_eq!(None, repo.load(&other_id).await.unwrap());
+    }
+
+    #[test]
+    fn test_push_matching_views_stops_at_limit() {
+        let item = |view_id: &str| {
+            let view = TestView {
+                events: vec![TestEvent::Created(Created {
+                    id: view_id.to_string(),
+                })],
+            };
+            HashMap::from([
+                ("ViewId".to_string(), AttributeValue::S(view_id.to_string())),
+                (
+                    "Payload".to_string(),
+                    AttributeValue::B(Blob::new(serde_json::to_vec(&view).unwrap())),
+                ),
+            ])
+        };
+        let query = ViewListQuery::new(2);
+
+        // the last item of the scan page must be left for the following page
+        let mut views: Vec<(String, TestView)> = Vec::new();
+        let items = vec![item("a"), item("b"), item("c")];
+        let remaining = push_matching_views(&mut views, items, &query).unwrap();
+        assert!(remaining);
+        let view_ids: Vec<&str> = views.iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(vec!["a", "b"], view_ids);
+
+        let mut views: Vec<(String, TestView)> = Vec::new();
+        let items = vec![item("a"), item("b")];
+        let remaining = push_matching_views(&mut views, items, &query).unwrap();
+        assert!(!remaining);
+        assert_eq!(2, views.len());
+    }
 }
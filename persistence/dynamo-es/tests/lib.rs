@@ -8,8 +8,9 @@ use aws_sdk_dynamodb::types::AttributeValue;
 use aws_sdk_dynamodb::Client;
 use cqrs_es::doc::{Customer, CustomerEvent};
 use cqrs_es::persist::{PersistedEventStore, SemanticVersionEventUpcaster};
+use cqrs_es::test::{EventRepositoryConformance, ViewRepositoryConformance};
 use cqrs_es::EventStore;
-use dynamo_es::DynamoEventRepository;
+use dynamo_es::{DynamoEventRepository, DynamoViewRepository};
 use serde_json::Value;
 
 pub fn test_dynamodb_client() -> Client {
@@ -151,3 +152,21 @@ async fn upcasted_event() {
     assert_eq!(1, result.current_sequence);
     assert_eq!(None, result.current_snapshot);
 }
+
+#[tokio::test]
+async fn event_repository_conformance() {
+    let client = test_dynamodb_client();
+    EventRepositoryConformance::with(move || DynamoEventRepository::new(client.clone()))
+        .verify()
+        .await;
+}
+
+#[tokio::test]
+async fn view_repository_conformance() {
+    let client = test_dynamodb_client();
+    ViewRepositoryConformance::with(move || {
+        DynamoViewRepository::new("ConformanceViewTable", client.clone())
+    })
+    .verify()
+    .await;
+}
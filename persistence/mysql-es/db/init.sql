@@ -8,6 +8,8 @@ CREATE TABLE events
     event_version  text                         NOT NULL,
     payload        json                         NOT NULL,
     metadata       json                         NOT NULL,
+    committed_at   timestamp(6)                 NOT NULL DEFAULT CURRENT_TIMESTAMP(6),
+    global_position bigint                      NOT NULL AUTO_INCREMENT UNIQUE,
     CONSTRAINT events_pk PRIMARY KEY (aggregate_type, aggregate_id, sequence)
 );
 
@@ -26,12 +28,34 @@ CREATE TABLE snapshots
 -- replace name with the value used in `MysqlViewRepository::new(view_name: String)`
 CREATE TABLE test_view
 (
-    view_id varchar(255)                NOT NULL,
-    version bigint CHECK (version >= 0) NOT NULL,
-    payload json                        NOT NULL,
+    view_id       varchar(255)                      NOT NULL,
+    version       bigint CHECK (version >= 0)       NOT NULL,
+    last_sequence bigint CHECK (last_sequence >= 0) NOT NULL DEFAULT 0,
+    payload       json                              NOT NULL,
     CONSTRAINT test_view_pk PRIMARY KEY (view_id)
 );
 
+-- a shadow table with an identical structure is needed for blue/green view rebuilds
+-- replace name with the value used in `MysqlViewRepository::with_shadow_table(shadow_name)`
+CREATE TABLE test_view_shadow
+(
+    view_id       varchar(255)                      NOT NULL,
+    version       bigint CHECK (version >= 0)       NOT NULL,
+    last_sequence bigint CHECK (last_sequence >= 0) NOT NULL DEFAULT 0,
+    payload       json                              NOT NULL,
+    CONSTRAINT test_view_shadow_pk PRIMARY KEY (view_id)
+);
+
+-- a dedicated view table for the `ViewRepositoryConformance` suite, which clears its views
+CREATE TABLE conformance_view
+(
+    view_id       varchar(255)                      NOT NULL,
+    version       bigint CHECK (version >= 0)       NOT NULL,
+    last_sequence bigint CHECK (last_sequence >= 0) NOT NULL DEFAULT 0,
+    payload       json                              NOT NULL,
+    CONSTRAINT conformance_view_pk PRIMARY KEY (view_id)
+);
+
 INSERT INTO events (aggregate_type, aggregate_id, sequence, event_type, event_version, payload, metadata)
 VALUES ('Customer', 'previous_event_in_need_of_upcast', 1, 'NameAdded', '1.0', '{
   "NameAdded": {}
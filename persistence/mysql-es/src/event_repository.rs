@@ -1,12 +1,15 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
 use cqrs_es::persist::{
-    PersistedEventRepository, PersistenceError, ReplayFeed, ReplayStream, SerializedEvent,
-    SerializedSnapshot,
+    PersistedEventRepository, PersistenceError, ReplayFeed, ReplayFilter, ReplayStream,
+    SerializedEvent, SerializedSnapshot,
 };
 use cqrs_es::Aggregate;
 use futures::stream::BoxStream;
 use futures::TryStreamExt;
 use serde_json::Value;
-use sqlx::mysql::MySqlRow;
+use sqlx::mysql::{MySqlArguments, MySqlRow};
+use sqlx::query::Query;
 use sqlx::{MySql, Pool, Row, SqlSafeStr, SqlStr, Transaction};
 
 use crate::error::MysqlAggregateError;
@@ -16,12 +19,16 @@ const DEFAULT_EVENT_TABLE: &str = "events";
 const DEFAULT_SNAPSHOT_TABLE: &str = "snapshots";
 
 const DEFAULT_STREAMING_CHANNEL_SIZE: usize = 200;
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(500);
+const DEFAULT_SETTLE_DELAY: Duration = Duration::from_secs(1);
 
 /// An event repository relying on a MySql database for persistence.
 pub struct MysqlEventRepository {
     pool: Pool<MySql>,
     query_factory: SqlQueryFactory,
     stream_channel_size: usize,
+    poll_interval: Duration,
+    settle_delay: Duration,
 }
 
 impl PersistedEventRepository for MysqlEventRepository {
@@ -101,6 +108,109 @@ impl PersistedEventRepository for MysqlEventRepository {
             self.stream_channel_size,
         ))
     }
+
+    async fn stream_filtered_events<A: Aggregate>(
+        &self,
+        filter: &ReplayFilter,
+    ) -> Result<ReplayStream, PersistenceError> {
+        let query = self.query_factory.filtered_events(filter);
+        let filter = filter.clone();
+        let pool = self.pool.clone();
+        let (feed, stream) = ReplayStream::new(self.stream_channel_size);
+        tokio::spawn(async move {
+            let query = bind_filter(sqlx::query(query).bind(A::TYPE), &filter);
+            let rows = query.fetch(&pool);
+            process_rows(feed, rows).await;
+        });
+        Ok(stream)
+    }
+
+    // Events are polled in the order of their global position. Positions are assigned on insert
+    // rather than on commit, so only events that have been committed for longer than the settle
+    // delay are read, giving any concurrent transaction holding an earlier position time to
+    // commit.
+    async fn subscribe_all_events<A: Aggregate>(&self) -> Result<ReplayStream, PersistenceError> {
+        let query = self.query_factory.events_after_position();
+        let pool = self.pool.clone();
+        let poll_interval = self.poll_interval;
+        let settle_delay = self.settle_delay.as_micros() as i64;
+        let page_size = self.stream_channel_size.max(1);
+        let (mut feed, stream) = ReplayStream::new(self.stream_channel_size);
+        tokio::spawn(async move {
+            let mut position: i64 = 0;
+            loop {
+                let rows = sqlx::query(query.clone())
+                    .bind(A::TYPE)
+                    .bind(position)
+                    .bind(settle_delay)
+                    .bind(page_size as i64)
+                    .fetch_all(&pool)
+                    .await;
+                let rows = match rows {
+                    Ok(rows) => rows,
+                    Err(err) => {
+                        let _ = feed.push(Err(MysqlAggregateError::from(err).into())).await;
+                        return;
+                    }
+                };
+                let page_full = rows.len() >= page_size;
+                for row in rows {
+                    let event = row
+                        .try_get("global_position")
+                        .map_err(MysqlAggregateError::from)
+                        .and_then(|global_position| {
+                            position = global_position;
+                            MysqlEventRepository::deser_event(row)
+                        });
+                    let failed = event.is_err();
+                    if feed.push(event.map_err(Into::into)).await.is_err() || failed {
+                        return;
+                    }
+                }
+                // a full page indicates the subscriber is still catching up
+                if page_full {
+                    continue;
+                }
+                tokio::select! {
+                    () = feed.closed() => return,
+                    () = tokio::time::sleep(poll_interval) => {}
+                }
+            }
+        });
+        Ok(stream)
+    }
+}
+
+// Binds the filter values in the order used by `SqlQueryFactory::filtered_events`.
+fn bind_filter<'q>(
+    mut query: Query<'q, MySql, MySqlArguments>,
+    filter: &ReplayFilter,
+) -> Query<'q, MySql, MySqlArguments> {
+    for aggregate_id in &filter.aggregate_ids {
+        query = query.bind(aggregate_id.clone());
+    }
+    for event_type in &filter.event_types {
+        query = query.bind(event_type.clone());
+    }
+    if let Some(min_sequence) = filter.min_sequence {
+        query = query.bind(min_sequence as i64);
+    }
+    if let Some(max_sequence) = filter.max_sequence {
+        query = query.bind(max_sequence as i64);
+    }
+    if let Some(committed_from) = filter.committed_from {
+        query = query.bind(epoch_seconds(committed_from));
+    }
+    if let Some(committed_to) = filter.committed_to {
+        query = query.bind(epoch_seconds(committed_to));
+    }
+    query
+}
+
+fn epoch_seconds(time: SystemTime) -> f64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs_f64())
+        .unwrap_or_default()
 }
 
 fn stream_events(
@@ -133,16 +243,21 @@ fn stream_all_events(
     stream
 }
 
+// Pushes each row onto the feed, any failure is pushed onto the feed and ends the stream.
 async fn process_rows(
     mut feed: ReplayFeed,
     mut rows: BoxStream<'_, Result<MySqlRow, sqlx::Error>>,
 ) {
-    while let Some(row) = rows.try_next().await.unwrap() {
-        let event_result: Result<SerializedEvent, PersistenceError> =
-            MysqlEventRepository::deser_event(row).map_err(Into::into);
-        if feed.push(event_result).await.is_err() {
-            // TODO: in the unlikely event of a broken channel this error should be reported.
-            break;
+    loop {
+        let event = match rows.try_next().await {
+            Ok(Some(row)) => MysqlEventRepository::deser_event(row),
+            Ok(None) => return feed.finish(),
+            Err(err) => Err(err.into()),
+        };
+        let failed = event.is_err();
+        // the stream has been dropped if the push fails, so there is no one to report to
+        if feed.push(event.map_err(Into::into)).await.is_err() || failed {
+            return;
         }
     }
 }
@@ -196,9 +311,37 @@ impl MysqlEventRepository {
     /// ```
     pub fn with_streaming_channel_size(self, stream_channel_size: usize) -> Self {
         Self {
-            pool: self.pool,
-            query_factory: self.query_factory,
             stream_channel_size,
+            ..self
+        }
+    }
+
+    /// Configures how a subscription polls for newly committed events, the default is to poll
+    /// every 500 milliseconds for events that were committed at least one second ago.
+    ///
+    /// The settle delay should exceed the longest expected commit transaction, events held in a
+    /// transaction that commits after the delay could be missed by a subscriber.
+    ///
+    /// _Example: poll every 100 milliseconds for events committed at least 2 seconds ago._
+    /// ```
+    /// use std::time::Duration;
+    /// use sqlx::{MySql, Pool};
+    /// use mysql_es::MysqlEventRepository;
+    ///
+    /// fn configure_repo(pool: Pool<MySql>) -> MysqlEventRepository {
+    ///     let store = MysqlEventRepository::new(pool);
+    ///     store.with_subscription_polling(Duration::from_millis(100), Duration::from_secs(2))
+    /// }
+    /// ```
+    pub fn with_subscription_polling(
+        self,
+        poll_interval: Duration,
+        settle_delay: Duration,
+    ) -> Self {
+        Self {
+            poll_interval,
+            settle_delay,
+            ..self
         }
     }
     /// Configures a `MysqlEventRepository` to use the provided table names.
@@ -231,6 +374,8 @@ impl MysqlEventRepository {
             pool,
             query_factory: SqlQueryFactory::new(events_table, snapshots_table),
             stream_channel_size: DEFAULT_STREAMING_CHANNEL_SIZE,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            settle_delay: DEFAULT_SETTLE_DELAY,
         }
     }
 
@@ -285,24 +430,25 @@ impl MysqlEventRepository {
             .bind((current_snapshot - 1) as u32)
             .execute(&mut *tx)
             .await?;
-        tx.commit().await?;
-        match result.rows_affected() {
-            1 => Ok(()),
-            _ => Err(MysqlAggregateError::OptimisticLock),
+        // dropping the transaction rolls back the events on a conflict
+        if result.rows_affected() != 1 {
+            return Err(MysqlAggregateError::OptimisticLock);
         }
+        tx.commit().await?;
+        Ok(())
     }
 
     fn deser_event(row: MySqlRow) -> Result<SerializedEvent, MysqlAggregateError> {
-        let aggregate_type: String = row.get("aggregate_type");
-        let aggregate_id: String = row.get("aggregate_id");
+        let aggregate_type: String = row.try_get("aggregate_type")?;
+        let aggregate_id: String = row.try_get("aggregate_id")?;
         let sequence = {
-            let s: i64 = row.get("sequence");
+            let s: i64 = row.try_get("sequence")?;
             s as usize
         };
-        let event_type: String = row.get("event_type");
-        let event_version: String = row.get("event_version");
-        let payload: Value = row.get("payload");
-        let metadata: Value = row.get("metadata");
+        let event_type: String = row.try_get("event_type")?;
+        let event_version: String = row.try_get("event_version")?;
+        let payload: Value = row.try_get("payload")?;
+        let metadata: Value = row.try_get("metadata")?;
         Ok(SerializedEvent::new(
             aggregate_id,
             sequence,
@@ -358,194 +504,185 @@ impl MysqlEventRepository {
 
 #[cfg(test)]
 mod test {
-    use cqrs_es::persist::PersistedEventRepository;
+    use std::time::{Duration, SystemTime};
+
+    use cqrs_es::persist::{PersistedEventRepository, ReplayFilter, SerializedEvent};
+    use serde_json::json;
 
     use crate::error::MysqlAggregateError;
     use crate::testing::tests::{
-        snapshot_context, test_event_envelope, Created, SomethingElse, TestAggregate, TestEvent,
-        Tested, TEST_CONNECTION_STRING,
+        test_event_envelope, Created, SomethingElse, TestAggregate, TestEvent, Tested,
+        TEST_CONNECTION_STRING,
     };
     use crate::{default_mysql_pool, MysqlEventRepository};
 
     #[tokio::test]
-    async fn event_repositories() {
+    async fn replay_stream_reports_errors() {
         let pool = default_mysql_pool(TEST_CONNECTION_STRING).await;
-        let id = uuid::Uuid::new_v4().to_string();
-        let event_repo = MysqlEventRepository::new(pool.clone()).with_streaming_channel_size(1);
-        let events = event_repo.get_events::<TestAggregate>(&id).await.unwrap();
-        assert!(events.is_empty());
+        let event_repo =
+            MysqlEventRepository::new(pool).with_tables("missing_events", "missing_snapshots");
+        let mut stream = event_repo
+            .stream_all_events::<TestAggregate>()
+            .await
+            .unwrap();
+        assert!(stream.next_serialized().await.unwrap().is_err());
+        assert!(stream.next_serialized().await.is_none());
+        assert!(!stream.is_complete());
+    }
 
+    #[tokio::test]
+    async fn filtered_replay_stream() {
+        let pool = default_mysql_pool(TEST_CONNECTION_STRING).await;
+        let id = uuid::Uuid::new_v4().to_string();
+        let event_repo = MysqlEventRepository::new(pool.clone());
+        let started = SystemTime::now() - Duration::from_secs(60);
+        // streamed events are deserialized, which requires metadata to be present
+        let event = |sequence, event| SerializedEvent {
+            metadata: json!({}),
+            ..test_event_envelope(&id, sequence, event)
+        };
         event_repo
             .insert_events::<TestAggregate>(&[
-                test_event_envelope(&id, 1, TestEvent::Created(Created { id: id.clone() })),
-                test_event_envelope(
-                    &id,
+                event(1, TestEvent::Created(Created { id: id.clone() })),
+                event(
                     2,
                     TestEvent::Tested(Tested {
-                        test_name: "a test was run".to_string(),
+                        test_name: "first test".to_string(),
                     }),
                 ),
-            ])
-            .await
-            .unwrap();
-        let events = event_repo.get_events::<TestAggregate>(&id).await.unwrap();
-        assert_eq!(2, events.len());
-        events.iter().for_each(|e| assert_eq!(&id, &e.aggregate_id));
-
-        let result = event_repo
-            .insert_events::<TestAggregate>(&[
-                test_event_envelope(
-                    &id,
+                event(
                     3,
-                    TestEvent::SomethingElse(SomethingElse {
-                        description: "this should not persist".to_string(),
-                    }),
-                ),
-                test_event_envelope(
-                    &id,
-                    2,
-                    TestEvent::SomethingElse(SomethingElse {
-                        description: "bad sequence number".to_string(),
+                    TestEvent::Tested(Tested {
+                        test_name: "second test".to_string(),
                     }),
                 ),
             ])
             .await
-            .unwrap_err();
-        match result {
-            MysqlAggregateError::OptimisticLock => {}
-            _ => panic!("invalid error result found during insert: {result}"),
-        }
-
-        let events = event_repo.get_events::<TestAggregate>(&id).await.unwrap();
-        assert_eq!(2, events.len());
+            .unwrap();
 
-        verify_replay_stream(&id, event_repo).await;
+        let filter = ReplayFilter::default()
+            .with_aggregate_ids(vec![id.clone()])
+            .with_event_types(vec!["Tested".to_string()])
+            .with_sequences(1..=2);
+        let sequences = stream_sequences(&event_repo, &filter).await;
+        assert_eq!(vec![2], sequences);
+
+        let filter = ReplayFilter::default()
+            .with_aggregate_ids(vec![id.clone()])
+            .committed_between(started, SystemTime::now() + Duration::from_secs(60));
+        let sequences = stream_sequences(&event_repo, &filter).await;
+        assert_eq!(vec![1, 2, 3], sequences);
+
+        let filter = ReplayFilter::default()
+            .with_aggregate_ids(vec![id.clone()])
+            .committed_between(started - Duration::from_secs(60), started);
+        let sequences = stream_sequences(&event_repo, &filter).await;
+        assert!(sequences.is_empty());
     }
 
-    async fn verify_replay_stream(id: &str, event_repo: MysqlEventRepository) {
-        let mut stream = event_repo.stream_events::<TestAggregate>(id).await.unwrap();
-        let mut found_in_stream = 0;
-        while (stream.next::<TestAggregate>(&[]).await).is_some() {
-            found_in_stream += 1;
+    #[tokio::test]
+    async fn subscription_follows_committed_events() {
+        let pool = default_mysql_pool(TEST_CONNECTION_STRING).await;
+        let id = uuid::Uuid::new_v4().to_string();
+        let event_repo = MysqlEventRepository::new(pool.clone())
+            .with_subscription_polling(Duration::from_millis(50), Duration::from_millis(10));
+        let event = |sequence| SerializedEvent {
+            metadata: json!({}),
+            ..test_event_envelope(
+                &id,
+                sequence,
+                TestEvent::Tested(Tested {
+                    test_name: format!("test {sequence}"),
+                }),
+            )
+        };
+        event_repo
+            .insert_events::<TestAggregate>(&[event(1)])
+            .await
+            .unwrap();
+        let mut stream = event_repo
+            .subscribe_all_events::<TestAggregate>()
+            .await
+            .unwrap();
+        event_repo
+            .insert_events::<TestAggregate>(&[event(2), event(3)])
+            .await
+            .unwrap();
+
+        // the committed event is replayed, followed by the live events
+        let mut sequences = Vec::new();
+        while sequences.len() < 3 {
+            let next = tokio::time::timeout(Duration::from_secs(5), stream.next_serialized())
+                .await
+                .expect("timed out waiting for subscribed events")
+                .unwrap()
+                .unwrap();
+            if next.aggregate_id == id {
+                sequences.push(next.sequence);
+            }
         }
-        assert_eq!(found_in_stream, 2);
+        assert_eq!(vec![1, 2, 3], sequences);
+    }
 
+    async fn stream_sequences(
+        event_repo: &MysqlEventRepository,
+        filter: &ReplayFilter,
+    ) -> Vec<usize> {
         let mut stream = event_repo
-            .stream_all_events::<TestAggregate>()
+            .stream_filtered_events::<TestAggregate>(filter)
             .await
             .unwrap();
-        let mut found_in_stream = 0;
-        while (stream.next::<TestAggregate>(&[]).await).is_some() {
-            found_in_stream += 1;
+        let mut sequences = Vec::new();
+        while let Some(event) = stream.next::<TestAggregate>(&[]).await {
+            sequences.push(event.unwrap().sequence);
         }
-        assert!(found_in_stream >= 2);
+        sequences
     }
 
     #[tokio::test]
-    async fn snapshot_repositories() {
+    async fn stale_snapshot_update_does_not_persist_events() {
         let pool = default_mysql_pool(TEST_CONNECTION_STRING).await;
         let id = uuid::Uuid::new_v4().to_string();
         let repo = MysqlEventRepository::new(pool.clone());
-        let snapshot = repo.get_snapshot::<TestAggregate>(&id).await.unwrap();
-        assert_eq!(None, snapshot);
-
-        let test_description = "some test snapshot here".to_string();
-        let test_tests = vec!["testA".to_string(), "testB".to_string()];
-        repo.insert::<TestAggregate>(
-            serde_json::to_value(TestAggregate {
-                id: id.clone(),
-                description: test_description.clone(),
-                tests: test_tests.clone(),
-            })
-            .unwrap(),
-            id.clone(),
-            1,
-            &[],
-        )
-        .await
+        let aggregate = serde_json::to_value(TestAggregate {
+            id: id.clone(),
+            description: "a test aggregate".to_string(),
+            tests: vec![],
+        })
         .unwrap();
-
-        let snapshot = repo.get_snapshot::<TestAggregate>(&id).await.unwrap();
-        assert_eq!(
-            Some(snapshot_context(
-                id.clone(),
-                0,
-                1,
-                serde_json::to_value(TestAggregate {
-                    id: id.clone(),
-                    description: test_description.clone(),
-                    tests: test_tests.clone(),
-                })
-                .unwrap()
-            )),
-            snapshot
-        );
-
-        // sequence iterated, does update
-        repo.update::<TestAggregate>(
-            serde_json::to_value(TestAggregate {
-                id: id.clone(),
-                description: "a test description that should be saved".to_string(),
-                tests: test_tests.clone(),
-            })
-            .unwrap(),
-            id.clone(),
+        let created = test_event_envelope(&id, 1, TestEvent::Created(Created { id: id.clone() }));
+        repo.insert::<TestAggregate>(aggregate.clone(), id.clone(), 1, &[created])
+            .await
+            .unwrap();
+        let tested = test_event_envelope(
+            &id,
             2,
-            &[],
-        )
-        .await
-        .unwrap();
-
-        let snapshot = repo.get_snapshot::<TestAggregate>(&id).await.unwrap();
-        assert_eq!(
-            Some(snapshot_context(
-                id.clone(),
-                0,
-                2,
-                serde_json::to_value(TestAggregate {
-                    id: id.clone(),
-                    description: "a test description that should be saved".to_string(),
-                    tests: test_tests.clone(),
-                })
-                .unwrap()
-            )),
-            snapshot
+            TestEvent::Tested(Tested {
+                test_name: "a test was run".to_string(),
+            }),
         );
+        repo.update::<TestAggregate>(aggregate.clone(), id.clone(), 2, &[tested])
+            .await
+            .unwrap();
 
-        // sequence out of order or not iterated, does not update
+        // a writer holding a stale snapshot uses an unused event sequence but the same snapshot version
+        let stale = test_event_envelope(
+            &id,
+            3,
+            TestEvent::SomethingElse(SomethingElse {
+                description: "this should not persist".to_string(),
+            }),
+        );
         let result = repo
-            .update::<TestAggregate>(
-                serde_json::to_value(TestAggregate {
-                    id: id.clone(),
-                    description: "a test description that should not be saved".to_string(),
-                    tests: test_tests.clone(),
-                })
-                .unwrap(),
-                id.clone(),
-                2,
-                &[],
-            )
+            .update::<TestAggregate>(aggregate, id.clone(), 2, &[stale])
             .await
             .unwrap_err();
         assert!(
             matches!(result, MysqlAggregateError::OptimisticLock),
-            "invalid error result found during insert: {result}"
+            "invalid error result found during update: {result}"
         );
 
-        let snapshot = repo.get_snapshot::<TestAggregate>(&id).await.unwrap();
-        assert_eq!(
-            Some(snapshot_context(
-                id.clone(),
-                0,
-                2,
-                serde_json::to_value(TestAggregate {
-                    id: id.clone(),
-                    description: "a test description that should be saved".to_string(),
-                    tests: test_tests.clone(),
-                })
-                .unwrap()
-            )),
-            snapshot
-        );
+        let events = repo.get_events::<TestAggregate>(&id).await.unwrap();
+        assert_eq!(2, events.len());
     }
 }
@@ -1,3 +1,4 @@
+use cqrs_es::persist::ReplayFilter;
 use sqlx::{AssertSqlSafe, SqlSafeStr, SqlStr};
 
 pub(crate) struct SqlQueryFactory {
@@ -5,6 +6,7 @@ pub(crate) struct SqlQueryFactory {
     select_events: SqlStr,
     insert_event: SqlStr,
     all_events: SqlStr,
+    events_after_position: SqlStr,
     insert_snapshot: SqlStr,
     update_snapshot: SqlStr,
     select_snapshot: SqlStr,
@@ -39,6 +41,16 @@ SELECT aggregate_type, aggregate_id, sequence, event_type, event_version, payloa
             event_table.as_str()
         ))
         .into_sql_str();
+        let events_after_position = AssertSqlSafe(format!(
+            "
+SELECT global_position, aggregate_type, aggregate_id, sequence, event_type, event_version, payload, metadata
+  FROM {}
+  WHERE aggregate_type = ? AND global_position > ? AND committed_at <= NOW(6) - INTERVAL ? MICROSECOND
+  ORDER BY global_position
+  LIMIT ?",
+            event_table.as_str()
+        ))
+        .into_sql_str();
         let insert_snapshot = AssertSqlSafe(format!(
             "
 INSERT INTO {} (aggregate_type, aggregate_id, last_sequence, current_snapshot, payload)
@@ -67,6 +79,7 @@ SELECT aggregate_type, aggregate_id, last_sequence, current_snapshot, payload
             select_events,
             insert_event,
             all_events,
+            events_after_position,
             insert_snapshot,
             update_snapshot,
             select_snapshot,
@@ -90,6 +103,9 @@ SELECT aggregate_type, aggregate_id, last_sequence, current_snapshot, payload
     pub fn all_events(&self) -> SqlStr {
         self.all_events.clone()
     }
+    pub fn events_after_position(&self) -> SqlStr {
+        self.events_after_position.clone()
+    }
     pub fn get_last_events(&self, last_sequence: usize) -> SqlStr {
         AssertSqlSafe(format!(
             "
@@ -102,6 +118,40 @@ SELECT aggregate_type, aggregate_id, sequence, event_type, event_version, payloa
         ))
         .into_sql_str()
     }
+    // Parameters following the aggregate type are bound in the order the conditions appear.
+    pub fn filtered_events(&self, filter: &ReplayFilter) -> SqlStr {
+        let mut conditions = vec!["aggregate_type = ?".to_string()];
+        if !filter.aggregate_ids.is_empty() {
+            let placeholders = vec!["?"; filter.aggregate_ids.len()].join(",");
+            conditions.push(format!("aggregate_id IN ({placeholders})"));
+        }
+        if !filter.event_types.is_empty() {
+            let placeholders = vec!["?"; filter.event_types.len()].join(",");
+            conditions.push(format!("event_type IN ({placeholders})"));
+        }
+        if filter.min_sequence.is_some() {
+            conditions.push("sequence >= ?".to_string());
+        }
+        if filter.max_sequence.is_some() {
+            conditions.push("sequence <= ?".to_string());
+        }
+        if filter.committed_from.is_some() {
+            conditions.push("committed_at >= FROM_UNIXTIME(?)".to_string());
+        }
+        if filter.committed_to.is_some() {
+            conditions.push("committed_at < FROM_UNIXTIME(?)".to_string());
+        }
+        AssertSqlSafe(format!(
+            "
+SELECT aggregate_type, aggregate_id, sequence, event_type, event_version, payload, metadata
+  FROM {}
+  WHERE {}
+  ORDER BY sequence",
+            self.event_table.as_str(),
+            conditions.join(" AND ")
+        ))
+        .into_sql_str()
+    }
 }
 
 #[test]
@@ -125,6 +175,15 @@ SELECT aggregate_type, aggregate_id, sequence, event_type, event_version, payloa
   FROM my_events
   WHERE aggregate_type = ?
   ORDER BY sequence"
+    );
+    assert_eq!(
+        query_factory.events_after_position().as_str(),
+        "
+SELECT global_position, aggregate_type, aggregate_id, sequence, event_type, event_version, payload, metadata
+  FROM my_events
+  WHERE aggregate_type = ? AND global_position > ? AND committed_at <= NOW(6) - INTERVAL ? MICROSECOND
+  ORDER BY global_position
+  LIMIT ?"
     );
     assert_eq!(
         query_factory.insert_snapshot().as_str(),
@@ -155,3 +214,27 @@ SELECT aggregate_type, aggregate_id, sequence, event_type, event_version, payloa
   ORDER BY sequence"
     );
 }
+
+#[test]
+fn test_filtered_events_query() {
+    let query_factory = SqlQueryFactory::new("my_events", "my_snapshots");
+    assert_eq!(
+        query_factory
+            .filtered_events(&ReplayFilter::default())
+            .as_str(),
+        query_factory.all_events().as_str()
+    );
+    let filter = ReplayFilter::default()
+        .with_aggregate_ids(vec!["a".to_string(), "b".to_string()])
+        .with_event_types(vec!["Created".to_string()])
+        .with_sequences(2..=4)
+        .committed_between(std::time::UNIX_EPOCH, std::time::SystemTime::now());
+    assert_eq!(
+        query_factory.filtered_events(&filter).as_str(),
+        "
+SELECT aggregate_type, aggregate_id, sequence, event_type, event_version, payload, metadata
+  FROM my_events
+  WHERE aggregate_type = ? AND aggregate_id IN (?,?) AND event_type IN (?) AND sequence >= ? AND sequence <= ? AND committed_at >= FROM_UNIXTIME(?) AND committed_at < FROM_UNIXTIME(?)
+  ORDER BY sequence"
+    );
+}
@@ -2,8 +2,9 @@ use std::collections::HashMap;
 
 use cqrs_es::doc::{Customer, CustomerEvent};
 use cqrs_es::persist::{PersistedEventStore, SemanticVersionEventUpcaster};
+use cqrs_es::test::{EventRepositoryConformance, ViewRepositoryConformance};
 use cqrs_es::EventStore;
-use mysql_es::{default_mysql_pool, MysqlEventRepository};
+use mysql_es::{default_mysql_pool, MysqlEventRepository, MysqlViewRepository};
 use serde_json::Value;
 use sqlx::{MySql, Pool};
 
@@ -93,3 +94,21 @@ async fn upcasted_event() {
     assert_eq!(1, result.current_sequence);
     assert_eq!(None, result.current_snapshot);
 }
+
+#[tokio::test]
+async fn event_repository_conformance() {
+    let pool = default_mysql_pool(TEST_CONNECTION_STRING).await;
+    EventRepositoryConformance::with(move || MysqlEventRepository::new(pool.clone()))
+        .verify()
+        .await;
+}
+
+#[tokio::test]
+async fn view_repository_conformance() {
+    let pool = default_mysql_pool(TEST_CONNECTION_STRING).await;
+    ViewRepositoryConformance::with(move || {
+        MysqlViewRepository::new("conformance_view", pool.clone())
+    })
+    .verify()
+    .await;
+}
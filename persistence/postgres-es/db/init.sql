@@ -8,6 +8,7 @@ CREATE TABLE events
     event_version  text                         NOT NULL,
     payload        json                         NOT NULL,
     metadata       json                         NOT NULL,
+    committed_at   timestamptz                  NOT NULL DEFAULT now(),
     PRIMARY KEY (aggregate_type, aggregate_id, sequence)
 );
 
@@ -26,9 +27,31 @@ CREATE TABLE snapshots
 -- replace name with the value used in `PostgresViewRepository::new(view_name: String)`
 CREATE TABLE test_view
 (
-    view_id text                        NOT NULL,
-    version bigint CHECK (version >= 0) NOT NULL,
-    payload json                        NOT NULL,
+    view_id       text                              NOT NULL,
+    version       bigint CHECK (version >= 0)       NOT NULL,
+    last_sequence bigint CHECK (last_sequence >= 0) NOT NULL DEFAULT 0,
+    payload       json                              NOT NULL,
+    PRIMARY KEY (view_id)
+);
+
+-- a shadow table with an identical structure is needed for blue/green view rebuilds
+-- replace name with the value used in `PostgresViewRepository::with_shadow_table(shadow_name)`
+CREATE TABLE test_view_shadow
+(
+    view_id       text                              NOT NULL,
+    version       bigint CHECK (version >= 0)       NOT NULL,
+    last_sequence bigint CHECK (last_sequence >= 0) NOT NULL DEFAULT 0,
+    payload       json                              NOT NULL,
+    PRIMARY KEY (view_id)
+);
+
+-- a dedicated view table for the `ViewRepositoryConformance` suite, which clears its views
+CREATE TABLE conformance_view
+(
+    view_id       text                              NOT NULL,
+    version       bigint CHECK (version >= 0)       NOT NULL,
+    last_sequence bigint CHECK (last_sequence >= 0) NOT NULL DEFAULT 0,
+    payload       json                              NOT NULL,
     PRIMARY KEY (view_id)
 );
 
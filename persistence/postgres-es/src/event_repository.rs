@@ -1,10 +1,17 @@
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
 use cqrs_es::persist::{
-    PersistedEventRepository, PersistenceError, ReplayStream, SerializedEvent, SerializedSnapshot,
+    PersistedEventRepository, PersistenceError, ReplayFeed, ReplayFilter, ReplayStream,
+    SerializedEvent, SerializedSnapshot,
 };
 use cqrs_es::Aggregate;
+use futures::stream::BoxStream;
 use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use sqlx::postgres::PgRow;
+use sqlx::postgres::{PgArguments, PgListener, PgRow};
+use sqlx::query::Query;
 use sqlx::{Pool, Postgres, Row, SqlSafeStr, SqlStr, Transaction};
 
 use crate::error::PostgresAggregateError;
@@ -15,11 +22,19 @@ const DEFAULT_SNAPSHOT_TABLE: &str = "snapshots";
 
 const DEFAULT_STREAMING_CHANNEL_SIZE: usize = 200;
 
+// The payload of the notification sent as events are committed.
+#[derive(Serialize, Deserialize)]
+struct CommittedNotification {
+    aggregate_type: String,
+    aggregate_id: String,
+}
+
 /// An event repository relying on a Postgres database for persistence.
 pub struct PostgresEventRepository {
     pool: Pool<Postgres>,
     query_factory: SqlQueryFactory,
     stream_channel_size: usize,
+    commit_notifications: bool,
 }
 
 impl PersistedEventRepository for PostgresEventRepository {
@@ -91,16 +106,191 @@ impl PersistedEventRepository for PostgresEventRepository {
         ))
     }
 
-    // TODO: aggregate id is unused here, `stream_events` function needs to be broken up
     async fn stream_all_events<A: Aggregate>(&self) -> Result<ReplayStream, PersistenceError> {
-        Ok(stream_events(
+        Ok(stream_all_events(
             self.query_factory.all_events(),
             A::TYPE.to_string(),
-            String::new(),
             self.pool.clone(),
             self.stream_channel_size,
         ))
     }
+
+    async fn stream_filtered_events<A: Aggregate>(
+        &self,
+        filter: &ReplayFilter,
+    ) -> Result<ReplayStream, PersistenceError> {
+        let query = self.query_factory.filtered_events(filter);
+        let filter = filter.clone();
+        let pool = self.pool.clone();
+        let (feed, stream) = ReplayStream::new(self.stream_channel_size);
+        tokio::spawn(async move {
+            let query = bind_filter(sqlx::query(query).bind(A::TYPE), &filter);
+            let rows = query.fetch(&pool);
+            process_rows(feed, rows).await;
+        });
+        Ok(stream)
+    }
+
+    // Committed events are announced with NOTIFY, listening before catching up ensures that
+    // no events are missed in the switch to live events.
+    async fn subscribe_all_events<A: Aggregate>(&self) -> Result<ReplayStream, PersistenceError> {
+        if !self.commit_notifications {
+            return Err(PersistenceError::UnknownError(
+                "subscriptions require commit notifications, configure the repository using `with_commit_notifications`".into(),
+            ));
+        }
+        let mut listener = PgListener::connect_with(&self.pool)
+            .await
+            .map_err(PostgresAggregateError::from)?;
+        listener
+            .listen(self.query_factory.committed_channel())
+            .await
+            .map_err(PostgresAggregateError::from)?;
+        Ok(subscribe_events(
+            listener,
+            self.query_factory.all_events(),
+            self.query_factory.events_after(),
+            A::TYPE,
+            self.pool.clone(),
+            self.stream_channel_size,
+        ))
+    }
+}
+
+fn subscribe_events(
+    mut listener: PgListener,
+    all_events: SqlStr,
+    events_after: SqlStr,
+    aggregate_type: &'static str,
+    pool: Pool<Postgres>,
+    channel_size: usize,
+) -> ReplayStream {
+    let (mut feed, stream) = ReplayStream::new(channel_size);
+    tokio::spawn(async move {
+        let mut last_sequences = HashMap::new();
+        let mut catch_up = true;
+        loop {
+            if catch_up {
+                let rows = sqlx::query(all_events.clone())
+                    .bind(aggregate_type)
+                    .fetch(&pool);
+                if !push_new_events(&mut feed, rows, &mut last_sequences).await {
+                    return;
+                }
+            }
+            let notification = tokio::select! {
+                () = feed.closed() => return,
+                notification = listener.try_recv() => notification,
+            };
+            let notification = match notification {
+                Ok(Some(notification)) => notification,
+                // Notifications are lost while the listener reconnects, so catch up again.
+                Ok(None) => {
+                    catch_up = true;
+                    continue;
+                }
+                Err(err) => {
+                    let _ = feed
+                        .push(Err(PostgresAggregateError::from(err).into()))
+                        .await;
+                    return;
+                }
+            };
+            catch_up = false;
+            let Ok(committed) =
+                serde_json::from_str::<CommittedNotification>(notification.payload())
+            else {
+                continue;
+            };
+            if committed.aggregate_type != aggregate_type {
+                continue;
+            }
+            let last_sequence = last_sequences
+                .get(&committed.aggregate_id)
+                .copied()
+                .unwrap_or_default();
+            let rows = sqlx::query(events_after.clone())
+                .bind(aggregate_type)
+                .bind(committed.aggregate_id)
+                .bind(last_sequence as i64)
+                .fetch(&pool);
+            if !push_new_events(&mut feed, rows, &mut last_sequences).await {
+                return;
+            }
+        }
+    });
+    stream
+}
+
+// Pushes any events that have not already been sent, returns false if the subscription should
+// end.
+async fn push_new_events(
+    feed: &mut ReplayFeed,
+    mut rows: BoxStream<'_, Result<PgRow, sqlx::Error>>,
+    last_sequences: &mut HashMap<String, usize>,
+) -> bool {
+    loop {
+        match rows.try_next().await {
+            Ok(Some(row)) => {
+                let event = match PostgresEventRepository::deser_event(&row) {
+                    Ok(event) => event,
+                    Err(err) => {
+                        let _ = feed.push(Err(err.into())).await;
+                        return false;
+                    }
+                };
+                let last_sequence = last_sequences
+                    .entry(event.aggregate_id.clone())
+                    .or_default();
+                if event.sequence <= *last_sequence {
+                    continue;
+                }
+                *last_sequence = event.sequence;
+                if feed.push(Ok(event)).await.is_err() {
+                    return false;
+                }
+            }
+            Ok(None) => return true,
+            Err(err) => {
+                let _ = feed
+                    .push(Err(PostgresAggregateError::from(err).into()))
+                    .await;
+                return false;
+            }
+        }
+    }
+}
+
+// Binds the filter values in the order used by `SqlQueryFactory::filtered_events`.
+fn bind_filter<'q>(
+    mut query: Query<'q, Postgres, PgArguments>,
+    filter: &ReplayFilter,
+) -> Query<'q, Postgres, PgArguments> {
+    if !filter.aggregate_ids.is_empty() {
+        query = query.bind(filter.aggregate_ids.clone());
+    }
+    if !filter.event_types.is_empty() {
+        query = query.bind(filter.event_types.clone());
+    }
+    if let Some(min_sequence) = filter.min_sequence {
+        query = query.bind(min_sequence as i64);
+    }
+    if let Some(max_sequence) = filter.max_sequence {
+        query = query.bind(max_sequence as i64);
+    }
+    if let Some(committed_from) = filter.committed_from {
+        query = query.bind(epoch_seconds(committed_from));
+    }
+    if let Some(committed_to) = filter.committed_to {
+        query = query.bind(epoch_seconds(committed_to));
+    }
+    query
+}
+
+fn epoch_seconds(time: SystemTime) -> f64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs_f64())
+        .unwrap_or_default()
 }
 
 fn stream_events(
@@ -110,21 +300,48 @@ fn stream_events(
     pool: Pool<Postgres>,
     channel_size: usize,
 ) -> ReplayStream {
-    let (mut feed, stream) = ReplayStream::new(channel_size);
+    let (feed, stream) = ReplayStream::new(channel_size);
     tokio::spawn(async move {
         let query = sqlx::query(query).bind(&aggregate_type).bind(&aggregate_id);
-        let mut rows = query.fetch(&pool);
-        while let Some(row) = rows.try_next().await.unwrap() {
-            let event = PostgresEventRepository::deser_event(&row);
-            if feed.push(Ok(event)).await.is_err() {
-                // TODO: in the unlikely event of a broken channel this error should be reported.
-                return;
-            }
-        }
+        let rows = query.fetch(&pool);
+        process_rows(feed, rows).await;
     });
     stream
 }
 
+// The all events query only takes the aggregate type, a statement prepared with an additional
+// unused parameter could not be reused by the subscription catch up.
+fn stream_all_events(
+    query: SqlStr,
+    aggregate_type: String,
+    pool: Pool<Postgres>,
+    channel_size: usize,
+) -> ReplayStream {
+    let (feed, stream) = ReplayStream::new(channel_size);
+    tokio::spawn(async move {
+        let query = sqlx::query(query).bind(&aggregate_type);
+        let rows = query.fetch(&pool);
+        process_rows(feed, rows).await;
+    });
+    stream
+}
+
+// Pushes each row onto the feed, any failure is pushed onto the feed and ends the stream.
+async fn process_rows(mut feed: ReplayFeed, mut rows: BoxStream<'_, Result<PgRow, sqlx::Error>>) {
+    loop {
+        let event = match rows.try_next().await {
+            Ok(Some(row)) => PostgresEventRepository::deser_event(&row),
+            Ok(None) => return feed.finish(),
+            Err(err) => Err(err.into()),
+        };
+        let failed = event.is_err();
+        // the stream has been dropped if the push fails, so there is no one to report to
+        if feed.push(event.map_err(Into::into)).await.is_err() || failed {
+            return;
+        }
+    }
+}
+
 impl PostgresEventRepository {
     async fn select_events<A: Aggregate>(
         &self,
@@ -141,7 +358,7 @@ impl PostgresEventRepository {
             .await
             .map_err(PostgresAggregateError::from)?
         {
-            result.push(Self::deser_event(&row));
+            result.push(Self::deser_event(&row)?);
         }
         Ok(result)
     }
@@ -177,9 +394,32 @@ impl PostgresEventRepository {
     /// ```
     pub fn with_streaming_channel_size(self, stream_channel_size: usize) -> Self {
         Self {
-            pool: self.pool,
-            query_factory: self.query_factory,
             stream_channel_size,
+            ..self
+        }
+    }
+
+    /// Configures a `PostgresEventRepository` to announce each commit with `NOTIFY`, this is
+    /// required to use `subscribe_all_events`.
+    ///
+    /// Notifications add a statement to every commit and are only sent by repositories
+    /// configured with this option, so it should be used by every repository that commits
+    /// events that will be subscribed to.
+    ///
+    /// ```
+    /// use sqlx::{Pool, Postgres};
+    /// use postgres_es::PostgresEventRepository;
+    ///
+    /// fn configure_repo(pool: Pool<Postgres>) -> PostgresEventRepository {
+    ///     let store = PostgresEventRepository::new(pool);
+    ///     store.with_commit_notifications()
+    /// }
+    /// ```
+    #[must_use]
+    pub fn with_commit_notifications(self) -> Self {
+        Self {
+            commit_notifications: true,
+            ..self
         }
     }
 
@@ -201,7 +441,10 @@ impl PostgresEventRepository {
         events_table: impl SqlSafeStr,
         snapshots_table: impl SqlSafeStr,
     ) -> Self {
-        Self::use_tables(self.pool, events_table, snapshots_table)
+        Self {
+            query_factory: SqlQueryFactory::new(events_table, snapshots_table),
+            ..self
+        }
     }
 
     fn use_tables(
@@ -213,6 +456,7 @@ impl PostgresEventRepository {
             pool,
             query_factory: SqlQueryFactory::new(events_table, snapshots_table),
             stream_channel_size: DEFAULT_STREAMING_CHANNEL_SIZE,
+            commit_notifications: false,
         }
     }
 
@@ -272,25 +516,26 @@ impl PostgresEventRepository {
             .bind(&aggregate_payload)
             .execute(&mut *tx)
             .await?;
-        tx.commit().await?;
-        match result.rows_affected() {
-            1 => Ok(()),
-            _ => Err(PostgresAggregateError::OptimisticLock),
+        // dropping the transaction rolls back the events on a conflict
+        if result.rows_affected() != 1 {
+            return Err(PostgresAggregateError::OptimisticLock);
         }
+        tx.commit().await?;
+        Ok(())
     }
 
-    fn deser_event(row: &PgRow) -> SerializedEvent {
-        let aggregate_type: String = row.get("aggregate_type");
-        let aggregate_id: String = row.get("aggregate_id");
+    fn deser_event(row: &PgRow) -> Result<SerializedEvent, PostgresAggregateError> {
+        let aggregate_type: String = row.try_get("aggregate_type")?;
+        let aggregate_id: String = row.try_get("aggregate_id")?;
         let sequence = {
-            let s: i64 = row.get("sequence");
+            let s: i64 = row.try_get("sequence")?;
             s as usize
         };
-        let event_type: String = row.get("event_type");
-        let event_version: String = row.get("event_version");
-        let payload: Value = row.get("payload");
-        let metadata: Value = row.get("metadata");
-        SerializedEvent::new(
+        let event_type: String = row.try_get("event_type")?;
+        let event_version: String = row.try_get("event_version")?;
+        let payload: Value = row.try_get("payload")?;
+        let metadata: Value = row.try_get("metadata")?;
+        Ok(SerializedEvent::new(
             aggregate_id,
             sequence,
             aggregate_type,
@@ -298,7 +543,7 @@ impl PostgresEventRepository {
             event_version,
             payload,
             metadata,
-        )
+        ))
     }
 
     fn deser_snapshot(row: &PgRow) -> SerializedSnapshot {
@@ -340,204 +585,212 @@ impl PostgresEventRepository {
                 .execute(&mut **tx)
                 .await?;
         }
+        // Notifications are delivered to subscribers once the transaction commits.
+        if let Some(event) = events.first().filter(|_| self.commit_notifications) {
+            let notification = serde_json::to_string(&CommittedNotification {
+                aggregate_type: A::TYPE.to_string(),
+                aggregate_id: event.aggregate_id.clone(),
+            })?;
+            sqlx::query(self.query_factory.notify_committed())
+                .bind(self.query_factory.committed_channel())
+                .bind(notification)
+                .execute(&mut **tx)
+                .await?;
+        }
         Ok(current_sequence)
     }
 }
 
 #[cfg(test)]
 mod test {
-    use cqrs_es::persist::PersistedEventRepository;
+    use std::time::{Duration, SystemTime};
+
+    use cqrs_es::persist::{
+        PersistedEventRepository, PersistenceError, ReplayFilter, SerializedEvent,
+    };
+    use serde_json::json;
 
     use crate::error::PostgresAggregateError;
     use crate::testing::tests::{
-        snapshot_context, test_event_envelope, Created, SomethingElse, TestAggregate, TestEvent,
-        Tested, TEST_CONNECTION_STRING,
+        test_event_envelope, Created, SomethingElse, TestAggregate, TestEvent, Tested,
+        TEST_CONNECTION_STRING,
     };
     use crate::{default_postgres_pool, PostgresEventRepository};
 
     #[tokio::test]
-    async fn event_repositories() {
+    async fn replay_stream_reports_errors() {
         let pool = default_postgres_pool(TEST_CONNECTION_STRING).await;
-        let id = uuid::Uuid::new_v4().to_string();
-        let event_repo: PostgresEventRepository =
-            PostgresEventRepository::new(pool.clone()).with_streaming_channel_size(1);
-        let events = event_repo.get_events::<TestAggregate>(&id).await.unwrap();
-        assert!(events.is_empty());
+        let event_repo =
+            PostgresEventRepository::new(pool).with_tables("missing_events", "missing_snapshots");
+        let mut stream = event_repo
+            .stream_all_events::<TestAggregate>()
+            .await
+            .unwrap();
+        assert!(stream.next_serialized().await.unwrap().is_err());
+        assert!(stream.next_serialized().await.is_none());
+        assert!(!stream.is_complete());
+    }
 
+    #[tokio::test]
+    async fn filtered_replay_stream() {
+        let pool = default_postgres_pool(TEST_CONNECTION_STRING).await;
+        let id = uuid::Uuid::new_v4().to_string();
+        let event_repo = PostgresEventRepository::new(pool.clone());
+        let started = SystemTime::now() - Duration::from_secs(60);
+        // streamed events are deserialized, which requires metadata to be present
+        let event = |sequence, event| SerializedEvent {
+            metadata: json!({}),
+            ..test_event_envelope(&id, sequence, event)
+        };
         event_repo
             .insert_events::<TestAggregate>(&[
-                test_event_envelope(&id, 1, TestEvent::Created(Created { id: id.clone() })),
-                test_event_envelope(
-                    &id,
+                event(1, TestEvent::Created(Created { id: id.clone() })),
+                event(
                     2,
                     TestEvent::Tested(Tested {
-                        test_name: "a test was run".to_string(),
+                        test_name: "first test".to_string(),
                     }),
                 ),
-            ])
-            .await
-            .unwrap();
-        let events = event_repo.get_events::<TestAggregate>(&id).await.unwrap();
-        assert_eq!(2, events.len());
-        events.iter().for_each(|e| assert_eq!(&id, &e.aggregate_id));
-
-        // Optimistic lock error
-        let result = event_repo
-            .insert_events::<TestAggregate>(&[
-                test_event_envelope(
-                    &id,
+                event(
                     3,
-                    TestEvent::SomethingElse(SomethingElse {
-                        description: "this should not persist".to_string(),
-                    }),
-                ),
-                test_event_envelope(
-                    &id,
-                    2,
-                    TestEvent::SomethingElse(SomethingElse {
-                        description: "bad sequence number".to_string(),
+                    TestEvent::Tested(Tested {
+                        test_name: "second test".to_string(),
                     }),
                 ),
             ])
             .await
-            .unwrap_err();
-        assert!(
-            matches!(result, PostgresAggregateError::OptimisticLock),
-            "invalid error result found during insert: {result}"
-        );
-
-        let events = event_repo.get_events::<TestAggregate>(&id).await.unwrap();
-        assert_eq!(2, events.len());
+            .unwrap();
 
-        verify_replay_stream(&id, event_repo).await;
+        let filter = ReplayFilter::default()
+            .with_aggregate_ids(vec![id.clone()])
+            .with_event_types(vec!["Tested".to_string()])
+            .with_sequences(1..=2);
+        let sequences = stream_sequences(&event_repo, &filter).await;
+        assert_eq!(vec![2], sequences);
+
+        let filter = ReplayFilter::default()
+            .with_aggregate_ids(vec![id.clone()])
+            .committed_between(started, SystemTime::now() + Duration::from_secs(60));
+        let sequences = stream_sequences(&event_repo, &filter).await;
+        assert_eq!(vec![1, 2, 3], sequences);
+
+        let filter = ReplayFilter::default()
+            .with_aggregate_ids(vec![id.clone()])
+            .committed_between(started - Duration::from_secs(60), started);
+        let sequences = stream_sequences(&event_repo, &filter).await;
+        assert!(sequences.is_empty());
     }
 
-    async fn verify_replay_stream(id: &str, event_repo: PostgresEventRepository) {
-        let mut stream = event_repo.stream_events::<TestAggregate>(id).await.unwrap();
-        let mut found_in_stream = 0;
-        while (stream.next::<TestAggregate>(&[]).await).is_some() {
-            found_in_stream += 1;
+    #[tokio::test]
+    async fn subscription_follows_committed_events() {
+        let pool = default_postgres_pool(TEST_CONNECTION_STRING).await;
+        let id = uuid::Uuid::new_v4().to_string();
+        let event_repo = PostgresEventRepository::new(pool.clone()).with_commit_notifications();
+        let event = |sequence| SerializedEvent {
+            metadata: json!({}),
+            ..test_event_envelope(
+                &id,
+                sequence,
+                TestEvent::Tested(Tested {
+                    test_name: format!("test {sequence}"),
+                }),
+            )
+        };
+        event_repo
+            .insert_events::<TestAggregate>(&[event(1)])
+            .await
+            .unwrap();
+        let mut stream = event_repo
+            .subscribe_all_events::<TestAggregate>()
+            .await
+            .unwrap();
+        event_repo
+            .insert_events::<TestAggregate>(&[event(2), event(3)])
+            .await
+            .unwrap();
+
+        // the committed event is replayed, followed by the live events
+        let mut sequences = Vec::new();
+        while sequences.len() < 3 {
+            let next = tokio::time::timeout(Duration::from_secs(5), stream.next_serialized())
+                .await
+                .expect("timed out waiting for subscribed events")
+                .unwrap()
+                .unwrap();
+            if next.aggregate_id == id {
+                sequences.push(next.sequence);
+            }
         }
-        assert_eq!(found_in_stream, 2);
+        assert_eq!(vec![1, 2, 3], sequences);
+    }
+
+    #[tokio::test]
+    async fn subscription_requires_commit_notifications() {
+        let pool = default_postgres_pool(TEST_CONNECTION_STRING).await;
+        let event_repo = PostgresEventRepository::new(pool);
+        let result = event_repo.subscribe_all_events::<TestAggregate>().await;
+        assert!(matches!(result, Err(PersistenceError::UnknownError(_))));
+    }
 
+    async fn stream_sequences(
+        event_repo: &PostgresEventRepository,
+        filter: &ReplayFilter,
+    ) -> Vec<usize> {
         let mut stream = event_repo
-            .stream_all_events::<TestAggregate>()
+            .stream_filtered_events::<TestAggregate>(filter)
             .await
             .unwrap();
-        let mut found_in_stream = 0;
-        while (stream.next::<TestAggregate>(&[]).await).is_some() {
-            found_in_stream += 1;
+        let mut sequences = Vec::new();
+        while let Some(event) = stream.next::<TestAggregate>(&[]).await {
+            sequences.push(event.unwrap().sequence);
         }
-        assert!(found_in_stream >= 2);
+        sequences
     }
 
     #[tokio::test]
-    async fn snapshot_repositories() {
+    async fn stale_snapshot_update_does_not_persist_events() {
         let pool = default_postgres_pool(TEST_CONNECTION_STRING).await;
         let id = uuid::Uuid::new_v4().to_string();
-        let event_repo: PostgresEventRepository = PostgresEventRepository::new(pool.clone());
-        let snapshot = event_repo.get_snapshot::<TestAggregate>(&id).await.unwrap();
-        assert_eq!(None, snapshot);
-
-        let test_description = "some test snapshot here".to_string();
-        let test_tests = vec!["testA".to_string(), "testB".to_string()];
-        event_repo
-            .insert::<TestAggregate>(
-                serde_json::to_value(TestAggregate {
-                    id: id.clone(),
-                    description: test_description.clone(),
-                    tests: test_tests.clone(),
-                })
-                .unwrap(),
-                id.clone(),
-                1,
-                &[],
-            )
+        let repo = PostgresEventRepository::new(pool.clone());
+        let aggregate = serde_json::to_value(TestAggregate {
+            id: id.clone(),
+            description: "a test aggregate".to_string(),
+            tests: vec![],
+        })
+        .unwrap();
+        let created = test_event_envelope(&id, 1, TestEvent::Created(Created { id: id.clone() }));
+        repo.insert::<TestAggregate>(aggregate.clone(), id.clone(), 1, &[created])
             .await
             .unwrap();
-
-        let snapshot = event_repo.get_snapshot::<TestAggregate>(&id).await.unwrap();
-        assert_eq!(
-            Some(snapshot_context(
-                id.clone(),
-                0,
-                1,
-                serde_json::to_value(TestAggregate {
-                    id: id.clone(),
-                    description: test_description.clone(),
-                    tests: test_tests.clone(),
-                })
-                .unwrap()
-            )),
-            snapshot
+        let tested = test_event_envelope(
+            &id,
+            2,
+            TestEvent::Tested(Tested {
+                test_name: "a test was run".to_string(),
+            }),
         );
-
-        // sequence iterated, does update
-        event_repo
-            .update::<TestAggregate>(
-                serde_json::to_value(TestAggregate {
-                    id: id.clone(),
-                    description: "a test description that should be saved".to_string(),
-                    tests: test_tests.clone(),
-                })
-                .unwrap(),
-                id.clone(),
-                2,
-                &[],
-            )
+        repo.update::<TestAggregate>(aggregate.clone(), id.clone(), 2, &[tested])
             .await
             .unwrap();
 
-        let snapshot = event_repo.get_snapshot::<TestAggregate>(&id).await.unwrap();
-        assert_eq!(
-            Some(snapshot_context(
-                id.clone(),
-                0,
-                2,
-                serde_json::to_value(TestAggregate {
-                    id: id.clone(),
-                    description: "a test description that should be saved".to_string(),
-                    tests: test_tests.clone(),
-                })
-                .unwrap()
-            )),
-            snapshot
+        // a writer holding a stale snapshot uses an unused event sequence but the same snapshot version
+        let stale = test_event_envelope(
+            &id,
+            3,
+            TestEvent::SomethingElse(SomethingElse {
+                description: "this should not persist".to_string(),
+            }),
         );
-
-        // sequence out of order or not iterated, does not update
-        let result = event_repo
-            .update::<TestAggregate>(
-                serde_json::to_value(TestAggregate {
-                    id: id.clone(),
-                    description: "a test description that should not be saved".to_string(),
-                    tests: test_tests.clone(),
-                })
-                .unwrap(),
-                id.clone(),
-                2,
-                &[],
-            )
+        let result = repo
+            .update::<TestAggregate>(aggregate, id.clone(), 2, &[stale])
             .await
             .unwrap_err();
         assert!(
             matches!(result, PostgresAggregateError::OptimisticLock),
-            "invalid error result found during insert: {result}"
+            "invalid error result found during update: {result}"
         );
 
-        let snapshot = event_repo.get_snapshot::<TestAggregate>(&id).await.unwrap();
-        assert_eq!(
-            Some(snapshot_context(
-                id.clone(),
-                0,
-                2,
-                serde_json::to_value(TestAggregate {
-                    id: id.clone(),
-                    description: "a test description that should be saved".to_string(),
-                    tests: test_tests.clone(),
-                })
-                .unwrap()
-            )),
-            snapshot
-        );
+        let events = repo.get_events::<TestAggregate>(&id).await.unwrap();
+        assert_eq!(2, events.len());
     }
 }
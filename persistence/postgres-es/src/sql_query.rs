@@ -1,3 +1,4 @@
+use cqrs_es::persist::ReplayFilter;
 use sqlx::{AssertSqlSafe, SqlSafeStr, SqlStr};
 
 pub(crate) struct SqlQueryFactory {
@@ -5,6 +6,9 @@ pub(crate) struct SqlQueryFactory {
     select_events: SqlStr,
     insert_event: SqlStr,
     all_events: SqlStr,
+    events_after: SqlStr,
+    notify_committed: SqlStr,
+    committed_channel: String,
     insert_snapshot: SqlStr,
     update_snapshot: SqlStr,
     select_snapshot: SqlStr,
@@ -39,6 +43,17 @@ SELECT aggregate_type, aggregate_id, sequence, event_type, event_version, payloa
             event_table.as_str()
         ))
         .into_sql_str();
+        let events_after = AssertSqlSafe(format!(
+            "
+SELECT aggregate_type, aggregate_id, sequence, event_type, event_version, payload, metadata
+  FROM {}
+  WHERE aggregate_type = $1 AND aggregate_id = $2 AND sequence > $3
+  ORDER BY sequence",
+            event_table.as_str()
+        ))
+        .into_sql_str();
+        let notify_committed = "SELECT pg_notify($1, $2)".into_sql_str();
+        let committed_channel = format!("{}_committed", event_table.as_str());
         let insert_snapshot = AssertSqlSafe(format!(
             "
 INSERT INTO {} (aggregate_type, aggregate_id, last_sequence, current_snapshot, payload)
@@ -67,6 +82,9 @@ SELECT aggregate_type, aggregate_id, last_sequence, current_snapshot, payload
             select_events,
             insert_event,
             all_events,
+            events_after,
+            notify_committed,
+            committed_channel,
             insert_snapshot,
             update_snapshot,
             select_snapshot,
@@ -90,6 +108,15 @@ SELECT aggregate_type, aggregate_id, last_sequence, current_snapshot, payload
     pub fn all_events(&self) -> SqlStr {
         self.all_events.clone()
     }
+    pub fn events_after(&self) -> SqlStr {
+        self.events_after.clone()
+    }
+    pub fn notify_committed(&self) -> SqlStr {
+        self.notify_committed.clone()
+    }
+    pub fn committed_channel(&self) -> &str {
+        &self.committed_channel
+    }
     pub fn get_last_events(&self, last_sequence: usize) -> SqlStr {
         AssertSqlSafe(format!(
             "
@@ -102,6 +129,43 @@ SELECT aggregate_type, aggregate_id, sequence, event_type, event_version, payloa
         ))
         .into_sql_str()
     }
+    // Parameters following the aggregate type are bound in the order the conditions appear.
+    pub fn filtered_events(&self, filter: &ReplayFilter) -> SqlStr {
+        let mut conditions = vec!["aggregate_type = $1".to_string()];
+        let mut param = 1;
+        let mut next_param = || {
+            param += 1;
+            param
+        };
+        if !filter.aggregate_ids.is_empty() {
+            conditions.push(format!("aggregate_id = ANY(${})", next_param()));
+        }
+        if !filter.event_types.is_empty() {
+            conditions.push(format!("event_type = ANY(${})", next_param()));
+        }
+        if filter.min_sequence.is_some() {
+            conditions.push(format!("sequence >= ${}", next_param()));
+        }
+        if filter.max_sequence.is_some() {
+            conditions.push(format!("sequence <= ${}", next_param()));
+        }
+        if filter.committed_from.is_some() {
+            conditions.push(format!("committed_at >= to_timestamp(${})", next_param()));
+        }
+        if filter.committed_to.is_some() {
+            conditions.push(format!("committed_at < to_timestamp(${})", next_param()));
+        }
+        AssertSqlSafe(format!(
+            "
+SELECT aggregate_type, aggregate_id, sequence, event_type, event_version, payload, metadata
+  FROM {}
+  WHERE {}
+  ORDER BY sequence",
+            self.event_table.as_str(),
+            conditions.join(" AND ")
+        ))
+        .into_sql_str()
+    }
 }
 
 #[test]
@@ -126,6 +190,15 @@ SELECT aggregate_type, aggregate_id, sequence, event_type, event_version, payloa
   WHERE aggregate_type = $1
   ORDER BY sequence"
     );
+    assert_eq!(
+        query_factory.events_after().as_str(),
+        "
+SELECT aggregate_type, aggregate_id, sequence, event_type, event_version, payload, metadata
+  FROM my_events
+  WHERE aggregate_type = $1 AND aggregate_id = $2 AND sequence > $3
+  ORDER BY sequence"
+    );
+    assert_eq!(query_factory.committed_channel(), "my_events_committed");
     assert_eq!(
         query_factory.insert_snapshot().as_str(),
         "
@@ -155,3 +228,26 @@ SELECT aggregate_type, aggregate_id, sequence, event_type, event_version, payloa
   ORDER BY sequence"
     );
 }
+
+#[test]
+fn test_filtered_events_query() {
+    let query_factory = SqlQueryFactory::new("my_events", "my_snapshots");
+    assert_eq!(
+        query_factory
+            .filtered_events(&ReplayFilter::default())
+            .as_str(),
+        query_factory.all_events().as_str()
+    );
+    let filter = ReplayFilter::default()
+        .with_event_types(vec!["Created".to_string()])
+        .with_sequences(2..=4)
+        .committed_between(std::time::UNIX_EPOCH, std::time::SystemTime::now());
+    assert_eq!(
+        query_factory.filtered_events(&filter).as_str(),
+        "
+SELECT aggregate_type, aggregate_id, sequence, event_type, event_version, payload, metadata
+  FROM my_events
+  WHERE aggregate_type = $1 AND event_type = ANY($2) AND sequence >= $3 AND sequence <= $4 AND committed_at >= to_timestamp($5) AND committed_at < to_timestamp($6)
+  ORDER BY sequence"
+    );
+}
@@ -2,7 +2,7 @@
 pub(crate) mod tests {
     use crate::PostgresViewRepository;
     use cqrs_es::event_sink::EventSink;
-    use cqrs_es::persist::{GenericQuery, SerializedEvent, SerializedSnapshot};
+    use cqrs_es::persist::{GenericQuery, SerializedEvent};
     use cqrs_es::{Aggregate, DomainEvent, EventEnvelope, View};
     use serde::{Deserialize, Serialize};
     use serde_json::Value;
@@ -119,18 +119,4 @@ pub(crate) mod tests {
             metadata: Value::default(),
         }
     }
-
-    pub(crate) fn snapshot_context(
-        aggregate_id: String,
-        current_sequence: usize,
-        current_snapshot: usize,
-        aggregate: Value,
-    ) -> SerializedSnapshot {
-        SerializedSnapshot {
-            aggregate_id,
-            aggregate,
-            current_sequence,
-            current_snapshot,
-        }
-    }
 }
@@ -1,8 +1,12 @@
 use std::marker::PhantomData;
 
-use cqrs_es::persist::{PersistenceError, ViewContext, ViewRepository};
+use cqrs_es::persist::{
+    ListableViewRepository, PersistenceError, ShadowViewRepository, ViewContext, ViewFilter,
+    ViewListQuery, ViewPage, ViewRepository,
+};
 use cqrs_es::{Aggregate, View};
-use sqlx::postgres::PgRow;
+use sqlx::postgres::{PgArguments, PgRow};
+use sqlx::query::Query;
 use sqlx::{AssertSqlSafe, Pool, Postgres, Row, SqlSafeStr, SqlStr};
 
 use crate::error::PostgresAggregateError;
@@ -12,6 +16,12 @@ pub struct PostgresViewRepository<V, A> {
     insert_sql: SqlStr,
     update_sql: SqlStr,
     select_sql: SqlStr,
+    delete_sql: SqlStr,
+    clear_sql: SqlStr,
+    select_all_sql: SqlStr,
+    upsert_all_sql: SqlStr,
+    view_name: SqlStr,
+    shadow_name: Option<SqlStr>,
     pool: Pool<Postgres>,
     _phantom: PhantomData<(V, A)>,
 }
@@ -38,17 +48,42 @@ where
     pub fn new(view_name: impl SqlSafeStr, pool: Pool<Postgres>) -> Self {
         let view_sql_str = view_name.into_sql_str();
         let insert_sql = AssertSqlSafe(format!(
-            "INSERT INTO {} (payload, version, view_id) VALUES ( $1, $2, $3 )",
+            "INSERT INTO {} (payload, version, last_sequence, view_id) VALUES ( $1, $2, $3, $4 )",
             view_sql_str.as_str()
         ))
         .into_sql_str();
         let update_sql = AssertSqlSafe(format!(
-            "UPDATE {} SET payload= $1 , version= $2 WHERE view_id= $3 AND version= $4",
+            "UPDATE {} SET payload= $1 , version= $2 , last_sequence= $3 WHERE view_id= $4 AND version= $5",
             view_sql_str.as_str()
         ))
         .into_sql_str();
         let select_sql = AssertSqlSafe(format!(
-            "SELECT version,payload FROM {} WHERE view_id= $1",
+            "SELECT version,last_sequence,payload FROM {} WHERE view_id= $1",
+            view_sql_str.as_str()
+        ))
+        .into_sql_str();
+        let delete_sql = AssertSqlSafe(format!(
+            "DELETE FROM {} WHERE view_id= $1 AND version= $2",
+            view_sql_str.as_str()
+        ))
+        .into_sql_str();
+        let clear_sql =
+            AssertSqlSafe(format!("DELETE FROM {}", view_sql_str.as_str())).into_sql_str();
+        let select_all_sql = AssertSqlSafe(format!(
+            "SELECT view_id,version,last_sequence,payload FROM {} WHERE view_id = ANY($1)",
+            view_sql_str.as_str()
+        ))
+        .into_sql_str();
+        // Views are only updated if they have not been modified since they were loaded,
+        // any views skipped are detected by the number of rows affected.
+        let upsert_all_sql = AssertSqlSafe(format!(
+            "INSERT INTO {} AS v (payload, version, last_sequence, view_id)
+             SELECT payload::json, version, last_sequence, view_id
+             FROM UNNEST($1::text[], $2::bigint[], $3::bigint[], $4::text[])
+               AS u(payload, version, last_sequence, view_id)
+             ON CONFLICT (view_id) DO UPDATE
+             SET payload= EXCLUDED.payload, version= EXCLUDED.version, last_sequence= EXCLUDED.last_sequence
+             WHERE v.version = EXCLUDED.version - 1",
             view_sql_str.as_str()
         ))
         .into_sql_str();
@@ -56,10 +91,86 @@ where
             insert_sql,
             update_sql,
             select_sql,
+            delete_sql,
+            clear_sql,
+            select_all_sql,
+            upsert_all_sql,
+            view_name: view_sql_str,
+            shadow_name: None,
             pool,
             _phantom: PhantomData,
         }
     }
+
+    /// Configures a shadow table that views will be rebuilt into during a blue/green rebuild.
+    /// The shadow table must have the same structure as the view table and, when promoted,
+    /// the two tables are swapped by renaming them.
+    ///
+    /// ```
+    /// # use cqrs_es::doc::MyAggregate;
+    /// # use cqrs_es::persist::doc::MyView;
+    /// use sqlx::{Pool, Postgres};
+    /// use postgres_es::PostgresViewRepository;
+    ///
+    /// fn configure_view_repo(pool: Pool<Postgres>) -> PostgresViewRepository<MyView,MyAggregate> {
+    ///     PostgresViewRepository::new("my_view_table", pool)
+    ///         .with_shadow_table("my_view_table_shadow")
+    /// }
+    /// ```
+    pub fn with_shadow_table(self, shadow_name: impl SqlSafeStr) -> Self {
+        Self {
+            shadow_name: Some(shadow_name.into_sql_str()),
+            ..self
+        }
+    }
+
+    fn shadow_name(&self) -> Result<&SqlStr, PersistenceError> {
+        self.shadow_name.as_ref().ok_or_else(|| {
+            PersistenceError::UnknownError("no shadow table configured for this view".into())
+        })
+    }
+}
+
+// Builds the `WHERE` clause for the provided filters, numbering their parameters after the
+// `params_in_use` parameters already used by `conditions`.
+fn where_clause(
+    mut conditions: Vec<String>,
+    params_in_use: usize,
+    filters: &[ViewFilter],
+) -> String {
+    let mut param = params_in_use;
+    for filter in filters {
+        param += 1;
+        match filter.value_as_text() {
+            None => conditions.push(format!("payload #>> ${param} IS NULL")),
+            Some(_) => {
+                conditions.push(format!("payload #>> ${param} = ${}", param + 1));
+                param += 1;
+            }
+        }
+    }
+    if conditions.is_empty() {
+        return String::new();
+    }
+    format!("WHERE {}", conditions.join(" AND "))
+}
+
+fn bind_filters<'q>(
+    mut query: Query<'q, Postgres, PgArguments>,
+    filters: &[ViewFilter],
+) -> Query<'q, Postgres, PgArguments> {
+    for filter in filters {
+        query = query.bind(filter.path.clone());
+        if let Some(value) = filter.value_as_text() {
+            query = query.bind(value);
+        }
+    }
+    query
+}
+
+// `ALTER TABLE .. RENAME TO` takes an unqualified table name as its target.
+fn unqualified(table_name: &str) -> &str {
+    table_name.rsplit('.').next().unwrap_or(table_name)
 }
 
 impl<V, A> ViewRepository<V, A> for PostgresViewRepository<V, A>
@@ -95,8 +206,10 @@ where
             None => Ok(None),
             Some(row) => {
                 let version = row.get("version");
+                let last_sequence: i64 = row.get("last_sequence");
                 let view = serde_json::from_value(row.get("payload"))?;
-                let view_context = ViewContext::new(view_id.to_string(), version);
+                let view_context = ViewContext::new(view_id.to_string(), version)
+                    .with_last_sequence(last_sequence as usize);
                 Ok(Some((view, view_context)))
             }
         }
@@ -112,6 +225,79 @@ where
         let rows_affected = sqlx::query(sql)
             .bind(payload)
             .bind(version)
+            .bind(context.last_sequence as i64)
+            .bind(context.view_instance_id)
+            .bind(context.version)
+            .execute(&self.pool)
+            .await
+            .map_err(PostgresAggregateError::from)?
+            .rows_affected();
+        if rows_affected < 1 {
+            return Err(PersistenceError::OptimisticLockError);
+        }
+        Ok(())
+    }
+
+    async fn load_all_with_context(
+        &self,
+        view_ids: &[String],
+    ) -> Result<Vec<(V, ViewContext)>, PersistenceError> {
+        let rows: Vec<PgRow> = sqlx::query(self.select_all_sql.clone())
+            .bind(view_ids)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(PostgresAggregateError::from)?;
+        let mut views = Vec::with_capacity(rows.len());
+        for row in rows {
+            let view_id = row.get("view_id");
+            let version = row.get("version");
+            let last_sequence: i64 = row.get("last_sequence");
+            let view = serde_json::from_value(row.get("payload"))?;
+            let view_context =
+                ViewContext::new(view_id, version).with_last_sequence(last_sequence as usize);
+            views.push((view, view_context));
+        }
+        Ok(views)
+    }
+
+    async fn update_all(&self, views: Vec<(V, ViewContext)>) -> Result<(), PersistenceError> {
+        if views.is_empty() {
+            return Ok(());
+        }
+        let view_count = views.len() as u64;
+        let mut payloads = Vec::with_capacity(views.len());
+        let mut versions = Vec::with_capacity(views.len());
+        let mut last_sequences = Vec::with_capacity(views.len());
+        let mut view_ids = Vec::with_capacity(views.len());
+        for (view, context) in views {
+            payloads.push(serde_json::to_string(&view).map_err(PostgresAggregateError::from)?);
+            versions.push(context.version + 1);
+            last_sequences.push(context.last_sequence as i64);
+            view_ids.push(context.view_instance_id);
+        }
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(PostgresAggregateError::from)?;
+        let rows_affected = sqlx::query(self.upsert_all_sql.clone())
+            .bind(payloads)
+            .bind(versions)
+            .bind(last_sequences)
+            .bind(view_ids)
+            .execute(&mut *tx)
+            .await
+            .map_err(PostgresAggregateError::from)?
+            .rows_affected();
+        if rows_affected < view_count {
+            return Err(PersistenceError::OptimisticLockError);
+        }
+        tx.commit().await.map_err(PostgresAggregateError::from)?;
+        Ok(())
+    }
+
+    async fn delete_view(&self, context: ViewContext) -> Result<(), PersistenceError> {
+        let rows_affected = sqlx::query(self.delete_sql.clone())
             .bind(context.view_instance_id)
             .bind(context.version)
             .execute(&self.pool)
@@ -123,6 +309,119 @@ where
         }
         Ok(())
     }
+
+    async fn clear_all(&self) -> Result<(), PersistenceError> {
+        sqlx::query(self.clear_sql.clone())
+            .execute(&self.pool)
+            .await
+            .map_err(PostgresAggregateError::from)?;
+        Ok(())
+    }
+}
+
+impl<V, A> ListableViewRepository<V, A> for PostgresViewRepository<V, A>
+where
+    V: View<A>,
+    A: Aggregate,
+{
+    async fn list_views(&self, query: &ViewListQuery) -> Result<ViewPage<V>, PersistenceError> {
+        let page_size = query.page_size();
+        let mut conditions = Vec::new();
+        if query.cursor.is_some() {
+            conditions.push("view_id > $1".to_string());
+        }
+        let params_in_use = conditions.len();
+        let sql = format!(
+            "SELECT view_id,payload FROM {} {} ORDER BY view_id LIMIT {}",
+            self.view_name.as_str(),
+            where_clause(conditions, params_in_use, &query.filters),
+            page_size.saturating_add(1)
+        );
+        let mut sql_query = sqlx::query(AssertSqlSafe(sql));
+        if let Some(cursor) = &query.cursor {
+            sql_query = sql_query.bind(cursor.clone());
+        }
+        let rows: Vec<PgRow> = bind_filters(sql_query, &query.filters)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(PostgresAggregateError::from)?;
+        let has_more = rows.len() > page_size;
+        let mut views = Vec::with_capacity(page_size);
+        for row in rows.into_iter().take(page_size) {
+            let view_id: String = row.get("view_id");
+            let view = serde_json::from_value(row.get("payload"))?;
+            views.push((view_id, view));
+        }
+        let next_cursor = match has_more {
+            true => views.last().map(|(view_id, _)| view_id.clone()),
+            false => None,
+        };
+        Ok(ViewPage { views, next_cursor })
+    }
+
+    async fn count_views(&self, filters: &[ViewFilter]) -> Result<u64, PersistenceError> {
+        let sql = format!(
+            "SELECT count(*) FROM {} {}",
+            self.view_name.as_str(),
+            where_clause(Vec::new(), 0, filters)
+        );
+        let row: PgRow = bind_filters(sqlx::query(AssertSqlSafe(sql)), filters)
+            .fetch_one(&self.pool)
+            .await
+            .map_err(PostgresAggregateError::from)?;
+        let count: i64 = row.get(0);
+        Ok(count as u64)
+    }
+}
+
+impl<V, A> ShadowViewRepository<V, A> for PostgresViewRepository<V, A>
+where
+    V: View<A>,
+    A: Aggregate,
+{
+    type Shadow = Self;
+
+    async fn prepare_shadow(&self) -> Result<Self::Shadow, PersistenceError> {
+        let shadow = Self::new(self.shadow_name()?.clone(), self.pool.clone());
+        shadow.clear_all().await?;
+        Ok(shadow)
+    }
+
+    async fn promote_shadow(&self) -> Result<(), PersistenceError> {
+        let view_name = self.view_name.as_str();
+        let shadow_name = self.shadow_name()?.as_str();
+        let swap_name = format!("{view_name}_swap");
+        let renames = [
+            format!(
+                "ALTER TABLE {} RENAME TO {}",
+                view_name,
+                unqualified(&swap_name)
+            ),
+            format!(
+                "ALTER TABLE {} RENAME TO {}",
+                shadow_name,
+                unqualified(view_name)
+            ),
+            format!(
+                "ALTER TABLE {} RENAME TO {}",
+                swap_name,
+                unqualified(shadow_name)
+            ),
+        ];
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(PostgresAggregateError::from)?;
+        for sql in renames {
+            sqlx::query(AssertSqlSafe(sql))
+                .execute(&mut *tx)
+                .await
+                .map_err(PostgresAggregateError::from)?;
+        }
+        tx.commit().await.map_err(PostgresAggregateError::from)?;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -131,7 +430,10 @@ mod test {
         Created, TestAggregate, TestEvent, TestView, TEST_CONNECTION_STRING,
     };
     use crate::{default_postgres_pool, PostgresViewRepository};
-    use cqrs_es::persist::{ViewContext, ViewRepository};
+    use cqrs_es::persist::{
+        ListableViewRepository, PersistenceError, ShadowViewRepository, ViewContext, ViewFilter,
+        ViewListQuery, ViewRepository,
+    };
 
     #[tokio::test]
     async fn test_valid_view_repository() {
@@ -145,15 +447,15 @@ mod test {
                 id: "just a test event for this view".to_string(),
             })],
         };
-        repo.update_view(view.clone(), ViewContext::new(test_view_id.to_string(), 0))
-            .await
-            .unwrap();
+        let context = ViewContext::new(test_view_id.to_string(), 0).with_last_sequence(3);
+        repo.update_view(view.clone(), context).await.unwrap();
         let (found, context) = repo
             .load_with_context(&test_view_id)
             .await
             .unwrap()
             .unwrap();
         assert_eq!(found, view);
+        assert_eq!(3, context.last_sequence);
         let found = repo.load(&test_view_id).await.unwrap().unwrap();
         assert_eq!(found, view);
 
@@ -170,4 +472,137 @@ mod test {
 
         assert_eq!(found, updated_view);
     }
+
+    #[tokio::test]
+    async fn test_shadow_view_repository() {
+        let pool = default_postgres_pool(TEST_CONNECTION_STRING).await;
+        let repo =
+            PostgresViewRepository::<TestView, TestAggregate>::new("test_view", pool.clone())
+                .with_shadow_table("test_view_shadow");
+        let test_view_id = uuid::Uuid::new_v4().to_string();
+
+        let view = TestView {
+            events: vec![TestEvent::Created(Created {
+                id: "a rebuilt view".to_string(),
+            })],
+        };
+        let shadow = repo.prepare_shadow().await.unwrap();
+        shadow
+            .update_view(view.clone(), ViewContext::new(test_view_id.to_string(), 0))
+            .await
+            .unwrap();
+        assert_eq!(None, repo.load(&test_view_id).await.unwrap());
+
+        repo.promote_shadow().await.unwrap();
+        let found = repo.load(&test_view_id).await.unwrap().unwrap();
+        assert_eq!(found, view);
+    }
+
+    #[tokio::test]
+    async fn test_list_views() {
+        let pool = default_postgres_pool(TEST_CONNECTION_STRING).await;
+        let repo =
+            PostgresViewRepository::<TestView, TestAggregate>::new("test_view", pool.clone());
+        let marker = uuid::Uuid::new_v4().to_string();
+        for _ in 0..3 {
+            let view = TestView {
+                events: vec![TestEvent::Created(Created { id: marker.clone() })],
+            };
+            let view_id = uuid::Uuid::new_v4().to_string();
+            repo.update_view(view, ViewContext::new(view_id, 0))
+                .await
+                .unwrap();
+        }
+        let filter = ViewFilter::equals("events.0.Created.id", marker.as_str());
+
+        let count = repo
+            .count_views(std::slice::from_ref(&filter))
+            .await
+            .unwrap();
+        assert_eq!(3, count);
+
+        let query = ViewListQuery::new(usize::MAX).with_filter(filter.clone());
+        let page = repo.list_views(&query).await.unwrap();
+        assert_eq!(3, page.views.len());
+        assert_eq!(None, page.next_cursor);
+
+        let query = ViewListQuery::new(2).with_filter(filter);
+        let first_page = repo.list_views(&query).await.unwrap();
+        assert_eq!(2, first_page.views.len());
+        assert!(first_page.next_cursor.is_some());
+
+        let query = query.after(first_page.next_cursor);
+        let second_page = repo.list_views(&query).await.unwrap();
+        assert_eq!(1, second_page.views.len());
+        assert_eq!(None, second_page.next_cursor);
+        assert!(first_page.views[1].0 < second_page.views[0].0);
+    }
+
+    #[tokio::test]
+    async fn test_delete_view() {
+        let pool = default_postgres_pool(TEST_CONNECTION_STRING).await;
+        let repo =
+            PostgresViewRepository::<TestView, TestAggregate>::new("test_view", pool.clone());
+        let test_view_id = uuid::Uuid::new_v4().to_string();
+
+        let view = TestView {
+            events: vec![TestEvent::Created(Created {
+                id: "a view to delete".to_string(),
+            })],
+        };
+        repo.update_view(view, ViewContext::new(test_view_id.to_string(), 0))
+            .await
+            .unwrap();
+        let stale_context = ViewContext::new(test_view_id.to_string(), 0);
+        let result = repo.delete_view(stale_context).await;
+        assert!(matches!(result, Err(PersistenceError::OptimisticLockError)));
+
+        let (_, context) = repo
+            .load_with_context(&test_view_id)
+            .await
+            .unwrap()
+            .unwrap();
+        repo.delete_view(context).await.unwrap();
+        assert_eq!(None, repo.load(&test_view_id).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_update_all() {
+        let pool = default_postgres_pool(TEST_CONNECTION_STRING).await;
+        let repo =
+            PostgresViewRepository::<TestView, TestAggregate>::new("test_view", pool.clone());
+        let existing_id = uuid::Uuid::new_v4().to_string();
+        let new_id = uuid::Uuid::new_v4().to_string();
+        let view = |id: &str| TestView {
+            events: vec![TestEvent::Created(Created { id: id.to_string() })],
+        };
+        repo.update_view(view("existing"), ViewContext::new(existing_id.clone(), 0))
+            .await
+            .unwrap();
+
+        let view_ids = vec![existing_id.clone(), new_id.clone()];
+        let loaded = repo.load_all_with_context(&view_ids).await.unwrap();
+        assert_eq!(1, loaded.len());
+        let (_, existing_context) = loaded.into_iter().next().unwrap();
+        repo.update_all(vec![
+            (view("updated"), existing_context.with_last_sequence(2)),
+            (view("new"), ViewContext::new(new_id.clone(), 0)),
+        ])
+        .await
+        .unwrap();
+        let (found, context) = repo.load_with_context(&existing_id).await.unwrap().unwrap();
+        assert_eq!(view("updated"), found);
+        assert_eq!((2, 2), (context.version, context.last_sequence));
+        assert_eq!(Some(view("new")), repo.load(&new_id).await.unwrap());
+
+        // a stale context fails the entire update
+        let result = repo
+            .update_all(vec![
+                (view("stale"), ViewContext::new(existing_id.clone(), 1)),
+                (view("newer"), ViewContext::new(new_id.clone(), 1)),
+            ])
+            .await;
+        assert!(matches!(result, Err(PersistenceError::OptimisticLockError)));
+        assert_eq!(Some(view("new")), repo.load(&new_id).await.unwrap());
+    }
 }
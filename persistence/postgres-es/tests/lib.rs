@@ -2,8 +2,9 @@ use std::collections::HashMap;
 
 use cqrs_es::doc::{Customer, CustomerEvent};
 use cqrs_es::persist::{PersistedEventStore, SemanticVersionEventUpcaster};
+use cqrs_es::test::{EventRepositoryConformance, ViewRepositoryConformance};
 use cqrs_es::EventStore;
-use postgres_es::{default_postgres_pool, PostgresEventRepository};
+use postgres_es::{default_postgres_pool, PostgresEventRepository, PostgresViewRepository};
 use serde_json::Value;
 use sqlx::{Pool, Postgres};
 
@@ -96,3 +97,23 @@ async fn upcasted_event() {
     assert_eq!(1, result.current_sequence);
     assert_eq!(None, result.current_snapshot);
 }
+
+#[tokio::test]
+async fn event_repository_conformance() {
+    let pool = default_postgres_pool(TEST_CONNECTION_STRING).await;
+    EventRepositoryConformance::with(move || {
+        PostgresEventRepository::new(pool.clone()).with_commit_notifications()
+    })
+    .verify()
+    .await;
+}
+
+#[tokio::test]
+async fn view_repository_conformance() {
+    let pool = default_postgres_pool(TEST_CONNECTION_STRING).await;
+    ViewRepositoryConformance::with(move || {
+        PostgresViewRepository::new("conformance_view", pool.clone())
+    })
+    .verify()
+    .await;
+}
@@ -0,0 +1,85 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use cqrs_es::persist::PersistedEventStore;
+use cqrs_es::{Aggregate, CqrsFramework, Query};
+use redb::Database;
+
+use crate::{RedbCqrs, RedbEventRepository};
+
+/// A convenience method for opening a redb database, the database file is created if it does
+/// not exist. The database is shared by the event and view repositories.
+///
+/// ```
+/// use std::sync::Arc;
+/// use redb::Database;
+/// use redb_es::default_redb_database;
+///
+/// # fn configure_database() {
+/// let database: Arc<Database> = default_redb_database("events.redb");
+/// # }
+/// ```
+pub fn default_redb_database(path: impl AsRef<Path>) -> Arc<Database> {
+    Arc::new(Database::create(path).expect("unable to open database"))
+}
+
+/// A convenience function for creating a CqrsFramework from a database and queries.
+pub fn redb_cqrs<A>(
+    database: Arc<Database>,
+    query_processor: Vec<Box<dyn Query<A>>>,
+    services: A::Services,
+) -> RedbCqrs<A>
+where
+    A: Aggregate,
+{
+    let repo = RedbEventRepository::new(database);
+    let store = PersistedEventStore::new_event_store(repo);
+    CqrsFramework::new(store, query_processor, services)
+}
+
+/// A convenience function for creating a CqrsFramework using a snapshot store.
+pub fn redb_snapshot_cqrs<A>(
+    database: Arc<Database>,
+    query_processor: Vec<Box<dyn Query<A>>>,
+    snapshot_size: usize,
+    services: A::Services,
+) -> RedbCqrs<A>
+where
+    A: Aggregate,
+{
+    let repo = RedbEventRepository::new(database);
+    let store = PersistedEventStore::new_snapshot_store(repo, snapshot_size);
+    CqrsFramework::new(store, query_processor, services)
+}
+
+/// A convenience function for creating a CqrsFramework using an aggregate store.
+pub fn redb_aggregate_cqrs<A>(
+    database: Arc<Database>,
+    query_processor: Vec<Box<dyn Query<A>>>,
+    services: A::Services,
+) -> RedbCqrs<A>
+where
+    A: Aggregate,
+{
+    let repo = RedbEventRepository::new(database);
+    let store = PersistedEventStore::new_aggregate_store(repo);
+    CqrsFramework::new(store, query_processor, services)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::testing::tests::{
+        test_database, TestAggregate, TestQueryRepository, TestServices, TestView,
+    };
+    use crate::{redb_cqrs, RedbViewRepository};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_valid_cqrs_framework() {
+        let database = test_database();
+        let repo =
+            RedbViewRepository::<TestView, TestAggregate>::new("test_view", database.clone());
+        let query = TestQueryRepository::new(Arc::new(repo));
+        let _ps = redb_cqrs(database, vec![Box::new(query)], TestServices);
+    }
+}
@@ -0,0 +1,29 @@
+use std::sync::Arc;
+
+use redb::{Database, Key, ReadOnlyTable, ReadTransaction, TableDefinition, TableError, Value};
+
+use crate::error::RedbAggregateError;
+
+// redb blocks on file access, so database work is moved off of the async runtime.
+pub(crate) async fn blocking<T, F>(database: &Arc<Database>, f: F) -> Result<T, RedbAggregateError>
+where
+    T: Send + 'static,
+    F: FnOnce(&Database) -> Result<T, RedbAggregateError> + Send + 'static,
+{
+    let database = database.clone();
+    tokio::task::spawn_blocking(move || f(&database))
+        .await
+        .map_err(|err| RedbAggregateError::UnknownError(Box::new(err)))?
+}
+
+// Tables are created by their first write, a table that does not exist yet is treated as empty.
+pub(crate) fn open_table<K: Key + 'static, V: Value + 'static>(
+    txn: &ReadTransaction,
+    definition: TableDefinition<'_, K, V>,
+) -> Result<Option<ReadOnlyTable<K, V>>, RedbAggregateError> {
+    match txn.open_table(definition) {
+        Ok(table) => Ok(Some(table)),
+        Err(TableError::TableDoesNotExist(_)) => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
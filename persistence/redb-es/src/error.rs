@@ -0,0 +1,82 @@
+use cqrs_es::persist::PersistenceError;
+use cqrs_es::AggregateError;
+
+#[derive(Debug, thiserror::Error)]
+pub enum RedbAggregateError {
+    #[error("optimistic lock error")]
+    OptimisticLock,
+    #[error(transparent)]
+    ConnectionError(Box<dyn std::error::Error + Send + Sync + 'static>),
+    #[error(transparent)]
+    DeserializationError(Box<dyn std::error::Error + Send + Sync + 'static>),
+    #[error(transparent)]
+    UnknownError(Box<dyn std::error::Error + Send + Sync + 'static>),
+}
+
+impl From<redb::Error> for RedbAggregateError {
+    fn from(err: redb::Error) -> Self {
+        match err {
+            redb::Error::Io(_) => Self::ConnectionError(Box::new(err)),
+            _ => Self::UnknownError(Box::new(err)),
+        }
+    }
+}
+
+impl From<redb::TransactionError> for RedbAggregateError {
+    fn from(err: redb::TransactionError) -> Self {
+        redb::Error::from(err).into()
+    }
+}
+
+impl From<redb::TableError> for RedbAggregateError {
+    fn from(err: redb::TableError) -> Self {
+        redb::Error::from(err).into()
+    }
+}
+
+impl From<redb::StorageError> for RedbAggregateError {
+    fn from(err: redb::StorageError) -> Self {
+        redb::Error::from(err).into()
+    }
+}
+
+impl From<redb::CommitError> for RedbAggregateError {
+    fn from(err: redb::CommitError) -> Self {
+        redb::Error::from(err).into()
+    }
+}
+
+impl<T: std::error::Error> From<RedbAggregateError> for AggregateError<T> {
+    fn from(err: RedbAggregateError) -> Self {
+        match err {
+            RedbAggregateError::OptimisticLock => Self::AggregateConflict,
+            RedbAggregateError::ConnectionError(error) => Self::DatabaseConnectionError(error),
+            RedbAggregateError::DeserializationError(error) => Self::DeserializationError(error),
+            RedbAggregateError::UnknownError(error) => Self::UnexpectedError(error),
+        }
+    }
+}
+
+impl From<serde_json::Error> for RedbAggregateError {
+    fn from(err: serde_json::Error) -> Self {
+        match err.classify() {
+            serde_json::error::Category::Data | serde_json::error::Category::Syntax => {
+                Self::DeserializationError(Box::new(err))
+            }
+            serde_json::error::Category::Io | serde_json::error::Category::Eof => {
+                Self::UnknownError(Box::new(err))
+            }
+        }
+    }
+}
+
+impl From<RedbAggregateError> for PersistenceError {
+    fn from(err: RedbAggregateError) -> Self {
+        match err {
+            RedbAggregateError::OptimisticLock => Self::OptimisticLockError,
+            RedbAggregateError::ConnectionError(error) => Self::ConnectionError(error),
+            RedbAggregateError::DeserializationError(error) => Self::DeserializationError(error),
+            RedbAggregateError::UnknownError(error) => Self::UnknownError(error),
+        }
+    }
+}
@@ -0,0 +1,755 @@
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use cqrs_es::persist::{
+    PersistedEventRepository, PersistenceError, ReplayFilter, ReplayStream, SerializedEvent,
+    SerializedSnapshot,
+};
+use cqrs_es::Aggregate;
+use redb::{Database, ReadableDatabase, ReadableTable, TableDefinition};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::watch;
+
+use crate::database::{blocking, open_table};
+use crate::error::RedbAggregateError;
+
+const DEFAULT_EVENT_TABLE: &str = "events";
+const DEFAULT_SNAPSHOT_TABLE: &str = "snapshots";
+
+const DEFAULT_STREAMING_CHANNEL_SIZE: usize = 200;
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+// aggregate type, aggregate id and sequence
+type EventKey = (&'static str, &'static str, u64);
+// aggregate type and position
+type PositionKey = (&'static str, u64);
+// aggregate id and sequence
+type PositionValue = (&'static str, u64);
+// aggregate type and aggregate id
+type SnapshotKey = (&'static str, &'static str);
+
+/// An event repository relying on an embedded redb database for persistence.
+///
+/// Events are keyed by aggregate type, aggregate id and sequence so that the events of an
+/// aggregate instance are read with a single range scan. Each event is also given a position
+/// within its aggregate type, held in a separate index table, which orders the events for
+/// `stream_all_events` and subscriptions.
+#[derive(Clone)]
+pub struct RedbEventRepository {
+    database: Arc<Database>,
+    tables: Arc<EventTables>,
+    stream_channel_size: usize,
+    poll_interval: Duration,
+    committed: Arc<watch::Sender<()>>,
+}
+
+struct EventTables {
+    events: String,
+    positions: String,
+    snapshots: String,
+}
+
+impl EventTables {
+    fn new(events_table: &str, snapshots_table: &str) -> Self {
+        Self {
+            events: events_table.to_string(),
+            positions: format!("{events_table}_positions"),
+            snapshots: snapshots_table.to_string(),
+        }
+    }
+    fn events(&self) -> TableDefinition<'_, EventKey, &'static [u8]> {
+        TableDefinition::new(&self.events)
+    }
+    fn positions(&self) -> TableDefinition<'_, PositionKey, PositionValue> {
+        TableDefinition::new(&self.positions)
+    }
+    fn snapshots(&self) -> TableDefinition<'_, SnapshotKey, &'static [u8]> {
+        TableDefinition::new(&self.snapshots)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredEvent {
+    event_type: String,
+    event_version: String,
+    payload: Value,
+    metadata: Value,
+    // milliseconds since the unix epoch
+    committed_at: u64,
+}
+
+impl StoredEvent {
+    fn into_event(
+        self,
+        aggregate_type: &str,
+        aggregate_id: &str,
+        sequence: u64,
+    ) -> SerializedEvent {
+        SerializedEvent::new(
+            aggregate_id.to_string(),
+            sequence as usize,
+            aggregate_type.to_string(),
+            self.event_type,
+            self.event_version,
+            self.payload,
+            self.metadata,
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredSnapshot {
+    last_sequence: usize,
+    current_snapshot: usize,
+    payload: Value,
+}
+
+// An event along with its position in the index.
+struct PositionedEvent {
+    position: u64,
+    committed_at: u64,
+    event: SerializedEvent,
+}
+
+impl PersistedEventRepository for RedbEventRepository {
+    async fn get_events<A: Aggregate>(
+        &self,
+        aggregate_id: &str,
+    ) -> Result<Vec<SerializedEvent>, PersistenceError> {
+        self.select_events::<A>(aggregate_id, 0).await
+    }
+
+    async fn get_last_events<A: Aggregate>(
+        &self,
+        aggregate_id: &str,
+        last_sequence: usize,
+    ) -> Result<Vec<SerializedEvent>, PersistenceError> {
+        self.select_events::<A>(aggregate_id, last_sequence as u64 + 1)
+            .await
+    }
+
+    async fn get_snapshot<A: Aggregate>(
+        &self,
+        aggregate_id: &str,
+    ) -> Result<Option<SerializedSnapshot>, PersistenceError> {
+        let tables = self.tables.clone();
+        let aggregate_id = aggregate_id.to_string();
+        let snapshot = blocking(&self.database, move |database| {
+            let txn = database.begin_read()?;
+            let Some(snapshots) = open_table(&txn, tables.snapshots())? else {
+                return Ok(None);
+            };
+            let Some(stored) = snapshots.get((A::TYPE, aggregate_id.as_str()))? else {
+                return Ok(None);
+            };
+            let stored: StoredSnapshot = serde_json::from_slice(stored.value())?;
+            Ok(Some(SerializedSnapshot {
+                aggregate_id,
+                aggregate: stored.payload,
+                current_sequence: stored.last_sequence,
+                current_snapshot: stored.current_snapshot,
+            }))
+        })
+        .await?;
+        Ok(snapshot)
+    }
+
+    async fn persist<A: Aggregate>(
+        &self,
+        events: &[SerializedEvent],
+        snapshot_update: Option<(String, Value, usize)>,
+    ) -> Result<(), PersistenceError> {
+        let tables = self.tables.clone();
+        let events = events.to_vec();
+        blocking(&self.database, move |database| {
+            commit(database, &tables, A::TYPE, &events, snapshot_update)
+        })
+        .await?;
+        self.committed.send_replace(());
+        Ok(())
+    }
+
+    async fn stream_events<A: Aggregate>(
+        &self,
+        aggregate_id: &str,
+    ) -> Result<ReplayStream, PersistenceError> {
+        let events = self.get_events::<A>(aggregate_id).await?;
+        let (mut feed, stream) = ReplayStream::new(self.stream_channel_size);
+        tokio::spawn(async move {
+            for event in events {
+                if feed.push(Ok(event)).await.is_err() {
+                    return;
+                }
+            }
+            feed.finish();
+        });
+        Ok(stream)
+    }
+
+    async fn stream_all_events<A: Aggregate>(&self) -> Result<ReplayStream, PersistenceError> {
+        Ok(self.stream_positions(A::TYPE, |_| true, false))
+    }
+
+    async fn stream_filtered_events<A: Aggregate>(
+        &self,
+        filter: &ReplayFilter,
+    ) -> Result<ReplayStream, PersistenceError> {
+        let filter = filter.clone();
+        let selected = move |event: &PositionedEvent| {
+            let committed_at = UNIX_EPOCH + Duration::from_millis(event.committed_at);
+            filter.matches(&event.event) && filter.is_committed_within(committed_at)
+        };
+        Ok(self.stream_positions(A::TYPE, selected, false))
+    }
+
+    // Commits made through this repository, or any clone of it, wake subscriptions immediately,
+    // other commits to the database are found by polling.
+    async fn subscribe_all_events<A: Aggregate>(&self) -> Result<ReplayStream, PersistenceError> {
+        Ok(self.stream_positions(A::TYPE, |_| true, true))
+    }
+}
+
+impl RedbEventRepository {
+    /// Creates a new `RedbEventRepository` from the provided database.
+    /// This uses the default tables 'events' and 'snapshots', tables are created as they are
+    /// first written to.
+    ///
+    /// ```
+    /// use std::sync::Arc;
+    /// use redb::Database;
+    /// use redb_es::RedbEventRepository;
+    ///
+    /// fn configure_repo(database: Arc<Database>) -> RedbEventRepository {
+    ///     RedbEventRepository::new(database)
+    /// }
+    /// ```
+    pub fn new(database: Arc<Database>) -> Self {
+        Self::use_tables(database, DEFAULT_EVENT_TABLE, DEFAULT_SNAPSHOT_TABLE)
+    }
+
+    /// Configures a `RedbEventRepository` to use a streaming queue of the provided size.
+    ///
+    /// _Example: configure the repository to stream with a 1000 event buffer._
+    /// ```
+    /// use std::sync::Arc;
+    /// use redb::Database;
+    /// use redb_es::RedbEventRepository;
+    ///
+    /// fn configure_repo(database: Arc<Database>) -> RedbEventRepository {
+    ///     let store = RedbEventRepository::new(database);
+    ///     store.with_streaming_channel_size(1000)
+    /// }
+    /// ```
+    pub fn with_streaming_channel_size(self, stream_channel_size: usize) -> Self {
+        Self {
+            stream_channel_size,
+            ..self
+        }
+    }
+
+    /// Configures how often a subscription polls for events committed by other repositories
+    /// sharing the database, the default is every 250 milliseconds.
+    ///
+    /// _Example: poll for new events every 50 milliseconds._
+    /// ```
+    /// use std::sync::Arc;
+    /// use std::time::Duration;
+    /// use redb::Database;
+    /// use redb_es::RedbEventRepository;
+    ///
+    /// fn configure_repo(database: Arc<Database>) -> RedbEventRepository {
+    ///     let store = RedbEventRepository::new(database);
+    ///     store.with_subscription_poll_interval(Duration::from_millis(50))
+    /// }
+    /// ```
+    pub fn with_subscription_poll_interval(self, poll_interval: Duration) -> Self {
+        Self {
+            poll_interval,
+            ..self
+        }
+    }
+
+    /// Configures a `RedbEventRepository` to use the provided table names, the position index
+    /// is held in a table named for the event table with a `_positions` suffix.
+    ///
+    /// _Example: configure the repository to use "my_event_table" and "my_snapshot_table"
+    /// for the event and snapshot table names._
+    /// ```
+    /// use std::sync::Arc;
+    /// use redb::Database;
+    /// use redb_es::RedbEventRepository;
+    ///
+    /// fn configure_repo(database: Arc<Database>) -> RedbEventRepository {
+    ///     let store = RedbEventRepository::new(database);
+    ///     store.with_tables("my_event_table", "my_snapshot_table")
+    /// }
+    /// ```
+    pub fn with_tables(self, events_table: &str, snapshots_table: &str) -> Self {
+        Self {
+            tables: Arc::new(EventTables::new(events_table, snapshots_table)),
+            ..self
+        }
+    }
+
+    fn use_tables(database: Arc<Database>, events_table: &str, snapshots_table: &str) -> Self {
+        let (committed, _) = watch::channel(());
+        Self {
+            database,
+            tables: Arc::new(EventTables::new(events_table, snapshots_table)),
+            stream_channel_size: DEFAULT_STREAMING_CHANNEL_SIZE,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            committed: Arc::new(committed),
+        }
+    }
+
+    async fn select_events<A: Aggregate>(
+        &self,
+        aggregate_id: &str,
+        from_sequence: u64,
+    ) -> Result<Vec<SerializedEvent>, PersistenceError> {
+        let tables = self.tables.clone();
+        let aggregate_id = aggregate_id.to_string();
+        let events = blocking(&self.database, move |database| {
+            let txn = database.begin_read()?;
+            let Some(events) = open_table(&txn, tables.events())? else {
+                return Ok(Vec::new());
+            };
+            let range = (A::TYPE, aggregate_id.as_str(), from_sequence)
+                ..=(A::TYPE, aggregate_id.as_str(), u64::MAX);
+            let mut result = Vec::new();
+            for entry in events.range(range)? {
+                let (key, stored) = entry?;
+                let (_, _, sequence) = key.value();
+                let stored: StoredEvent = serde_json::from_slice(stored.value())?;
+                result.push(stored.into_event(A::TYPE, &aggregate_id, sequence));
+            }
+            Ok(result)
+        })
+        .await?;
+        Ok(events)
+    }
+
+    // Pages through the events of an aggregate type in position order, pushing those that are
+    // selected. A following stream waits for new commits once it has caught up.
+    fn stream_positions(
+        &self,
+        aggregate_type: &'static str,
+        selected: impl Fn(&PositionedEvent) -> bool + Send + 'static,
+        follow: bool,
+    ) -> ReplayStream {
+        let database = self.database.clone();
+        let tables = self.tables.clone();
+        let committed = self.committed.clone();
+        let poll_interval = self.poll_interval;
+        let page_size = self.stream_channel_size.max(1);
+        let (mut feed, stream) = ReplayStream::new(self.stream_channel_size);
+        tokio::spawn(async move {
+            let mut commits = committed.subscribe();
+            let mut position = 0;
+            loop {
+                commits.borrow_and_update();
+                let page_tables = tables.clone();
+                let page = blocking(&database, move |database| {
+                    events_after(database, &page_tables, aggregate_type, position, page_size)
+                })
+                .await;
+                let page = match page {
+                    Ok(page) => page,
+                    Err(err) => {
+                        let _ = feed.push(Err(err.into())).await;
+                        return;
+                    }
+                };
+                let page_full = page.len() >= page_size;
+                for event in page {
+                    position = event.position;
+                    if !selected(&event) {
+                        continue;
+                    }
+                    if feed.push(Ok(event.event)).await.is_err() {
+                        return;
+                    }
+                }
+                if page_full {
+                    continue;
+                }
+                if !follow {
+                    return feed.finish();
+                }
+                tokio::select! {
+                    () = feed.closed() => return,
+                    () = tokio::time::sleep(poll_interval) => {}
+                    _ = commits.changed() => {}
+                }
+            }
+        });
+        stream
+    }
+}
+
+fn epoch_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+// Events and any snapshot update are written in a single transaction. The transaction is
+// aborted, by dropping it, if any event has already been committed or the snapshot does not
+// directly follow the current snapshot.
+fn commit(
+    database: &Database,
+    tables: &EventTables,
+    aggregate_type: &str,
+    events: &[SerializedEvent],
+    snapshot_update: Option<(String, Value, usize)>,
+) -> Result<(), RedbAggregateError> {
+    let txn = database.begin_write()?;
+    {
+        let mut event_table = txn.open_table(tables.events())?;
+        let mut position_table = txn.open_table(tables.positions())?;
+        let mut position = position_table
+            .range((aggregate_type, 0)..=(aggregate_type, u64::MAX))?
+            .next_back()
+            .transpose()?
+            .map_or(0, |(key, _)| key.value().1);
+        let committed_at = epoch_millis(SystemTime::now());
+        let mut current_sequence = 0;
+        for event in events {
+            let sequence = event.sequence as u64;
+            let key = (aggregate_type, event.aggregate_id.as_str(), sequence);
+            if event_table.get(key)?.is_some() {
+                return Err(RedbAggregateError::OptimisticLock);
+            }
+            let stored = serde_json::to_vec(&StoredEvent {
+                event_type: event.event_type.clone(),
+                event_version: event.event_version.clone(),
+                payload: event.payload.clone(),
+                metadata: event.metadata.clone(),
+                committed_at,
+            })?;
+            event_table.insert(key, stored.as_slice())?;
+            position += 1;
+            position_table.insert(
+                (aggregate_type, position),
+                (event.aggregate_id.as_str(), sequence),
+            )?;
+            current_sequence = event.sequence;
+        }
+        if let Some((aggregate_id, aggregate, current_snapshot)) = snapshot_update {
+            let mut snapshot_table = txn.open_table(tables.snapshots())?;
+            let key = (aggregate_type, aggregate_id.as_str());
+            let stored_snapshot = match snapshot_table.get(key)? {
+                None => 0,
+                Some(stored) => {
+                    serde_json::from_slice::<StoredSnapshot>(stored.value())?.current_snapshot
+                }
+            };
+            if stored_snapshot + 1 != current_snapshot {
+                return Err(RedbAggregateError::OptimisticLock);
+            }
+            let stored = serde_json::to_vec(&StoredSnapshot {
+                last_sequence: current_sequence,
+                current_snapshot,
+                payload: aggregate,
+            })?;
+            snapshot_table.insert(key, stored.as_slice())?;
+        }
+    }
+    txn.commit()?;
+    Ok(())
+}
+
+// Reads up to `limit` events following the provided position.
+fn events_after(
+    database: &Database,
+    tables: &EventTables,
+    aggregate_type: &str,
+    position: u64,
+    limit: usize,
+) -> Result<Vec<PositionedEvent>, RedbAggregateError> {
+    let txn = database.begin_read()?;
+    let (Some(positions), Some(events)) = (
+        open_table(&txn, tables.positions())?,
+        open_table(&txn, tables.events())?,
+    ) else {
+        return Ok(Vec::new());
+    };
+    let mut result = Vec::new();
+    let range = (aggregate_type, position + 1)..=(aggregate_type, u64::MAX);
+    for entry in positions.range(range)?.take(limit) {
+        let (key, value) = entry?;
+        let (aggregate_id, sequence) = value.value();
+        let Some(stored) = events.get((aggregate_type, aggregate_id, sequence))? else {
+            return Err(RedbAggregateError::UnknownError(
+                format!("no event found for position {}", key.value().1).into(),
+            ));
+        };
+        let stored: StoredEvent = serde_json::from_slice(stored.value())?;
+        result.push(PositionedEvent {
+            position: key.value().1,
+            committed_at: stored.committed_at,
+            event: stored.into_event(aggregate_type, aggregate_id, sequence),
+        });
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, SystemTime};
+
+    use cqrs_es::persist::{PersistedEventRepository, PersistenceError, ReplayFilter};
+    use serde_json::json;
+
+    use crate::testing::tests::{
+        snapshot_context, test_database, test_event_envelope, Created, SomethingElse,
+        TestAggregate, TestEvent, Tested,
+    };
+    use crate::RedbEventRepository;
+
+    fn tested(id: &str, sequence: usize) -> cqrs_es::persist::SerializedEvent {
+        test_event_envelope(
+            id,
+            sequence,
+            TestEvent::Tested(Tested {
+                test_name: format!("test {sequence}"),
+            }),
+        )
+    }
+
+    #[tokio::test]
+    async fn event_repositories() {
+        let id = uuid::Uuid::new_v4().to_string();
+        let event_repo = RedbEventRepository::new(test_database()).with_streaming_channel_size(1);
+        let events = event_repo.get_events::<TestAggregate>(&id).await.unwrap();
+        assert!(events.is_empty());
+
+        event_repo
+            .persist::<TestAggregate>(
+                &[
+                    test_event_envelope(&id, 1, TestEvent::Created(Created { id: id.clone() })),
+                    tested(&id, 2),
+                ],
+                None,
+            )
+            .await
+            .unwrap();
+        let events = event_repo.get_events::<TestAggregate>(&id).await.unwrap();
+        assert_eq!(2, events.len());
+        events.iter().for_each(|e| assert_eq!(&id, &e.aggregate_id));
+        let events = event_repo
+            .get_last_events::<TestAggregate>(&id, 1)
+            .await
+            .unwrap();
+        assert_eq!(vec![tested(&id, 2)], events);
+
+        // Optimistic lock error
+        let result = event_repo
+            .persist::<TestAggregate>(
+                &[
+                    test_event_envelope(
+                        &id,
+                        3,
+                        TestEvent::SomethingElse(SomethingElse {
+                            description: "this should not persist".to_string(),
+                        }),
+                    ),
+                    tested(&id, 2),
+                ],
+                None,
+            )
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(result, PersistenceError::OptimisticLockError),
+            "invalid error result found during insert: {result}"
+        );
+
+        let events = event_repo.get_events::<TestAggregate>(&id).await.unwrap();
+        assert_eq!(2, events.len());
+
+        let mut stream = event_repo
+            .stream_events::<TestAggregate>(&id)
+            .await
+            .unwrap();
+        let mut found_in_stream = 0;
+        while (stream.next::<TestAggregate>(&[]).await).is_some() {
+            found_in_stream += 1;
+        }
+        assert_eq!(found_in_stream, 2);
+        assert!(stream.is_complete());
+    }
+
+    #[tokio::test]
+    async fn stream_all_events_in_commit_order() {
+        let event_repo = RedbEventRepository::new(test_database()).with_streaming_channel_size(2);
+        event_repo
+            .persist::<TestAggregate>(&[tested("b", 1), tested("b", 2)], None)
+            .await
+            .unwrap();
+        event_repo
+            .persist::<TestAggregate>(&[tested("a", 1)], None)
+            .await
+            .unwrap();
+        event_repo
+            .persist::<TestAggregate>(&[tested("b", 3)], None)
+            .await
+            .unwrap();
+
+        let mut stream = event_repo
+            .stream_all_events::<TestAggregate>()
+            .await
+            .unwrap();
+        let mut found = Vec::new();
+        while let Some(event) = stream.next_serialized().await {
+            let event = event.unwrap();
+            found.push((event.aggregate_id, event.sequence));
+        }
+        assert!(stream.is_complete());
+        assert_eq!(
+            vec![
+                ("b".to_string(), 1),
+                ("b".to_string(), 2),
+                ("a".to_string(), 1),
+                ("b".to_string(), 3)
+            ],
+            found
+        );
+    }
+
+    #[tokio::test]
+    async fn filtered_replay_stream() {
+        let id = uuid::Uuid::new_v4().to_string();
+        let event_repo = RedbEventRepository::new(test_database());
+        let started = SystemTime::now() - Duration::from_secs(60);
+        event_repo
+            .persist::<TestAggregate>(
+                &[
+                    test_event_envelope(&id, 1, TestEvent::Created(Created { id: id.clone() })),
+                    tested(&id, 2),
+                    tested(&id, 3),
+                ],
+                None,
+            )
+            .await
+            .unwrap();
+
+        let filter = ReplayFilter::default()
+            .with_aggregate_ids(vec![id.clone()])
+            .with_event_types(vec!["Tested".to_string()])
+            .with_sequences(1..=2);
+        assert_eq!(vec![2], stream_sequences(&event_repo, &filter).await);
+
+        let filter = ReplayFilter::default()
+            .committed_between(started, SystemTime::now() + Duration::from_secs(60));
+        assert_eq!(vec![1, 2, 3], stream_sequences(&event_repo, &filter).await);
+
+        let filter =
+            ReplayFilter::default().committed_between(started - Duration::from_secs(60), started);
+        assert!(stream_sequences(&event_repo, &filter).await.is_empty());
+    }
+
+    async fn stream_sequences(
+        event_repo: &RedbEventRepository,
+        filter: &ReplayFilter,
+    ) -> Vec<usize> {
+        let mut stream = event_repo
+            .stream_filtered_events::<TestAggregate>(filter)
+            .await
+            .unwrap();
+        let mut sequences = Vec::new();
+        while let Some(event) = stream.next_serialized().await {
+            sequences.push(event.unwrap().sequence);
+        }
+        sequences
+    }
+
+    #[tokio::test]
+    async fn subscription_follows_committed_events() {
+        let id = uuid::Uuid::new_v4().to_string();
+        let event_repo = RedbEventRepository::new(test_database());
+        event_repo
+            .persist::<TestAggregate>(&[tested(&id, 1)], None)
+            .await
+            .unwrap();
+        let mut stream = event_repo
+            .subscribe_all_events::<TestAggregate>()
+            .await
+            .unwrap();
+        event_repo
+            .persist::<TestAggregate>(&[tested(&id, 2), tested(&id, 3)], None)
+            .await
+            .unwrap();
+
+        // the committed event is replayed, followed by the live events
+        let mut sequences = Vec::new();
+        while sequences.len() < 3 {
+            let next = tokio::time::timeout(Duration::from_secs(5), stream.next_serialized())
+                .await
+                .expect("timed out waiting for subscribed events")
+                .unwrap()
+                .unwrap();
+            sequences.push(next.sequence);
+        }
+        assert_eq!(vec![1, 2, 3], sequences);
+    }
+
+    #[tokio::test]
+    async fn snapshot_repositories() {
+        let id = uuid::Uuid::new_v4().to_string();
+        let event_repo = RedbEventRepository::new(test_database());
+        let snapshot = event_repo.get_snapshot::<TestAggregate>(&id).await.unwrap();
+        assert_eq!(None, snapshot);
+
+        let snapshot_update = |description: &str, current_snapshot| {
+            Some((
+                id.clone(),
+                json!({ "description": description }),
+                current_snapshot,
+            ))
+        };
+        event_repo
+            .persist::<TestAggregate>(&[tested(&id, 1)], snapshot_update("first", 1))
+            .await
+            .unwrap();
+        let snapshot = event_repo.get_snapshot::<TestAggregate>(&id).await.unwrap();
+        assert_eq!(
+            Some(snapshot_context(
+                id.clone(),
+                1,
+                1,
+                json!({ "description": "first" })
+            )),
+            snapshot
+        );
+
+        // sequence iterated, does update
+        event_repo
+            .persist::<TestAggregate>(&[tested(&id, 2)], snapshot_update("second", 2))
+            .await
+            .unwrap();
+
+        // snapshot out of order or not iterated, does not update and no events are committed
+        let result = event_repo
+            .persist::<TestAggregate>(&[tested(&id, 3)], snapshot_update("stale", 2))
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(result, PersistenceError::OptimisticLockError),
+            "invalid error result found during insert: {result}"
+        );
+        let snapshot = event_repo.get_snapshot::<TestAggregate>(&id).await.unwrap();
+        assert_eq!(
+            Some(snapshot_context(
+                id.clone(),
+                2,
+                2,
+                json!({ "description": "second" })
+            )),
+            snapshot
+        );
+        let events = event_repo.get_events::<TestAggregate>(&id).await.unwrap();
+        assert_eq!(2, events.len());
+    }
+}
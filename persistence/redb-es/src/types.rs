@@ -0,0 +1,7 @@
+use crate::RedbEventRepository;
+use cqrs_es::persist::PersistedEventStore;
+use cqrs_es::CqrsFramework;
+
+/// A convenience type for a CqrsFramework backed by
+/// [RedbEventRepository](struct.RedbEventRepository.html).
+pub type RedbCqrs<A> = CqrsFramework<A, PersistedEventStore<RedbEventRepository, A>>;
@@ -0,0 +1,523 @@
+use std::marker::PhantomData;
+use std::ops::Bound;
+use std::sync::Arc;
+
+use cqrs_es::persist::{
+    ListableViewRepository, PersistenceError, ShadowViewRepository, ViewContext, ViewFilter,
+    ViewListQuery, ViewPage, ViewRepository,
+};
+use cqrs_es::{Aggregate, View};
+use redb::{Database, ReadableDatabase, ReadableTable, Table, TableDefinition};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::database::{blocking, open_table};
+use crate::error::RedbAggregateError;
+
+/// A redb backed query repository for use in backing a `GenericQuery`.
+pub struct RedbViewRepository<V, A> {
+    view_name: Arc<str>,
+    shadow_name: Option<Arc<str>>,
+    database: Arc<Database>,
+    _phantom: PhantomData<(V, A)>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredView {
+    version: i64,
+    last_sequence: usize,
+    payload: Value,
+}
+
+fn view_table(view_name: &str) -> TableDefinition<'_, &'static str, &'static [u8]> {
+    TableDefinition::new(view_name)
+}
+
+impl<V, A> RedbViewRepository<V, A>
+where
+    V: View<A>,
+    A: Aggregate,
+{
+    /// Creates a new `RedbViewRepository` that will store serialized views in a redb table named
+    /// identically to the `view_name` value provided. The table is created as it is first
+    /// written to.
+    ///
+    /// ```
+    /// # use cqrs_es::doc::MyAggregate;
+    /// # use cqrs_es::persist::doc::MyView;
+    /// use std::sync::Arc;
+    /// use redb::Database;
+    /// use redb_es::RedbViewRepository;
+    ///
+    /// fn configure_view_repo(database: Arc<Database>) -> RedbViewRepository<MyView,MyAggregate> {
+    ///     RedbViewRepository::new("my_view_table", database)
+    /// }
+    /// ```
+    pub fn new(view_name: &str, database: Arc<Database>) -> Self {
+        Self {
+            view_name: view_name.into(),
+            shadow_name: None,
+            database,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Configures a shadow table that views will be rebuilt into during a blue/green rebuild.
+    /// When promoted, the view and shadow tables are swapped by renaming them.
+    ///
+    /// ```
+    /// # use cqrs_es::doc::MyAggregate;
+    /// # use cqrs_es::persist::doc::MyView;
+    /// use std::sync::Arc;
+    /// use redb::Database;
+    /// use redb_es::RedbViewRepository;
+    ///
+    /// fn configure_view_repo(database: Arc<Database>) -> RedbViewRepository<MyView,MyAggregate> {
+    ///     RedbViewRepository::new("my_view_table", database)
+    ///         .with_shadow_table("my_view_table_shadow")
+    /// }
+    /// ```
+    pub fn with_shadow_table(self, shadow_name: &str) -> Self {
+        Self {
+            shadow_name: Some(shadow_name.into()),
+            ..self
+        }
+    }
+
+    fn shadow_name(&self) -> Result<Arc<str>, PersistenceError> {
+        self.shadow_name.clone().ok_or_else(|| {
+            PersistenceError::UnknownError("no shadow table configured for this view".into())
+        })
+    }
+
+    // Reads the stored views with the provided ids, those not found are skipped.
+    async fn select_views(
+        &self,
+        view_ids: Vec<String>,
+    ) -> Result<Vec<(String, StoredView)>, PersistenceError> {
+        let view_name = self.view_name.clone();
+        let views = blocking(&self.database, move |database| {
+            let txn = database.begin_read()?;
+            let Some(table) = open_table(&txn, view_table(&view_name))? else {
+                return Ok(Vec::new());
+            };
+            let mut views = Vec::with_capacity(view_ids.len());
+            for view_id in view_ids {
+                if let Some(stored) = table.get(view_id.as_str())? {
+                    let stored: StoredView = serde_json::from_slice(stored.value())?;
+                    views.push((view_id, stored));
+                }
+            }
+            Ok(views)
+        })
+        .await?;
+        Ok(views)
+    }
+
+    // Reads every stored view in view id order, starting after the cursor if one is provided.
+    async fn scan_views(
+        &self,
+        cursor: Option<String>,
+    ) -> Result<Vec<(String, Value)>, PersistenceError> {
+        let view_name = self.view_name.clone();
+        let views = blocking(&self.database, move |database| {
+            let txn = database.begin_read()?;
+            let Some(table) = open_table(&txn, view_table(&view_name))? else {
+                return Ok(Vec::new());
+            };
+            let start = match &cursor {
+                Some(cursor) => Bound::Excluded(cursor.as_str()),
+                None => Bound::Unbounded,
+            };
+            let mut views = Vec::new();
+            for entry in table.range::<&str>((start, Bound::Unbounded))? {
+                let (view_id, stored) = entry?;
+                let stored: StoredView = serde_json::from_slice(stored.value())?;
+                views.push((view_id.value().to_string(), stored.payload));
+            }
+            Ok(views)
+        })
+        .await?;
+        Ok(views)
+    }
+}
+
+// Writes the view if the stored version matches the version it was loaded with, a view that
+// has not been stored is at version 0.
+fn write_view(
+    table: &mut Table<'_, &'static str, &'static [u8]>,
+    payload: Value,
+    context: &ViewContext,
+) -> Result<(), RedbAggregateError> {
+    let view_id = context.view_instance_id.as_str();
+    let stored_version = match table.get(view_id)? {
+        None => 0,
+        Some(stored) => serde_json::from_slice::<StoredView>(stored.value())?.version,
+    };
+    if stored_version != context.version {
+        return Err(RedbAggregateError::OptimisticLock);
+    }
+    let stored = serde_json::to_vec(&StoredView {
+        version: context.version + 1,
+        last_sequence: context.last_sequence,
+        payload,
+    })?;
+    table.insert(view_id, stored.as_slice())?;
+    Ok(())
+}
+
+fn into_view<V: View<A>, A: Aggregate>(
+    view_id: String,
+    stored: StoredView,
+) -> Result<(V, ViewContext), PersistenceError> {
+    let view = serde_json::from_value(stored.payload)?;
+    let view_context =
+        ViewContext::new(view_id, stored.version).with_last_sequence(stored.last_sequence);
+    Ok((view, view_context))
+}
+
+impl<V, A> ViewRepository<V, A> for RedbViewRepository<V, A>
+where
+    V: View<A>,
+    A: Aggregate,
+{
+    async fn load(&self, view_id: &str) -> Result<Option<V>, PersistenceError> {
+        Ok(self.load_with_context(view_id).await?.map(|(view, _)| view))
+    }
+
+    async fn load_with_context(
+        &self,
+        view_id: &str,
+    ) -> Result<Option<(V, ViewContext)>, PersistenceError> {
+        match self.select_views(vec![view_id.to_string()]).await?.pop() {
+            None => Ok(None),
+            Some((view_id, stored)) => Ok(Some(into_view(view_id, stored)?)),
+        }
+    }
+
+    async fn update_view(&self, view: V, context: ViewContext) -> Result<(), PersistenceError> {
+        self.update_all(vec![(view, context)]).await
+    }
+
+    async fn load_all_with_context(
+        &self,
+        view_ids: &[String],
+    ) -> Result<Vec<(V, ViewContext)>, PersistenceError> {
+        self.select_views(view_ids.to_vec())
+            .await?
+            .into_iter()
+            .map(|(view_id, stored)| into_view(view_id, stored))
+            .collect()
+    }
+
+    async fn update_all(&self, views: Vec<(V, ViewContext)>) -> Result<(), PersistenceError> {
+        if views.is_empty() {
+            return Ok(());
+        }
+        let mut updates = Vec::with_capacity(views.len());
+        for (view, context) in views {
+            let payload = serde_json::to_value(&view).map_err(RedbAggregateError::from)?;
+            updates.push((payload, context));
+        }
+        let view_name = self.view_name.clone();
+        // Every view is written within a single transaction, which is dropped without being
+        // committed if any of the views has been modified since it was loaded.
+        blocking(&self.database, move |database| {
+            let txn = database.begin_write()?;
+            {
+                let mut table = txn.open_table(view_table(&view_name))?;
+                for (payload, context) in updates {
+                    write_view(&mut table, payload, &context)?;
+                }
+            }
+            txn.commit()?;
+            Ok(())
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_view(&self, context: ViewContext) -> Result<(), PersistenceError> {
+        let view_name = self.view_name.clone();
+        blocking(&self.database, move |database| {
+            let txn = database.begin_write()?;
+            {
+                let mut table = txn.open_table(view_table(&view_name))?;
+                let view_id = context.view_instance_id.as_str();
+                let stored_version = match table.get(view_id)? {
+                    None => return Err(RedbAggregateError::OptimisticLock),
+                    Some(stored) => serde_json::from_slice::<StoredView>(stored.value())?.version,
+                };
+                if stored_version != context.version {
+                    return Err(RedbAggregateError::OptimisticLock);
+                }
+                table.remove(view_id)?;
+            }
+            txn.commit()?;
+            Ok(())
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn clear_all(&self) -> Result<(), PersistenceError> {
+        let view_name = self.view_name.clone();
+        blocking(&self.database, move |database| {
+            let txn = database.begin_write()?;
+            txn.delete_table(view_table(&view_name))?;
+            txn.commit()?;
+            Ok(())
+        })
+        .await?;
+        Ok(())
+    }
+}
+
+// Views are filtered as they are read, redb has no secondary indexes over the view payloads.
+impl<V, A> ListableViewRepository<V, A> for RedbViewRepository<V, A>
+where
+    V: View<A>,
+    A: Aggregate,
+{
+    async fn list_views(&self, query: &ViewListQuery) -> Result<ViewPage<V>, PersistenceError> {
+        let mut matching = self
+            .scan_views(query.cursor.clone())
+            .await?
+            .into_iter()
+            .filter(|(_, payload)| matches_all(&query.filters, payload));
+        let mut views = Vec::with_capacity(query.page_size());
+        for (view_id, payload) in matching.by_ref().take(query.page_size()) {
+            views.push((view_id, serde_json::from_value(payload)?));
+        }
+        let next_cursor = match matching.next() {
+            Some(_) => views.last().map(|(view_id, _)| view_id.clone()),
+            None => None,
+        };
+        Ok(ViewPage { views, next_cursor })
+    }
+
+    async fn count_views(&self, filters: &[ViewFilter]) -> Result<u64, PersistenceError> {
+        let count = self
+            .scan_views(None)
+            .await?
+            .iter()
+            .filter(|(_, payload)| matches_all(filters, payload))
+            .count();
+        Ok(count as u64)
+    }
+}
+
+fn matches_all(filters: &[ViewFilter], payload: &Value) -> bool {
+    filters.iter().all(|filter| filter.matches(payload))
+}
+
+impl<V, A> ShadowViewRepository<V, A> for RedbViewRepository<V, A>
+where
+    V: View<A>,
+    A: Aggregate,
+{
+    type Shadow = Self;
+
+    async fn prepare_shadow(&self) -> Result<Self::Shadow, PersistenceError> {
+        let shadow = Self::new(&self.shadow_name()?, self.database.clone());
+        shadow.clear_all().await?;
+        Ok(shadow)
+    }
+
+    async fn promote_shadow(&self) -> Result<(), PersistenceError> {
+        let view_name = self.view_name.clone();
+        let shadow_name = self.shadow_name()?;
+        blocking(&self.database, move |database| {
+            let swap_name = format!("{view_name}_swap");
+            let txn = database.begin_write()?;
+            // a table must exist to be renamed
+            txn.open_table(view_table(&view_name))?;
+            txn.open_table(view_table(&shadow_name))?;
+            txn.rename_table(view_table(&view_name), view_table(&swap_name))?;
+            txn.rename_table(view_table(&shadow_name), view_table(&view_name))?;
+            txn.rename_table(view_table(&swap_name), view_table(&shadow_name))?;
+            txn.commit()?;
+            Ok(())
+        })
+        .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::testing::tests::{test_database, Created, TestAggregate, TestEvent, TestView};
+    use crate::RedbViewRepository;
+    use cqrs_es::persist::{
+        ListableViewRepository, PersistenceError, ShadowViewRepository, ViewContext, ViewFilter,
+        ViewListQuery, ViewRepository,
+    };
+
+    #[tokio::test]
+    async fn test_valid_view_repository() {
+        let repo = RedbViewRepository::<TestView, TestAggregate>::new("test_view", test_database());
+        let test_view_id = uuid::Uuid::new_v4().to_string();
+
+        let view = TestView {
+            events: vec![TestEvent::Created(Created {
+                id: "just a test event for this view".to_string(),
+            })],
+        };
+        let context = ViewContext::new(test_view_id.to_string(), 0).with_last_sequence(3);
+        repo.update_view(view.clone(), context).await.unwrap();
+        let (found, context) = repo
+            .load_with_context(&test_view_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(found, view);
+        assert_eq!(3, context.last_sequence);
+        let found = repo.load(&test_view_id).await.unwrap().unwrap();
+        assert_eq!(found, view);
+
+        let updated_view = TestView {
+            events: vec![TestEvent::Created(Created {
+                id: "a totally different view".to_string(),
+            })],
+        };
+        repo.update_view(updated_view.clone(), context)
+            .await
+            .unwrap();
+        let found_option = repo.load(&test_view_id).await.unwrap();
+        let found = found_option.unwrap();
+        assert_eq!(found, updated_view);
+
+        // the view has been updated since this version was loaded
+        let stale_context = ViewContext::new(test_view_id.to_string(), 1);
+        let result = repo.update_view(updated_view, stale_context).await;
+        assert!(matches!(result, Err(PersistenceError::OptimisticLockError)));
+    }
+
+    #[tokio::test]
+    async fn test_shadow_view_repository() {
+        let repo = RedbViewRepository::<TestView, TestAggregate>::new("test_view", test_database())
+            .with_shadow_table("test_view_shadow");
+        let test_view_id = uuid::Uuid::new_v4().to_string();
+
+        let view = TestView {
+            events: vec![TestEvent::Created(Created {
+                id: "a rebuilt view".to_string(),
+            })],
+        };
+        let shadow = repo.prepare_shadow().await.unwrap();
+        shadow
+            .update_view(view.clone(), ViewContext::new(test_view_id.to_string(), 0))
+            .await
+            .unwrap();
+        assert_eq!(None, repo.load(&test_view_id).await.unwrap());
+
+        repo.promote_shadow().await.unwrap();
+        let found = repo.load(&test_view_id).await.unwrap().unwrap();
+        assert_eq!(found, view);
+    }
+
+    #[tokio::test]
+    async fn test_list_views() {
+        let repo = RedbViewRepository::<TestView, TestAggregate>::new("test_view", test_database());
+        let marker = uuid::Uuid::new_v4().to_string();
+        for _ in 0..3 {
+            let view = TestView {
+                events: vec![TestEvent::Created(Created { id: marker.clone() })],
+            };
+            let view_id = uuid::Uuid::new_v4().to_string();
+            repo.update_view(view, ViewContext::new(view_id, 0))
+                .await
+                .unwrap();
+        }
+        let other = TestView {
+            events: vec![TestEvent::Created(Created {
+                id: "not matching".to_string(),
+            })],
+        };
+        repo.update_view(other, ViewContext::new("other".to_string(), 0))
+            .await
+            .unwrap();
+        let filter = ViewFilter::equals("events.0.Created.id", marker.as_str());
+
+        let count = repo
+            .count_views(std::slice::from_ref(&filter))
+            .await
+            .unwrap();
+        assert_eq!(3, count);
+
+        let query = ViewListQuery::new(2).with_filter(filter);
+        let first_page = repo.list_views(&query).await.unwrap();
+        assert_eq!(2, first_page.views.len());
+        assert!(first_page.next_cursor.is_some());
+
+        let query = query.after(first_page.next_cursor);
+        let second_page = repo.list_views(&query).await.unwrap();
+        assert_eq!(1, second_page.views.len());
+        assert_eq!(None, second_page.next_cursor);
+        assert!(first_page.views[1].0 < second_page.views[0].0);
+    }
+
+    #[tokio::test]
+    async fn test_delete_view() {
+        let repo = RedbViewRepository::<TestView, TestAggregate>::new("test_view", test_database());
+        let test_view_id = uuid::Uuid::new_v4().to_string();
+
+        let view = TestView {
+            events: vec![TestEvent::Created(Created {
+                id: "a view to delete".to_string(),
+            })],
+        };
+        repo.update_view(view, ViewContext::new(test_view_id.to_string(), 0))
+            .await
+            .unwrap();
+        let stale_context = ViewContext::new(test_view_id.to_string(), 0);
+        let result = repo.delete_view(stale_context).await;
+        assert!(matches!(result, Err(PersistenceError::OptimisticLockError)));
+
+        let (_, context) = repo
+            .load_with_context(&test_view_id)
+            .await
+            .unwrap()
+            .unwrap();
+        repo.delete_view(context).await.unwrap();
+        assert_eq!(None, repo.load(&test_view_id).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_update_all() {
+        let repo = RedbViewRepository::<TestView, TestAggregate>::new("test_view", test_database());
+        let existing_id = uuid::Uuid::new_v4().to_string();
+        let new_id = uuid::Uuid::new_v4().to_string();
+        let view = |id: &str| TestView {
+            events: vec![TestEvent::Created(Created { id: id.to_string() })],
+        };
+        repo.update_view(view("existing"), ViewContext::new(existing_id.clone(), 0))
+            .await
+            .unwrap();
+
+        let view_ids = vec![existing_id.clone(), new_id.clone()];
+        let loaded = repo.load_all_with_context(&view_ids).await.unwrap();
+        assert_eq!(1, loaded.len());
+        let (_, existing_context) = loaded.into_iter().next().unwrap();
+        repo.update_all(vec![
+            (view("updated"), existing_context.with_last_sequence(2)),
+            (view("new"), ViewContext::new(new_id.clone(), 0)),
+        ])
+        .await
+        .unwrap();
+        let (found, context) = repo.load_with_context(&existing_id).await.unwrap().unwrap();
+        assert_eq!(view("updated"), found);
+        assert_eq!((2, 2), (context.version, context.last_sequence));
+        assert_eq!(Some(view("new")), repo.load(&new_id).await.unwrap());
+
+        // a stale context fails the entire update
+        let result = repo
+            .update_all(vec![
+                (view("stale"), ViewContext::new(existing_id.clone(), 1)),
+                (view("newer"), ViewContext::new(new_id.clone(), 1)),
+            ])
+            .await;
+        assert!(matches!(result, Err(PersistenceError::OptimisticLockError)));
+        assert_eq!(Some(view("new")), repo.load(&new_id).await.unwrap());
+    }
+}
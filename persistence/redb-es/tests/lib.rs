@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use cqrs_es::doc::{Customer, CustomerEvent};
+use cqrs_es::persist::{
+    PersistedEventRepository, PersistedEventStore, SemanticVersionEventUpcaster, SerializedEvent,
+};
+use cqrs_es::test::{EventRepositoryConformance, ViewRepositoryConformance};
+use cqrs_es::EventStore;
+use redb::backends::InMemoryBackend;
+use redb::Database;
+use redb_es::{RedbEventRepository, RedbViewRepository};
+use serde_json::{json, Value};
+
+// Each test uses its own in-memory database.
+fn test_database() -> Arc<Database> {
+    let database = Database::builder()
+        .create_with_backend(InMemoryBackend::new())
+        .unwrap();
+    Arc::new(database)
+}
+
+fn new_test_event_store(
+    database: Arc<Database>,
+) -> PersistedEventStore<RedbEventRepository, Customer> {
+    let repo = RedbEventRepository::new(database);
+    PersistedEventStore::<RedbEventRepository, Customer>::new_event_store(repo)
+}
+
+#[tokio::test]
+async fn commit_and_load_events() {
+    let repo = RedbEventRepository::new(test_database());
+    let event_store = PersistedEventStore::<RedbEventRepository, Customer>::new_event_store(repo);
+
+    simple_es_commit_and_load_test(event_store).await;
+}
+
+#[tokio::test]
+async fn commit_and_load_events_snapshot_store() {
+    let repo = RedbEventRepository::new(test_database());
+    let event_store =
+        PersistedEventStore::<RedbEventRepository, Customer>::new_aggregate_store(repo);
+
+    simple_es_commit_and_load_test(event_store).await;
+}
+
+async fn simple_es_commit_and_load_test(
+    event_store: PersistedEventStore<RedbEventRepository, Customer>,
+) {
+    let id = uuid::Uuid::new_v4().to_string();
+    assert_eq!(0, event_store.load_events(id.as_str()).await.unwrap().len());
+    let context = event_store.load_aggregate(id.as_str()).await.unwrap();
+
+    event_store
+        .commit(
+            vec![
+                CustomerEvent::NameAdded {
+                    name: "test_event_A".to_string(),
+                },
+                CustomerEvent::EmailUpdated {
+                    new_email: "email A".to_string(),
+                },
+            ],
+            context,
+            HashMap::default(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(2, event_store.load_events(id.as_str()).await.unwrap().len());
+    let context = event_store.load_aggregate(id.as_str()).await.unwrap();
+
+    event_store
+        .commit(
+            vec![CustomerEvent::EmailUpdated {
+                new_email: "email B".to_string(),
+            }],
+            context,
+            HashMap::default(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(3, event_store.load_events(id.as_str()).await.unwrap().len());
+}
+
+#[tokio::test]
+async fn upcasted_event() {
+    let database = test_database();
+    let id = "previous_event_in_need_of_upcast".to_string();
+    // an event committed under an earlier version of the payload
+    RedbEventRepository::new(database.clone())
+        .persist::<Customer>(
+            &[SerializedEvent::new(
+                id.clone(),
+                1,
+                "Customer".to_string(),
+                "NameAdded".to_string(),
+                "1.0".to_string(),
+                json!({"NameAdded": {}}),
+                json!({}),
+            )],
+            None,
+        )
+        .await
+        .unwrap();
+    let upcaster = SemanticVersionEventUpcaster::new(
+        "NameAdded",
+        "1.0.1",
+        Box::new(|mut event| match event.get_mut("NameAdded").unwrap() {
+            Value::Object(object) => {
+                object.insert("name".to_string(), Value::String("UNKNOWN".to_string()));
+                event
+            }
+            _ => panic!("not the expected object"),
+        }),
+    );
+    let event_store = new_test_event_store(database).with_upcasters(vec![Box::new(upcaster)]);
+
+    let result = event_store.load_aggregate(id.as_str()).await.unwrap();
+    assert_eq!(1, result.current_sequence);
+    assert_eq!(None, result.current_snapshot);
+}
+
+#[tokio::test]
+async fn event_repository_conformance() {
+    let database = test_database();
+    EventRepositoryConformance::with(move || RedbEventRepository::new(database.clone()))
+        .verify()
+        .await;
+}
+
+#[tokio::test]
+async fn view_repository_conformance() {
+    let database = test_database();
+    ViewRepositoryConformance::with(move || RedbViewRepository::new("test_view", database.clone()))
+        .verify()
+        .await;
+}
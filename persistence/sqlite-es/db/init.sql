@@ -0,0 +1,51 @@
+-- a single table is used for all events in the cqrs system
+-- subscriptions follow the table's rowid, so this must not be a WITHOUT ROWID table
+CREATE TABLE events
+(
+    aggregate_type text                         NOT NULL,
+    aggregate_id   text                         NOT NULL,
+    sequence       bigint CHECK (sequence >= 0) NOT NULL,
+    event_type     text                         NOT NULL,
+    event_version  text                         NOT NULL,
+    payload        text                         NOT NULL,
+    metadata       text                         NOT NULL,
+    -- seconds since the unix epoch
+    committed_at   real                         NOT NULL DEFAULT ((julianday('now') - 2440587.5) * 86400.0),
+    PRIMARY KEY (aggregate_type, aggregate_id, sequence)
+);
+
+-- this table is only needed if snapshotting is employed
+CREATE TABLE snapshots
+(
+    aggregate_type   text                                 NOT NULL,
+    aggregate_id     text                                 NOT NULL,
+    last_sequence    bigint CHECK (last_sequence >= 0)    NOT NULL,
+    current_snapshot bigint CHECK (current_snapshot >= 0) NOT NULL,
+    payload          text                                 NOT NULL,
+    PRIMARY KEY (aggregate_type, aggregate_id)
+);
+
+-- one view table should be created for every `SqliteViewRepository` used
+-- replace name with the value used in `SqliteViewRepository::new(view_name: String)`
+CREATE TABLE test_view
+(
+    view_id       text                              NOT NULL,
+    version       bigint CHECK (version >= 0)       NOT NULL,
+    last_sequence bigint CHECK (last_sequence >= 0) NOT NULL DEFAULT 0,
+    payload       text                              NOT NULL,
+    PRIMARY KEY (view_id)
+);
+
+-- a shadow table with an identical structure is needed for blue/green view rebuilds
+-- replace name with the value used in `SqliteViewRepository::with_shadow_table(shadow_name)`
+CREATE TABLE test_view_shadow
+(
+    view_id       text                              NOT NULL,
+    version       bigint CHECK (version >= 0)       NOT NULL,
+    last_sequence bigint CHECK (last_sequence >= 0) NOT NULL DEFAULT 0,
+    payload       text                              NOT NULL,
+    PRIMARY KEY (view_id)
+);
+
+INSERT INTO events (aggregate_type, aggregate_id, sequence, event_type, event_version, payload, metadata)
+VALUES ('Customer', 'previous_event_in_need_of_upcast', 1, 'NameAdded', '1.0', '{"NameAdded": {}}', '{}');
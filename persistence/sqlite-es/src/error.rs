@@ -0,0 +1,63 @@
+use cqrs_es::persist::PersistenceError;
+use cqrs_es::AggregateError;
+use sqlx::Error;
+
+#[derive(Debug, thiserror::Error)]
+pub enum SqliteAggregateError {
+    #[error("optimistic lock error")]
+    OptimisticLock,
+    #[error(transparent)]
+    ConnectionError(Box<dyn std::error::Error + Send + Sync + 'static>),
+    #[error(transparent)]
+    DeserializationError(Box<dyn std::error::Error + Send + Sync + 'static>),
+    #[error(transparent)]
+    UnknownError(Box<dyn std::error::Error + Send + Sync + 'static>),
+}
+
+impl From<sqlx::Error> for SqliteAggregateError {
+    fn from(err: sqlx::Error) -> Self {
+        match &err {
+            // a primary key violation indicates that the event sequence was already committed
+            Error::Database(database_error) if database_error.is_unique_violation() => {
+                Self::OptimisticLock
+            }
+            Error::Io(_) => Self::ConnectionError(Box::new(err)),
+            _ => Self::UnknownError(Box::new(err)),
+        }
+    }
+}
+
+impl<T: std::error::Error> From<SqliteAggregateError> for AggregateError<T> {
+    fn from(err: SqliteAggregateError) -> Self {
+        match err {
+            SqliteAggregateError::OptimisticLock => Self::AggregateConflict,
+            SqliteAggregateError::ConnectionError(error) => Self::DatabaseConnectionError(error),
+            SqliteAggregateError::DeserializationError(error) => Self::DeserializationError(error),
+            SqliteAggregateError::UnknownError(error) => Self::UnexpectedError(error),
+        }
+    }
+}
+
+impl From<serde_json::Error> for SqliteAggregateError {
+    fn from(err: serde_json::Error) -> Self {
+        match err.classify() {
+            serde_json::error::Category::Data | serde_json::error::Category::Syntax => {
+                Self::DeserializationError(Box::new(err))
+            }
+            serde_json::error::Category::Io | serde_json::error::Category::Eof => {
+                Self::UnknownError(Box::new(err))
+            }
+        }
+    }
+}
+
+impl From<SqliteAggregateError> for PersistenceError {
+    fn from(err: SqliteAggregateError) -> Self {
+        match err {
+            SqliteAggregateError::OptimisticLock => Self::OptimisticLockError,
+            SqliteAggregateError::ConnectionError(error) => Self::ConnectionError(error),
+            SqliteAggregateError::DeserializationError(error) => Self::UnknownError(error),
+            SqliteAggregateError::UnknownError(error) => Self::UnknownError(error),
+        }
+    }
+}
@@ -0,0 +1,672 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use cqrs_es::persist::{
+    PersistedEventRepository, PersistenceError, ReplayFeed, ReplayFilter, ReplayStream,
+    SerializedEvent, SerializedSnapshot,
+};
+use cqrs_es::Aggregate;
+use futures::stream::BoxStream;
+use futures::TryStreamExt;
+use serde_json::Value;
+use sqlx::query::Query;
+use sqlx::sqlite::{SqliteArguments, SqliteRow};
+use sqlx::{Pool, Row, SqlSafeStr, SqlStr, Sqlite, Transaction};
+
+use crate::error::SqliteAggregateError;
+use crate::sql_query::SqlQueryFactory;
+
+const DEFAULT_EVENT_TABLE: &str = "events";
+const DEFAULT_SNAPSHOT_TABLE: &str = "snapshots";
+
+const DEFAULT_STREAMING_CHANNEL_SIZE: usize = 200;
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// An event repository relying on a SQLite database for persistence.
+pub struct SqliteEventRepository {
+    pool: Pool<Sqlite>,
+    query_factory: SqlQueryFactory,
+    stream_channel_size: usize,
+    poll_interval: Duration,
+}
+
+impl PersistedEventRepository for SqliteEventRepository {
+    async fn get_events<A: Aggregate>(
+        &self,
+        aggregate_id: &str,
+    ) -> Result<Vec<SerializedEvent>, PersistenceError> {
+        self.select_events::<A>(aggregate_id, self.query_factory.select_events())
+            .await
+    }
+
+    async fn get_last_events<A: Aggregate>(
+        &self,
+        aggregate_id: &str,
+        last_sequence: usize,
+    ) -> Result<Vec<SerializedEvent>, PersistenceError> {
+        let query = self.query_factory.get_last_events(last_sequence);
+        self.select_events::<A>(aggregate_id, query).await
+    }
+
+    async fn get_snapshot<A: Aggregate>(
+        &self,
+        aggregate_id: &str,
+    ) -> Result<Option<SerializedSnapshot>, PersistenceError> {
+        let Some(row) = sqlx::query(self.query_factory.select_snapshot())
+            .bind(A::TYPE)
+            .bind(aggregate_id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(SqliteAggregateError::from)?
+        else {
+            return Ok(None);
+        };
+        Ok(Some(self.deser_snapshot(&row)))
+    }
+
+    async fn persist<A: Aggregate>(
+        &self,
+        events: &[SerializedEvent],
+        snapshot_update: Option<(String, Value, usize)>,
+    ) -> Result<(), PersistenceError> {
+        match snapshot_update {
+            None => {
+                self.insert_events::<A>(events).await?;
+            }
+            Some((aggregate_id, aggregate, current_snapshot)) => {
+                if current_snapshot == 1 {
+                    self.insert::<A>(aggregate, aggregate_id, current_snapshot, events)
+                        .await?;
+                } else {
+                    self.update::<A>(aggregate, aggregate_id, current_snapshot, events)
+                        .await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn stream_events<A: Aggregate>(
+        &self,
+        aggregate_id: &str,
+    ) -> Result<ReplayStream, PersistenceError> {
+        Ok(stream_events(
+            self.query_factory.select_events(),
+            A::TYPE.to_string(),
+            aggregate_id.to_string(),
+            self.pool.clone(),
+            self.stream_channel_size,
+        ))
+    }
+
+    async fn stream_all_events<A: Aggregate>(&self) -> Result<ReplayStream, PersistenceError> {
+        Ok(stream_all_events(
+            self.query_factory.all_events(),
+            A::TYPE.to_string(),
+            self.pool.clone(),
+            self.stream_channel_size,
+        ))
+    }
+
+    async fn stream_filtered_events<A: Aggregate>(
+        &self,
+        filter: &ReplayFilter,
+    ) -> Result<ReplayStream, PersistenceError> {
+        let query = self.query_factory.filtered_events(filter);
+        let filter = filter.clone();
+        let pool = self.pool.clone();
+        let (feed, stream) = ReplayStream::new(self.stream_channel_size);
+        tokio::spawn(async move {
+            let query = bind_filter(sqlx::query(query).bind(A::TYPE), &filter);
+            let rows = query.fetch(&pool);
+            process_rows(feed, rows).await;
+        });
+        Ok(stream)
+    }
+
+    // Events are polled in the order of their rowid. SQLite allows a single writer at a time,
+    // so rowids become visible in the order that they are assigned and no event is skipped.
+    async fn subscribe_all_events<A: Aggregate>(&self) -> Result<ReplayStream, PersistenceError> {
+        let query = self.query_factory.events_after_position();
+        let pool = self.pool.clone();
+        let poll_interval = self.poll_interval;
+        let page_size = self.stream_channel_size.max(1);
+        let (mut feed, stream) = ReplayStream::new(self.stream_channel_size);
+        tokio::spawn(async move {
+            let mut position: i64 = 0;
+            loop {
+                let rows = sqlx::query(query.clone())
+                    .bind(A::TYPE)
+                    .bind(position)
+                    .bind(page_size as i64)
+                    .fetch_all(&pool)
+                    .await;
+                let rows = match rows {
+                    Ok(rows) => rows,
+                    Err(err) => {
+                        let _ = feed.push(Err(SqliteAggregateError::from(err).into())).await;
+                        return;
+                    }
+                };
+                let page_full = rows.len() >= page_size;
+                for row in rows {
+                    let event = row
+                        .try_get("global_position")
+                        .map_err(SqliteAggregateError::from)
+                        .and_then(|global_position| {
+                            position = global_position;
+                            SqliteEventRepository::deser_event(row)
+                        });
+                    let failed = event.is_err();
+                    if feed.push(event.map_err(Into::into)).await.is_err() || failed {
+                        return;
+                    }
+                }
+                // a full page indicates the subscriber is still catching up
+                if page_full {
+                    continue;
+                }
+                tokio::select! {
+                    () = feed.closed() => return,
+                    () = tokio::time::sleep(poll_interval) => {}
+                }
+            }
+        });
+        Ok(stream)
+    }
+}
+
+// Binds the filter values in the order used by `SqlQueryFactory::filtered_events`.
+fn bind_filter<'q>(
+    mut query: Query<'q, Sqlite, SqliteArguments>,
+    filter: &ReplayFilter,
+) -> Query<'q, Sqlite, SqliteArguments> {
+    for aggregate_id in &filter.aggregate_ids {
+        query = query.bind(aggregate_id.clone());
+    }
+    for event_type in &filter.event_types {
+        query = query.bind(event_type.clone());
+    }
+    if let Some(min_sequence) = filter.min_sequence {
+        query = query.bind(min_sequence as i64);
+    }
+    if let Some(max_sequence) = filter.max_sequence {
+        query = query.bind(max_sequence as i64);
+    }
+    if let Some(committed_from) = filter.committed_from {
+        query = query.bind(epoch_seconds(committed_from));
+    }
+    if let Some(committed_to) = filter.committed_to {
+        query = query.bind(epoch_seconds(committed_to));
+    }
+    query
+}
+
+fn epoch_seconds(time: SystemTime) -> f64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs_f64())
+        .unwrap_or_default()
+}
+
+fn stream_events(
+    query: SqlStr,
+    aggregate_type: String,
+    aggregate_id: String,
+    pool: Pool<Sqlite>,
+    channel_size: usize,
+) -> ReplayStream {
+    let (feed, stream) = ReplayStream::new(channel_size);
+    tokio::spawn(async move {
+        let query = sqlx::query(query).bind(&aggregate_type).bind(&aggregate_id);
+        let rows = query.fetch(&pool);
+        process_rows(feed, rows).await;
+    });
+    stream
+}
+fn stream_all_events(
+    query: SqlStr,
+    aggregate_type: String,
+    pool: Pool<Sqlite>,
+    channel_size: usize,
+) -> ReplayStream {
+    let (feed, stream) = ReplayStream::new(channel_size);
+    tokio::spawn(async move {
+        let query = sqlx::query(query).bind(&aggregate_type);
+        let rows = query.fetch(&pool);
+        process_rows(feed, rows).await;
+    });
+    stream
+}
+
+// Pushes each row onto the feed, any failure is pushed onto the feed and ends the stream.
+async fn process_rows(
+    mut feed: ReplayFeed,
+    mut rows: BoxStream<'_, Result<SqliteRow, sqlx::Error>>,
+) {
+    loop {
+        let event = match rows.try_next().await {
+            Ok(Some(row)) => SqliteEventRepository::deser_event(row),
+            Ok(None) => return feed.finish(),
+            Err(err) => Err(err.into()),
+        };
+        let failed = event.is_err();
+        // the stream has been dropped if the push fails, so there is no one to report to
+        if feed.push(event.map_err(Into::into)).await.is_err() || failed {
+            return;
+        }
+    }
+}
+
+impl SqliteEventRepository {
+    async fn select_events<A: Aggregate>(
+        &self,
+        aggregate_id: &str,
+        query: SqlStr,
+    ) -> Result<Vec<SerializedEvent>, PersistenceError> {
+        let mut rows = sqlx::query(query)
+            .bind(A::TYPE)
+            .bind(aggregate_id)
+            .fetch(&self.pool);
+        let mut result: Vec<SerializedEvent> = Default::default();
+        while let Some(row) = rows.try_next().await.map_err(SqliteAggregateError::from)? {
+            result.push(Self::deser_event(row)?);
+        }
+        Ok(result)
+    }
+}
+
+impl SqliteEventRepository {
+    /// Creates a new `SqliteEventRepository` from the provided database connection.
+    /// This uses the default tables 'events' and 'snapshots'.
+    ///
+    /// ```
+    /// use sqlx::{Pool, Sqlite};
+    /// use sqlite_es::SqliteEventRepository;
+    ///
+    /// fn configure_repo(pool: Pool<Sqlite>) -> SqliteEventRepository {
+    ///     SqliteEventRepository::new(pool)
+    /// }
+    /// ```
+    pub fn new(pool: Pool<Sqlite>) -> Self {
+        Self::use_tables(pool, DEFAULT_EVENT_TABLE, DEFAULT_SNAPSHOT_TABLE)
+    }
+
+    /// Configures a `SqliteEventRepository` to use a streaming queue of the provided size.
+    ///
+    /// _Example: configure the repository to stream with a 1000 event buffer._
+    /// ```
+    /// use sqlx::{Pool, Sqlite};
+    /// use sqlite_es::SqliteEventRepository;
+    ///
+    /// fn configure_repo(pool: Pool<Sqlite>) -> SqliteEventRepository {
+    ///     let store = SqliteEventRepository::new(pool);
+    ///     store.with_streaming_channel_size(1000)
+    /// }
+    /// ```
+    pub fn with_streaming_channel_size(self, stream_channel_size: usize) -> Self {
+        Self {
+            stream_channel_size,
+            ..self
+        }
+    }
+
+    /// Configures how often a subscription polls for newly committed events, the default is
+    /// every 250 milliseconds.
+    ///
+    /// _Example: poll for new events every 50 milliseconds._
+    /// ```
+    /// use std::time::Duration;
+    /// use sqlx::{Pool, Sqlite};
+    /// use sqlite_es::SqliteEventRepository;
+    ///
+    /// fn configure_repo(pool: Pool<Sqlite>) -> SqliteEventRepository {
+    ///     let store = SqliteEventRepository::new(pool);
+    ///     store.with_subscription_poll_interval(Duration::from_millis(50))
+    /// }
+    /// ```
+    pub fn with_subscription_poll_interval(self, poll_interval: Duration) -> Self {
+        Self {
+            poll_interval,
+            ..self
+        }
+    }
+
+    /// Configures a `SqliteEventRepository` to use the provided table names.
+    ///
+    /// _Example: configure the repository to use "my_event_table" and "my_snapshot_table"
+    /// for the event and snapshot table names._
+    /// ```
+    /// use sqlx::{Pool, Sqlite};
+    /// use sqlite_es::SqliteEventRepository;
+    ///
+    /// fn configure_repo(pool: Pool<Sqlite>) -> SqliteEventRepository {
+    ///     let store = SqliteEventRepository::new(pool);
+    ///     store.with_tables("my_event_table", "my_snapshot_table")
+    /// }
+    /// ```
+    pub fn with_tables(
+        self,
+        events_table: impl SqlSafeStr,
+        snapshots_table: impl SqlSafeStr,
+    ) -> Self {
+        Self::use_tables(self.pool, events_table, snapshots_table)
+    }
+
+    fn use_tables(
+        pool: Pool<Sqlite>,
+        events_table: impl SqlSafeStr,
+        snapshots_table: impl SqlSafeStr,
+    ) -> Self {
+        Self {
+            pool,
+            query_factory: SqlQueryFactory::new(events_table, snapshots_table),
+            stream_channel_size: DEFAULT_STREAMING_CHANNEL_SIZE,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+        }
+    }
+
+    pub(crate) async fn insert_events<A: Aggregate>(
+        &self,
+        events: &[SerializedEvent],
+    ) -> Result<(), SqliteAggregateError> {
+        let mut tx: Transaction<'_, Sqlite> = sqlx::Acquire::begin(&self.pool).await?;
+        self.persist_events::<A>(&mut tx, events).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    pub(crate) async fn insert<A: Aggregate>(
+        &self,
+        aggregate_payload: Value,
+        aggregate_id: String,
+        current_snapshot: usize,
+        events: &[SerializedEvent],
+    ) -> Result<(), SqliteAggregateError> {
+        let mut tx: Transaction<'_, Sqlite> = sqlx::Acquire::begin(&self.pool).await?;
+        let current_sequence = self.persist_events::<A>(&mut tx, events).await?;
+        sqlx::query(self.query_factory.insert_snapshot())
+            .bind(A::TYPE)
+            .bind(aggregate_id.as_str())
+            .bind(current_sequence as i64)
+            .bind(current_snapshot as i64)
+            .bind(&aggregate_payload)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    pub(crate) async fn update<A: Aggregate>(
+        &self,
+        aggregate: Value,
+        aggregate_id: String,
+        current_snapshot: usize,
+        events: &[SerializedEvent],
+    ) -> Result<(), SqliteAggregateError> {
+        let mut tx: Transaction<'_, Sqlite> = sqlx::Acquire::begin(&self.pool).await?;
+        let current_sequence = self.persist_events::<A>(&mut tx, events).await?;
+
+        let aggregate_payload = serde_json::to_value(&aggregate)?;
+        let result = sqlx::query(self.query_factory.update_snapshot())
+            .bind(current_sequence as i64)
+            .bind(&aggregate_payload)
+            .bind(current_snapshot as i64)
+            .bind(A::TYPE)
+            .bind(aggregate_id.as_str())
+            .bind((current_snapshot - 1) as i64)
+            .execute(&mut *tx)
+            .await?;
+        // dropping the transaction rolls back the events on a conflict
+        if result.rows_affected() != 1 {
+            return Err(SqliteAggregateError::OptimisticLock);
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    fn deser_event(row: SqliteRow) -> Result<SerializedEvent, SqliteAggregateError> {
+        let aggregate_type: String = row.try_get("aggregate_type")?;
+        let aggregate_id: String = row.try_get("aggregate_id")?;
+        let sequence = {
+            let s: i64 = row.try_get("sequence")?;
+            s as usize
+        };
+        let event_type: String = row.try_get("event_type")?;
+        let event_version: String = row.try_get("event_version")?;
+        let payload: Value = row.try_get("payload")?;
+        let metadata: Value = row.try_get("metadata")?;
+        Ok(SerializedEvent::new(
+            aggregate_id,
+            sequence,
+            aggregate_type,
+            event_type,
+            event_version,
+            payload,
+            metadata,
+        ))
+    }
+
+    fn deser_snapshot(&self, row: &SqliteRow) -> SerializedSnapshot {
+        let aggregate_id = row.get("aggregate_id");
+        let s: i64 = row.get("last_sequence");
+        let current_sequence = s as usize;
+        let s: i64 = row.get("current_snapshot");
+        let current_snapshot = s as usize;
+        let aggregate: Value = row.get("payload");
+        SerializedSnapshot {
+            aggregate_id,
+            aggregate,
+            current_sequence,
+            current_snapshot,
+        }
+    }
+
+    pub(crate) async fn persist_events<A: Aggregate>(
+        &self,
+        tx: &mut Transaction<'_, Sqlite>,
+        events: &[SerializedEvent],
+    ) -> Result<usize, SqliteAggregateError> {
+        let mut current_sequence: usize = 0;
+        for event in events {
+            current_sequence = event.sequence;
+            let event_type = &event.event_type;
+            let event_version = &event.event_version;
+            let payload = serde_json::to_value(&event.payload)?;
+            let metadata = serde_json::to_value(&event.metadata)?;
+            sqlx::query(self.query_factory.insert_event())
+                .bind(A::TYPE)
+                .bind(event.aggregate_id.as_str())
+                .bind(event.sequence as i64)
+                .bind(event_type)
+                .bind(event_version)
+                .bind(&payload)
+                .bind(&metadata)
+                .execute(&mut **tx)
+                .await?;
+        }
+        Ok(current_sequence)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, SystemTime};
+
+    use cqrs_es::persist::{PersistedEventRepository, ReplayFilter, SerializedEvent};
+    use serde_json::json;
+
+    use crate::error::SqliteAggregateError;
+    use crate::testing::tests::{
+        test_event_envelope, test_pool, Created, SomethingElse, TestAggregate, TestEvent, Tested,
+    };
+    use crate::SqliteEventRepository;
+
+    #[tokio::test]
+    async fn replay_stream_reports_errors() {
+        let pool = test_pool().await;
+        let event_repo =
+            SqliteEventRepository::new(pool).with_tables("missing_events", "missing_snapshots");
+        let mut stream = event_repo
+            .stream_all_events::<TestAggregate>()
+            .await
+            .unwrap();
+        assert!(stream.next_serialized().await.unwrap().is_err());
+        assert!(stream.next_serialized().await.is_none());
+        assert!(!stream.is_complete());
+    }
+
+    #[tokio::test]
+    async fn filtered_replay_stream() {
+        let pool = test_pool().await;
+        let id = uuid::Uuid::new_v4().to_string();
+        let event_repo = SqliteEventRepository::new(pool.clone());
+        let started = SystemTime::now() - Duration::from_secs(60);
+        // streamed events are deserialized, which requires metadata to be present
+        let event = |sequence, event| SerializedEvent {
+            metadata: json!({}),
+            ..test_event_envelope(&id, sequence, event)
+        };
+        event_repo
+            .insert_events::<TestAggregate>(&[
+                event(1, TestEvent::Created(Created { id: id.clone() })),
+                event(
+                    2,
+                    TestEvent::Tested(Tested {
+                        test_name: "first test".to_string(),
+                    }),
+                ),
+                event(
+                    3,
+                    TestEvent::Tested(Tested {
+                        test_name: "second test".to_string(),
+                    }),
+                ),
+            ])
+            .await
+            .unwrap();
+
+        let filter = ReplayFilter::default()
+            .with_aggregate_ids(vec![id.clone()])
+            .with_event_types(vec!["Tested".to_string()])
+            .with_sequences(1..=2);
+        let sequences = stream_sequences(&event_repo, &filter).await;
+        assert_eq!(vec![2], sequences);
+
+        let filter = ReplayFilter::default()
+            .with_aggregate_ids(vec![id.clone()])
+            .committed_between(started, SystemTime::now() + Duration::from_secs(60));
+        let sequences = stream_sequences(&event_repo, &filter).await;
+        assert_eq!(vec![1, 2, 3], sequences);
+
+        let filter = ReplayFilter::default()
+            .with_aggregate_ids(vec![id.clone()])
+            .committed_between(started - Duration::from_secs(60), started);
+        let sequences = stream_sequences(&event_repo, &filter).await;
+        assert!(sequences.is_empty());
+    }
+
+    #[tokio::test]
+    async fn subscription_follows_committed_events() {
+        let pool = test_pool().await;
+        let id = uuid::Uuid::new_v4().to_string();
+        let event_repo = SqliteEventRepository::new(pool.clone())
+            .with_subscription_poll_interval(Duration::from_millis(50));
+        let event = |sequence| SerializedEvent {
+            metadata: json!({}),
+            ..test_event_envelope(
+                &id,
+                sequence,
+                TestEvent::Tested(Tested {
+                    test_name: format!("test {sequence}"),
+                }),
+            )
+        };
+        event_repo
+            .insert_events::<TestAggregate>(&[event(1)])
+            .await
+            .unwrap();
+        let mut stream = event_repo
+            .subscribe_all_events::<TestAggregate>()
+            .await
+            .unwrap();
+        event_repo
+            .insert_events::<TestAggregate>(&[event(2), event(3)])
+            .await
+            .unwrap();
+
+        // the committed event is replayed, followed by the live events
+        let mut sequences = Vec::new();
+        while sequences.len() < 3 {
+            let next = tokio::time::timeout(Duration::from_secs(5), stream.next_serialized())
+                .await
+                .expect("timed out waiting for subscribed events")
+                .unwrap()
+                .unwrap();
+            if next.aggregate_id == id {
+                sequences.push(next.sequence);
+            }
+        }
+        assert_eq!(vec![1, 2, 3], sequences);
+    }
+
+    async fn stream_sequences(
+        event_repo: &SqliteEventRepository,
+        filter: &ReplayFilter,
+    ) -> Vec<usize> {
+        let mut stream = event_repo
+            .stream_filtered_events::<TestAggregate>(filter)
+            .await
+            .unwrap();
+        let mut sequences = Vec::new();
+        while let Some(event) = stream.next::<TestAggregate>(&[]).await {
+            sequences.push(event.unwrap().sequence);
+        }
+        sequences
+    }
+
+    #[tokio::test]
+    async fn stale_snapshot_update_does_not_persist_events() {
+        let pool = test_pool().await;
+        let id = uuid::Uuid::new_v4().to_string();
+        let repo = SqliteEventRepository::new(pool.clone());
+        let aggregate = serde_json::to_value(TestAggregate {
+            id: id.clone(),
+            description: "a test aggregate".to_string(),
+            tests: vec![],
+        })
+        .unwrap();
+        let created = test_event_envelope(&id, 1, TestEvent::Created(Created { id: id.clone() }));
+        repo.insert::<TestAggregate>(aggregate.clone(), id.clone(), 1, &[created])
+            .await
+            .unwrap();
+        let tested = test_event_envelope(
+            &id,
+            2,
+            TestEvent::Tested(Tested {
+                test_name: "a test was run".to_string(),
+            }),
+        );
+        repo.update::<TestAggregate>(aggregate.clone(), id.clone(), 2, &[tested])
+            .await
+            .unwrap();
+
+        // a writer holding a stale snapshot uses an unused event sequence but the same snapshot version
+        let stale = test_event_envelope(
+            &id,
+            3,
+            TestEvent::SomethingElse(SomethingElse {
+                description: "this should not persist".to_string(),
+            }),
+        );
+        let result = repo
+            .update::<TestAggregate>(aggregate, id.clone(), 2, &[stale])
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(result, SqliteAggregateError::OptimisticLock),
+            "invalid error result found during update: {result}"
+        );
+
+        let events = repo.get_events::<TestAggregate>(&id).await.unwrap();
+        assert_eq!(2, events.len());
+    }
+}
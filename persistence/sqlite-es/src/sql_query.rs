@@ -0,0 +1,240 @@
+use cqrs_es::persist::ReplayFilter;
+use sqlx::{AssertSqlSafe, SqlSafeStr, SqlStr};
+
+pub(crate) struct SqlQueryFactory {
+    event_table: SqlStr,
+    select_events: SqlStr,
+    insert_event: SqlStr,
+    all_events: SqlStr,
+    events_after_position: SqlStr,
+    insert_snapshot: SqlStr,
+    update_snapshot: SqlStr,
+    select_snapshot: SqlStr,
+}
+
+impl SqlQueryFactory {
+    pub fn new(event_table: impl SqlSafeStr, snapshot_table: impl SqlSafeStr) -> Self {
+        let event_table = event_table.into_sql_str();
+        let snapshot_table = snapshot_table.into_sql_str();
+        let select_events = AssertSqlSafe(format!(
+            "
+SELECT aggregate_type, aggregate_id, sequence, event_type, event_version, payload, metadata
+  FROM {}
+  WHERE aggregate_type = ? AND aggregate_id = ?
+  ORDER BY sequence",
+            event_table.as_str()
+        ))
+        .into_sql_str();
+        let insert_event = AssertSqlSafe(format!(
+            "
+INSERT INTO {} (aggregate_type, aggregate_id, sequence, event_type, event_version, payload, metadata)
+VALUES (?, ?, ?, ?, ?, ?, ?)",
+            event_table.as_str()
+        ))
+        .into_sql_str();
+        let all_events = AssertSqlSafe(format!(
+            "
+SELECT aggregate_type, aggregate_id, sequence, event_type, event_version, payload, metadata
+  FROM {}
+  WHERE aggregate_type = ?
+  ORDER BY sequence",
+            event_table.as_str()
+        ))
+        .into_sql_str();
+        let events_after_position = AssertSqlSafe(format!(
+            "
+SELECT rowid AS global_position, aggregate_type, aggregate_id, sequence, event_type, event_version, payload, metadata
+  FROM {}
+  WHERE aggregate_type = ? AND rowid > ?
+  ORDER BY rowid
+  LIMIT ?",
+            event_table.as_str()
+        ))
+        .into_sql_str();
+        let insert_snapshot = AssertSqlSafe(format!(
+            "
+INSERT INTO {} (aggregate_type, aggregate_id, last_sequence, current_snapshot, payload)
+VALUES (?, ?, ?, ?, ?)",
+            snapshot_table.as_str()
+        ))
+        .into_sql_str();
+        let update_snapshot = AssertSqlSafe(format!(
+            "
+UPDATE {}
+  SET last_sequence= ? , payload= ?, current_snapshot= ?
+  WHERE aggregate_type= ? AND aggregate_id= ? AND current_snapshot= ?",
+            snapshot_table.as_str()
+        ))
+        .into_sql_str();
+        let select_snapshot = AssertSqlSafe(format!(
+            "
+SELECT aggregate_type, aggregate_id, last_sequence, current_snapshot, payload
+  FROM {}
+  WHERE aggregate_type = ? AND aggregate_id = ?",
+            snapshot_table.as_str()
+        ))
+        .into_sql_str();
+        Self {
+            event_table,
+            select_events,
+            insert_event,
+            all_events,
+            events_after_position,
+            insert_snapshot,
+            update_snapshot,
+            select_snapshot,
+        }
+    }
+    pub fn select_events(&self) -> SqlStr {
+        self.select_events.clone()
+    }
+    pub fn insert_event(&self) -> SqlStr {
+        self.insert_event.clone()
+    }
+    pub fn insert_snapshot(&self) -> SqlStr {
+        self.insert_snapshot.clone()
+    }
+    pub fn update_snapshot(&self) -> SqlStr {
+        self.update_snapshot.clone()
+    }
+    pub fn select_snapshot(&self) -> SqlStr {
+        self.select_snapshot.clone()
+    }
+    pub fn all_events(&self) -> SqlStr {
+        self.all_events.clone()
+    }
+    pub fn events_after_position(&self) -> SqlStr {
+        self.events_after_position.clone()
+    }
+    pub fn get_last_events(&self, last_sequence: usize) -> SqlStr {
+        AssertSqlSafe(format!(
+            "
+SELECT aggregate_type, aggregate_id, sequence, event_type, event_version, payload, metadata
+  FROM {}
+  WHERE aggregate_type = ? AND aggregate_id = ? AND sequence > {}
+  ORDER BY sequence",
+            self.event_table.as_str(),
+            last_sequence
+        ))
+        .into_sql_str()
+    }
+    // Parameters following the aggregate type are bound in the order the conditions appear.
+    pub fn filtered_events(&self, filter: &ReplayFilter) -> SqlStr {
+        let mut conditions = vec!["aggregate_type = ?".to_string()];
+        if !filter.aggregate_ids.is_empty() {
+            let placeholders = vec!["?"; filter.aggregate_ids.len()].join(",");
+            conditions.push(format!("aggregate_id IN ({placeholders})"));
+        }
+        if !filter.event_types.is_empty() {
+            let placeholders = vec!["?"; filter.event_types.len()].join(",");
+            conditions.push(format!("event_type IN ({placeholders})"));
+        }
+        if filter.min_sequence.is_some() {
+            conditions.push("sequence >= ?".to_string());
+        }
+        if filter.max_sequence.is_some() {
+            conditions.push("sequence <= ?".to_string());
+        }
+        if filter.committed_from.is_some() {
+            conditions.push("committed_at >= ?".to_string());
+        }
+        if filter.committed_to.is_some() {
+            conditions.push("committed_at < ?".to_string());
+        }
+        AssertSqlSafe(format!(
+            "
+SELECT aggregate_type, aggregate_id, sequence, event_type, event_version, payload, metadata
+  FROM {}
+  WHERE {}
+  ORDER BY sequence",
+            self.event_table.as_str(),
+            conditions.join(" AND ")
+        ))
+        .into_sql_str()
+    }
+}
+
+#[test]
+fn test_queries() {
+    let query_factory = SqlQueryFactory::new("my_events", "my_snapshots");
+    assert_eq!(
+        query_factory.select_events().as_str(),
+        "
+SELECT aggregate_type, aggregate_id, sequence, event_type, event_version, payload, metadata
+  FROM my_events
+  WHERE aggregate_type = ? AND aggregate_id = ?
+  ORDER BY sequence"
+    );
+    assert_eq!(query_factory.insert_event().as_str(), "
+INSERT INTO my_events (aggregate_type, aggregate_id, sequence, event_type, event_version, payload, metadata)
+VALUES (?, ?, ?, ?, ?, ?, ?)");
+    assert_eq!(
+        query_factory.all_events().as_str(),
+        "
+SELECT aggregate_type, aggregate_id, sequence, event_type, event_version, payload, metadata
+  FROM my_events
+  WHERE aggregate_type = ?
+  ORDER BY sequence"
+    );
+    assert_eq!(
+        query_factory.events_after_position().as_str(),
+        "
+SELECT rowid AS global_position, aggregate_type, aggregate_id, sequence, event_type, event_version, payload, metadata
+  FROM my_events
+  WHERE aggregate_type = ? AND rowid > ?
+  ORDER BY rowid
+  LIMIT ?"
+    );
+    assert_eq!(
+        query_factory.insert_snapshot().as_str(),
+        "
+INSERT INTO my_snapshots (aggregate_type, aggregate_id, last_sequence, current_snapshot, payload)
+VALUES (?, ?, ?, ?, ?)"
+    );
+    assert_eq!(
+        query_factory.update_snapshot().as_str(),
+        "
+UPDATE my_snapshots
+  SET last_sequence= ? , payload= ?, current_snapshot= ?
+  WHERE aggregate_type= ? AND aggregate_id= ? AND current_snapshot= ?"
+    );
+    assert_eq!(
+        query_factory.select_snapshot().as_str(),
+        "
+SELECT aggregate_type, aggregate_id, last_sequence, current_snapshot, payload
+  FROM my_snapshots
+  WHERE aggregate_type = ? AND aggregate_id = ?"
+    );
+    assert_eq!(
+        query_factory.get_last_events(20).as_str(),
+        "
+SELECT aggregate_type, aggregate_id, sequence, event_type, event_version, payload, metadata
+  FROM my_events
+  WHERE aggregate_type = ? AND aggregate_id = ? AND sequence > 20
+  ORDER BY sequence"
+    );
+}
+
+#[test]
+fn test_filtered_events_query() {
+    let query_factory = SqlQueryFactory::new("my_events", "my_snapshots");
+    assert_eq!(
+        query_factory
+            .filtered_events(&ReplayFilter::default())
+            .as_str(),
+        query_factory.all_events().as_str()
+    );
+    let filter = ReplayFilter::default()
+        .with_aggregate_ids(vec!["a".to_string(), "b".to_string()])
+        .with_event_types(vec!["Created".to_string()])
+        .with_sequences(2..=4)
+        .committed_between(std::time::UNIX_EPOCH, std::time::SystemTime::now());
+    assert_eq!(
+        query_factory.filtered_events(&filter).as_str(),
+        "
+SELECT aggregate_type, aggregate_id, sequence, event_type, event_version, payload, metadata
+  FROM my_events
+  WHERE aggregate_type = ? AND aggregate_id IN (?,?) AND event_type IN (?) AND sequence >= ? AND sequence <= ? AND committed_at >= ? AND committed_at < ?
+  ORDER BY sequence"
+    );
+}
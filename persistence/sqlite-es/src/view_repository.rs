@@ -0,0 +1,620 @@
+use std::marker::PhantomData;
+
+use cqrs_es::persist::{
+    ListableViewRepository, PersistenceError, ShadowViewRepository, ViewContext, ViewFilter,
+    ViewListQuery, ViewPage, ViewRepository,
+};
+use cqrs_es::{Aggregate, View};
+use sqlx::query::Query;
+use sqlx::sqlite::{SqliteArguments, SqliteRow};
+use sqlx::{AssertSqlSafe, Pool, Row, SqlSafeStr, SqlStr, Sqlite};
+
+use crate::error::SqliteAggregateError;
+
+// Keeps the number of parameters in bulk statements well below the SQLite limit.
+const BULK_CHUNK_SIZE: usize = 1000;
+
+/// A SQLite backed query repository for use in backing a `GenericQuery`.
+pub struct SqliteViewRepository<V, A> {
+    insert_sql: SqlStr,
+    update_sql: SqlStr,
+    select_sql: SqlStr,
+    delete_sql: SqlStr,
+    clear_sql: SqlStr,
+    view_name: SqlStr,
+    shadow_name: Option<SqlStr>,
+    pool: Pool<Sqlite>,
+    _phantom: PhantomData<(V, A)>,
+}
+
+impl<V, A> SqliteViewRepository<V, A>
+where
+    V: View<A>,
+    A: Aggregate,
+{
+    /// Creates a new `SqliteViewRepository` that will store serialized views in a SQLite table named
+    /// identically to the `view_name` value provided. This table should be created by the user
+    /// before using this query repository (see `/db/init.sql` sql initialization file).
+    ///
+    /// ```
+    /// # use cqrs_es::doc::MyAggregate;
+    /// # use cqrs_es::persist::doc::MyView;
+    /// use sqlx::{Pool, Sqlite};
+    /// use sqlite_es::SqliteViewRepository;
+    ///
+    /// fn configure_view_repo(pool: Pool<Sqlite>) -> SqliteViewRepository<MyView,MyAggregate> {
+    ///     SqliteViewRepository::new("my_view_table", pool)
+    /// }
+    /// ```
+    pub fn new(view_name: impl SqlSafeStr, pool: Pool<Sqlite>) -> Self {
+        let view_sql_str = view_name.into_sql_str();
+        let insert_sql = AssertSqlSafe(format!(
+            "INSERT INTO {} (payload, version, last_sequence, view_id) VALUES ( ?, ?, ?, ? )",
+            view_sql_str.as_str()
+        ))
+        .into_sql_str();
+        let update_sql = AssertSqlSafe(format!(
+            "UPDATE {} SET payload= ? , version= ? , last_sequence= ? WHERE view_id= ? AND version= ?",
+            view_sql_str.as_str()
+        ))
+        .into_sql_str();
+        let select_sql = AssertSqlSafe(format!(
+            "SELECT version,last_sequence,payload FROM {} WHERE view_id= ?",
+            view_sql_str.as_str()
+        ))
+        .into_sql_str();
+        let delete_sql = AssertSqlSafe(format!(
+            "DELETE FROM {} WHERE view_id= ? AND version= ?",
+            view_sql_str.as_str()
+        ))
+        .into_sql_str();
+        let clear_sql =
+            AssertSqlSafe(format!("DELETE FROM {}", view_sql_str.as_str())).into_sql_str();
+        Self {
+            insert_sql,
+            update_sql,
+            select_sql,
+            delete_sql,
+            clear_sql,
+            view_name: view_sql_str,
+            shadow_name: None,
+            pool,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Configures a shadow table that views will be rebuilt into during a blue/green rebuild.
+    /// The shadow table must have the same structure as the view table and, when promoted,
+    /// the two tables are swapped by renaming them within a single transaction.
+    ///
+    /// ```
+    /// # use cqrs_es::doc::MyAggregate;
+    /// # use cqrs_es::persist::doc::MyView;
+    /// use sqlx::{Pool, Sqlite};
+    /// use sqlite_es::SqliteViewRepository;
+    ///
+    /// fn configure_view_repo(pool: Pool<Sqlite>) -> SqliteViewRepository<MyView,MyAggregate> {
+    ///     SqliteViewRepository::new("my_view_table", pool)
+    ///         .with_shadow_table("my_view_table_shadow")
+    /// }
+    /// ```
+    pub fn with_shadow_table(self, shadow_name: impl SqlSafeStr) -> Self {
+        Self {
+            shadow_name: Some(shadow_name.into_sql_str()),
+            ..self
+        }
+    }
+
+    fn shadow_name(&self) -> Result<&SqlStr, PersistenceError> {
+        self.shadow_name.as_ref().ok_or_else(|| {
+            PersistenceError::UnknownError("no shadow table configured for this view".into())
+        })
+    }
+}
+
+// Strings are compared by their value and any other field by its JSON text, matching the
+// text representation used by `ViewFilter`.
+fn where_clause(mut conditions: Vec<String>, filters: &[ViewFilter]) -> String {
+    for filter in filters {
+        match filter.value_as_text() {
+            None => conditions.push("json_extract(payload, ?) IS NULL".to_string()),
+            Some(_) => conditions.push(
+                "(CASE json_type(payload, ?) WHEN 'text' THEN json_extract(payload, ?) ELSE payload -> ? END) = ?"
+                    .to_string(),
+            ),
+        }
+    }
+    if conditions.is_empty() {
+        return String::new();
+    }
+    format!("WHERE {}", conditions.join(" AND "))
+}
+
+fn bind_filters<'q>(
+    mut query: Query<'q, Sqlite, SqliteArguments>,
+    filters: &[ViewFilter],
+) -> Query<'q, Sqlite, SqliteArguments> {
+    for filter in filters {
+        let path = json_path(&filter.path);
+        match filter.value_as_text() {
+            None => query = query.bind(path),
+            Some(value) => {
+                query = query
+                    .bind(path.clone())
+                    .bind(path.clone())
+                    .bind(path)
+                    .bind(value);
+            }
+        }
+    }
+    query
+}
+
+// Builds the placeholders for `rows` rows of `columns` values, e.g., `(?,?),(?,?)`.
+// A single column is left unwrapped for use within an `IN` clause.
+fn placeholders(columns: usize, rows: usize) -> String {
+    let row = vec!["?"; columns].join(",");
+    let row = match columns {
+        1 => row,
+        _ => format!("({row})"),
+    };
+    vec![row; rows].join(",")
+}
+
+// Converts the field names into a SQLite JSON path, numeric fields are treated as array indexes.
+fn json_path(path: &[String]) -> String {
+    let mut json_path = "$".to_string();
+    for field in path {
+        if !field.is_empty() && field.chars().all(|c| c.is_ascii_digit()) {
+            json_path.push_str(&format!("[{field}]"));
+        } else {
+            let escaped = field.replace('\\', "\\\\").replace('"', "\\\"");
+            json_path.push_str(&format!(".\"{escaped}\""));
+        }
+    }
+    json_path
+}
+
+impl<V, A> ViewRepository<V, A> for SqliteViewRepository<V, A>
+where
+    V: View<A>,
+    A: Aggregate,
+{
+    async fn load(&self, view_id: &str) -> Result<Option<V>, PersistenceError> {
+        let row: Option<SqliteRow> = sqlx::query(self.select_sql.clone())
+            .bind(view_id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(SqliteAggregateError::from)?;
+        match row {
+            None => Ok(None),
+            Some(row) => {
+                let view = serde_json::from_value(row.get("payload"))?;
+                Ok(Some(view))
+            }
+        }
+    }
+
+    async fn load_with_context(
+        &self,
+        view_id: &str,
+    ) -> Result<Option<(V, ViewContext)>, PersistenceError> {
+        let row: Option<SqliteRow> = sqlx::query(self.select_sql.clone())
+            .bind(view_id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(SqliteAggregateError::from)?;
+        match row {
+            None => Ok(None),
+            Some(row) => {
+                let version = row.get("version");
+                let last_sequence: i64 = row.get("last_sequence");
+                let view = serde_json::from_value(row.get("payload"))?;
+                let view_context = ViewContext::new(view_id.to_string(), version)
+                    .with_last_sequence(last_sequence as usize);
+                Ok(Some((view, view_context)))
+            }
+        }
+    }
+
+    async fn update_view(&self, view: V, context: ViewContext) -> Result<(), PersistenceError> {
+        let version = context.version + 1;
+        let payload = serde_json::to_value(&view).map_err(SqliteAggregateError::from)?;
+        let query = match context.version {
+            0 => sqlx::query(self.insert_sql.clone()),
+            _ => sqlx::query(self.update_sql.clone()),
+        }
+        .bind(payload)
+        .bind(version)
+        .bind(context.last_sequence as i64)
+        .bind(context.view_instance_id);
+        let query = match context.version {
+            0 => query,
+            _ => query.bind(context.version),
+        };
+        let rows_affected = query
+            .execute(&self.pool)
+            .await
+            .map_err(SqliteAggregateError::from)?
+            .rows_affected();
+        if rows_affected < 1 {
+            return Err(PersistenceError::OptimisticLockError);
+        }
+        Ok(())
+    }
+
+    async fn load_all_with_context(
+        &self,
+        view_ids: &[String],
+    ) -> Result<Vec<(V, ViewContext)>, PersistenceError> {
+        let mut views = Vec::with_capacity(view_ids.len());
+        for chunk in view_ids.chunks(BULK_CHUNK_SIZE) {
+            let sql = format!(
+                "SELECT view_id,version,last_sequence,payload FROM {} WHERE view_id IN ({})",
+                self.view_name.as_str(),
+                placeholders(1, chunk.len())
+            );
+            let mut query = sqlx::query(AssertSqlSafe(sql));
+            for view_id in chunk {
+                query = query.bind(view_id.clone());
+            }
+            let rows: Vec<SqliteRow> = query
+                .fetch_all(&self.pool)
+                .await
+                .map_err(SqliteAggregateError::from)?;
+            for row in rows {
+                let view_id = row.get("view_id");
+                let version = row.get("version");
+                let last_sequence: i64 = row.get("last_sequence");
+                let view = serde_json::from_value(row.get("payload"))?;
+                let view_context =
+                    ViewContext::new(view_id, version).with_last_sequence(last_sequence as usize);
+                views.push((view, view_context));
+            }
+        }
+        Ok(views)
+    }
+
+    // Views are only updated if they have not been modified since they were loaded, any views
+    // skipped are detected by the number of rows changed.
+    async fn update_all(&self, views: Vec<(V, ViewContext)>) -> Result<(), PersistenceError> {
+        if views.is_empty() {
+            return Ok(());
+        }
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(SqliteAggregateError::from)?;
+        for chunk in views.chunks(BULK_CHUNK_SIZE) {
+            let upsert_sql = format!(
+                "INSERT INTO {} (payload, version, last_sequence, view_id) VALUES {}
+                 ON CONFLICT (view_id) DO UPDATE
+                 SET payload= excluded.payload, version= excluded.version, last_sequence= excluded.last_sequence
+                 WHERE version = excluded.version - 1",
+                self.view_name.as_str(),
+                placeholders(4, chunk.len())
+            );
+            let mut upsert_query = sqlx::query(AssertSqlSafe(upsert_sql));
+            for (view, context) in chunk {
+                let payload = serde_json::to_value(view).map_err(SqliteAggregateError::from)?;
+                upsert_query = upsert_query
+                    .bind(payload)
+                    .bind(context.version + 1)
+                    .bind(context.last_sequence as i64)
+                    .bind(context.view_instance_id.clone());
+            }
+            let rows_affected = upsert_query
+                .execute(&mut *tx)
+                .await
+                .map_err(SqliteAggregateError::from)?
+                .rows_affected();
+            if rows_affected < chunk.len() as u64 {
+                return Err(PersistenceError::OptimisticLockError);
+            }
+        }
+        tx.commit().await.map_err(SqliteAggregateError::from)?;
+        Ok(())
+    }
+
+    async fn delete_view(&self, context: ViewContext) -> Result<(), PersistenceError> {
+        let rows_affected = sqlx::query(self.delete_sql.clone())
+            .bind(context.view_instance_id)
+            .bind(context.version)
+            .execute(&self.pool)
+            .await
+            .map_err(SqliteAggregateError::from)?
+            .rows_affected();
+        if rows_affected < 1 {
+            return Err(PersistenceError::OptimisticLockError);
+        }
+        Ok(())
+    }
+
+    async fn clear_all(&self) -> Result<(), PersistenceError> {
+        sqlx::query(self.clear_sql.clone())
+            .execute(&self.pool)
+            .await
+            .map_err(SqliteAggregateError::from)?;
+        Ok(())
+    }
+}
+
+impl<V, A> ListableViewRepository<V, A> for SqliteViewRepository<V, A>
+where
+    V: View<A>,
+    A: Aggregate,
+{
+    async fn list_views(&self, query: &ViewListQuery) -> Result<ViewPage<V>, PersistenceError> {
+        let page_size = query.page_size();
+        let mut conditions = Vec::new();
+        if query.cursor.is_some() {
+            conditions.push("view_id > ?".to_string());
+        }
+        let sql = format!(
+            "SELECT view_id,payload FROM {} {} ORDER BY view_id LIMIT {}",
+            self.view_name.as_str(),
+            where_clause(conditions, &query.filters),
+            page_size.saturating_add(1)
+        );
+        let mut sql_query = sqlx::query(AssertSqlSafe(sql));
+        if let Some(cursor) = &query.cursor {
+            sql_query = sql_query.bind(cursor.clone());
+        }
+        let rows: Vec<SqliteRow> = bind_filters(sql_query, &query.filters)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(SqliteAggregateError::from)?;
+        let has_more = rows.len() > page_size;
+        let mut views = Vec::with_capacity(page_size);
+        for row in rows.into_iter().take(page_size) {
+            let view_id: String = row.get("view_id");
+            let view = serde_json::from_value(row.get("payload"))?;
+            views.push((view_id, view));
+        }
+        let next_cursor = match has_more {
+            true => views.last().map(|(view_id, _)| view_id.clone()),
+            false => None,
+        };
+        Ok(ViewPage { views, next_cursor })
+    }
+
+    async fn count_views(&self, filters: &[ViewFilter]) -> Result<u64, PersistenceError> {
+        let sql = format!(
+            "SELECT count(*) FROM {} {}",
+            self.view_name.as_str(),
+            where_clause(Vec::new(), filters)
+        );
+        let row: SqliteRow = bind_filters(sqlx::query(AssertSqlSafe(sql)), filters)
+            .fetch_one(&self.pool)
+            .await
+            .map_err(SqliteAggregateError::from)?;
+        let count: i64 = row.get(0);
+        Ok(count as u64)
+    }
+}
+
+impl<V, A> ShadowViewRepository<V, A> for SqliteViewRepository<V, A>
+where
+    V: View<A>,
+    A: Aggregate,
+{
+    type Shadow = Self;
+
+    async fn prepare_shadow(&self) -> Result<Self::Shadow, PersistenceError> {
+        let shadow = Self::new(self.shadow_name()?.clone(), self.pool.clone());
+        shadow.clear_all().await?;
+        Ok(shadow)
+    }
+
+    async fn promote_shadow(&self) -> Result<(), PersistenceError> {
+        let view_name = self.view_name.as_str();
+        let shadow_name = self.shadow_name()?.as_str();
+        let swap_name = format!("{view_name}_swap");
+        let renames = [
+            format!("ALTER TABLE {view_name} RENAME TO {swap_name}"),
+            format!("ALTER TABLE {shadow_name} RENAME TO {view_name}"),
+            format!("ALTER TABLE {swap_name} RENAME TO {shadow_name}"),
+        ];
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(SqliteAggregateError::from)?;
+        for sql in renames {
+            sqlx::query(AssertSqlSafe(sql))
+                .execute(&mut *tx)
+                .await
+                .map_err(SqliteAggregateError::from)?;
+        }
+        tx.commit().await.map_err(SqliteAggregateError::from)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::testing::tests::{test_pool, Created, TestAggregate, TestEvent, TestView};
+    use crate::view_repository::{json_path, placeholders};
+    use crate::SqliteViewRepository;
+    use cqrs_es::persist::{
+        ListableViewRepository, PersistenceError, ShadowViewRepository, ViewContext, ViewFilter,
+        ViewListQuery, ViewRepository,
+    };
+
+    #[tokio::test]
+    async fn test_valid_view_repository() {
+        let pool = test_pool().await;
+        let repo = SqliteViewRepository::<TestView, TestAggregate>::new("test_view", pool.clone());
+        let test_view_id = uuid::Uuid::new_v4().to_string();
+
+        let view = TestView {
+            events: vec![TestEvent::Created(Created {
+                id: "just a test event for this view".to_string(),
+            })],
+        };
+        let context = ViewContext::new(test_view_id.to_string(), 0).with_last_sequence(3);
+        repo.update_view(view.clone(), context).await.unwrap();
+        let (found, context) = repo
+            .load_with_context(&test_view_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(found, view);
+        assert_eq!(3, context.last_sequence);
+
+        let updated_view = TestView {
+            events: vec![TestEvent::Created(Created {
+                id: "a totally different view".to_string(),
+            })],
+        };
+        repo.update_view(updated_view.clone(), context)
+            .await
+            .unwrap();
+        let found_option = repo.load(&test_view_id).await.unwrap();
+        let found = found_option.unwrap();
+
+        assert_eq!(found, updated_view);
+    }
+
+    #[tokio::test]
+    async fn test_shadow_view_repository() {
+        let pool = test_pool().await;
+        let repo = SqliteViewRepository::<TestView, TestAggregate>::new("test_view", pool.clone())
+            .with_shadow_table("test_view_shadow");
+        let test_view_id = uuid::Uuid::new_v4().to_string();
+
+        let view = TestView {
+            events: vec![TestEvent::Created(Created {
+                id: "a rebuilt view".to_string(),
+            })],
+        };
+        let shadow = repo.prepare_shadow().await.unwrap();
+        shadow
+            .update_view(view.clone(), ViewContext::new(test_view_id.to_string(), 0))
+            .await
+            .unwrap();
+        assert_eq!(None, repo.load(&test_view_id).await.unwrap());
+
+        repo.promote_shadow().await.unwrap();
+        let found = repo.load(&test_view_id).await.unwrap().unwrap();
+        assert_eq!(found, view);
+    }
+
+    #[test]
+    fn test_json_path() {
+        let path = ["events", "0", "Created", "an \"id\""].map(ToString::to_string);
+        assert_eq!(json_path(&path), r#"$."events"[0]."Created"."an \"id\"""#);
+    }
+
+    #[tokio::test]
+    async fn test_list_views() {
+        let pool = test_pool().await;
+        let repo = SqliteViewRepository::<TestView, TestAggregate>::new("test_view", pool.clone());
+        let marker = uuid::Uuid::new_v4().to_string();
+        for _ in 0..3 {
+            let view = TestView {
+                events: vec![TestEvent::Created(Created { id: marker.clone() })],
+            };
+            let view_id = uuid::Uuid::new_v4().to_string();
+            repo.update_view(view, ViewContext::new(view_id, 0))
+                .await
+                .unwrap();
+        }
+        let filter = ViewFilter::equals("events.0.Created.id", marker.as_str());
+
+        let count = repo
+            .count_views(std::slice::from_ref(&filter))
+            .await
+            .unwrap();
+        assert_eq!(3, count);
+
+        let query = ViewListQuery::new(usize::MAX).with_filter(filter.clone());
+        let page = repo.list_views(&query).await.unwrap();
+        assert_eq!(3, page.views.len());
+        assert_eq!(None, page.next_cursor);
+
+        let query = ViewListQuery::new(2).with_filter(filter);
+        let first_page = repo.list_views(&query).await.unwrap();
+        assert_eq!(2, first_page.views.len());
+        assert!(first_page.next_cursor.is_some());
+
+        let query = query.after(first_page.next_cursor);
+        let second_page = repo.list_views(&query).await.unwrap();
+        assert_eq!(1, second_page.views.len());
+        assert_eq!(None, second_page.next_cursor);
+        assert!(first_page.views[1].0 < second_page.views[0].0);
+    }
+
+    #[tokio::test]
+    async fn test_delete_view() {
+        let pool = test_pool().await;
+        let repo = SqliteViewRepository::<TestView, TestAggregate>::new("test_view", pool.clone());
+        let test_view_id = uuid::Uuid::new_v4().to_string();
+
+        let view = TestView {
+            events: vec![TestEvent::Created(Created {
+                id: "a view to delete".to_string(),
+            })],
+        };
+        repo.update_view(view, ViewContext::new(test_view_id.to_string(), 0))
+            .await
+            .unwrap();
+        let stale_context = ViewContext::new(test_view_id.to_string(), 0);
+        let result = repo.delete_view(stale_context).await;
+        assert!(matches!(result, Err(PersistenceError::OptimisticLockError)));
+
+        let (_, context) = repo
+            .load_with_context(&test_view_id)
+            .await
+            .unwrap()
+            .unwrap();
+        repo.delete_view(context).await.unwrap();
+        assert_eq!(None, repo.load(&test_view_id).await.unwrap());
+    }
+
+    #[test]
+    fn test_placeholders() {
+        assert_eq!("?,?,?", placeholders(1, 3));
+        assert_eq!("(?,?),(?,?)", placeholders(2, 2));
+    }
+
+    #[tokio::test]
+    async fn test_update_all() {
+        let pool = test_pool().await;
+        let repo = SqliteViewRepository::<TestView, TestAggregate>::new("test_view", pool.clone());
+        let existing_id = uuid::Uuid::new_v4().to_string();
+        let new_id = uuid::Uuid::new_v4().to_string();
+        let view = |id: &str| TestView {
+            events: vec![TestEvent::Created(Created { id: id.to_string() })],
+        };
+        repo.update_view(view("existing"), ViewContext::new(existing_id.clone(), 0))
+            .await
+            .unwrap();
+
+        let view_ids = vec![existing_id.clone(), new_id.clone()];
+        let loaded = repo.load_all_with_context(&view_ids).await.unwrap();
+        assert_eq!(1, loaded.len());
+        let (_, existing_context) = loaded.into_iter().next().unwrap();
+        repo.update_all(vec![
+            (view("updated"), existing_context.with_last_sequence(2)),
+            (view("new"), ViewContext::new(new_id.clone(), 0)),
+        ])
+        .await
+        .unwrap();
+        let (found, context) = repo.load_with_context(&existing_id).await.unwrap().unwrap();
+        assert_eq!(view("updated"), found);
+        assert_eq!((2, 2), (context.version, context.last_sequence));
+        assert_eq!(Some(view("new")), repo.load(&new_id).await.unwrap());
+
+        // a stale context fails the entire update
+        let result = repo
+            .update_all(vec![
+                (view("stale"), ViewContext::new(existing_id.clone(), 1)),
+                (view("newer"), ViewContext::new(new_id.clone(), 1)),
+            ])
+            .await;
+        assert!(matches!(result, Err(PersistenceError::OptimisticLockError)));
+        assert_eq!(Some(view("new")), repo.load(&new_id).await.unwrap());
+    }
+}
@@ -6,7 +6,7 @@ use crate::event_sink::EventSink;
 use crate::persist::{
     PersistedEventRepository, PersistenceError, ReplayStream, SerializedEvent, SerializedSnapshot,
 };
-use crate::{Aggregate, DomainEvent, EventEnvelope, Query};
+use crate::{Aggregate, DomainEvent, EventEnvelope, Query, View};
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
 pub enum MyEvents {
@@ -174,6 +174,26 @@ pub enum CustomerCommand {
     UpdateEmail { new_email: String },
 }
 
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CustomerView {
+    pub customer_id: String,
+    pub name: String,
+    pub email: String,
+    pub updates: usize,
+}
+
+impl View<Customer> for CustomerView {
+    fn update(&mut self, event: &EventEnvelope<Customer>) {
+        self.customer_id.clone_from(&event.aggregate_id);
+        self.updates = event.sequence;
+        match &event.payload {
+            CustomerEvent::NameAdded { name } => self.name.clone_from(name),
+            CustomerEvent::EmailUpdated { new_email } => self.email.clone_from(new_email),
+            CustomerEvent::CustomerDataPopulated => {}
+        }
+    }
+}
+
 pub struct MyRepository;
 
 impl PersistedEventRepository for MyRepository {
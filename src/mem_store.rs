@@ -1,11 +1,20 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::sync::{Arc, RwLock};
 
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+
 use crate::event::EventEnvelope;
 use crate::{Aggregate, AggregateContext, AggregateError, EventStore};
 
+// The number of committed events buffered for each subscriber.
+const SUBSCRIPTION_BUFFER_SIZE: usize = 1024;
+
 ///  Simple memory store useful for application development and testing purposes.
 ///
+/// As with the persisted event stores, a commit against an aggregate that has been modified
+/// since it was loaded fails with an `AggregateError::AggregateConflict`.
+///
 /// Creation and use in a constructing a `CqrsFramework`:
 /// ```
 /// # use cqrs_es::doc::{MyAggregate, MyService};
@@ -18,12 +27,14 @@ use crate::{Aggregate, AggregateContext, AggregateError, EventStore};
 #[derive(Debug, Clone)]
 pub struct MemStore<A: Aggregate + Send + Sync> {
     events: Arc<LockedEventEnvelopeMap<A>>,
+    committed: broadcast::Sender<EventEnvelope<A>>,
 }
 
 impl<A: Aggregate> Default for MemStore<A> {
     fn default() -> Self {
         let events = Arc::default();
-        Self { events }
+        let (committed, _) = broadcast::channel(SUBSCRIPTION_BUFFER_SIZE);
+        Self { events, committed }
     }
 }
 
@@ -53,6 +64,33 @@ impl<A: Aggregate> MemStore<A> {
         Arc::clone(&self.events)
     }
 
+    /// Subscribe to all events within the store, the subscription first returns the events
+    /// that have already been committed and then follows newly committed events.
+    ///
+    /// ```
+    /// # use cqrs_es::doc::MyAggregate;
+    /// # use cqrs_es::mem_store::MemStore;
+    /// async fn follow(store: MemStore<MyAggregate>) {
+    ///     let mut subscription = store.subscribe();
+    ///     while let Some(event) = subscription.next().await {
+    ///         println!("{}: {:?}", event.aggregate_id, event.payload);
+    ///     }
+    /// }
+    /// ```
+    pub fn subscribe(&self) -> MemStoreSubscription<A> {
+        // Holding the lock ensures no events are committed between the copy and subscribing.
+        let events = self.events.read().unwrap();
+        let receiver = self.committed.subscribe();
+        let backlog = events.values().flatten().cloned().collect();
+        drop(events);
+        MemStoreSubscription {
+            events: Arc::clone(&self.events),
+            backlog,
+            receiver,
+            last_sequences: HashMap::new(),
+        }
+    }
+
     fn load_committed_events(
         &self,
         aggregate_id: &str,
@@ -126,19 +164,28 @@ impl<A: Aggregate> EventStore<A> for MemStore<A> {
             return Ok(Vec::default());
         }
         let aggregate_id = Self::aggregate_id(&wrapped_events);
-        let mut new_events = self.load_committed_events(&aggregate_id).unwrap();
-        for event in &wrapped_events {
-            new_events.push(event.clone());
+        // uninteresting unwrap: this is not a struct for production use
+        let mut events = self.events.write().unwrap();
+        // the aggregate has been modified since it was loaded if the sequences do not match
+        let committed_sequence = events
+            .get(&aggregate_id)
+            .and_then(|committed| committed.last())
+            .map_or(0, |event| event.sequence);
+        if committed_sequence != current_sequence {
+            return Err(AggregateError::AggregateConflict);
         }
+        events
+            .entry(aggregate_id.clone())
+            .or_default()
+            .extend(wrapped_events.iter().cloned());
         println!(
             "storing: {} new events for aggregate ID '{}'",
             new_events_qty, &aggregate_id
         );
-        // uninteresting unwrap: this is not a struct for production use
-        self.events
-            .write()
-            .unwrap()
-            .insert(aggregate_id, new_events);
+        for event in &wrapped_events {
+            // an error only indicates that there are no subscribers
+            let _ = self.committed.send(event.clone());
+        }
         Ok(wrapped_events)
     }
 }
@@ -166,6 +213,59 @@ impl<A: Aggregate> MemStore<A> {
             .collect()
     }
 }
+/// Follows the events committed to a `MemStore`, see `MemStore::subscribe`.
+pub struct MemStoreSubscription<A: Aggregate> {
+    events: Arc<LockedEventEnvelopeMap<A>>,
+    backlog: VecDeque<EventEnvelope<A>>,
+    receiver: broadcast::Receiver<EventEnvelope<A>>,
+    last_sequences: HashMap<String, usize>,
+}
+
+impl<A: Aggregate> MemStoreSubscription<A> {
+    /// Receive the next event, waiting for an event to be committed if none are available.
+    ///
+    /// A subscriber that falls behind by more than the buffered events is caught up from the
+    /// store, so no events are missed.
+    pub async fn next(&mut self) -> Option<EventEnvelope<A>> {
+        loop {
+            let event = match self.backlog.pop_front() {
+                Some(event) => event,
+                None => match self.receiver.recv().await {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(_)) => {
+                        self.backlog = self.missed_events();
+                        continue;
+                    }
+                    Err(RecvError::Closed) => return None,
+                },
+            };
+            let last_sequence = self
+                .last_sequences
+                .entry(event.aggregate_id.clone())
+                .or_default();
+            // events already received while catching up are skipped
+            if event.sequence > *last_sequence {
+                *last_sequence = event.sequence;
+                return Some(event);
+            }
+        }
+    }
+
+    fn missed_events(&self) -> VecDeque<EventEnvelope<A>> {
+        let events = self.events.read().unwrap();
+        events
+            .iter()
+            .flat_map(|(aggregate_id, events)| {
+                let last_sequence = self.last_sequences.get(aggregate_id).copied();
+                events
+                    .iter()
+                    .filter(move |event| event.sequence > last_sequence.unwrap_or_default())
+            })
+            .cloned()
+            .collect()
+    }
+}
+
 /// Holds context for a pure event store implementation for MemStore.
 ///
 /// This is used internally by the `CqrsFramework`.
@@ -11,25 +11,43 @@ pub use context::EventStoreAggregateContext;
 pub use error::PersistenceError;
 pub use event_repository::PersistedEventRepository;
 pub use event_store::PersistedEventStore;
-pub use event_stream::{ReplayFeed, ReplayStream};
-pub use generic_query::{GenericQuery, QueryErrorHandler};
+pub use event_stream::{ReplayEvents, ReplayFeed, ReplayStream};
+pub use file_event_repository::FileEventRepository;
+pub use generic_query::{GenericQuery, QueryErrorHandler, ViewIdMapper};
+pub use mem_event_repository::MemEventRepository;
+pub use mem_view_repository::MemViewRepository;
 pub use replay::QueryReplay;
+pub use replay_filter::ReplayFilter;
+pub use replay_report::{
+    DeadLetterHandler, ReplayErrorPolicy, ReplayFailure, ReplayPosition, ReplayReport,
+};
 pub use serialized_event::{SerializedEvent, SerializedSnapshot};
 pub use upcaster::{
     EventUpcaster, SemanticVersion, SemanticVersionError, SemanticVersionEventUpcaster,
     SemanticVersionEventUpcasterFunc,
 };
-pub use view_repository::{ViewContext, ViewRepository};
+pub use view_listing::{
+    ListableViewRepository, ViewFilter, ViewListQuery, ViewPage, MAX_VIEW_PAGE_SIZE,
+};
+pub use view_rebuild::ViewRebuild;
+pub use view_repository::{ShadowViewRepository, ViewContext, ViewRepository};
 
 mod context;
 mod error;
 mod event_repository;
 mod event_store;
 mod event_stream;
+mod file_event_repository;
 mod generic_query;
+mod mem_event_repository;
+mod mem_view_repository;
 mod replay;
+mod replay_filter;
+mod replay_report;
 mod serialized_event;
 mod upcaster;
+mod view_listing;
+mod view_rebuild;
 mod view_repository;
 
 // Documentation items
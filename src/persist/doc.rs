@@ -1,8 +1,8 @@
 use crate::doc::MyAggregate;
 use crate::persist::event_stream::ReplayStream;
 use crate::persist::{
-    PersistedEventRepository, PersistenceError, SerializedEvent, SerializedSnapshot, ViewContext,
-    ViewRepository,
+    PersistedEventRepository, PersistenceError, SerializedEvent, SerializedSnapshot,
+    ShadowViewRepository, ViewContext, ViewRepository,
 };
 use crate::{Aggregate, EventEnvelope, View};
 use serde::{Deserialize, Serialize};
@@ -47,6 +47,18 @@ impl ViewRepository<MyView, MyAggregate> for MyViewRepository {
     }
 }
 
+impl ShadowViewRepository<MyView, MyAggregate> for MyViewRepository {
+    type Shadow = Self;
+
+    async fn prepare_shadow(&self) -> Result<Self, PersistenceError> {
+        todo!()
+    }
+
+    async fn promote_shadow(&self) -> Result<(), PersistenceError> {
+        todo!()
+    }
+}
+
 pub struct MyEventIterator;
 impl Iterator for MyEventIterator {
     type Item = Result<SerializedEvent, PersistenceError>;
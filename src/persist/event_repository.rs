@@ -1,10 +1,12 @@
 use std::future::Future;
 
 use crate::persist::event_stream::ReplayStream;
-use crate::persist::{PersistenceError, SerializedEvent, SerializedSnapshot};
+use crate::persist::{PersistenceError, ReplayFilter, SerializedEvent, SerializedSnapshot};
 use crate::Aggregate;
 use serde_json::Value;
 
+const FILTERED_STREAM_CHANNEL_SIZE: usize = 200;
+
 /// Handles the database access needed for operation of a PersistedSnapshotStore.
 pub trait PersistedEventRepository: Send + Sync {
     /// Returns all events for a single aggregate instance.
@@ -43,4 +45,123 @@ pub trait PersistedEventRepository: Send + Sync {
     fn stream_all_events<A: Aggregate>(
         &self,
     ) -> impl Future<Output = Result<ReplayStream, PersistenceError>> + Send;
+
+    /// Streams the events for an aggregate type that match the filter, with the events of
+    /// each aggregate instance in sequence order.
+    ///
+    /// The default implementation filters the events returned by `stream_all_events` and, as
+    /// these do not carry a commit time, returns an error for filters with a commit time window.
+    /// Repositories should override this to push the filter down into the database.
+    fn stream_filtered_events<A: Aggregate>(
+        &self,
+        filter: &ReplayFilter,
+    ) -> impl Future<Output = Result<ReplayStream, PersistenceError>> + Send {
+        let filter = filter.clone();
+        async move {
+            if filter.committed_from.is_some() || filter.committed_to.is_some() {
+                return Err(PersistenceError::UnknownError(
+                    "commit time filters are not supported by this repository".into(),
+                ));
+            }
+            let events = self.stream_all_events::<A>().await?;
+            Ok(filter_stream(events, filter))
+        }
+    }
+
+    /// Subscribes to the events for an aggregate type, the stream first replays all committed
+    /// events and then follows events as they are committed.
+    ///
+    /// The stream does not end, the subscription is closed when the `ReplayStream` is dropped.
+    ///
+    /// The default implementation returns an error for repositories that cannot follow newly
+    /// committed events.
+    fn subscribe_all_events<A: Aggregate>(
+        &self,
+    ) -> impl Future<Output = Result<ReplayStream, PersistenceError>> + Send {
+        async {
+            Err(PersistenceError::UnknownError(
+                "subscriptions are not supported by this repository".into(),
+            ))
+        }
+    }
+}
+
+// Forwards the events that match the filter, a source stream that ends without completing
+// leaves the filtered stream incomplete as well.
+fn filter_stream(mut events: ReplayStream, filter: ReplayFilter) -> ReplayStream {
+    let (mut feed, stream) = ReplayStream::new(FILTERED_STREAM_CHANNEL_SIZE);
+    tokio::spawn(async move {
+        loop {
+            let next = tokio::select! {
+                () = feed.closed() => return,
+                next = events.next_serialized() => next,
+            };
+            match next {
+                Some(Ok(event)) => {
+                    if filter.matches(&event) && feed.push(Ok(event)).await.is_err() {
+                        return;
+                    }
+                }
+                Some(Err(err)) => {
+                    let _ = feed.push(Err(err)).await;
+                    return;
+                }
+                None => break,
+            }
+        }
+        if events.is_complete() {
+            feed.finish();
+        }
+    });
+    stream
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::SystemTime;
+
+    use futures::TryStreamExt;
+
+    use crate::persist::event_store::shared_test::{
+        test_serialized_event, MockRepo, TestAggregate, TestEvents,
+    };
+    use crate::persist::{PersistedEventRepository, PersistenceError, ReplayFilter};
+
+    #[tokio::test]
+    async fn default_stream_filtered_events() {
+        let repo = MockRepo::with_events(Ok(vec![
+            test_serialized_event(1, TestEvents::Started),
+            test_serialized_event(2, TestEvents::SomethingWasDone),
+            test_serialized_event(3, TestEvents::SomethingWasDone),
+        ]));
+        let filter = ReplayFilter::default()
+            .with_event_types(vec!["SomethingWasDone".to_string()])
+            .with_sequences(1..=2);
+        let stream = repo
+            .stream_filtered_events::<TestAggregate>(&filter)
+            .await
+            .unwrap();
+        let sequences: Vec<usize> = stream
+            .map_ok(|event| event.sequence)
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(vec![2], sequences);
+    }
+
+    #[tokio::test]
+    async fn default_stream_filtered_events_rejects_commit_time() {
+        let repo = MockRepo::with_events(Ok(vec![]));
+        let filter =
+            ReplayFilter::default().committed_between(SystemTime::UNIX_EPOCH, SystemTime::now());
+        let result = repo.stream_filtered_events::<TestAggregate>(&filter).await;
+        assert!(matches!(result, Err(PersistenceError::UnknownError(_))));
+    }
+
+    #[tokio::test]
+    async fn default_subscribe_all_events_is_unsupported() {
+        let repo = MockRepo::with_events(Ok(vec![]));
+        let result = repo.subscribe_all_events::<TestAggregate>().await;
+        assert!(matches!(result, Err(PersistenceError::UnknownError(_))));
+    }
 }
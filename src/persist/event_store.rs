@@ -512,6 +512,7 @@ pub(crate) mod shared_test {
             for event in events {
                 feed.push(Ok(event)).await?;
             }
+            feed.finish();
             Ok(stream)
         }
     }
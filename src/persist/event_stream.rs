@@ -1,19 +1,69 @@
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+
+use futures::{Stream, StreamExt};
+use tokio::sync::mpsc::{Receiver, Sender};
+
 use crate::persist::{EventUpcaster, PersistenceError, SerializedEvent};
 use crate::{Aggregate, EventEnvelope};
-use tokio::sync::mpsc::{Receiver, Sender};
 
 /// Accesses a domain event stream for a particular aggregate.
 ///
-/// _Note: design expected to change after [implementation of RFC 2996](https://github.com/rust-lang/rust/issues/79024)._
+/// `ReplayStream` is a `futures::Stream` of serialized events, use `events` to obtain a stream
+/// of upcasted and deserialized events that works with the `StreamExt` and `TryStreamExt`
+/// combinators.
+///
+/// ```
+/// # use cqrs_es::doc::MyAggregate;
+/// # use cqrs_es::persist::{PersistenceError, ReplayStream};
+/// use futures::TryStreamExt;
+///
+/// async fn export(stream: ReplayStream) -> Result<(), PersistenceError> {
+///     let mut events = stream
+///         .events::<MyAggregate>(&[])
+///         .try_filter(|event| futures::future::ready(event.sequence > 1))
+///         .try_chunks(100);
+///     while let Some(chunk) = events.try_next().await.map_err(|err| err.1)? {
+///         println!("exporting {} events", chunk.len());
+///     }
+///     Ok(())
+/// }
+/// ```
+///
+/// A stream that ends before its `ReplayFeed` has finished, e.g., because the task feeding the
+/// stream has panicked, returns a final error so that a truncated replay is not mistaken for a
+/// complete one.
 pub struct ReplayStream {
     queue: Receiver<Result<SerializedEvent, PersistenceError>>,
+    status: Arc<FeedStatus>,
+    ended: bool,
+}
+
+// Shared between a feed and its stream to determine how the stream ended.
+#[derive(Default)]
+struct FeedStatus {
+    finished: AtomicBool,
+    failed: AtomicBool,
 }
 
 impl ReplayStream {
     /// Creates a new `ReplayStream` that will buffer events up to the `queue_size`.
     pub fn new(queue_size: usize) -> (ReplayFeed, Self) {
         let (sender, queue) = tokio::sync::mpsc::channel(queue_size);
-        (ReplayFeed { sender }, Self { queue })
+        let status = Arc::<FeedStatus>::default();
+        let feed = ReplayFeed {
+            sender,
+            status: status.clone(),
+        };
+        let stream = Self {
+            queue,
+            status,
+            ended: false,
+        };
+        (feed, stream)
     }
 
     /// Receive the next upcasted event or error in the stream, if no event is available this will block.
@@ -21,16 +71,84 @@ impl ReplayStream {
         &mut self,
         upcasters: &[Box<dyn EventUpcaster>],
     ) -> Option<Result<EventEnvelope<A>, PersistenceError>> {
-        self.queue
-            .recv()
+        self.next_serialized()
             .await
             .map(|result| result.and_then(|event| event.upcast(upcasters).try_into()))
     }
+
+    /// Receive the next event or error in the stream without upcasting or deserializing the
+    /// event, if no event is available this will block.
+    pub async fn next_serialized(&mut self) -> Option<Result<SerializedEvent, PersistenceError>> {
+        StreamExt::next(self).await
+    }
+
+    /// Returns true once every event has been received from a feed that finished successfully.
+    pub fn is_complete(&self) -> bool {
+        self.ended && self.status.finished.load(Ordering::SeqCst)
+    }
+
+    // Reports a truncated stream the first time that the end of the stream is reached.
+    fn end(&mut self) -> Option<Result<SerializedEvent, PersistenceError>> {
+        if self.ended {
+            return None;
+        }
+        self.ended = true;
+        if self.status.finished.load(Ordering::SeqCst) || self.status.failed.load(Ordering::SeqCst)
+        {
+            return None;
+        }
+        Some(Err(PersistenceError::UnknownError(
+            "replay stream ended before the feed finished".into(),
+        )))
+    }
+
+    /// Converts this into a stream of events that are upcasted and deserialized with the
+    /// provided upcasters.
+    pub fn events<A: Aggregate>(self, upcasters: &[Box<dyn EventUpcaster>]) -> ReplayEvents<'_, A> {
+        ReplayEvents {
+            stream: self,
+            upcasters,
+            phantom_data: PhantomData,
+        }
+    }
+}
+
+impl Stream for ReplayStream {
+    type Item = Result<SerializedEvent, PersistenceError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match ready!(self.queue.poll_recv(cx)) {
+            Some(result) => Poll::Ready(Some(result)),
+            None => Poll::Ready(self.end()),
+        }
+    }
+}
+
+/// A stream of upcasted and deserialized events, see `ReplayStream::events`.
+pub struct ReplayEvents<'a, A: Aggregate> {
+    stream: ReplayStream,
+    upcasters: &'a [Box<dyn EventUpcaster>],
+    phantom_data: PhantomData<fn() -> A>,
+}
+
+impl<A: Aggregate> Stream for ReplayEvents<'_, A> {
+    type Item = Result<EventEnvelope<A>, PersistenceError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let upcasters = self.upcasters;
+        Pin::new(&mut self.stream).poll_next(cx).map(|next| {
+            next.map(|result| result.and_then(|event| event.upcast(upcasters).try_into()))
+        })
+    }
 }
 
 /// Used to send events to a `ReplayStream` for replaying events.
+///
+/// A feed should either be finished once all events have been pushed, or have an error pushed
+/// onto it, a feed that is dropped otherwise causes the stream to end with an error.
 pub struct ReplayFeed {
     sender: Sender<Result<SerializedEvent, PersistenceError>>,
+    status: Arc<FeedStatus>,
 }
 
 impl ReplayFeed {
@@ -39,14 +157,43 @@ impl ReplayFeed {
         &mut self,
         result: Result<SerializedEvent, PersistenceError>,
     ) -> Result<(), PersistenceError> {
+        if result.is_err() {
+            self.status.failed.store(true, Ordering::SeqCst);
+        }
         self.sender.send(result).await?;
         Ok(())
     }
+
+    /// Marks the stream as complete, the stream ends once any buffered events are received.
+    pub fn finish(self) {
+        self.status.finished.store(true, Ordering::SeqCst);
+    }
+
+    /// Completes once the `ReplayStream` has been dropped, allowing a feed that is waiting on
+    /// new events to stop.
+    pub async fn closed(&self) {
+        self.sender.closed().await;
+    }
 }
 #[cfg(test)]
 mod test {
-    use crate::doc::MyAggregate;
-    use crate::persist::{PersistenceError, ReplayStream};
+    use std::collections::HashMap;
+
+    use futures::{StreamExt, TryStreamExt};
+
+    use crate::doc::{MyAggregate, MyEvents};
+    use crate::persist::{PersistenceError, ReplayStream, SerializedEvent};
+    use crate::EventEnvelope;
+
+    fn serialized_event(sequence: usize) -> SerializedEvent {
+        let event = EventEnvelope::<MyAggregate> {
+            aggregate_id: "agg-1".to_string(),
+            sequence,
+            payload: MyEvents::SomethingWasDone,
+            metadata: HashMap::default(),
+        };
+        SerializedEvent::try_from(&event).unwrap()
+    }
 
     #[tokio::test]
     async fn test_replay_stream() {
@@ -64,4 +211,73 @@ mod test {
             "expected optimistic lock error"
         );
     }
+
+    #[tokio::test]
+    async fn test_replay_stream_combinators() {
+        let (mut feed, stream) = ReplayStream::new(5);
+        tokio::spawn(async move {
+            for sequence in 1..=5 {
+                feed.push(Ok(serialized_event(sequence))).await.unwrap();
+            }
+            feed.finish();
+        });
+        let chunks: Vec<Vec<usize>> = stream
+            .events::<MyAggregate>(&[])
+            .try_filter(|event| futures::future::ready(event.sequence != 3))
+            .map_ok(|event| event.sequence)
+            .try_chunks(2)
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(vec![vec![1, 2], vec![4, 5]], chunks);
+    }
+
+    #[tokio::test]
+    async fn test_replay_stream_serialized() {
+        let (mut feed, stream) = ReplayStream::new(5);
+        feed.push(Ok(serialized_event(1))).await.unwrap();
+        feed.push(Err(PersistenceError::OptimisticLockError))
+            .await
+            .unwrap();
+        drop(feed);
+        let found: Vec<_> = stream.collect().await;
+        assert_eq!(2, found.len());
+        assert_eq!(1, found[0].as_ref().unwrap().sequence);
+        assert!(matches!(
+            found[1],
+            Err(PersistenceError::OptimisticLockError)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_replay_stream_finished() {
+        let (mut feed, mut stream) = ReplayStream::new(5);
+        feed.push(Ok(serialized_event(1))).await.unwrap();
+        feed.finish();
+        assert!(!stream.is_complete());
+        assert_eq!(1, stream.next_serialized().await.unwrap().unwrap().sequence);
+        assert!(stream.next_serialized().await.is_none());
+        assert!(stream.is_complete());
+    }
+
+    #[tokio::test]
+    async fn test_replay_stream_truncated() {
+        let (mut feed, mut stream) = ReplayStream::new(5);
+        feed.push(Ok(serialized_event(1))).await.unwrap();
+        drop(feed);
+        assert_eq!(1, stream.next_serialized().await.unwrap().unwrap().sequence);
+        assert!(matches!(
+            stream.next_serialized().await,
+            Some(Err(PersistenceError::UnknownError(_)))
+        ));
+        assert!(stream.next_serialized().await.is_none());
+        assert!(!stream.is_complete());
+    }
+
+    #[tokio::test]
+    async fn test_replay_feed_closed() {
+        let (feed, stream) = ReplayStream::new(5);
+        drop(stream);
+        feed.closed().await;
+    }
 }
@@ -0,0 +1,796 @@
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{BufRead, BufReader, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::watch;
+
+use crate::persist::{
+    PersistedEventRepository, PersistenceError, ReplayFilter, ReplayStream, SerializedEvent,
+    SerializedSnapshot,
+};
+use crate::Aggregate;
+
+const DEFAULT_STREAMING_CHANNEL_SIZE: usize = 200;
+
+/// A file backed event repository for local development, allowing a `PersistedEventStore` to
+/// keep its events and snapshots in a local directory across restarts.
+///
+/// Each aggregate type is stored in an append-only log named for the aggregate type, e.g.,
+/// `BankAccount.jsonl`, with each commit written as a single line of JSON holding the
+/// committed events and any snapshot update. An index of each aggregate instance's commits
+/// is built when a log is first opened. A commit that was only partially written, e.g.,
+/// because the process crashed, is discarded when the log is opened.
+///
+/// File access is run on the blocking thread pool and every operation on a log is serialized,
+/// this is intended for local development and is not suitable for production use.
+///
+/// The index is held only by the repository that opened the log, so a log may only be used by
+/// a single repository and its clones. An exclusive advisory lock is taken on each log as it is
+/// opened and an error is returned if another repository, in this or another process, holds
+/// the log. The lock is released once the repository and all of its clones are dropped.
+///
+/// ```
+/// # use cqrs_es::doc::{MyAggregate, MyService};
+/// use cqrs_es::CqrsFramework;
+/// use cqrs_es::persist::{FileEventRepository, PersistedEventStore, PersistenceError};
+///
+/// fn configure_cqrs() -> Result<CqrsFramework<MyAggregate, PersistedEventStore<FileEventRepository, MyAggregate>>, PersistenceError> {
+///     let repo = FileEventRepository::new("target/event_logs")?;
+///     let store = PersistedEventStore::new_snapshot_store(repo, 100);
+///     Ok(CqrsFramework::new(store, vec![], MyService))
+/// }
+/// ```
+#[derive(Clone)]
+pub struct FileEventRepository {
+    directory: PathBuf,
+    fsync: bool,
+    stream_channel_size: usize,
+    logs: Arc<Mutex<HashMap<String, Arc<Mutex<EventLog>>>>>,
+}
+
+// A single commit, written as one line of the log.
+#[derive(Serialize, Deserialize)]
+struct CommitRecord {
+    committed_at: u64,
+    events: Vec<EventRecord>,
+    snapshot: Option<SnapshotRecord>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct EventRecord {
+    aggregate_id: String,
+    sequence: usize,
+    event_type: String,
+    event_version: String,
+    payload: Value,
+    metadata: Value,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SnapshotRecord {
+    aggregate_id: String,
+    aggregate: Value,
+    current_sequence: usize,
+    current_snapshot: usize,
+}
+
+#[derive(Default)]
+struct AggregateIndex {
+    last_sequence: usize,
+    // the offsets of the commits holding events for the aggregate instance
+    commits: Vec<u64>,
+    snapshot: Option<SerializedSnapshot>,
+}
+
+struct EventLog {
+    aggregate_type: String,
+    path: PathBuf,
+    file: File,
+    len: u64,
+    index: HashMap<String, AggregateIndex>,
+    committed: watch::Sender<u64>,
+    // set when a failed commit could not be removed from the end of the log
+    poisoned: bool,
+}
+
+impl FileEventRepository {
+    /// Creates a new `FileEventRepository` storing its logs in the provided directory, the
+    /// directory is created if it does not exist.
+    ///
+    /// By default each commit is flushed to disk before it completes.
+    pub fn new(directory: impl Into<PathBuf>) -> Result<Self, PersistenceError> {
+        let directory = directory.into();
+        std::fs::create_dir_all(&directory).map_err(io_error)?;
+        Ok(Self {
+            directory,
+            fsync: true,
+            stream_channel_size: DEFAULT_STREAMING_CHANNEL_SIZE,
+            logs: Arc::default(),
+        })
+    }
+
+    /// Configures whether each commit is flushed to disk before it completes. Disabling this
+    /// is faster but commits may be lost if the machine, rather than the process, crashes.
+    #[must_use]
+    pub fn with_fsync(self, fsync: bool) -> Self {
+        Self { fsync, ..self }
+    }
+
+    /// Configures a `FileEventRepository` to use a streaming queue of the provided size.
+    #[must_use]
+    pub fn with_streaming_channel_size(self, stream_channel_size: usize) -> Self {
+        Self {
+            stream_channel_size,
+            ..self
+        }
+    }
+
+    // Logs are opened, and recovered if needed, the first time that they are used.
+    fn log(&self, aggregate_type: &str) -> Result<Arc<Mutex<EventLog>>, PersistenceError> {
+        // uninteresting unwrap: this is not a struct for production use
+        let mut logs = self.logs.lock().unwrap();
+        if let Some(log) = logs.get(aggregate_type) {
+            return Ok(log.clone());
+        }
+        let path = self.directory.join(format!("{aggregate_type}.jsonl"));
+        let log = Arc::new(Mutex::new(EventLog::open(aggregate_type, path)?));
+        logs.insert(aggregate_type.to_string(), log.clone());
+        Ok(log)
+    }
+
+    // Runs an operation on the log for an aggregate type without blocking the async runtime.
+    async fn with_log<T: Send + 'static>(
+        &self,
+        aggregate_type: &'static str,
+        operation: impl FnOnce(&mut EventLog) -> Result<T, PersistenceError> + Send + 'static,
+    ) -> Result<T, PersistenceError> {
+        let repo = self.clone();
+        run_blocking(move || {
+            let log = repo.log(aggregate_type)?;
+            let mut log = log.lock().unwrap();
+            operation(&mut log)
+        })
+        .await
+    }
+
+    async fn select_events(
+        &self,
+        aggregate_type: &'static str,
+        selected: impl Fn(&SerializedEvent, u64) -> bool + Send + 'static,
+    ) -> Result<Vec<SerializedEvent>, PersistenceError> {
+        self.with_log(aggregate_type, move |log| {
+            let mut events = Vec::new();
+            for record in log.read_records(0, log.len)? {
+                let committed_at = record.committed_at;
+                events.extend(
+                    log.serialized_events(record)
+                        .filter(|event| selected(event, committed_at)),
+                );
+            }
+            Ok(events)
+        })
+        .await
+    }
+
+    fn stream(&self, events: Vec<SerializedEvent>) -> ReplayStream {
+        let (mut feed, stream) = ReplayStream::new(self.stream_channel_size);
+        tokio::spawn(async move {
+            for event in events {
+                if feed.push(Ok(event)).await.is_err() {
+                    return;
+                }
+            }
+            feed.finish();
+        });
+        stream
+    }
+}
+
+impl EventLog {
+    fn open(aggregate_type: &str, path: PathBuf) -> Result<Self, PersistenceError> {
+        let file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(&path)
+            .map_err(io_error)?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                return Err(PersistenceError::UnknownError(
+                    format!(
+                        "the event log {} is in use by another repository",
+                        path.display()
+                    )
+                    .into(),
+                ))
+            }
+            Err(TryLockError::Error(err)) => return Err(io_error(err)),
+        }
+        let file_len = file.metadata().map_err(io_error)?.len();
+        let (committed, _) = watch::channel(0);
+        let mut log = Self {
+            aggregate_type: aggregate_type.to_string(),
+            path,
+            file,
+            len: 0,
+            index: HashMap::new(),
+            committed,
+            poisoned: false,
+        };
+        let mut reader = BufReader::new(File::open(&log.path).map_err(io_error)?);
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            let read = reader.read_until(b'\n', &mut line).map_err(io_error)?;
+            if read == 0 {
+                break;
+            }
+            let record = match serde_json::from_slice::<CommitRecord>(&line) {
+                Ok(record) if line.ends_with(b"\n") => record,
+                // only the final commit can have been torn by a crash
+                _ if reader.fill_buf().map_err(io_error)?.is_empty() => break,
+                Ok(_) => {
+                    return Err(PersistenceError::UnknownError(
+                        format!(
+                            "the event log {} was modified while it was being opened",
+                            log.path.display()
+                        )
+                        .into(),
+                    ))
+                }
+                Err(err) => return Err(PersistenceError::DeserializationError(Box::new(err))),
+            };
+            log.index_record(log.len, &record);
+            log.len += read as u64;
+        }
+        if log.len < file_len {
+            log.file.set_len(log.len).map_err(io_error)?;
+            log.file.sync_all().map_err(io_error)?;
+        }
+        log.committed.send_replace(log.len);
+        Ok(log)
+    }
+
+    fn index_record(&mut self, offset: u64, record: &CommitRecord) {
+        for event in &record.events {
+            let index = self.index.entry(event.aggregate_id.clone()).or_default();
+            index.last_sequence = event.sequence;
+            if index.commits.last() != Some(&offset) {
+                index.commits.push(offset);
+            }
+        }
+        if let Some(snapshot) = &record.snapshot {
+            let index = self.index.entry(snapshot.aggregate_id.clone()).or_default();
+            index.snapshot = Some(SerializedSnapshot {
+                aggregate_id: snapshot.aggregate_id.clone(),
+                aggregate: snapshot.aggregate.clone(),
+                current_sequence: snapshot.current_sequence,
+                current_snapshot: snapshot.current_snapshot,
+            });
+        }
+    }
+
+    // Rejects events that have already been committed, and snapshots that do not directly
+    // follow the current snapshot, as the database backed repositories do.
+    fn check_commit(
+        &self,
+        events: &[SerializedEvent],
+        snapshot: Option<&SnapshotRecord>,
+    ) -> Result<(), PersistenceError> {
+        let mut last_sequences = HashMap::new();
+        for event in events {
+            let last_sequence = last_sequences
+                .entry(event.aggregate_id.as_str())
+                .or_insert_with(|| {
+                    self.index
+                        .get(&event.aggregate_id)
+                        .map_or(0, |index| index.last_sequence)
+                });
+            if event.sequence <= *last_sequence {
+                return Err(PersistenceError::OptimisticLockError);
+            }
+            *last_sequence = event.sequence;
+        }
+        if let Some(snapshot) = snapshot {
+            let current_snapshot = self
+                .index
+                .get(&snapshot.aggregate_id)
+                .and_then(|index| index.snapshot.as_ref())
+                .map_or(0, |current| current.current_snapshot);
+            if current_snapshot + 1 != snapshot.current_snapshot {
+                return Err(PersistenceError::OptimisticLockError);
+            }
+        }
+        Ok(())
+    }
+
+    fn append(&mut self, record: &CommitRecord, fsync: bool) -> Result<(), PersistenceError> {
+        if self.poisoned {
+            return Err(PersistenceError::UnknownError(
+                format!(
+                    "the event log {} holds a failed commit, reopen the repository to recover",
+                    self.path.display()
+                )
+                .into(),
+            ));
+        }
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        if let Err(err) = self.file.write_all(&line) {
+            return Err(self.discard_write(err));
+        }
+        if fsync {
+            if let Err(err) = self.file.sync_data() {
+                return Err(self.discard_write(err));
+            }
+        }
+        self.index_record(self.len, record);
+        self.len += line.len() as u64;
+        self.committed.send_replace(self.len);
+        Ok(())
+    }
+
+    // Removes a failed write so that later commits are not appended to it. If it cannot be
+    // removed the failed commit could reappear when the log is next opened, so no further
+    // commits are accepted.
+    fn discard_write(&mut self, err: std::io::Error) -> PersistenceError {
+        if self.file.set_len(self.len).is_err() {
+            self.poisoned = true;
+        }
+        io_error(err)
+    }
+
+    fn read_record(&self, reader: &mut BufReader<File>) -> Result<CommitRecord, PersistenceError> {
+        let mut line = Vec::new();
+        reader.read_until(b'\n', &mut line).map_err(io_error)?;
+        Ok(serde_json::from_slice(&line)?)
+    }
+
+    // Reads the commits written between the two offsets.
+    fn read_records(&self, from: u64, to: u64) -> Result<Vec<CommitRecord>, PersistenceError> {
+        let mut reader = self.reader(from)?;
+        let mut records = Vec::new();
+        let mut offset = from;
+        while offset < to {
+            records.push(self.read_record(&mut reader)?);
+            offset = reader.stream_position().map_err(io_error)?;
+        }
+        Ok(records)
+    }
+
+    fn reader(&self, offset: u64) -> Result<BufReader<File>, PersistenceError> {
+        let mut reader = BufReader::new(File::open(&self.path).map_err(io_error)?);
+        reader.seek(SeekFrom::Start(offset)).map_err(io_error)?;
+        Ok(reader)
+    }
+
+    fn aggregate_events(
+        &self,
+        aggregate_id: &str,
+    ) -> Result<Vec<SerializedEvent>, PersistenceError> {
+        let Some(index) = self.index.get(aggregate_id) else {
+            return Ok(Vec::new());
+        };
+        let mut events = Vec::new();
+        let mut reader = self.reader(0)?;
+        for offset in &index.commits {
+            // commits are indexed in the order they were written, the reader only moves forwards
+            let position = reader.stream_position().map_err(io_error)?;
+            reader
+                .seek_relative((offset - position) as i64)
+                .map_err(io_error)?;
+            let record = self.read_record(&mut reader)?;
+            events.extend(
+                self.serialized_events(record)
+                    .filter(|event| event.aggregate_id == aggregate_id),
+            );
+        }
+        Ok(events)
+    }
+
+    fn serialized_events(
+        &self,
+        record: CommitRecord,
+    ) -> impl Iterator<Item = SerializedEvent> + '_ {
+        record.events.into_iter().map(|event| SerializedEvent {
+            aggregate_id: event.aggregate_id,
+            sequence: event.sequence,
+            aggregate_type: self.aggregate_type.clone(),
+            event_type: event.event_type,
+            event_version: event.event_version,
+            payload: event.payload,
+            metadata: event.metadata,
+        })
+    }
+}
+
+fn io_error(err: std::io::Error) -> PersistenceError {
+    PersistenceError::UnknownError(Box::new(err))
+}
+
+async fn run_blocking<T: Send + 'static>(
+    operation: impl FnOnce() -> Result<T, PersistenceError> + Send + 'static,
+) -> Result<T, PersistenceError> {
+    tokio::task::spawn_blocking(operation)
+        .await
+        .map_err(|err| PersistenceError::UnknownError(Box::new(err)))?
+}
+
+fn epoch_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+impl PersistedEventRepository for FileEventRepository {
+    async fn get_events<A: Aggregate>(
+        &self,
+        aggregate_id: &str,
+    ) -> Result<Vec<SerializedEvent>, PersistenceError> {
+        let aggregate_id = aggregate_id.to_string();
+        self.with_log(A::TYPE, move |log| log.aggregate_events(&aggregate_id))
+            .await
+    }
+
+    async fn get_last_events<A: Aggregate>(
+        &self,
+        aggregate_id: &str,
+        last_sequence: usize,
+    ) -> Result<Vec<SerializedEvent>, PersistenceError> {
+        let mut events = self.get_events::<A>(aggregate_id).await?;
+        events.retain(|event| event.sequence > last_sequence);
+        Ok(events)
+    }
+
+    async fn get_snapshot<A: Aggregate>(
+        &self,
+        aggregate_id: &str,
+    ) -> Result<Option<SerializedSnapshot>, PersistenceError> {
+        let aggregate_id = aggregate_id.to_string();
+        self.with_log(A::TYPE, move |log| {
+            Ok(log
+                .index
+                .get(&aggregate_id)
+                .and_then(|index| index.snapshot.clone()))
+        })
+        .await
+    }
+
+    async fn persist<A: Aggregate>(
+        &self,
+        events: &[SerializedEvent],
+        snapshot_update: Option<(String, Value, usize)>,
+    ) -> Result<(), PersistenceError> {
+        let snapshot =
+            snapshot_update.map(
+                |(aggregate_id, aggregate, current_snapshot)| SnapshotRecord {
+                    aggregate_id,
+                    aggregate,
+                    current_sequence: events.last().map_or(0, |event| event.sequence),
+                    current_snapshot,
+                },
+            );
+        let record = CommitRecord {
+            committed_at: epoch_millis(SystemTime::now()),
+            events: events
+                .iter()
+                .map(|event| EventRecord {
+                    aggregate_id: event.aggregate_id.clone(),
+                    sequence: event.sequence,
+                    event_type: event.event_type.clone(),
+                    event_version: event.event_version.clone(),
+                    payload: event.payload.clone(),
+                    metadata: event.metadata.clone(),
+                })
+                .collect(),
+            snapshot,
+        };
+        let events = events.to_vec();
+        let fsync = self.fsync;
+        self.with_log(A::TYPE, move |log| {
+            log.check_commit(&events, record.snapshot.as_ref())?;
+            log.append(&record, fsync)
+        })
+        .await
+    }
+
+    async fn stream_events<A: Aggregate>(
+        &self,
+        aggregate_id: &str,
+    ) -> Result<ReplayStream, PersistenceError> {
+        let events = self.get_events::<A>(aggregate_id).await?;
+        Ok(self.stream(events))
+    }
+
+    async fn stream_all_events<A: Aggregate>(&self) -> Result<ReplayStream, PersistenceError> {
+        let events = self.select_events(A::TYPE, |_, _| true).await?;
+        Ok(self.stream(events))
+    }
+
+    async fn stream_filtered_events<A: Aggregate>(
+        &self,
+        filter: &ReplayFilter,
+    ) -> Result<ReplayStream, PersistenceError> {
+        let filter = filter.clone();
+        let events = self
+            .select_events(A::TYPE, move |event, committed_at| {
+                let committed_at = UNIX_EPOCH + Duration::from_millis(committed_at);
+                filter.matches(event) && filter.is_committed_within(committed_at)
+            })
+            .await?;
+        Ok(self.stream(events))
+    }
+
+    // Commits are followed by their offset within the log, a watch on the length of the log
+    // wakes the subscription as new commits are appended.
+    async fn subscribe_all_events<A: Aggregate>(&self) -> Result<ReplayStream, PersistenceError> {
+        let repo = self.clone();
+        let log = run_blocking(move || repo.log(A::TYPE)).await?;
+        let mut committed = log.lock().unwrap().committed.subscribe();
+        let (mut feed, stream) = ReplayStream::new(self.stream_channel_size);
+        tokio::spawn(async move {
+            let mut position = 0;
+            loop {
+                committed.borrow_and_update();
+                let from = position;
+                let log = log.clone();
+                let events = run_blocking(move || {
+                    let log = log.lock().unwrap();
+                    let records = log.read_records(from, log.len)?;
+                    let events = records
+                        .into_iter()
+                        .flat_map(|record| log.serialized_events(record))
+                        .collect::<Vec<_>>();
+                    Ok((log.len, events))
+                })
+                .await;
+                let events = match events {
+                    Ok((len, events)) => {
+                        position = len;
+                        events
+                    }
+                    Err(err) => {
+                        let _ = feed.push(Err(err)).await;
+                        return;
+                    }
+                };
+                for event in events {
+                    if feed.push(Ok(event)).await.is_err() {
+                        return;
+                    }
+                }
+                tokio::select! {
+                    () = feed.closed() => return,
+                    _ = committed.changed() => {}
+                }
+            }
+        });
+        Ok(stream)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use crate::doc::MyAggregate;
+    use crate::persist::{
+        FileEventRepository, PersistedEventRepository, PersistenceError, ReplayFilter,
+        SerializedEvent,
+    };
+
+    fn test_directory() -> PathBuf {
+        std::env::temp_dir().join(format!("cqrs-es-{}", uuid::Uuid::new_v4()))
+    }
+
+    fn event(aggregate_id: &str, sequence: usize) -> SerializedEvent {
+        SerializedEvent::new(
+            aggregate_id.to_string(),
+            sequence,
+            "MyAggregate".to_string(),
+            "SomethingWasDone".to_string(),
+            "0.1.0".to_string(),
+            json!("SomethingWasDone"),
+            json!({}),
+        )
+    }
+
+    #[tokio::test]
+    async fn events_and_snapshots_survive_reopening() {
+        let directory = test_directory();
+        let repo = FileEventRepository::new(&directory).unwrap();
+        repo.persist::<MyAggregate>(&[event("agg-1", 1), event("agg-2", 1)], None)
+            .await
+            .unwrap();
+        let snapshot = Some(("agg-1".to_string(), json!({"state": 2}), 1));
+        repo.persist::<MyAggregate>(&[event("agg-1", 2)], snapshot)
+            .await
+            .unwrap();
+        let result = repo
+            .persist::<MyAggregate>(&[event("agg-1", 2)], None)
+            .await;
+        assert!(matches!(result, Err(PersistenceError::OptimisticLockError)));
+
+        drop(repo);
+        let repo = FileEventRepository::new(&directory).unwrap();
+        let events = repo.get_events::<MyAggregate>("agg-1").await.unwrap();
+        assert_eq!(vec![event("agg-1", 1), event("agg-1", 2)], events);
+        let snapshot = repo
+            .get_snapshot::<MyAggregate>("agg-1")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            (json!({"state": 2}), 2, 1),
+            (
+                snapshot.aggregate,
+                snapshot.current_sequence,
+                snapshot.current_snapshot
+            )
+        );
+        let last_events = repo
+            .get_last_events::<MyAggregate>("agg-1", 1)
+            .await
+            .unwrap();
+        assert_eq!(vec![event("agg-1", 2)], last_events);
+
+        let mut stream = repo.stream_all_events::<MyAggregate>().await.unwrap();
+        let mut found = Vec::new();
+        while let Some(event) = stream.next_serialized().await {
+            let event = event.unwrap();
+            found.push((event.aggregate_id, event.sequence));
+        }
+        assert_eq!(
+            vec![
+                ("agg-1".to_string(), 1),
+                ("agg-2".to_string(), 1),
+                ("agg-1".to_string(), 2)
+            ],
+            found
+        );
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn torn_writes_are_discarded() {
+        let directory = test_directory();
+        let repo = FileEventRepository::new(&directory).unwrap();
+        repo.persist::<MyAggregate>(&[event("agg-1", 1)], None)
+            .await
+            .unwrap();
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(directory.join("MyAggregate.jsonl"))
+            .unwrap();
+        file.write_all(br#"{"committed_at":1,"events":[{"aggregate_id":"agg-1","#)
+            .unwrap();
+
+        drop(repo);
+        let repo = FileEventRepository::new(&directory).unwrap();
+        repo.persist::<MyAggregate>(&[event("agg-1", 2)], None)
+            .await
+            .unwrap();
+        drop(repo);
+        let repo = FileEventRepository::new(&directory).unwrap();
+        let events = repo.get_events::<MyAggregate>("agg-1").await.unwrap();
+        assert_eq!(vec![event("agg-1", 1), event("agg-1", 2)], events);
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn poisoned_logs_reject_commits() {
+        let directory = test_directory();
+        let repo = FileEventRepository::new(&directory).unwrap();
+        repo.persist::<MyAggregate>(&[event("agg-1", 1)], None)
+            .await
+            .unwrap();
+        // as if a failed commit could not be removed from the end of the log
+        repo.log("MyAggregate").unwrap().lock().unwrap().poisoned = true;
+        let result = repo
+            .persist::<MyAggregate>(&[event("agg-1", 2)], None)
+            .await;
+        assert!(matches!(result, Err(PersistenceError::UnknownError(_))));
+        let events = repo.get_events::<MyAggregate>("agg-1").await.unwrap();
+        assert_eq!(vec![event("agg-1", 1)], events);
+
+        drop(repo);
+        let repo = FileEventRepository::new(&directory).unwrap();
+        repo.persist::<MyAggregate>(&[event("agg-1", 2)], None)
+            .await
+            .unwrap();
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn logs_are_locked_while_in_use() {
+        let directory = test_directory();
+        let repo = FileEventRepository::new(&directory).unwrap();
+        repo.persist::<MyAggregate>(&[event("agg-1", 1)], None)
+            .await
+            .unwrap();
+
+        let other_repo = FileEventRepository::new(&directory).unwrap();
+        let result = other_repo.get_events::<MyAggregate>("agg-1").await;
+        assert!(matches!(result, Err(PersistenceError::UnknownError(_))));
+
+        // clones share the open log
+        let events = repo
+            .clone()
+            .get_events::<MyAggregate>("agg-1")
+            .await
+            .unwrap();
+        assert_eq!(vec![event("agg-1", 1)], events);
+
+        drop(repo);
+        let events = other_repo.get_events::<MyAggregate>("agg-1").await.unwrap();
+        assert_eq!(vec![event("agg-1", 1)], events);
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn stream_filtered_events() {
+        let directory = test_directory();
+        let repo = FileEventRepository::new(&directory)
+            .unwrap()
+            .with_fsync(false);
+        repo.persist::<MyAggregate>(&[event("agg-1", 1), event("agg-1", 2)], None)
+            .await
+            .unwrap();
+        repo.persist::<MyAggregate>(&[event("agg-2", 1)], None)
+            .await
+            .unwrap();
+
+        let filter = ReplayFilter::default().with_sequences(2..=5);
+        let mut stream = repo
+            .stream_filtered_events::<MyAggregate>(&filter)
+            .await
+            .unwrap();
+        let event = stream.next_serialized().await.unwrap().unwrap();
+        assert_eq!(("agg-1", 2), (event.aggregate_id.as_str(), event.sequence));
+        assert!(stream.next_serialized().await.is_none());
+        assert!(stream.is_complete());
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn subscription_follows_committed_events() {
+        let directory = test_directory();
+        let repo = FileEventRepository::new(&directory).unwrap();
+        repo.persist::<MyAggregate>(&[event("agg-1", 1)], None)
+            .await
+            .unwrap();
+        let mut stream = repo.subscribe_all_events::<MyAggregate>().await.unwrap();
+        repo.persist::<MyAggregate>(&[event("agg-1", 2), event("agg-2", 1)], None)
+            .await
+            .unwrap();
+
+        let mut found = Vec::new();
+        while found.len() < 3 {
+            let next = tokio::time::timeout(Duration::from_secs(1), stream.next_serialized())
+                .await
+                .expect("timed out waiting for subscribed events")
+                .unwrap()
+                .unwrap();
+            found.push((next.aggregate_id, next.sequence));
+        }
+        assert_eq!(
+            vec![
+                ("agg-1".to_string(), 1),
+                ("agg-1".to_string(), 2),
+                ("agg-2".to_string(), 1)
+            ],
+            found
+        );
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
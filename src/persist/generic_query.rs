@@ -1,11 +1,20 @@
+use std::collections::{HashMap, HashSet};
 use std::marker::PhantomData;
 use std::sync::Arc;
 
 use async_trait::async_trait;
+use serde_json::Value;
 
-use crate::persist::{PersistenceError, ViewContext, ViewRepository};
+use crate::persist::view_rebuild::RebuildState;
+use crate::persist::{
+    PersistedEventRepository, PersistenceError, ShadowViewRepository, ViewContext, ViewRebuild,
+    ViewRepository,
+};
 use crate::{Aggregate, EventEnvelope, Query, View};
 
+// Number of times an update is reapplied after a conflicting update to the same view.
+const DEFAULT_OPTIMISTIC_LOCK_RETRIES: usize = 3;
+
 /// A simple query and view repository. This is used both to act as a `Query` for processing events
 /// and to return materialized views.
 pub struct GenericQuery<R, V, A>
@@ -16,6 +25,9 @@ where
 {
     view_repository: Arc<R>,
     error_handler: Option<Box<QueryErrorHandler>>,
+    view_id_mapper: Option<Arc<ViewIdMapper<A>>>,
+    optimistic_lock_retries: usize,
+    rebuild: Arc<RebuildState<A>>,
     phantom: PhantomData<(V, A)>,
 }
 
@@ -41,6 +53,9 @@ where
         Self {
             view_repository,
             error_handler: None,
+            view_id_mapper: None,
+            optimistic_lock_retries: DEFAULT_OPTIMISTIC_LOCK_RETRIES,
+            rebuild: Arc::default(),
             phantom: PhantomData,
         }
     }
@@ -66,6 +81,57 @@ where
         self.error_handler = Some(error_handler);
     }
 
+    /// Sets the number of times that events are reapplied to a freshly loaded view when the
+    /// update fails due to a conflicting update of the same view, defaults to 3.
+    /// Once the retries are exhausted the `OptimisticLockError` is passed to the error handler.
+    ///
+    /// ```
+    /// # use cqrs_es::doc::MyAggregate;
+    /// # use cqrs_es::persist::GenericQuery;
+    /// # use cqrs_es::persist::doc::{MyViewRepository,MyView};
+    /// # fn config(mut query: GenericQuery<MyViewRepository,MyView,MyAggregate>) {
+    /// query.use_optimistic_lock_retries(5);
+    /// # }
+    /// ```
+    pub fn use_optimistic_lock_retries(&mut self, retries: usize) {
+        self.optimistic_lock_retries = retries;
+    }
+
+    /// Allows the user to choose which views are updated by each event, by default every event
+    /// updates the single view with a view id matching its aggregate id.
+    ///
+    /// The mapper returns the ids of the views that should be updated with an event, an
+    /// empty `Vec` if the event does not affect any view. This allows views such as
+    /// "accounts per customer" or "daily totals" that span many aggregate instances.
+    ///
+    /// Duplicate or out-of-order events are only ignored for a view whose id matches the
+    /// aggregate id of the event, the sequence of the last event applied is not kept for any
+    /// other aggregate instances. Views updated by events from other aggregate instances should
+    /// tolerate an event being applied more than once.
+    ///
+    /// _Example: Views of the events committed each day._
+    /// ```
+    /// # use cqrs_es::doc::MyAggregate;
+    /// # use cqrs_es::persist::GenericQuery;
+    /// # use cqrs_es::persist::doc::{MyViewRepository,MyView};
+    /// # fn config(mut query: GenericQuery<MyViewRepository,MyView,MyAggregate>) {
+    /// query.use_view_id_mapper(Box::new(|event| match event.metadata.get("date") {
+    ///     Some(date) => vec![date.to_string()],
+    ///     None => vec![],
+    /// }));
+    /// # }
+    /// ```
+    pub fn use_view_id_mapper(&mut self, view_id_mapper: Box<ViewIdMapper<A>>) {
+        self.view_id_mapper = Some(Arc::from(view_id_mapper));
+    }
+
+    pub(crate) fn with_view_id_mapper(self, view_id_mapper: Option<Arc<ViewIdMapper<A>>>) -> Self {
+        Self {
+            view_id_mapper,
+            ..self
+        }
+    }
+
     /// Loads and deserializes a view based on the provided view id.
     /// Use this method to load a materialized view when requested by a user.
     ///
@@ -95,16 +161,178 @@ where
             .unwrap_or_else(|| (Default::default(), ViewContext::new(view_id, 0))))
     }
 
-    pub(crate) async fn apply_events(
+    // Groups the events by the views that they update, preserving the order of the events.
+    // Without a view id mapper events update the view provided, or the view for their aggregate
+    // instance if no view is provided.
+    fn events_by_view<'a>(
+        &self,
+        view_id: Option<&str>,
+        events: &'a [EventEnvelope<A>],
+    ) -> Vec<(String, Vec<&'a EventEnvelope<A>>)> {
+        let mut events_by_view: Vec<(String, Vec<&EventEnvelope<A>>)> = Vec::new();
+        let mut positions: HashMap<String, usize> = HashMap::new();
+        for event in events {
+            let view_ids = match (&self.view_id_mapper, view_id) {
+                (Some(view_id_mapper), _) => view_id_mapper(event),
+                (None, Some(view_id)) => vec![view_id.to_string()],
+                (None, None) => vec![event.aggregate_id.clone()],
+            };
+            for view_id in view_ids {
+                match positions.get(&view_id) {
+                    Some(position) => events_by_view[*position].1.push(event),
+                    None => {
+                        positions.insert(view_id.clone(), events_by_view.len());
+                        events_by_view.push((view_id, vec![event]));
+                    }
+                }
+            }
+        }
+        events_by_view
+    }
+
+    /// Applies the events to each of the views that they update.
+    pub(crate) async fn update_views(
         &self,
         view_id: &str,
         events: &[EventEnvelope<A>],
     ) -> Result<(), PersistenceError> {
-        let (mut view, view_context) = self.load_mut(view_id.to_string()).await?;
-        for event in events {
-            view.update(event);
+        for (view_id, view_events) in self.events_by_view(Some(view_id), events) {
+            self.apply_events(&view_id, &view_events).await?;
+        }
+        Ok(())
+    }
+
+    /// Applies a batch of events from many aggregate instances, loading and writing the
+    /// affected views in bulk.
+    ///
+    /// If the bulk update fails due to a conflicting update, the views that were not written are
+    /// instead updated individually.
+    pub(crate) async fn update_views_in_batch(
+        &self,
+        events: &[EventEnvelope<A>],
+    ) -> Result<(), PersistenceError> {
+        let events_by_view = self.events_by_view(None, events);
+        let view_ids: Vec<String> = events_by_view
+            .iter()
+            .map(|(view_id, _)| view_id.clone())
+            .collect();
+        let mut loaded: HashMap<String, (V, ViewContext)> = self
+            .view_repository
+            .load_all_with_context(&view_ids)
+            .await?
+            .into_iter()
+            .map(|(view, context)| (context.view_instance_id.clone(), (view, context)))
+            .collect();
+        let mut updates = Vec::new();
+        let mut deletes = Vec::new();
+        for (view_id, view_events) in &events_by_view {
+            let (mut view, mut view_context) = loaded
+                .remove(view_id)
+                .unwrap_or_else(|| (V::default(), ViewContext::new(view_id.clone(), 0)));
+            if !apply_to_view(&mut view, &mut view_context, view_events) {
+                continue;
+            }
+            if !view.is_deleted() {
+                updates.push((view, view_context));
+            } else if view_context.version > 0 {
+                deletes.push(view_context);
+            }
+        }
+        // kept in case a conflict leaves only some of the views written
+        let expected = updates
+            .iter()
+            .map(|(view, context)| {
+                let payload = serde_json::to_value(view)?;
+                Ok((
+                    context.view_instance_id.clone(),
+                    (context.version + 1, payload),
+                ))
+            })
+            .collect::<Result<HashMap<String, (i64, Value)>, PersistenceError>>()?;
+        match self.view_repository.update_all(updates).await {
+            Err(PersistenceError::OptimisticLockError) => {
+                // reapplying events to a view that was written would apply them twice
+                let written = self.written_views(expected).await?;
+                for (view_id, view_events) in &events_by_view {
+                    if !written.contains(view_id) {
+                        self.apply_events(view_id, view_events).await?;
+                    }
+                }
+                return Ok(());
+            }
+            result => result?,
+        }
+        for view_context in deletes {
+            let view_id = view_context.view_instance_id.clone();
+            match self.view_repository.delete_view(view_context).await {
+                Err(PersistenceError::OptimisticLockError) => {
+                    let (_, view_events) = events_by_view
+                        .iter()
+                        .find(|(id, _)| *id == view_id)
+                        .expect("deleted views have events");
+                    self.apply_events(&view_id, view_events).await?;
+                }
+                result => result?,
+            }
+        }
+        Ok(())
+    }
+
+    // Finds the views holding the expected version and payload following a bulk update that
+    // may have only been partially written.
+    async fn written_views(
+        &self,
+        expected: HashMap<String, (i64, Value)>,
+    ) -> Result<HashSet<String>, PersistenceError> {
+        let view_ids: Vec<String> = expected.keys().cloned().collect();
+        let mut written = HashSet::new();
+        for (view, context) in self
+            .view_repository
+            .load_all_with_context(&view_ids)
+            .await?
+        {
+            let Some((version, payload)) = expected.get(&context.view_instance_id) else {
+                continue;
+            };
+            if context.version == *version && serde_json::to_value(&view)? == *payload {
+                written.insert(context.view_instance_id);
+            }
+        }
+        Ok(written)
+    }
+
+    async fn apply_events(
+        &self,
+        view_id: &str,
+        events: &[&EventEnvelope<A>],
+    ) -> Result<(), PersistenceError> {
+        let mut retries = 0;
+        loop {
+            match self.try_apply_events(view_id, events).await {
+                Err(PersistenceError::OptimisticLockError)
+                    if retries < self.optimistic_lock_retries =>
+                {
+                    retries += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn try_apply_events(
+        &self,
+        view_id: &str,
+        events: &[&EventEnvelope<A>],
+    ) -> Result<(), PersistenceError> {
+        let (mut view, mut view_context) = self.load_mut(view_id.to_string()).await?;
+        if !apply_to_view(&mut view, &mut view_context, events) {
+            return Ok(());
+        }
+        if !view.is_deleted() {
+            self.view_repository.update_view(view, view_context).await?;
+        } else if view_context.version > 0 {
+            self.view_repository.delete_view(view_context).await?;
         }
-        self.view_repository.update_view(view, view_context).await?;
         Ok(())
     }
 
@@ -115,6 +343,62 @@ where
     }
 }
 
+// Applies the events to the view, returning false if all of the events had already been applied.
+fn apply_to_view<V, A>(
+    view: &mut V,
+    view_context: &mut ViewContext,
+    events: &[&EventEnvelope<A>],
+) -> bool
+where
+    V: View<A>,
+    A: Aggregate,
+{
+    let mut updated = false;
+    for event in events {
+        // Only events from the aggregate instance sharing the view's id are tracked,
+        // the sequences of events from different aggregate instances are unrelated.
+        if event.aggregate_id == view_context.view_instance_id {
+            if event.sequence <= view_context.last_sequence {
+                continue;
+            }
+            view_context.last_sequence = event.sequence;
+        }
+        view.update(event);
+        updated = true;
+    }
+    updated
+}
+
+impl<R, V, A> GenericQuery<R, V, A>
+where
+    R: ShadowViewRepository<V, A>,
+    V: View<A>,
+    A: Aggregate,
+{
+    /// Creates a `ViewRebuild` that will rebuild the views of this query from the events held
+    /// in the provided event repository.
+    ///
+    /// ```
+    /// # use cqrs_es::doc::{MyAggregate, MyRepository};
+    /// # use cqrs_es::persist::GenericQuery;
+    /// # use cqrs_es::persist::doc::{MyView, MyViewRepository};
+    /// # async fn config(query: GenericQuery<MyViewRepository, MyView, MyAggregate>, repo: MyRepository) {
+    /// let rebuild = query.rebuilder(repo);
+    /// # }
+    /// ```
+    pub fn rebuilder<ER: PersistedEventRepository>(
+        &self,
+        event_repository: ER,
+    ) -> ViewRebuild<ER, R, V, A> {
+        ViewRebuild::new(
+            event_repository,
+            self.view_repository.clone(),
+            self.view_id_mapper.clone(),
+            self.rebuild.clone(),
+        )
+    }
+}
+
 #[async_trait]
 impl<R, V, A> Query<A> for GenericQuery<R, V, A>
 where
@@ -123,9 +407,28 @@ where
     A: Aggregate,
 {
     async fn dispatch(&self, view_id: &str, events: &[EventEnvelope<A>]) {
-        if let Err(err) = self.apply_events(view_id, events).await {
+        let epoch = self.rebuild.epoch();
+        let _switch = self.rebuild.enter().await;
+        if self.rebuild.is_superseded(epoch) {
+            // these events were applied by a rebuild that has since replaced the views
+            return;
+        }
+        for (view_id, view_events) in self.events_by_view(Some(view_id), events) {
+            if let Err(err) = self.apply_events(&view_id, &view_events).await {
+                self.handle_error(err);
+            };
+        }
+    }
+
+    async fn dispatch_batch(&self, events: &[EventEnvelope<A>]) {
+        let epoch = self.rebuild.epoch();
+        let _switch = self.rebuild.enter().await;
+        if self.rebuild.is_superseded(epoch) {
+            return;
+        }
+        if let Err(err) = self.update_views_in_batch(events).await {
             self.handle_error(err);
-        };
+        }
     }
 }
 
@@ -149,3 +452,380 @@ where
 /// }
 /// ```
 pub type QueryErrorHandler = dyn Fn(PersistenceError) + Send + Sync + 'static;
+
+/// A convenience type for mapping events to the ids of the views they update.
+///
+/// A view id mapper takes a single `EventEnvelope` and returns the ids of all views that should
+/// be updated with the event. Redelivered events are only ignored by the view whose id matches
+/// the event's aggregate id.
+///
+/// ```rust
+/// use cqrs_es::doc::MyAggregate;
+/// use cqrs_es::persist::ViewIdMapper;
+/// fn create_view_id_mapper() {
+///     // Updates both the view for the aggregate instance and a view of all aggregates.
+///     let view_id_mapper: Box<ViewIdMapper<MyAggregate>> =
+///         Box::new(|event| vec![event.aggregate_id.clone(), "all".to_string()]);
+/// }
+/// ```
+pub type ViewIdMapper<A> = dyn Fn(&EventEnvelope<A>) -> Vec<String> + Send + Sync + 'static;
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    use serde::{Deserialize, Serialize};
+
+    use crate::persist::event_store::shared_test::{
+        test_serialized_event, MockRepo, TestAggregate, TestEvents, TEST_AGGREGATE_ID,
+    };
+    use crate::persist::{
+        GenericQuery, PersistenceError, QueryReplay, ViewContext, ViewRepository,
+    };
+    use crate::{EventEnvelope, Query, View};
+
+    #[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+    struct ClosableView {
+        started: bool,
+        closed: bool,
+    }
+
+    impl View<TestAggregate> for ClosableView {
+        fn update(&mut self, event: &EventEnvelope<TestAggregate>) {
+            match event.payload {
+                TestEvents::Started => self.started = true,
+                TestEvents::SomethingWasDone => self.closed = true,
+            }
+        }
+
+        fn is_deleted(&self) -> bool {
+            self.closed
+        }
+    }
+
+    #[derive(Default)]
+    struct TestViewRepository {
+        views: Mutex<HashMap<String, (ClosableView, i64, usize)>>,
+        // the number of updates that should fail as if a conflicting update had been made
+        conflicts: Mutex<usize>,
+        // views whose next update should fail as if a conflicting update had been made
+        conflicting_views: Mutex<Vec<String>>,
+        // whether deletes should fail as if the database could not be reached
+        failing_deletes: Mutex<bool>,
+    }
+
+    impl ViewRepository<ClosableView, TestAggregate> for TestViewRepository {
+        async fn load(&self, view_id: &str) -> Result<Option<ClosableView>, PersistenceError> {
+            Ok(self.load_with_context(view_id).await?.map(|(view, _)| view))
+        }
+
+        async fn load_with_context(
+            &self,
+            view_id: &str,
+        ) -> Result<Option<(ClosableView, ViewContext)>, PersistenceError> {
+            let views = self.views.lock().unwrap();
+            Ok(views.get(view_id).map(|(view, version, last_sequence)| {
+                let context = ViewContext::new(view_id.to_string(), *version)
+                    .with_last_sequence(*last_sequence);
+                (view.clone(), context)
+            }))
+        }
+
+        async fn update_view(
+            &self,
+            view: ClosableView,
+            context: ViewContext,
+        ) -> Result<(), PersistenceError> {
+            let mut conflicts = self.conflicts.lock().unwrap();
+            if *conflicts > 0 {
+                *conflicts -= 1;
+                return Err(PersistenceError::OptimisticLockError);
+            }
+            let mut conflicting_views = self.conflicting_views.lock().unwrap();
+            if let Some(position) = conflicting_views
+                .iter()
+                .position(|view_id| *view_id == context.view_instance_id)
+            {
+                conflicting_views.remove(position);
+                return Err(PersistenceError::OptimisticLockError);
+            }
+            self.views.lock().unwrap().insert(
+                context.view_instance_id,
+                (view, context.version + 1, context.last_sequence),
+            );
+            Ok(())
+        }
+
+        async fn delete_view(&self, context: ViewContext) -> Result<(), PersistenceError> {
+            if *self.failing_deletes.lock().unwrap() {
+                return Err(PersistenceError::ConnectionError("connection lost".into()));
+            }
+            let mut views = self.views.lock().unwrap();
+            match views.get(&context.view_instance_id) {
+                Some((_, version, _)) if *version == context.version => {
+                    views.remove(&context.view_instance_id);
+                    Ok(())
+                }
+                _ => Err(PersistenceError::OptimisticLockError),
+            }
+        }
+
+        async fn clear_all(&self) -> Result<(), PersistenceError> {
+            self.views.lock().unwrap().clear();
+            Ok(())
+        }
+    }
+
+    fn envelope(sequence: usize, payload: TestEvents) -> EventEnvelope<TestAggregate> {
+        EventEnvelope {
+            aggregate_id: TEST_AGGREGATE_ID.to_string(),
+            sequence,
+            payload,
+            metadata: HashMap::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn deleted_view_is_removed() {
+        let view_repo = Arc::new(TestViewRepository::default());
+        let mut query = GenericQuery::<_, ClosableView, TestAggregate>::new(view_repo.clone());
+        query.use_error_handler(Box::new(|e| panic!("{e}")));
+
+        query
+            .dispatch(TEST_AGGREGATE_ID, &[envelope(1, TestEvents::Started)])
+            .await;
+        let expected = ClosableView {
+            started: true,
+            closed: false,
+        };
+        assert_eq!(Some(expected), query.load(TEST_AGGREGATE_ID).await);
+
+        query
+            .dispatch(
+                TEST_AGGREGATE_ID,
+                &[envelope(2, TestEvents::SomethingWasDone)],
+            )
+            .await;
+        assert_eq!(None, query.load(TEST_AGGREGATE_ID).await);
+    }
+
+    #[tokio::test]
+    async fn view_deleted_before_being_stored_is_not_persisted() {
+        let view_repo = Arc::new(TestViewRepository::default());
+        let mut query = GenericQuery::<_, ClosableView, TestAggregate>::new(view_repo.clone());
+        query.use_error_handler(Box::new(|e| panic!("{e}")));
+
+        query
+            .dispatch(
+                TEST_AGGREGATE_ID,
+                &[
+                    envelope(1, TestEvents::Started),
+                    envelope(2, TestEvents::SomethingWasDone),
+                ],
+            )
+            .await;
+        assert!(view_repo.views.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn events_update_mapped_views() {
+        let view_repo = Arc::new(TestViewRepository::default());
+        let mut query = GenericQuery::<_, ClosableView, TestAggregate>::new(view_repo.clone());
+        query.use_error_handler(Box::new(|e| panic!("{e}")));
+        query.use_view_id_mapper(Box::new(|event| match event.payload {
+            TestEvents::Started => vec!["started".to_string(), "all".to_string()],
+            TestEvents::SomethingWasDone => vec![],
+        }));
+
+        query
+            .dispatch(
+                TEST_AGGREGATE_ID,
+                &[
+                    envelope(1, TestEvents::Started),
+                    envelope(2, TestEvents::SomethingWasDone),
+                ],
+            )
+            .await;
+        let expected = ClosableView {
+            started: true,
+            closed: false,
+        };
+        assert_eq!(Some(expected.clone()), query.load("started").await);
+        assert_eq!(Some(expected), query.load("all").await);
+        assert_eq!(None, query.load(TEST_AGGREGATE_ID).await);
+    }
+
+    #[tokio::test]
+    async fn replay_updates_mapped_views() {
+        let view_repo = Arc::new(TestViewRepository::default());
+        let mut query = GenericQuery::<_, ClosableView, TestAggregate>::new(view_repo.clone());
+        query.use_view_id_mapper(Box::new(|_| vec!["all".to_string()]));
+
+        let event_repo = MockRepo::with_events(Ok(vec![
+            test_serialized_event(1, TestEvents::Started),
+            test_serialized_event(2, TestEvents::Started),
+        ]));
+        QueryReplay::new(event_repo, query)
+            .replay_all()
+            .await
+            .unwrap();
+
+        let views = view_repo.views.lock().unwrap();
+        assert_eq!(1, views.len());
+        let (view, version, _) = views.get("all").unwrap();
+        assert!(view.started);
+        assert_eq!(2, *version);
+    }
+
+    #[tokio::test]
+    async fn duplicate_events_are_ignored() {
+        let view_repo = Arc::new(TestViewRepository::default());
+        let mut query = GenericQuery::<_, ClosableView, TestAggregate>::new(view_repo.clone());
+        query.use_error_handler(Box::new(|e| panic!("{e}")));
+
+        query
+            .dispatch(TEST_AGGREGATE_ID, &[envelope(2, TestEvents::Started)])
+            .await;
+        query
+            .dispatch(TEST_AGGREGATE_ID, &[envelope(2, TestEvents::Started)])
+            .await;
+        query
+            .dispatch(
+                TEST_AGGREGATE_ID,
+                &[envelope(1, TestEvents::SomethingWasDone)],
+            )
+            .await;
+
+        let views = view_repo.views.lock().unwrap();
+        let (view, version, last_sequence) = views.get(TEST_AGGREGATE_ID).unwrap();
+        assert!(!view.closed);
+        assert_eq!(1, *version);
+        assert_eq!(2, *last_sequence);
+    }
+
+    #[tokio::test]
+    async fn conflicting_updates_are_retried() {
+        let view_repo = Arc::new(TestViewRepository::default());
+        *view_repo.conflicts.lock().unwrap() = 3;
+        let mut query = GenericQuery::<_, ClosableView, TestAggregate>::new(view_repo.clone());
+        query.use_error_handler(Box::new(|e| panic!("{e}")));
+
+        query
+            .dispatch(TEST_AGGREGATE_ID, &[envelope(1, TestEvents::Started)])
+            .await;
+        assert!(query.load(TEST_AGGREGATE_ID).await.unwrap().started);
+    }
+
+    #[tokio::test]
+    async fn conflicting_updates_are_reported_once_retries_are_exhausted() {
+        let view_repo = Arc::new(TestViewRepository::default());
+        *view_repo.conflicts.lock().unwrap() = 2;
+        let errors = Arc::new(Mutex::new(Vec::new()));
+        let reported = errors.clone();
+        let mut query = GenericQuery::<_, ClosableView, TestAggregate>::new(view_repo.clone());
+        query.use_error_handler(Box::new(move |e| reported.lock().unwrap().push(e)));
+        query.use_optimistic_lock_retries(1);
+
+        query
+            .dispatch(TEST_AGGREGATE_ID, &[envelope(1, TestEvents::Started)])
+            .await;
+        assert_eq!(None, query.load(TEST_AGGREGATE_ID).await);
+        let errors = errors.lock().unwrap();
+        assert!(matches!(
+            errors.as_slice(),
+            [PersistenceError::OptimisticLockError]
+        ));
+    }
+
+    #[tokio::test]
+    async fn replay_in_batches_writes_each_view_once() {
+        let view_repo = Arc::new(TestViewRepository::default());
+        let query = GenericQuery::<_, ClosableView, TestAggregate>::new(view_repo.clone());
+
+        let mut other_event = test_serialized_event(1, TestEvents::Started);
+        other_event.aggregate_id = "other-aggregate".to_string();
+        let event_repo = MockRepo::with_events(Ok(vec![
+            test_serialized_event(1, TestEvents::Started),
+            other_event,
+            test_serialized_event(2, TestEvents::Started),
+        ]));
+        QueryReplay::new(event_repo, query)
+            .with_batch_size(10)
+            .replay_all()
+            .await
+            .unwrap();
+
+        let views = view_repo.views.lock().unwrap();
+        let (view, version, last_sequence) = views.get(TEST_AGGREGATE_ID).unwrap();
+        assert!(view.started);
+        assert_eq!((1, 2), (*version, *last_sequence));
+        let (view, version, last_sequence) = views.get("other-aggregate").unwrap();
+        assert!(view.started);
+        assert_eq!((1, 1), (*version, *last_sequence));
+    }
+
+    #[tokio::test]
+    async fn conflicting_batch_is_applied_individually() {
+        let view_repo = Arc::new(TestViewRepository::default());
+        *view_repo.conflicts.lock().unwrap() = 1;
+        let mut query = GenericQuery::<_, ClosableView, TestAggregate>::new(view_repo.clone());
+        query.use_error_handler(Box::new(|e| panic!("{e}")));
+
+        query
+            .dispatch_batch(&[
+                envelope(1, TestEvents::Started),
+                envelope(2, TestEvents::Started),
+            ])
+            .await;
+
+        let views = view_repo.views.lock().unwrap();
+        let (view, version, last_sequence) = views.get(TEST_AGGREGATE_ID).unwrap();
+        assert!(view.started);
+        assert_eq!((1, 2), (*version, *last_sequence));
+    }
+
+    #[tokio::test]
+    async fn partially_written_batch_only_retries_unwritten_views() {
+        let view_repo = Arc::new(TestViewRepository::default());
+        *view_repo.conflicting_views.lock().unwrap() = vec!["second".to_string()];
+        let mut query = GenericQuery::<_, ClosableView, TestAggregate>::new(view_repo.clone());
+        query.use_error_handler(Box::new(|e| panic!("{e}")));
+        query.use_view_id_mapper(Box::new(|_| {
+            vec!["first".to_string(), "second".to_string()]
+        }));
+
+        query
+            .dispatch_batch(&[envelope(1, TestEvents::Started)])
+            .await;
+
+        // the first view was written by the bulk update and must not be updated again
+        let views = view_repo.views.lock().unwrap();
+        let (view, version, _) = views.get("first").unwrap();
+        assert!(view.started);
+        assert_eq!(1, *version);
+        let (view, version, _) = views.get("second").unwrap();
+        assert!(view.started);
+        assert_eq!(1, *version);
+    }
+
+    #[tokio::test]
+    async fn failed_delete_in_batch_is_reported() {
+        let view_repo = Arc::new(TestViewRepository::default());
+        let query = GenericQuery::<_, ClosableView, TestAggregate>::new(view_repo.clone());
+        query
+            .dispatch_batch(&[envelope(1, TestEvents::Started)])
+            .await;
+        *view_repo.failing_deletes.lock().unwrap() = true;
+
+        let result = query
+            .update_views_in_batch(&[envelope(2, TestEvents::SomethingWasDone)])
+            .await;
+        assert!(matches!(result, Err(PersistenceError::ConnectionError(_))));
+        assert!(view_repo
+            .views
+            .lock()
+            .unwrap()
+            .contains_key(TEST_AGGREGATE_ID));
+    }
+}
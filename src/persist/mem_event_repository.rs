@@ -0,0 +1,488 @@
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Bound;
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+
+use serde_json::Value;
+use tokio::sync::watch;
+
+use crate::persist::{
+    PersistedEventRepository, PersistenceError, ReplayFilter, ReplayStream, SerializedEvent,
+    SerializedSnapshot,
+};
+use crate::Aggregate;
+
+const DEFAULT_STREAMING_CHANNEL_SIZE: usize = 200;
+
+/// An in-memory event repository for use with a `PersistedEventStore`, this allows the
+/// persisted stack to be tested, including snapshots, upcasters and optimistic locking,
+/// without a database.
+///
+/// Events and snapshots are held in memory and are lost once every clone of the repository
+/// is dropped, this is not intended for production use.
+///
+/// ```
+/// # use cqrs_es::doc::{MyAggregate, MyService};
+/// use cqrs_es::CqrsFramework;
+/// use cqrs_es::persist::{MemEventRepository, PersistedEventStore};
+///
+/// let repo = MemEventRepository::default();
+/// let store = PersistedEventStore::<MemEventRepository, MyAggregate>::new_snapshot_store(repo, 10);
+/// let cqrs = CqrsFramework::new(store, vec![], MyService);
+/// ```
+#[derive(Clone)]
+pub struct MemEventRepository {
+    state: Arc<RwLock<MemRepositoryState>>,
+    committed: Arc<watch::Sender<usize>>,
+    stream_channel_size: usize,
+}
+
+// An aggregate instance, identified by its aggregate type and aggregate id.
+type AggregateKey = (String, String);
+
+#[derive(Default)]
+struct MemRepositoryState {
+    // every event in the order it was committed
+    events: Vec<CommittedEvent>,
+    // the position of each event within `events`, by aggregate instance and sequence
+    positions: HashMap<AggregateKey, BTreeMap<usize, usize>>,
+    snapshots: HashMap<AggregateKey, SerializedSnapshot>,
+}
+
+#[derive(Clone)]
+struct CommittedEvent {
+    aggregate_type: String,
+    event: SerializedEvent,
+    committed_at: SystemTime,
+}
+
+impl Default for MemEventRepository {
+    fn default() -> Self {
+        let (committed, _) = watch::channel(0);
+        Self {
+            state: Arc::default(),
+            committed: Arc::new(committed),
+            stream_channel_size: DEFAULT_STREAMING_CHANNEL_SIZE,
+        }
+    }
+}
+
+impl MemEventRepository {
+    /// Configures a `MemEventRepository` to use a streaming queue of the provided size.
+    #[must_use]
+    pub fn with_streaming_channel_size(self, stream_channel_size: usize) -> Self {
+        Self {
+            stream_channel_size,
+            ..self
+        }
+    }
+
+    /// Inserts events for an aggregate type directly, e.g., to seed the repository with events
+    /// of an older version that must be upcast. An `OptimisticLockError` is returned if any of
+    /// the events has already been committed.
+    ///
+    /// ```
+    /// # use cqrs_es::doc::MyAggregate;
+    /// use cqrs_es::persist::{MemEventRepository, SerializedEvent};
+    /// use serde_json::json;
+    ///
+    /// let repo = MemEventRepository::default();
+    /// let event = SerializedEvent::new(
+    ///     "agg-1".to_string(),
+    ///     1,
+    ///     "MyAggregate".to_string(),
+    ///     "SomethingWasDone".to_string(),
+    ///     "0.1.0".to_string(),
+    ///     json!("SomethingWasDone"),
+    ///     json!({}),
+    /// );
+    /// repo.insert_events::<MyAggregate>(&[event]).unwrap();
+    /// ```
+    pub fn insert_events<A: Aggregate>(
+        &self,
+        events: &[SerializedEvent],
+    ) -> Result<(), PersistenceError> {
+        self.persist_events(A::TYPE, events, None)
+    }
+
+    /// The number of events held for all aggregate types.
+    pub fn event_count(&self) -> usize {
+        self.state.read().unwrap().events.len()
+    }
+
+    // Events and the snapshot are committed together, nothing is written on a conflict.
+    fn persist_events(
+        &self,
+        aggregate_type: &str,
+        events: &[SerializedEvent],
+        snapshot_update: Option<(String, Value, usize)>,
+    ) -> Result<(), PersistenceError> {
+        // uninteresting unwrap: this is not a struct for production use
+        let mut state = self.state.write().unwrap();
+        for (i, event) in events.iter().enumerate() {
+            let duplicate = events[..i].iter().any(|other| {
+                other.aggregate_id == event.aggregate_id && other.sequence == event.sequence
+            });
+            if duplicate || state.contains_event(aggregate_type, event) {
+                return Err(PersistenceError::OptimisticLockError);
+            }
+        }
+        if let Some((aggregate_id, aggregate, current_snapshot)) = snapshot_update {
+            let current_sequence = events.last().map_or(0, |event| event.sequence);
+            let snapshot = SerializedSnapshot {
+                aggregate_id,
+                aggregate,
+                current_sequence,
+                current_snapshot,
+            };
+            state.update_snapshot(aggregate_type, snapshot)?;
+        }
+        let committed_at = SystemTime::now();
+        for event in events {
+            let position = state.events.len();
+            state
+                .positions
+                .entry(key(aggregate_type, &event.aggregate_id))
+                .or_default()
+                .insert(event.sequence, position);
+            state.events.push(CommittedEvent {
+                aggregate_type: aggregate_type.to_string(),
+                event: event.clone(),
+                committed_at,
+            });
+        }
+        self.committed.send_replace(state.events.len());
+        Ok(())
+    }
+
+    // Returns the events of an aggregate instance after the sequence, in sequence order.
+    fn aggregate_events(
+        &self,
+        aggregate_type: &str,
+        aggregate_id: &str,
+        last_sequence: usize,
+    ) -> Vec<SerializedEvent> {
+        let state = self.state.read().unwrap();
+        let Some(positions) = state.positions.get(&key(aggregate_type, aggregate_id)) else {
+            return vec![];
+        };
+        positions
+            .range((Bound::Excluded(last_sequence), Bound::Unbounded))
+            .map(|(_, &position)| state.events[position].event.clone())
+            .collect()
+    }
+
+    fn select_events(
+        &self,
+        aggregate_type: &str,
+        selected: impl Fn(&CommittedEvent) -> bool,
+    ) -> Vec<SerializedEvent> {
+        let state = self.state.read().unwrap();
+        state
+            .events
+            .iter()
+            .filter(|committed| committed.aggregate_type == aggregate_type && selected(committed))
+            .map(|committed| committed.event.clone())
+            .collect()
+    }
+
+    fn stream(&self, events: Vec<SerializedEvent>) -> ReplayStream {
+        let (mut feed, stream) = ReplayStream::new(self.stream_channel_size);
+        tokio::spawn(async move {
+            for event in events {
+                if feed.push(Ok(event)).await.is_err() {
+                    return;
+                }
+            }
+            feed.finish();
+        });
+        stream
+    }
+}
+
+fn key(aggregate_type: &str, aggregate_id: &str) -> AggregateKey {
+    (aggregate_type.to_string(), aggregate_id.to_string())
+}
+
+impl MemRepositoryState {
+    fn contains_event(&self, aggregate_type: &str, event: &SerializedEvent) -> bool {
+        self.positions
+            .get(&key(aggregate_type, &event.aggregate_id))
+            .is_some_and(|positions| positions.contains_key(&event.sequence))
+    }
+
+    // As with the database backed repositories, the first snapshot is inserted and each later
+    // snapshot must directly follow the current snapshot.
+    fn update_snapshot(
+        &mut self,
+        aggregate_type: &str,
+        snapshot: SerializedSnapshot,
+    ) -> Result<(), PersistenceError> {
+        let key = key(aggregate_type, &snapshot.aggregate_id);
+        let current_snapshot = self
+            .snapshots
+            .get(&key)
+            .map_or(0, |stored| stored.current_snapshot);
+        if current_snapshot + 1 != snapshot.current_snapshot {
+            return Err(PersistenceError::OptimisticLockError);
+        }
+        self.snapshots.insert(key, snapshot);
+        Ok(())
+    }
+}
+
+impl PersistedEventRepository for MemEventRepository {
+    async fn get_events<A: Aggregate>(
+        &self,
+        aggregate_id: &str,
+    ) -> Result<Vec<SerializedEvent>, PersistenceError> {
+        Ok(self.aggregate_events(A::TYPE, aggregate_id, 0))
+    }
+
+    async fn get_last_events<A: Aggregate>(
+        &self,
+        aggregate_id: &str,
+        last_sequence: usize,
+    ) -> Result<Vec<SerializedEvent>, PersistenceError> {
+        Ok(self.aggregate_events(A::TYPE, aggregate_id, last_sequence))
+    }
+
+    async fn get_snapshot<A: Aggregate>(
+        &self,
+        aggregate_id: &str,
+    ) -> Result<Option<SerializedSnapshot>, PersistenceError> {
+        let state = self.state.read().unwrap();
+        Ok(state.snapshots.get(&key(A::TYPE, aggregate_id)).cloned())
+    }
+
+    async fn persist<A: Aggregate>(
+        &self,
+        events: &[SerializedEvent],
+        snapshot_update: Option<(String, Value, usize)>,
+    ) -> Result<(), PersistenceError> {
+        self.persist_events(A::TYPE, events, snapshot_update)
+    }
+
+    async fn stream_events<A: Aggregate>(
+        &self,
+        aggregate_id: &str,
+    ) -> Result<ReplayStream, PersistenceError> {
+        let events = self.get_events::<A>(aggregate_id).await?;
+        Ok(self.stream(events))
+    }
+
+    async fn stream_all_events<A: Aggregate>(&self) -> Result<ReplayStream, PersistenceError> {
+        let events = self.select_events(A::TYPE, |_| true);
+        Ok(self.stream(events))
+    }
+
+    async fn stream_filtered_events<A: Aggregate>(
+        &self,
+        filter: &ReplayFilter,
+    ) -> Result<ReplayStream, PersistenceError> {
+        let events = self.select_events(A::TYPE, |committed| {
+            filter.matches(&committed.event) && filter.is_committed_within(committed.committed_at)
+        });
+        Ok(self.stream(events))
+    }
+
+    // Events are followed by their position in the commit order, a watch on the number of
+    // committed events wakes the subscription as new events are committed.
+    async fn subscribe_all_events<A: Aggregate>(&self) -> Result<ReplayStream, PersistenceError> {
+        let state = self.state.clone();
+        let mut committed = self.committed.subscribe();
+        let (mut feed, stream) = ReplayStream::new(self.stream_channel_size);
+        tokio::spawn(async move {
+            let mut position = 0;
+            loop {
+                committed.borrow_and_update();
+                let events: Vec<SerializedEvent> = {
+                    let state = state.read().unwrap();
+                    let events = state.events[position..]
+                        .iter()
+                        .filter(|committed| committed.aggregate_type == A::TYPE)
+                        .map(|committed| committed.event.clone())
+                        .collect();
+                    position = state.events.len();
+                    events
+                };
+                for event in events {
+                    if feed.push(Ok(event)).await.is_err() {
+                        return;
+                    }
+                }
+                tokio::select! {
+                    () = feed.closed() => return,
+                    _ = committed.changed() => {}
+                }
+            }
+        });
+        Ok(stream)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, SystemTime};
+
+    use serde_json::json;
+
+    use crate::doc::MyAggregate;
+    use crate::persist::{
+        MemEventRepository, PersistedEventRepository, PersistenceError, ReplayFilter,
+        SerializedEvent,
+    };
+
+    fn event(aggregate_id: &str, sequence: usize) -> SerializedEvent {
+        SerializedEvent::new(
+            aggregate_id.to_string(),
+            sequence,
+            "MyAggregate".to_string(),
+            "SomethingWasDone".to_string(),
+            "0.1.0".to_string(),
+            json!("SomethingWasDone"),
+            json!({}),
+        )
+    }
+
+    async fn stream_sequences(repo: &MemEventRepository, filter: &ReplayFilter) -> Vec<usize> {
+        let mut stream = repo
+            .stream_filtered_events::<MyAggregate>(filter)
+            .await
+            .unwrap();
+        let mut sequences = Vec::new();
+        while let Some(event) = stream.next_serialized().await {
+            sequences.push(event.unwrap().sequence);
+        }
+        assert!(stream.is_complete());
+        sequences
+    }
+
+    #[tokio::test]
+    async fn persist_detects_conflicts() {
+        let repo = MemEventRepository::default();
+        repo.persist::<MyAggregate>(&[event("agg-1", 1), event("agg-1", 2)], None)
+            .await
+            .unwrap();
+        let result = repo
+            .persist::<MyAggregate>(&[event("agg-1", 3), event("agg-1", 2)], None)
+            .await;
+        assert!(matches!(result, Err(PersistenceError::OptimisticLockError)));
+
+        // nothing is written when a commit conflicts
+        assert_eq!(2, repo.event_count());
+        let last_events = repo
+            .get_last_events::<MyAggregate>("agg-1", 1)
+            .await
+            .unwrap();
+        assert_eq!(vec![event("agg-1", 2)], last_events);
+    }
+
+    #[tokio::test]
+    async fn get_events_by_aggregate_instance() {
+        let repo = MemEventRepository::default();
+        repo.insert_events::<MyAggregate>(&[event("agg-1", 2), event("agg-2", 1)])
+            .unwrap();
+        repo.insert_events::<MyAggregate>(&[event("agg-1", 1), event("agg-1", 3)])
+            .unwrap();
+
+        // events are returned in sequence order regardless of the order they were inserted
+        let events = repo.get_events::<MyAggregate>("agg-1").await.unwrap();
+        assert_eq!(
+            vec![event("agg-1", 1), event("agg-1", 2), event("agg-1", 3)],
+            events
+        );
+        let events = repo
+            .get_last_events::<MyAggregate>("agg-1", 2)
+            .await
+            .unwrap();
+        assert_eq!(vec![event("agg-1", 3)], events);
+        let events = repo.get_events::<MyAggregate>("agg-3").await.unwrap();
+        assert!(events.is_empty());
+    }
+
+    #[tokio::test]
+    async fn persist_snapshots() {
+        let repo = MemEventRepository::default();
+        let update = |snapshot| {
+            Some((
+                "agg-1".to_string(),
+                json!({ "snapshot": snapshot }),
+                snapshot,
+            ))
+        };
+        repo.persist::<MyAggregate>(&[event("agg-1", 1)], update(1))
+            .await
+            .unwrap();
+        repo.persist::<MyAggregate>(&[event("agg-1", 2), event("agg-1", 3)], update(2))
+            .await
+            .unwrap();
+        let result = repo
+            .persist::<MyAggregate>(&[event("agg-1", 4)], update(2))
+            .await;
+        assert!(matches!(result, Err(PersistenceError::OptimisticLockError)));
+
+        let snapshot = repo
+            .get_snapshot::<MyAggregate>("agg-1")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            (json!({ "snapshot": 2 }), 3, 2),
+            (
+                snapshot.aggregate,
+                snapshot.current_sequence,
+                snapshot.current_snapshot
+            )
+        );
+        assert_eq!(3, repo.event_count());
+    }
+
+    #[tokio::test]
+    async fn stream_filtered_events() {
+        let repo = MemEventRepository::default();
+        let started = SystemTime::now() - Duration::from_secs(60);
+        repo.insert_events::<MyAggregate>(&[
+            event("agg-1", 1),
+            event("agg-2", 1),
+            event("agg-1", 2),
+        ])
+        .unwrap();
+
+        let filter = ReplayFilter::default().with_aggregate_ids(vec!["agg-1".to_string()]);
+        assert_eq!(vec![1, 2], stream_sequences(&repo, &filter).await);
+        let filter = ReplayFilter::default().with_sequences(2..=2);
+        assert_eq!(vec![2], stream_sequences(&repo, &filter).await);
+        let filter =
+            ReplayFilter::default().committed_between(started - Duration::from_secs(60), started);
+        assert!(stream_sequences(&repo, &filter).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn subscription_follows_committed_events() {
+        let repo = MemEventRepository::default();
+        repo.insert_events::<MyAggregate>(&[event("agg-1", 1)])
+            .unwrap();
+        let mut stream = repo.subscribe_all_events::<MyAggregate>().await.unwrap();
+        repo.insert_events::<MyAggregate>(&[event("agg-1", 2), event("agg-2", 1)])
+            .unwrap();
+
+        let mut found = Vec::new();
+        while found.len() < 3 {
+            let next = tokio::time::timeout(Duration::from_secs(1), stream.next_serialized())
+                .await
+                .expect("timed out waiting for subscribed events")
+                .unwrap()
+                .unwrap();
+            found.push((next.aggregate_id, next.sequence));
+        }
+        assert_eq!(
+            vec![
+                ("agg-1".to_string(), 1),
+                ("agg-1".to_string(), 2),
+                ("agg-2".to_string(), 1)
+            ],
+            found
+        );
+    }
+}
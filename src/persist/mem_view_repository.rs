@@ -0,0 +1,403 @@
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+use std::sync::{Arc, RwLock};
+
+use serde_json::Value;
+
+use crate::persist::{
+    ListableViewRepository, PersistenceError, ShadowViewRepository, ViewContext, ViewFilter,
+    ViewListQuery, ViewPage, ViewRepository,
+};
+use crate::{Aggregate, View};
+
+/// A thread-safe in-memory view repository for use in backing a `GenericQuery` in tests.
+///
+/// Views are held serialized, as they would be in a database, so serialization issues with a
+/// view surface in tests rather than in production. Updates and deletes are checked against
+/// the view version with the same optimistic locking semantics as the database backed
+/// repositories.
+///
+/// Clones of a repository share the same views, a clone can be held by a test to inspect the
+/// views written by a query.
+///
+/// ```
+/// # use cqrs_es::doc::MyAggregate;
+/// # use cqrs_es::persist::doc::MyView;
+/// use std::sync::Arc;
+/// use cqrs_es::persist::{GenericQuery, MemViewRepository};
+///
+/// let view_repo = MemViewRepository::<MyView, MyAggregate>::default();
+/// let query = GenericQuery::new(Arc::new(view_repo.clone()));
+/// assert!(view_repo.view_ids().is_empty());
+/// ```
+pub struct MemViewRepository<V, A> {
+    views: Arc<RwLock<ViewTable>>,
+    shadow: Arc<RwLock<ViewTable>>,
+    _phantom: PhantomData<(V, A)>,
+}
+
+type ViewTable = BTreeMap<String, StoredView>;
+
+struct StoredView {
+    payload: Value,
+    version: i64,
+    last_sequence: usize,
+}
+
+impl StoredView {
+    fn context(&self, view_id: &str) -> ViewContext {
+        ViewContext::new(view_id.to_string(), self.version).with_last_sequence(self.last_sequence)
+    }
+}
+
+impl<V, A> Default for MemViewRepository<V, A> {
+    fn default() -> Self {
+        Self {
+            views: Arc::default(),
+            shadow: Arc::default(),
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<V, A> Clone for MemViewRepository<V, A> {
+    fn clone(&self) -> Self {
+        Self {
+            views: self.views.clone(),
+            shadow: self.shadow.clone(),
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<V, A> MemViewRepository<V, A>
+where
+    V: View<A>,
+    A: Aggregate,
+{
+    /// The ids of every view held by the repository, in order.
+    pub fn view_ids(&self) -> Vec<String> {
+        // uninteresting unwrap: this is not a struct for production use
+        self.views.read().unwrap().keys().cloned().collect()
+    }
+
+    /// Returns the view and its context without going through the async `ViewRepository`
+    /// methods, the context carries the current version and last applied sequence.
+    pub fn view(&self, view_id: &str) -> Option<(V, ViewContext)> {
+        let views = self.views.read().unwrap();
+        views.get(view_id).map(|stored| {
+            let view = serde_json::from_value(stored.payload.clone())
+                .expect("stored view could not be deserialized");
+            (view, stored.context(view_id))
+        })
+    }
+
+    /// Returns the serialized form of a view, e.g., to verify the stored field names.
+    pub fn payload(&self, view_id: &str) -> Option<Value> {
+        let views = self.views.read().unwrap();
+        views.get(view_id).map(|stored| stored.payload.clone())
+    }
+
+    // Checks that a write is consistent with the stored version, new views must have a version
+    // of zero.
+    fn check_version(views: &ViewTable, context: &ViewContext) -> Result<(), PersistenceError> {
+        let stored_version = views
+            .get(&context.view_instance_id)
+            .map_or(0, |stored| stored.version);
+        if stored_version != context.version {
+            return Err(PersistenceError::OptimisticLockError);
+        }
+        Ok(())
+    }
+
+    fn stored_view(view: &V, context: &ViewContext) -> Result<StoredView, PersistenceError> {
+        Ok(StoredView {
+            payload: serde_json::to_value(view)?,
+            version: context.version + 1,
+            last_sequence: context.last_sequence,
+        })
+    }
+
+    fn matching<'a>(
+        views: &'a ViewTable,
+        filters: &'a [ViewFilter],
+    ) -> impl Iterator<Item = (&'a String, &'a StoredView)> {
+        views
+            .iter()
+            .filter(|(_, stored)| filters.iter().all(|filter| filter.matches(&stored.payload)))
+    }
+}
+
+impl<V, A> ViewRepository<V, A> for MemViewRepository<V, A>
+where
+    V: View<A>,
+    A: Aggregate,
+{
+    async fn load(&self, view_id: &str) -> Result<Option<V>, PersistenceError> {
+        Ok(self.load_with_context(view_id).await?.map(|(view, _)| view))
+    }
+
+    async fn load_with_context(
+        &self,
+        view_id: &str,
+    ) -> Result<Option<(V, ViewContext)>, PersistenceError> {
+        let views = self.views.read().unwrap();
+        match views.get(view_id) {
+            None => Ok(None),
+            Some(stored) => {
+                let view = serde_json::from_value(stored.payload.clone())?;
+                Ok(Some((view, stored.context(view_id))))
+            }
+        }
+    }
+
+    async fn update_view(&self, view: V, context: ViewContext) -> Result<(), PersistenceError> {
+        let stored = Self::stored_view(&view, &context)?;
+        let mut views = self.views.write().unwrap();
+        Self::check_version(&views, &context)?;
+        views.insert(context.view_instance_id, stored);
+        Ok(())
+    }
+
+    async fn load_all_with_context(
+        &self,
+        view_ids: &[String],
+    ) -> Result<Vec<(V, ViewContext)>, PersistenceError> {
+        let views = self.views.read().unwrap();
+        let mut result = Vec::with_capacity(view_ids.len());
+        for view_id in view_ids {
+            if let Some(stored) = views.get(view_id) {
+                let view = serde_json::from_value(stored.payload.clone())?;
+                result.push((view, stored.context(view_id)));
+            }
+        }
+        Ok(result)
+    }
+
+    // Either every view is written or, on a conflict, none of them.
+    async fn update_all(&self, updates: Vec<(V, ViewContext)>) -> Result<(), PersistenceError> {
+        let mut stored_views = Vec::with_capacity(updates.len());
+        for (view, context) in &updates {
+            stored_views.push(Self::stored_view(view, context)?);
+        }
+        let mut views = self.views.write().unwrap();
+        for (_, context) in &updates {
+            Self::check_version(&views, context)?;
+        }
+        for ((_, context), stored) in updates.into_iter().zip(stored_views) {
+            views.insert(context.view_instance_id, stored);
+        }
+        Ok(())
+    }
+
+    async fn delete_view(&self, context: ViewContext) -> Result<(), PersistenceError> {
+        let mut views = self.views.write().unwrap();
+        match views.get(&context.view_instance_id) {
+            Some(stored) if stored.version == context.version => {
+                views.remove(&context.view_instance_id);
+                Ok(())
+            }
+            _ => Err(PersistenceError::OptimisticLockError),
+        }
+    }
+
+    async fn clear_all(&self) -> Result<(), PersistenceError> {
+        self.views.write().unwrap().clear();
+        Ok(())
+    }
+}
+
+impl<V, A> ListableViewRepository<V, A> for MemViewRepository<V, A>
+where
+    V: View<A>,
+    A: Aggregate,
+{
+    async fn list_views(&self, query: &ViewListQuery) -> Result<ViewPage<V>, PersistenceError> {
+        let views = self.views.read().unwrap();
+        let mut page = Vec::with_capacity(query.page_size());
+        let mut has_more = false;
+        for (view_id, stored) in Self::matching(&views, &query.filters) {
+            if query
+                .cursor
+                .as_ref()
+                .is_some_and(|cursor| view_id <= cursor)
+            {
+                continue;
+            }
+            if page.len() == query.page_size() {
+                has_more = true;
+                break;
+            }
+            let view = serde_json::from_value(stored.payload.clone())?;
+            page.push((view_id.clone(), view));
+        }
+        let next_cursor = match has_more {
+            true => page.last().map(|(view_id, _)| view_id.clone()),
+            false => None,
+        };
+        Ok(ViewPage {
+            views: page,
+            next_cursor,
+        })
+    }
+
+    async fn count_views(&self, filters: &[ViewFilter]) -> Result<u64, PersistenceError> {
+        let views = self.views.read().unwrap();
+        Ok(Self::matching(&views, filters).count() as u64)
+    }
+}
+
+impl<V, A> ShadowViewRepository<V, A> for MemViewRepository<V, A>
+where
+    V: View<A>,
+    A: Aggregate,
+{
+    type Shadow = Self;
+
+    async fn prepare_shadow(&self) -> Result<Self::Shadow, PersistenceError> {
+        self.shadow.write().unwrap().clear();
+        Ok(Self {
+            views: self.shadow.clone(),
+            shadow: self.views.clone(),
+            _phantom: PhantomData,
+        })
+    }
+
+    async fn promote_shadow(&self) -> Result<(), PersistenceError> {
+        let mut views = self.views.write().unwrap();
+        let mut shadow = self.shadow.write().unwrap();
+        std::mem::swap(&mut *views, &mut *shadow);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde::{Deserialize, Serialize};
+    use serde_json::json;
+
+    use crate::doc::MyAggregate;
+    use crate::persist::{
+        ListableViewRepository, MemViewRepository, PersistenceError, ShadowViewRepository,
+        ViewContext, ViewFilter, ViewListQuery, ViewRepository,
+    };
+    use crate::{EventEnvelope, View};
+
+    #[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+    struct CountingView {
+        status: String,
+        count: usize,
+    }
+
+    impl View<MyAggregate> for CountingView {
+        fn update(&mut self, _event: &EventEnvelope<MyAggregate>) {
+            self.count += 1;
+        }
+    }
+
+    fn view(status: &str, count: usize) -> CountingView {
+        CountingView {
+            status: status.to_string(),
+            count,
+        }
+    }
+
+    #[tokio::test]
+    async fn update_checks_versions() {
+        let repo = MemViewRepository::<CountingView, MyAggregate>::default();
+        let context = || ViewContext::new("view-1".to_string(), 0);
+        repo.update_view(view("open", 1), context().with_last_sequence(1))
+            .await
+            .unwrap();
+        let result = repo.update_view(view("open", 1), context()).await;
+        assert!(matches!(result, Err(PersistenceError::OptimisticLockError)));
+
+        let (loaded, context) = repo.load_with_context("view-1").await.unwrap().unwrap();
+        assert_eq!(
+            (view("open", 1), 1, 1),
+            (loaded, context.version, context.last_sequence)
+        );
+        repo.update_view(view("open", 2), context).await.unwrap();
+        assert_eq!(
+            Some(json!({"status": "open", "count": 2})),
+            repo.payload("view-1")
+        );
+
+        let stale = ViewContext::new("view-1".to_string(), 1);
+        let result = repo.delete_view(stale).await;
+        assert!(matches!(result, Err(PersistenceError::OptimisticLockError)));
+        let (_, context) = repo.view("view-1").unwrap();
+        repo.delete_view(context).await.unwrap();
+        assert!(repo.view_ids().is_empty());
+    }
+
+    #[tokio::test]
+    async fn update_all_is_atomic() {
+        let repo = MemViewRepository::<CountingView, MyAggregate>::default();
+        repo.update_view(view("open", 1), ViewContext::new("view-2".to_string(), 0))
+            .await
+            .unwrap();
+        let updates = vec![
+            (view("open", 1), ViewContext::new("view-1".to_string(), 0)),
+            (view("open", 2), ViewContext::new("view-2".to_string(), 0)),
+        ];
+        let result = repo.update_all(updates).await;
+        assert!(matches!(result, Err(PersistenceError::OptimisticLockError)));
+        assert_eq!(vec!["view-2".to_string()], repo.view_ids());
+
+        let updates = vec![
+            (view("open", 1), ViewContext::new("view-1".to_string(), 0)),
+            (view("open", 2), ViewContext::new("view-2".to_string(), 1)),
+        ];
+        repo.update_all(updates).await.unwrap();
+        let views = repo
+            .load_all_with_context(&["view-1".to_string(), "view-2".to_string()])
+            .await
+            .unwrap();
+        let versions: Vec<i64> = views.iter().map(|(_, context)| context.version).collect();
+        assert_eq!(vec![1, 2], versions);
+    }
+
+    #[tokio::test]
+    async fn list_and_count_views() {
+        let repo = MemViewRepository::<CountingView, MyAggregate>::default();
+        for (view_id, status) in [("a", "open"), ("b", "closed"), ("c", "open"), ("d", "open")] {
+            let context = ViewContext::new(view_id.to_string(), 0);
+            repo.update_view(view(status, 1), context).await.unwrap();
+        }
+        let open = ViewFilter::equals("status", "open");
+        assert_eq!(
+            3,
+            repo.count_views(std::slice::from_ref(&open)).await.unwrap()
+        );
+
+        let query = ViewListQuery::new(2).with_filter(open);
+        let page = repo.list_views(&query).await.unwrap();
+        let ids: Vec<&str> = page.views.iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(vec!["a", "c"], ids);
+        let page = repo
+            .list_views(&query.after(page.next_cursor))
+            .await
+            .unwrap();
+        let ids: Vec<&str> = page.views.iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(vec!["d"], ids);
+        assert_eq!(None, page.next_cursor);
+    }
+
+    #[tokio::test]
+    async fn promote_shadow() {
+        let repo = MemViewRepository::<CountingView, MyAggregate>::default();
+        repo.update_view(view("old", 1), ViewContext::new("view-1".to_string(), 0))
+            .await
+            .unwrap();
+        let shadow = repo.prepare_shadow().await.unwrap();
+        shadow
+            .update_view(view("new", 1), ViewContext::new("view-2".to_string(), 0))
+            .await
+            .unwrap();
+        assert_eq!(vec!["view-1".to_string()], repo.view_ids());
+
+        repo.promote_shadow().await.unwrap();
+        assert_eq!(vec!["view-2".to_string()], repo.view_ids());
+    }
+}
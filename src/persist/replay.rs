@@ -1,7 +1,11 @@
 use std::marker::PhantomData;
+use std::sync::Arc;
+
+use tokio::sync::mpsc::{Receiver, Sender};
 
 use crate::persist::{
     EventUpcaster, PersistedEventRepository, PersistenceError, QueryErrorHandler,
+    ReplayErrorPolicy, ReplayFailure, ReplayFilter, ReplayReport, ReplayStream,
 };
 use crate::{Aggregate, AggregateError, EventEnvelope, Query};
 
@@ -23,9 +27,11 @@ where
     A: Aggregate,
 {
     repository: R,
-    query: Q,
+    query: Arc<Q>,
     event_upcasters: Vec<Box<dyn EventUpcaster>>,
     error_handler: Option<Box<QueryErrorHandler>>,
+    error_policy: ReplayErrorPolicy,
+    batch_size: usize,
     phantom_data: PhantomData<A>,
 }
 
@@ -40,9 +46,11 @@ where
     pub fn new(repository: R, query: Q) -> Self {
         Self {
             repository,
-            query,
+            query: Arc::new(query),
             event_upcasters: vec![],
             error_handler: None,
+            error_policy: ReplayErrorPolicy::default(),
+            batch_size: 1,
             phantom_data: PhantomData,
         }
     }
@@ -54,15 +62,35 @@ where
     /// E.g., an upcaster for version 0.2.3 should be placed before an upcaster for version 0.2.4
     pub fn with_upcasters(self, event_upcasters: Vec<Box<dyn EventUpcaster>>) -> Self {
         Self {
-            repository: self.repository,
-            query: self.query,
-            error_handler: self.error_handler,
             event_upcasters,
-            phantom_data: self.phantom_data,
+            ..self
+        }
+    }
+
+    /// Configures the query replayer to dispatch events in batches of up to `batch_size` events
+    /// using `Query::dispatch_batch`, by default events are dispatched one at a time.
+    ///
+    /// When replaying to a `GenericQuery` the events in each batch are applied to the views in
+    /// memory and the views are then written in bulk, greatly reducing the number of database
+    /// round-trips.
+    ///
+    /// ```
+    /// # use cqrs_es::doc::{MyAggregate, MyQuery, MyRepository};
+    /// # use cqrs_es::persist::QueryReplay;
+    /// # fn config(repo: MyRepository, query: MyQuery) {
+    /// let replay = QueryReplay::new(repo, query).with_batch_size(1000);
+    /// # }
+    /// ```
+    pub fn with_batch_size(self, batch_size: usize) -> Self {
+        Self {
+            batch_size: batch_size.max(1),
+            ..self
         }
     }
 
     /// Allows the user to apply a custom error handler to the query replay.
+    /// The handler receives the errors of any events skipped by the `ReplayErrorPolicy::Skip`
+    /// policy.
     ///
     /// _Example: An error handler that panics on any error._
     /// ```
@@ -76,37 +104,242 @@ where
         self.error_handler = Some(error_handler);
     }
 
+    /// Configures how events that cannot be read, upcast or deserialized are handled, by
+    /// default these events are skipped.
+    ///
+    /// _Example: halt the replay at the first failure._
+    /// ```
+    /// # use cqrs_es::doc::{MyAggregate, MyQuery, MyRepository};
+    /// # use cqrs_es::persist::{QueryReplay, ReplayErrorPolicy};
+    /// # fn config(mut replay: QueryReplay<MyRepository,MyQuery,MyAggregate>) {
+    /// replay.use_error_policy(ReplayErrorPolicy::FailFast);
+    /// # }
+    /// ```
+    pub fn use_error_policy(&mut self, error_policy: ReplayErrorPolicy) {
+        self.error_policy = error_policy;
+    }
+
     /// Replay the events of a single aggregate instance.
-    pub async fn replay(&self, aggregate_id: &str) -> Result<(), AggregateError<A::Error>> {
-        let mut stream = self.repository.stream_events::<A>(aggregate_id).await?;
-        while let Some(event) = stream.next(&self.event_upcasters).await {
-            self.apply(event).await;
-        }
-        Ok(())
+    pub async fn replay(
+        &self,
+        aggregate_id: &str,
+    ) -> Result<ReplayReport, AggregateError<A::Error>> {
+        let stream = self.repository.stream_events::<A>(aggregate_id).await?;
+        Ok(self.apply_stream(stream).await?)
     }
 
     /// Replay the events of all aggregate instances within the database.
-    pub async fn replay_all(&self) -> Result<(), AggregateError<A::Error>> {
-        let mut stream = self.repository.stream_all_events::<A>().await?;
-        while let Some(event) = stream.next(&self.event_upcasters).await {
-            self.apply(event).await;
+    pub async fn replay_all(&self) -> Result<ReplayReport, AggregateError<A::Error>> {
+        let stream = self.repository.stream_all_events::<A>().await?;
+        Ok(self.apply_stream(stream).await?)
+    }
+
+    /// Replay only the events matching the filter, e.g., a subset of event types or the events
+    /// committed within a time window.
+    ///
+    /// ```
+    /// # use cqrs_es::doc::{MyAggregate, MyQuery, MyRepository};
+    /// # use cqrs_es::persist::{QueryReplay, ReplayFilter};
+    /// # async fn config(replay: QueryReplay<MyRepository,MyQuery,MyAggregate>) {
+    /// let filter = ReplayFilter::default()
+    ///     .with_aggregate_ids(vec!["order-1".to_string()])
+    ///     .with_sequences(10..=20);
+    /// replay.replay_filtered(&filter).await.unwrap();
+    /// # }
+    /// ```
+    pub async fn replay_filtered(
+        &self,
+        filter: &ReplayFilter,
+    ) -> Result<ReplayReport, AggregateError<A::Error>> {
+        let stream = self.repository.stream_filtered_events::<A>(filter).await?;
+        Ok(self.apply_stream(stream).await?)
+    }
+
+    /// Replay the events of all aggregate instances within the database, partitioned across
+    /// `partitions` workers that dispatch to the query concurrently.
+    ///
+    /// Events are assigned to a partition by a hash of their aggregate id so the events of any
+    /// single aggregate instance are always dispatched in order. Each partition buffers at most
+    /// one batch of events, once a partition is full reading from the event stream is paused
+    /// until the partition has caught up.
+    ///
+    /// Each worker is spawned as a separate task and so may run in parallel on a multi-threaded
+    /// runtime. The events dispatched by each partition are reported in `events_by_partition`,
+    /// a partition whose worker panics is reported in `failed_partitions` and its remaining
+    /// events are not dispatched.
+    ///
+    /// ```
+    /// # use cqrs_es::doc::{MyAggregate, MyQuery, MyRepository};
+    /// # use cqrs_es::persist::QueryReplay;
+    /// # async fn config(replay: QueryReplay<MyRepository,MyQuery,MyAggregate>) {
+    /// let report = replay.replay_all_partitioned(8).await.unwrap();
+    /// println!("replayed {} events", report.events_processed);
+    /// # }
+    /// ```
+    pub async fn replay_all_partitioned(
+        &self,
+        partitions: usize,
+    ) -> Result<ReplayReport, AggregateError<A::Error>>
+    where
+        Q: 'static,
+        A: 'static,
+    {
+        let partitions = partitions.max(1);
+        let stream = self.repository.stream_all_events::<A>().await?;
+        let mut senders = Vec::with_capacity(partitions);
+        let mut workers = Vec::with_capacity(partitions);
+        for _ in 0..partitions {
+            let (sender, receiver) = tokio::sync::mpsc::channel(self.batch_size);
+            senders.push(sender);
+            workers.push(tokio::spawn(apply_partition(
+                self.query.clone(),
+                self.batch_size,
+                receiver,
+            )));
+        }
+        // The senders are dropped once the stream has been read, the workers then dispatch
+        // their remaining events and complete.
+        let result = self.partition_stream(stream, senders).await;
+        let mut events_by_partition = Vec::with_capacity(partitions);
+        let mut failed_partitions = Vec::new();
+        for (index, worker) in workers.into_iter().enumerate() {
+            match worker.await {
+                Ok(dispatched) => events_by_partition.push(dispatched),
+                Err(_) => {
+                    events_by_partition.push(0);
+                    failed_partitions.push(index);
+                }
+            }
         }
-        Ok(())
+        let mut report = result?;
+        report.events_processed = events_by_partition.iter().sum();
+        report.events_by_partition = events_by_partition;
+        report.failed_partitions = failed_partitions;
+        Ok(report)
     }
 
-    async fn apply(&self, event: Result<EventEnvelope<A>, PersistenceError>) {
-        match event {
-            Ok(event) => {
-                let aggregate_id = event.aggregate_id.clone();
-                self.query.dispatch(&aggregate_id, &[event]).await;
+    // Reads the stream and sends each event to its partition, the senders are dropped on
+    // completion or failure which closes the partitions.
+    async fn partition_stream(
+        &self,
+        mut stream: ReplayStream,
+        senders: Vec<Sender<EventEnvelope<A>>>,
+    ) -> Result<ReplayReport, PersistenceError> {
+        let mut report = ReplayReport::default();
+        while let Some(event) = self.next_event(&mut stream, &mut report).await? {
+            let sender = &senders[partition(&event.aggregate_id, senders.len())];
+            // A partition only closes early if its worker has panicked.
+            let _ = sender.send(event).await;
+        }
+        Ok(report)
+    }
+
+    async fn apply_stream(
+        &self,
+        mut stream: ReplayStream,
+    ) -> Result<ReplayReport, PersistenceError> {
+        let mut report = ReplayReport::default();
+        let mut batch = Vec::with_capacity(self.batch_size);
+        let result = loop {
+            match self.next_event(&mut stream, &mut report).await {
+                Ok(Some(event)) => batch.push(event),
+                Ok(None) => break Ok(()),
+                Err(error) => break Err(error),
+            }
+            if batch.len() >= self.batch_size {
+                report.events_processed += batch.len();
+                self.dispatch(std::mem::take(&mut batch)).await;
             }
-            Err(error) => {
-                if let Some(handler) = &self.error_handler {
-                    (handler)(error);
+        };
+        report.events_processed += batch.len();
+        self.dispatch(batch).await;
+        result.map(|()| report)
+    }
+
+    // Returns the next event that can be dispatched, failed events are handled according to
+    // the error policy and an error is only returned if the replay should halt.
+    async fn next_event(
+        &self,
+        stream: &mut ReplayStream,
+        report: &mut ReplayReport,
+    ) -> Result<Option<EventEnvelope<A>>, PersistenceError> {
+        while let Some(result) = stream.next_serialized().await {
+            let position = report.events_read;
+            report.events_read += 1;
+            let (event, error) = match result {
+                Ok(event) => {
+                    let event = event.upcast(&self.event_upcasters);
+                    match EventEnvelope::try_from(&event) {
+                        Ok(event) => return Ok(Some(event)),
+                        Err(error) => (Some(event), error),
+                    }
+                }
+                Err(error) => (None, error),
+            };
+            report.record_failure(position, event.as_ref());
+            match &self.error_policy {
+                ReplayErrorPolicy::FailFast => return Err(error),
+                ReplayErrorPolicy::Skip => {
+                    report.events_skipped += 1;
+                    if let Some(handler) = &self.error_handler {
+                        (handler)(error);
+                    }
+                }
+                ReplayErrorPolicy::DeadLetter(handler) => {
+                    report.events_dead_lettered += 1;
+                    (handler)(ReplayFailure {
+                        position,
+                        event,
+                        error,
+                    });
                 }
             }
         }
+        Ok(None)
+    }
+
+    async fn dispatch(&self, events: Vec<EventEnvelope<A>>) {
+        dispatch(self.query.as_ref(), events).await;
+    }
+}
+
+// Dispatches the events received by a partition in batches, returns the number dispatched.
+async fn apply_partition<Q: Query<A>, A: Aggregate>(
+    query: Arc<Q>,
+    batch_size: usize,
+    mut receiver: Receiver<EventEnvelope<A>>,
+) -> usize {
+    let mut dispatched = 0;
+    let mut batch = Vec::with_capacity(batch_size);
+    while let Some(event) = receiver.recv().await {
+        batch.push(event);
+        if batch.len() >= batch_size {
+            dispatched += batch.len();
+            dispatch(query.as_ref(), std::mem::take(&mut batch)).await;
+        }
     }
+    dispatched += batch.len();
+    dispatch(query.as_ref(), batch).await;
+    dispatched
+}
+
+async fn dispatch<Q: Query<A>, A: Aggregate>(query: &Q, events: Vec<EventEnvelope<A>>) {
+    match events.as_slice() {
+        [] => {}
+        [event] => query.dispatch(&event.aggregate_id, &events).await,
+        _ => query.dispatch_batch(&events).await,
+    }
+}
+
+// Partitions are assigned with a 64-bit FNV-1a hash of the aggregate id, unlike the standard
+// library hashers this is fixed across processes and releases.
+fn partition(aggregate_id: &str, partitions: usize) -> usize {
+    const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const FNV_PRIME: u64 = 0x0100_0000_01b3;
+    let hash = aggregate_id.bytes().fold(FNV_OFFSET_BASIS, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME)
+    });
+    (hash % partitions as u64) as usize
 }
 
 #[cfg(test)]
@@ -120,9 +353,12 @@ mod test {
 
     use crate::doc::{MyAggregate, MyEvents};
     use crate::persist::event_store::shared_test::MockRepo;
-    use crate::persist::replay::QueryReplay;
-    use crate::persist::{SemanticVersionEventUpcaster, SerializedEvent};
-    use crate::{EventEnvelope, Query};
+    use crate::persist::replay::{partition, QueryReplay};
+    use crate::persist::{
+        ReplayErrorPolicy, ReplayFilter, ReplayPosition, ReplayReport,
+        SemanticVersionEventUpcaster, SerializedEvent,
+    };
+    use crate::{AggregateError, EventEnvelope, Query};
 
     #[derive(Debug)]
     struct MockQuery {
@@ -232,6 +468,256 @@ mod test {
         assert_events_eq(&expected_events, &events);
     }
 
+    #[tokio::test]
+    async fn query_replay_in_batches() {
+        let expected_events: Vec<EventEnvelope<MyAggregate>> = (1..=5)
+            .map(|sequence| EventEnvelope {
+                aggregate_id: format!("{AGGREGATE_ID}-{}", sequence % 2),
+                sequence,
+                payload: MyEvents::SomethingWasDone,
+                metadata: HashMap::default(),
+            })
+            .collect();
+        let ser_events: Vec<SerializedEvent> = expected_events
+            .iter()
+            .map(|e| SerializedEvent::try_from(e).unwrap())
+            .collect();
+        let event_repo = MockRepo::with_events(Ok(ser_events));
+        let (query, event_list) = MockQuery::new();
+        let query_replay = QueryReplay::new(event_repo, query).with_batch_size(2);
+        query_replay.replay_all().await.unwrap();
+
+        let events = event_list.lock().unwrap().to_owned();
+        assert_events_eq(&expected_events, &events);
+    }
+
+    #[tokio::test]
+    async fn query_replay_partitioned() {
+        let expected_events: Vec<EventEnvelope<MyAggregate>> = (1..=40)
+            .map(|sequence| EventEnvelope {
+                aggregate_id: format!("{AGGREGATE_ID}-{}", sequence % 7),
+                sequence,
+                payload: MyEvents::SomethingWasDone,
+                metadata: HashMap::default(),
+            })
+            .collect();
+        let mut ser_events: Vec<SerializedEvent> = expected_events
+            .iter()
+            .map(|e| SerializedEvent::try_from(e).unwrap())
+            .collect();
+        ser_events[10].payload = json!({"UnknownEvent": ()});
+        let event_repo = MockRepo::with_events(Ok(ser_events));
+        let (query, event_list) = MockQuery::new();
+        let query_replay = QueryReplay::new(event_repo, query).with_batch_size(2);
+        let report = query_replay.replay_all_partitioned(3).await.unwrap();
+
+        assert_eq!(3, report.events_by_partition.len());
+        assert_eq!(40, report.events_read);
+        assert_eq!(39, report.events_processed);
+        assert_eq!(1, report.events_skipped);
+        assert_eq!(10, report.first_failures[0].position);
+
+        // events are reordered across aggregate instances but never within one
+        let events = event_list.lock().unwrap().to_owned();
+        for aggregate in 0..7 {
+            let aggregate_id = format!("{AGGREGATE_ID}-{aggregate}");
+            let expected: Vec<EventEnvelope<MyAggregate>> = expected_events
+                .iter()
+                .filter(|e| e.aggregate_id == aggregate_id && e.sequence != 11)
+                .cloned()
+                .collect();
+            let found: Vec<EventEnvelope<MyAggregate>> = events
+                .iter()
+                .filter(|e| e.aggregate_id == aggregate_id)
+                .cloned()
+                .collect();
+            assert_events_eq(&expected, &found);
+        }
+    }
+
+    struct PanickingQuery {
+        aggregate_id: String,
+    }
+
+    #[async_trait]
+    impl Query<MyAggregate> for PanickingQuery {
+        async fn dispatch(&self, aggregate_id: &str, _events: &[EventEnvelope<MyAggregate>]) {
+            assert_ne!(self.aggregate_id, aggregate_id, "dispatch failed");
+        }
+    }
+
+    #[tokio::test]
+    async fn query_replay_partitioned_reports_failed_partitions() {
+        let ser_events: Vec<SerializedEvent> = (1..=20)
+            .map(|sequence| {
+                SerializedEvent::try_from(&EventEnvelope::<MyAggregate> {
+                    aggregate_id: format!("{AGGREGATE_ID}-{}", sequence % 5),
+                    sequence,
+                    payload: MyEvents::SomethingWasDone,
+                    metadata: HashMap::default(),
+                })
+                .unwrap()
+            })
+            .collect();
+        let failing_id = format!("{AGGREGATE_ID}-3");
+        let failing_partition = partition(&failing_id, 3);
+        let event_repo = MockRepo::with_events(Ok(ser_events));
+        let query = PanickingQuery {
+            aggregate_id: failing_id,
+        };
+        let query_replay = QueryReplay::new(event_repo, query);
+        let report = query_replay.replay_all_partitioned(3).await.unwrap();
+
+        assert_eq!(vec![failing_partition], report.failed_partitions);
+        assert_eq!(0, report.events_by_partition[failing_partition]);
+        assert_eq!(20, report.events_read);
+    }
+
+    #[tokio::test]
+    async fn query_replay_filtered() {
+        let all_events: Vec<EventEnvelope<MyAggregate>> = (1..=6)
+            .map(|sequence| EventEnvelope {
+                aggregate_id: format!("{AGGREGATE_ID}-{}", sequence % 2),
+                sequence,
+                payload: MyEvents::SomethingWasDone,
+                metadata: HashMap::default(),
+            })
+            .collect();
+        let ser_events: Vec<SerializedEvent> = all_events
+            .iter()
+            .map(|e| SerializedEvent::try_from(e).unwrap())
+            .collect();
+        let event_repo = MockRepo::with_events(Ok(ser_events));
+        let (query, event_list) = MockQuery::new();
+        let query_replay = QueryReplay::new(event_repo, query);
+        let filter = ReplayFilter::default()
+            .with_aggregate_ids(vec![format!("{AGGREGATE_ID}-1")])
+            .with_sequences(2..=5);
+        query_replay.replay_filtered(&filter).await.unwrap();
+
+        let events = event_list.lock().unwrap().to_owned();
+        assert_events_eq(&[all_events[2].clone(), all_events[4].clone()], &events);
+    }
+
+    fn events_with_failures() -> Vec<SerializedEvent> {
+        (1..=5)
+            .map(|sequence| {
+                let event = EventEnvelope::<MyAggregate> {
+                    aggregate_id: AGGREGATE_ID.to_string(),
+                    sequence,
+                    payload: MyEvents::SomethingWasDone,
+                    metadata: HashMap::default(),
+                };
+                let mut event = SerializedEvent::try_from(&event).unwrap();
+                if sequence % 2 == 0 {
+                    event.payload = json!({"UnknownEvent": ()});
+                }
+                event
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn query_replay_skips_failures() {
+        let event_repo = MockRepo::with_events(Ok(events_with_failures()));
+        let (query, event_list) = MockQuery::new();
+        let mut query_replay = QueryReplay::new(event_repo, query);
+        let logged = Arc::new(Mutex::new(0));
+        let handler_logged = logged.clone();
+        query_replay.use_error_handler(Box::new(move |_| *handler_logged.lock().unwrap() += 1));
+        let report = query_replay.replay_all().await.unwrap();
+
+        assert_eq!(3, event_list.lock().unwrap().len());
+        assert_eq!(2, *logged.lock().unwrap());
+        assert_eq!(
+            ReplayReport {
+                events_read: 5,
+                events_processed: 3,
+                events_skipped: 2,
+                events_dead_lettered: 0,
+                events_by_partition: vec![],
+                failed_partitions: vec![],
+                first_failures: vec![
+                    ReplayPosition {
+                        position: 1,
+                        aggregate_id: Some(AGGREGATE_ID.to_string()),
+                        sequence: Some(2),
+                    },
+                    ReplayPosition {
+                        position: 3,
+                        aggregate_id: Some(AGGREGATE_ID.to_string()),
+                        sequence: Some(4),
+                    },
+                ],
+            },
+            report
+        );
+        assert_eq!(2, report.events_failed());
+    }
+
+    #[tokio::test]
+    async fn query_replay_fails_fast() {
+        let event_repo = MockRepo::with_events(Ok(events_with_failures()));
+        let (query, event_list) = MockQuery::new();
+        let mut query_replay = QueryReplay::new(event_repo, query).with_batch_size(10);
+        query_replay.use_error_policy(ReplayErrorPolicy::FailFast);
+        let result = query_replay.replay_all().await;
+
+        assert!(matches!(
+            result,
+            Err(AggregateError::DeserializationError(_))
+        ));
+        // events read before the failure are still dispatched
+        let events = event_list.lock().unwrap().to_owned();
+        assert_eq!(1, events.len());
+        assert_eq!(1, events[0].sequence);
+    }
+
+    #[tokio::test]
+    async fn query_replay_dead_letters_failures() {
+        let event_repo = MockRepo::with_events(Ok(events_with_failures()));
+        let (query, event_list) = MockQuery::new();
+        let mut query_replay = QueryReplay::new(event_repo, query);
+        let dead_letters = Arc::new(Mutex::new(Vec::new()));
+        let sink = dead_letters.clone();
+        query_replay.use_error_policy(ReplayErrorPolicy::DeadLetter(Box::new(move |failure| {
+            sink.lock().unwrap().push(failure)
+        })));
+        let report = query_replay.replay_all_partitioned(2).await.unwrap();
+
+        assert_eq!(3, event_list.lock().unwrap().len());
+        assert_eq!(
+            (3, 0, 2),
+            (
+                report.events_processed,
+                report.events_skipped,
+                report.events_dead_lettered
+            )
+        );
+        let dead_letters = dead_letters.lock().unwrap();
+        let positions: Vec<usize> = dead_letters.iter().map(|f| f.position).collect();
+        assert_eq!(vec![1, 3], positions);
+        let sequences: Vec<usize> = dead_letters
+            .iter()
+            .map(|f| f.event.as_ref().unwrap().sequence)
+            .collect();
+        assert_eq!(vec![2, 4], sequences);
+    }
+
+    #[test]
+    fn partition_is_stable() {
+        for aggregate_id in ["a", "b", "c", "some-longer-aggregate-id"] {
+            let found = partition(aggregate_id, 5);
+            assert!(found < 5);
+            assert_eq!(found, partition(aggregate_id, 5));
+        }
+        assert_eq!(0, partition("a", 1));
+        // assignments must not change between processes or releases
+        assert_eq!(1, partition("a", 5));
+        assert_eq!(4, partition("b", 5));
+        assert_eq!(2, partition("some-longer-aggregate-id", 5));
+    }
+
     fn assert_events_eq(
         expected: &[EventEnvelope<MyAggregate>],
         found: &[EventEnvelope<MyAggregate>],
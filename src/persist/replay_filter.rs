@@ -0,0 +1,148 @@
+use std::ops::RangeInclusive;
+use std::time::SystemTime;
+
+use crate::persist::SerializedEvent;
+
+/// Selects a subset of the events of an aggregate type to replay.
+///
+/// Each configured criteria must be met for an event to be replayed, an empty filter replays
+/// all events. Repositories push these criteria down into the database wherever possible.
+///
+/// ```
+/// use std::time::{Duration, SystemTime};
+/// use cqrs_es::persist::ReplayFilter;
+///
+/// let an_hour_ago = SystemTime::now() - Duration::from_secs(3600);
+/// let filter = ReplayFilter::default()
+///     .with_aggregate_ids(vec!["account-1".to_string(), "account-2".to_string()])
+///     .with_event_types(vec!["CustomerDepositedMoney".to_string()])
+///     .with_sequences(5..=10)
+///     .committed_between(an_hour_ago, SystemTime::now());
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReplayFilter {
+    /// Only replay events of these aggregate instances, all instances if empty.
+    pub aggregate_ids: Vec<String>,
+    /// Only replay events of these event types, all event types if empty.
+    pub event_types: Vec<String>,
+    /// Only replay events with a sequence greater than or equal to this value.
+    pub min_sequence: Option<usize>,
+    /// Only replay events with a sequence less than or equal to this value.
+    pub max_sequence: Option<usize>,
+    /// Only replay events committed at or after this time.
+    pub committed_from: Option<SystemTime>,
+    /// Only replay events committed before this time.
+    pub committed_to: Option<SystemTime>,
+}
+
+impl ReplayFilter {
+    /// Limits the replay to the events of the provided aggregate instances.
+    #[must_use]
+    pub fn with_aggregate_ids(self, aggregate_ids: Vec<String>) -> Self {
+        Self {
+            aggregate_ids,
+            ..self
+        }
+    }
+
+    /// Limits the replay to events of the provided event types.
+    #[must_use]
+    pub fn with_event_types(self, event_types: Vec<String>) -> Self {
+        Self {
+            event_types,
+            ..self
+        }
+    }
+
+    /// Limits the replay to events with a sequence within the range, this is generally used
+    /// alongside a single aggregate id.
+    #[must_use]
+    pub fn with_sequences(self, sequences: RangeInclusive<usize>) -> Self {
+        Self {
+            min_sequence: Some(*sequences.start()),
+            max_sequence: Some(*sequences.end()),
+            ..self
+        }
+    }
+
+    /// Limits the replay to events committed at or after `from` and before `to`.
+    ///
+    /// _Note: events committed before the commit time was recorded by the repository are not
+    /// guaranteed to be matched by this criteria._
+    #[must_use]
+    pub fn committed_between(self, from: SystemTime, to: SystemTime) -> Self {
+        Self {
+            committed_from: Some(from),
+            committed_to: Some(to),
+            ..self
+        }
+    }
+
+    /// Checks the aggregate id, event type and sequence of a serialized event against this
+    /// filter, for use by repositories that cannot filter within the database.
+    ///
+    /// The commit time is not part of a `SerializedEvent` and must be checked separately using
+    /// `is_committed_within`.
+    pub fn matches(&self, event: &SerializedEvent) -> bool {
+        (self.aggregate_ids.is_empty() || self.aggregate_ids.contains(&event.aggregate_id))
+            && (self.event_types.is_empty() || self.event_types.contains(&event.event_type))
+            && self.min_sequence.is_none_or(|min| event.sequence >= min)
+            && self.max_sequence.is_none_or(|max| event.sequence <= max)
+    }
+
+    /// Checks whether an event committed at `committed_at` falls within the commit time window.
+    pub fn is_committed_within(&self, committed_at: SystemTime) -> bool {
+        self.committed_from.is_none_or(|from| committed_at >= from)
+            && self.committed_to.is_none_or(|to| committed_at < to)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, SystemTime};
+
+    use serde_json::json;
+
+    use crate::persist::{ReplayFilter, SerializedEvent};
+
+    fn event(aggregate_id: &str, sequence: usize, event_type: &str) -> SerializedEvent {
+        SerializedEvent::new(
+            aggregate_id.to_string(),
+            sequence,
+            "TestAggregate".to_string(),
+            event_type.to_string(),
+            "1.0".to_string(),
+            json!({}),
+            json!({}),
+        )
+    }
+
+    #[test]
+    fn matches() {
+        assert!(ReplayFilter::default().matches(&event("a", 1, "Created")));
+
+        let filter = ReplayFilter::default()
+            .with_aggregate_ids(vec!["a".to_string()])
+            .with_event_types(vec!["Updated".to_string()])
+            .with_sequences(2..=3);
+        assert!(filter.matches(&event("a", 2, "Updated")));
+        assert!(filter.matches(&event("a", 3, "Updated")));
+        assert!(!filter.matches(&event("b", 2, "Updated")));
+        assert!(!filter.matches(&event("a", 2, "Created")));
+        assert!(!filter.matches(&event("a", 1, "Updated")));
+        assert!(!filter.matches(&event("a", 4, "Updated")));
+    }
+
+    #[test]
+    fn is_committed_within() {
+        let now = SystemTime::now();
+        let minute = Duration::from_secs(60);
+        assert!(ReplayFilter::default().is_committed_within(now));
+
+        let filter = ReplayFilter::default().committed_between(now - minute, now);
+        assert!(filter.is_committed_within(now - minute));
+        assert!(filter.is_committed_within(now - minute / 2));
+        assert!(!filter.is_committed_within(now));
+        assert!(!filter.is_committed_within(now - minute * 2));
+    }
+}
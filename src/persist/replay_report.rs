@@ -0,0 +1,92 @@
+use crate::persist::{PersistenceError, SerializedEvent};
+
+// The number of failure positions recorded in a `ReplayReport`.
+const MAX_REPORTED_FAILURES: usize = 10;
+
+/// How a `QueryReplay` handles events that cannot be read, upcast or deserialized.
+#[derive(Default)]
+pub enum ReplayErrorPolicy {
+    /// Halt the replay at the first failure and return the error, events read before the
+    /// failure are still dispatched to the query.
+    FailFast,
+    /// Skip any failed events, passing the error to the replay error handler if one is
+    /// configured.
+    #[default]
+    Skip,
+    /// Skip any failed events, sending each failure to the dead-letter handler so that the
+    /// events can be inspected and replayed later.
+    DeadLetter(Box<DeadLetterHandler>),
+}
+
+/// Receives the failed events of a replay using the `ReplayErrorPolicy::DeadLetter` policy.
+///
+/// ```
+/// use std::sync::mpsc::Sender;
+/// use cqrs_es::persist::{DeadLetterHandler, ReplayErrorPolicy, ReplayFailure};
+///
+/// fn dead_letter_policy(sender: Sender<ReplayFailure>) -> ReplayErrorPolicy {
+///     let handler: Box<DeadLetterHandler> = Box::new(move |failure| sender.send(failure).unwrap());
+///     ReplayErrorPolicy::DeadLetter(handler)
+/// }
+/// ```
+pub type DeadLetterHandler = dyn Fn(ReplayFailure) + Send + Sync + 'static;
+
+/// An event that could not be replayed.
+#[derive(Debug)]
+pub struct ReplayFailure {
+    /// The position of the failed entry within the replay, starting from zero.
+    pub position: usize,
+    /// The upcast event that could not be deserialized, `None` if the event could not be read
+    /// from the repository.
+    pub event: Option<SerializedEvent>,
+    /// The error that caused the failure.
+    pub error: PersistenceError,
+}
+
+/// Identifies a failed entry within a replay.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplayPosition {
+    /// The position of the failed entry within the replay, starting from zero.
+    pub position: usize,
+    /// The aggregate id of the failed event, if it could be read.
+    pub aggregate_id: Option<String>,
+    /// The sequence of the failed event, if it could be read.
+    pub sequence: Option<usize>,
+}
+
+/// A summary of a completed replay.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ReplayReport {
+    /// The number of entries read from the event repository, including any failures.
+    pub events_read: usize,
+    /// The number of events dispatched to the query.
+    pub events_processed: usize,
+    /// The number of failed events that were skipped.
+    pub events_skipped: usize,
+    /// The number of failed events that were sent to the dead-letter handler.
+    pub events_dead_lettered: usize,
+    /// The number of events dispatched by each partition of a partitioned replay.
+    pub events_by_partition: Vec<usize>,
+    /// The partitions of a partitioned replay whose worker failed, e.g., because dispatching
+    /// to the query panicked, not all the events of these partitions were dispatched.
+    pub failed_partitions: Vec<usize>,
+    /// The positions of the first failures encountered, at most ten are recorded.
+    pub first_failures: Vec<ReplayPosition>,
+}
+
+impl ReplayReport {
+    /// The total number of events that could not be replayed.
+    pub fn events_failed(&self) -> usize {
+        self.events_skipped + self.events_dead_lettered
+    }
+
+    pub(crate) fn record_failure(&mut self, position: usize, event: Option<&SerializedEvent>) {
+        if self.first_failures.len() < MAX_REPORTED_FAILURES {
+            self.first_failures.push(ReplayPosition {
+                position,
+                aggregate_id: event.map(|event| event.aggregate_id.clone()),
+                sequence: event.map(|event| event.sequence),
+            });
+        }
+    }
+}
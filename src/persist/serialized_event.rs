@@ -1,6 +1,8 @@
+use std::collections::HashMap;
 use std::convert::TryFrom;
 
 use crate::{Aggregate, DomainEvent, EventEnvelope};
+use serde::Deserialize;
 use serde_json::Value;
 
 use crate::persist::{EventStoreAggregateContext, EventUpcaster, PersistenceError};
@@ -102,7 +104,7 @@ impl<A: Aggregate> TryFrom<&EventEnvelope<A>> for SerializedEvent {
 
 /// A serialized version of a snapshot.
 /// Used by repositories to store and load snapshots from a database.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct SerializedSnapshot {
     /// The aggregate ID of the aggregate instance that has been loaded.
     pub aggregate_id: String,
@@ -128,6 +130,22 @@ impl<A: Aggregate> TryFrom<SerializedSnapshot> for EventStoreAggregateContext<A>
     }
 }
 
+// Deserializes without consuming the event so that it remains available if this fails.
+impl<A: Aggregate> TryFrom<&SerializedEvent> for EventEnvelope<A> {
+    type Error = PersistenceError;
+
+    fn try_from(event: &SerializedEvent) -> Result<Self, Self::Error> {
+        let payload = A::Event::deserialize(&event.payload)?;
+        let metadata = HashMap::deserialize(&event.metadata)?;
+        Ok(Self {
+            aggregate_id: event.aggregate_id.clone(),
+            sequence: event.sequence,
+            payload,
+            metadata,
+        })
+    }
+}
+
 impl<A: Aggregate> TryFrom<SerializedEvent> for EventEnvelope<A> {
     type Error = PersistenceError;
 
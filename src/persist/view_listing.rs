@@ -0,0 +1,192 @@
+use std::future::Future;
+
+use serde_json::Value;
+
+use crate::persist::{PersistenceError, ViewRepository};
+use crate::{Aggregate, View};
+
+/// The largest number of views that will be returned in a single page, larger limits are
+/// reduced to this size.
+pub const MAX_VIEW_PAGE_SIZE: usize = 1000;
+
+/// A `ViewRepository` that can list and count the views it holds, used to back list endpoints
+/// on the read side.
+pub trait ListableViewRepository<V, A>: ViewRepository<V, A>
+where
+    V: View<A>,
+    A: Aggregate,
+{
+    /// Returns a page of views matching the query along with their view ids.
+    ///
+    /// The order of views is determined by the repository but is stable, so that the
+    /// `next_cursor` of a returned page can be used to request the following page.
+    fn list_views(
+        &self,
+        query: &ViewListQuery,
+    ) -> impl Future<Output = Result<ViewPage<V>, PersistenceError>> + Send;
+
+    /// Returns the number of views matching all of the provided filters.
+    fn count_views(
+        &self,
+        filters: &[ViewFilter],
+    ) -> impl Future<Output = Result<u64, PersistenceError>> + Send;
+}
+
+/// A filter matching views with a specific value at a path within their serialized payload.
+///
+/// Values are compared using their text representation, so this is intended for matching
+/// strings, numbers and booleans. A `null` value matches views where the field is either
+/// `null` or missing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ViewFilter {
+    /// The field names leading to the value within the serialized view.
+    pub path: Vec<String>,
+    /// The value that the field must be equal to.
+    pub value: Value,
+}
+
+impl ViewFilter {
+    /// Creates a filter matching views where the field at the dot-separated `path` is equal to
+    /// `value`.
+    ///
+    /// ```
+    /// use cqrs_es::persist::ViewFilter;
+    ///
+    /// let filter = ViewFilter::equals("address.city", "Seattle");
+    /// assert_eq!(filter.path, vec!["address", "city"]);
+    /// ```
+    pub fn equals(path: &str, value: impl Into<Value>) -> Self {
+        Self {
+            path: path.split('.').map(ToString::to_string).collect(),
+            value: value.into(),
+        }
+    }
+
+    /// The text representation used to compare the filter value against a stored field,
+    /// `None` when matching a `null` or missing field.
+    pub fn value_as_text(&self) -> Option<String> {
+        match &self.value {
+            Value::Null => None,
+            Value::String(value) => Some(value.clone()),
+            value => Some(value.to_string()),
+        }
+    }
+
+    /// Checks whether a serialized view matches this filter, for use by repositories that
+    /// cannot filter within the database.
+    ///
+    /// Array elements are addressed by their index, e.g. `tags.1`, as with a postgres text path.
+    pub fn matches(&self, payload: &Value) -> bool {
+        let field = self
+            .path
+            .iter()
+            .try_fold(payload, |value, field| match value {
+                Value::Array(elements) => elements.get(field.parse::<usize>().ok()?),
+                value => value.get(field),
+            });
+        let field_text = match field {
+            None | Some(Value::Null) => None,
+            Some(Value::String(value)) => Some(value.clone()),
+            Some(value) => Some(value.to_string()),
+        };
+        field_text == self.value_as_text()
+    }
+}
+
+/// A request for a page of views.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ViewListQuery {
+    /// Filters that every returned view must match.
+    pub filters: Vec<ViewFilter>,
+    /// The cursor returned with the previous page, `None` to start from the first page.
+    pub cursor: Option<String>,
+    /// The maximum number of views to return, no more than `MAX_VIEW_PAGE_SIZE` views are
+    /// returned regardless of this limit.
+    pub limit: usize,
+}
+
+impl ViewListQuery {
+    /// Creates a query for the first page of views, returning at most `limit` views.
+    ///
+    /// ```
+    /// use cqrs_es::persist::{ViewFilter, ViewListQuery};
+    ///
+    /// let query = ViewListQuery::new(20)
+    ///     .with_filter(ViewFilter::equals("status", "open"))
+    ///     .after(Some("last-view-id".to_string()));
+    /// ```
+    pub fn new(limit: usize) -> Self {
+        Self {
+            filters: Vec::default(),
+            cursor: None,
+            limit,
+        }
+    }
+
+    /// Adds a filter that every returned view must match.
+    #[must_use]
+    pub fn with_filter(mut self, filter: ViewFilter) -> Self {
+        self.filters.push(filter);
+        self
+    }
+
+    /// Continues listing after the page that returned this cursor.
+    #[must_use]
+    pub fn after(self, cursor: Option<String>) -> Self {
+        Self { cursor, ..self }
+    }
+
+    /// The number of views that should be returned in the page, the limit reduced to at most
+    /// `MAX_VIEW_PAGE_SIZE`.
+    ///
+    /// ```
+    /// use cqrs_es::persist::{ViewListQuery, MAX_VIEW_PAGE_SIZE};
+    ///
+    /// assert_eq!(20, ViewListQuery::new(20).page_size());
+    /// assert_eq!(MAX_VIEW_PAGE_SIZE, ViewListQuery::new(usize::MAX).page_size());
+    /// ```
+    pub fn page_size(&self) -> usize {
+        self.limit.min(MAX_VIEW_PAGE_SIZE)
+    }
+}
+
+/// A page of views returned from a `ListableViewRepository`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ViewPage<V> {
+    /// The view ids and views on this page.
+    pub views: Vec<(String, V)>,
+    /// The cursor used to request the next page, `None` if there are no further views.
+    pub next_cursor: Option<String>,
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use crate::persist::ViewFilter;
+
+    #[test]
+    fn filter_matches() {
+        let payload =
+            json!({"name": "Jim", "address": {"zip": 98101, "city": null}, "active": true});
+        assert!(ViewFilter::equals("name", "Jim").matches(&payload));
+        assert!(!ViewFilter::equals("name", "Bob").matches(&payload));
+        assert!(ViewFilter::equals("address.zip", 98101).matches(&payload));
+        assert!(ViewFilter::equals("address.zip", "98101").matches(&payload));
+        assert!(ViewFilter::equals("active", true).matches(&payload));
+        assert!(ViewFilter::equals("address.city", json!(null)).matches(&payload));
+        assert!(ViewFilter::equals("address.country", json!(null)).matches(&payload));
+        assert!(!ViewFilter::equals("name.first", "Jim").matches(&payload));
+    }
+
+    #[test]
+    fn filter_matches_array_elements() {
+        let payload = json!({"tags": ["a", "b"], "owners": [{"name": "Jim"}]});
+        assert!(ViewFilter::equals("tags.0", "a").matches(&payload));
+        assert!(ViewFilter::equals("tags.1", "b").matches(&payload));
+        assert!(!ViewFilter::equals("tags.1", "a").matches(&payload));
+        assert!(ViewFilter::equals("tags.2", json!(null)).matches(&payload));
+        assert!(!ViewFilter::equals("tags.first", "a").matches(&payload));
+        assert!(ViewFilter::equals("owners.0.name", "Jim").matches(&payload));
+    }
+}
@@ -0,0 +1,426 @@
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::{RwLock, RwLockReadGuard};
+
+use crate::persist::{
+    EventUpcaster, GenericQuery, PersistedEventRepository, PersistenceError, ShadowViewRepository,
+    ViewIdMapper,
+};
+use crate::{Aggregate, AggregateError, View};
+
+// Number of passes made over the event repository to apply events committed during a rebuild
+// before live updates are paused for the final switch.
+const MAX_CATCH_UP_PASSES: usize = 5;
+
+/// Rebuilds the views of a `GenericQuery` without disturbing the live views.
+///
+/// Views are projected into the shadow location of a `ShadowViewRepository` while the live
+/// views continue to be updated. The sequence reached for each aggregate instance is recorded
+/// and the event repository is then read again to catch up on the events committed while the
+/// rebuild was running, including those committed by other processes. Live updates by this
+/// query are paused for a final catch up while readers are switched over to the rebuilt views.
+///
+/// Each catch up pass reads every event of the aggregate type, so a rebuild reads the events
+/// several times. Other processes updating the same views are not paused, an event they apply
+/// to the live views after the final catch up but before the switch is not carried over to the
+/// rebuilt views.
+///
+/// ```
+/// # use cqrs_es::doc::{MyAggregate, MyRepository};
+/// # use cqrs_es::persist::GenericQuery;
+/// # use cqrs_es::persist::doc::{MyView, MyViewRepository};
+/// async fn rebuild(query: &GenericQuery<MyViewRepository, MyView, MyAggregate>, repo: MyRepository) {
+///     let rebuild = query.rebuilder(repo);
+///     rebuild.run().await.unwrap();
+/// }
+/// ```
+pub struct ViewRebuild<ER, R, V, A>
+where
+    ER: PersistedEventRepository,
+    R: ShadowViewRepository<V, A>,
+    V: View<A>,
+    A: Aggregate,
+{
+    event_repository: ER,
+    view_repository: Arc<R>,
+    view_id_mapper: Option<Arc<ViewIdMapper<A>>>,
+    state: Arc<RebuildState<A>>,
+    event_upcasters: Vec<Box<dyn EventUpcaster>>,
+    phantom: PhantomData<V>,
+}
+
+impl<ER, R, V, A> ViewRebuild<ER, R, V, A>
+where
+    ER: PersistedEventRepository,
+    R: ShadowViewRepository<V, A>,
+    V: View<A>,
+    A: Aggregate,
+{
+    pub(crate) fn new(
+        event_repository: ER,
+        view_repository: Arc<R>,
+        view_id_mapper: Option<Arc<ViewIdMapper<A>>>,
+        state: Arc<RebuildState<A>>,
+    ) -> Self {
+        Self {
+            event_repository,
+            view_repository,
+            view_id_mapper,
+            state,
+            event_upcasters: vec![],
+            phantom: PhantomData,
+        }
+    }
+
+    /// Configures the rebuild to use event upcasters when replaying events.
+    /// The EventUpcasters within the Vec should be placed in the
+    /// order that they should be applied
+    ///
+    /// E.g., an upcaster for version 0.2.3 should be placed before an upcaster for version 0.2.4
+    pub fn with_upcasters(self, event_upcasters: Vec<Box<dyn EventUpcaster>>) -> Self {
+        Self {
+            event_upcasters,
+            ..self
+        }
+    }
+
+    /// Rebuilds all views from the committed events and switches readers over to the rebuilt
+    /// views.
+    ///
+    /// If an error is encountered the live views are left in place and continue to be updated.
+    pub async fn run(&self) -> Result<(), AggregateError<A::Error>> {
+        let shadow = self.view_repository.prepare_shadow().await?;
+        let shadow = GenericQuery::<R::Shadow, V, A>::new(Arc::new(shadow))
+            .with_view_id_mapper(self.view_id_mapper.clone());
+        self.state.begin()?;
+        let result = self.rebuild(&shadow).await;
+        if result.is_err() {
+            self.state.abandon();
+        }
+        result
+    }
+
+    async fn rebuild(
+        &self,
+        shadow: &GenericQuery<R::Shadow, V, A>,
+    ) -> Result<(), AggregateError<A::Error>> {
+        // the highest sequence applied to the shadow views for each aggregate instance
+        let mut applied: HashMap<String, usize> = HashMap::new();
+        self.catch_up(shadow, &mut applied).await?;
+        for _ in 0..MAX_CATCH_UP_PASSES {
+            if self.catch_up(shadow, &mut applied).await? == 0 {
+                break;
+            }
+        }
+        let _paused = self.state.gate.write().await;
+        // events dispatched to the live query before this point have been committed and so are
+        // included in the final catch up
+        self.state.finish();
+        self.catch_up(shadow, &mut applied).await?;
+        self.view_repository.promote_shadow().await?;
+        self.state.promoted();
+        Ok(())
+    }
+
+    // Applies the committed events beyond the sequence already applied for each aggregate
+    // instance, returning the number of events applied.
+    async fn catch_up(
+        &self,
+        shadow: &GenericQuery<R::Shadow, V, A>,
+        applied: &mut HashMap<String, usize>,
+    ) -> Result<usize, AggregateError<A::Error>> {
+        let mut count = 0;
+        let mut stream = self.event_repository.stream_all_events::<A>().await?;
+        while let Some(event) = stream.next::<A>(&self.event_upcasters).await {
+            let event = event?;
+            let last_applied = applied
+                .get(&event.aggregate_id)
+                .copied()
+                .unwrap_or_default();
+            if event.sequence <= last_applied {
+                continue;
+            }
+            let aggregate_id = event.aggregate_id.clone();
+            applied.insert(aggregate_id.clone(), event.sequence);
+            shadow.update_views(&aggregate_id, &[event]).await?;
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+/// Shared between a `GenericQuery` and any rebuilds of its views, this pauses the live query
+/// while rebuilt views are switched in and tracks which dispatched events they already hold.
+pub(crate) struct RebuildState<A: Aggregate> {
+    gate: RwLock<()>,
+    tracking: Mutex<Tracking>,
+    phantom: PhantomData<A>,
+}
+
+struct Tracking {
+    // Incremented each time a rebuild starts its final catch up.
+    epoch: usize,
+    // The epoch at which the most recent successful rebuild started its final catch up.
+    promoted: usize,
+    in_progress: bool,
+}
+
+impl<A: Aggregate> Default for RebuildState<A> {
+    fn default() -> Self {
+        Self {
+            gate: RwLock::default(),
+            tracking: Mutex::new(Tracking {
+                epoch: 0,
+                promoted: 0,
+                in_progress: false,
+            }),
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<A: Aggregate> RebuildState<A> {
+    /// Returns the current epoch, taken as events are dispatched to the live query.
+    pub(crate) fn epoch(&self) -> usize {
+        self.tracking.lock().unwrap().epoch
+    }
+
+    /// Waits for any view switch in progress to complete.
+    pub(crate) async fn enter(&self) -> RwLockReadGuard<'_, ()> {
+        self.gate.read().await
+    }
+
+    /// True if the events dispatched at this epoch are already held in views that have since
+    /// been promoted.
+    pub(crate) fn is_superseded(&self, epoch: usize) -> bool {
+        self.tracking.lock().unwrap().promoted > epoch
+    }
+
+    fn begin(&self) -> Result<(), PersistenceError> {
+        let mut tracking = self.tracking.lock().unwrap();
+        if tracking.in_progress {
+            return Err(PersistenceError::UnknownError(
+                "a rebuild of these views is already in progress".into(),
+            ));
+        }
+        tracking.in_progress = true;
+        Ok(())
+    }
+
+    fn finish(&self) {
+        let mut tracking = self.tracking.lock().unwrap();
+        tracking.epoch += 1;
+        tracking.in_progress = false;
+    }
+
+    fn promoted(&self) {
+        let mut tracking = self.tracking.lock().unwrap();
+        tracking.promoted = tracking.epoch;
+    }
+
+    fn abandon(&self) {
+        self.tracking.lock().unwrap().in_progress = false;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    use serde::{Deserialize, Serialize};
+
+    use crate::persist::event_store::shared_test::{
+        test_serialized_event, TestAggregate, TestEvents, TEST_AGGREGATE_ID,
+    };
+    use crate::persist::view_rebuild::RebuildState;
+    use crate::persist::{
+        GenericQuery, MemEventRepository, PersistenceError, ShadowViewRepository, ViewContext,
+        ViewRepository,
+    };
+    use crate::{EventEnvelope, Query, View};
+
+    #[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+    struct TestView {
+        events: Vec<TestEvents>,
+    }
+
+    impl View<TestAggregate> for TestView {
+        fn update(&mut self, event: &EventEnvelope<TestAggregate>) {
+            self.events.push(event.payload.clone());
+        }
+    }
+
+    type ViewTable = Arc<Mutex<HashMap<String, (TestView, i64)>>>;
+
+    // Run once on the next view update, shared with the shadow repository.
+    type UpdateHook = Arc<Mutex<Option<Box<dyn FnOnce() + Send>>>>;
+
+    #[derive(Default)]
+    struct TestViewRepository {
+        live: Mutex<ViewTable>,
+        shadow: Mutex<ViewTable>,
+        on_update: UpdateHook,
+    }
+
+    impl TestViewRepository {
+        fn table(&self) -> ViewTable {
+            self.live.lock().unwrap().clone()
+        }
+    }
+
+    impl ViewRepository<TestView, TestAggregate> for TestViewRepository {
+        async fn load(&self, view_id: &str) -> Result<Option<TestView>, PersistenceError> {
+            Ok(self.load_with_context(view_id).await?.map(|(view, _)| view))
+        }
+
+        async fn load_with_context(
+            &self,
+            view_id: &str,
+        ) -> Result<Option<(TestView, ViewContext)>, PersistenceError> {
+            let table = self.table();
+            let table = table.lock().unwrap();
+            Ok(table.get(view_id).map(|(view, version)| {
+                (
+                    view.clone(),
+                    ViewContext::new(view_id.to_string(), *version),
+                )
+            }))
+        }
+
+        async fn update_view(
+            &self,
+            view: TestView,
+            context: ViewContext,
+        ) -> Result<(), PersistenceError> {
+            if let Some(hook) = self.on_update.lock().unwrap().take() {
+                hook();
+            }
+            let table = self.table();
+            table
+                .lock()
+                .unwrap()
+                .insert(context.view_instance_id, (view, context.version + 1));
+            Ok(())
+        }
+
+        async fn delete_view(&self, context: ViewContext) -> Result<(), PersistenceError> {
+            let table = self.table();
+            table.lock().unwrap().remove(&context.view_instance_id);
+            Ok(())
+        }
+
+        async fn clear_all(&self) -> Result<(), PersistenceError> {
+            self.table().lock().unwrap().clear();
+            Ok(())
+        }
+    }
+
+    impl ShadowViewRepository<TestView, TestAggregate> for TestViewRepository {
+        type Shadow = Self;
+
+        async fn prepare_shadow(&self) -> Result<Self, PersistenceError> {
+            let shadow = Self {
+                live: Mutex::new(self.shadow.lock().unwrap().clone()),
+                shadow: Mutex::default(),
+                on_update: self.on_update.clone(),
+            };
+            shadow.clear_all().await?;
+            Ok(shadow)
+        }
+
+        async fn promote_shadow(&self) -> Result<(), PersistenceError> {
+            let mut live = self.live.lock().unwrap();
+            let mut shadow = self.shadow.lock().unwrap();
+            std::mem::swap(&mut *live, &mut *shadow);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn rebuild_replaces_live_views() {
+        let view_repo = Arc::new(TestViewRepository::default());
+        let stale = TestView {
+            events: vec![TestEvents::Started],
+        };
+        view_repo
+            .update_view(stale, ViewContext::new(TEST_AGGREGATE_ID.to_string(), 0))
+            .await
+            .unwrap();
+        let query = GenericQuery::<_, TestView, TestAggregate>::new(view_repo.clone());
+
+        let event_repo = MemEventRepository::default();
+        event_repo
+            .insert_events::<TestAggregate>(&[
+                test_serialized_event(1, TestEvents::SomethingWasDone),
+                test_serialized_event(2, TestEvents::SomethingWasDone),
+            ])
+            .unwrap();
+        query.rebuilder(event_repo).run().await.unwrap();
+
+        let expected = TestView {
+            events: vec![TestEvents::SomethingWasDone, TestEvents::SomethingWasDone],
+        };
+        assert_eq!(Some(expected), query.load(TEST_AGGREGATE_ID).await);
+
+        // live updates continue against the rebuilt views
+        query
+            .dispatch(
+                TEST_AGGREGATE_ID,
+                &[EventEnvelope {
+                    aggregate_id: TEST_AGGREGATE_ID.to_string(),
+                    sequence: 3,
+                    payload: TestEvents::Started,
+                    metadata: HashMap::default(),
+                }],
+            )
+            .await;
+        let found = query.load(TEST_AGGREGATE_ID).await.unwrap();
+        assert_eq!(3, found.events.len());
+    }
+
+    #[tokio::test]
+    async fn rebuild_catches_up_on_events_committed_elsewhere() {
+        let view_repo = Arc::new(TestViewRepository::default());
+        let query = GenericQuery::<_, TestView, TestAggregate>::new(view_repo.clone());
+        let event_repo = MemEventRepository::default();
+        event_repo
+            .insert_events::<TestAggregate>(&[test_serialized_event(1, TestEvents::Started)])
+            .unwrap();
+
+        // another process commits an event once the rebuild has started
+        let other_process = event_repo.clone();
+        *view_repo.on_update.lock().unwrap() = Some(Box::new(move || {
+            other_process
+                .insert_events::<TestAggregate>(&[test_serialized_event(
+                    2,
+                    TestEvents::SomethingWasDone,
+                )])
+                .unwrap();
+        }));
+        query.rebuilder(event_repo).run().await.unwrap();
+
+        let expected = TestView {
+            events: vec![TestEvents::Started, TestEvents::SomethingWasDone],
+        };
+        assert_eq!(Some(expected), query.load(TEST_AGGREGATE_ID).await);
+    }
+
+    #[test]
+    fn rebuild_state_tracks_promotion() {
+        let state = RebuildState::<TestAggregate>::default();
+        assert_eq!(0, state.epoch());
+
+        state.begin().unwrap();
+        assert!(state.begin().is_err());
+        let epoch = state.epoch();
+        state.finish();
+        assert!(!state.is_superseded(epoch));
+        state.promoted();
+        assert!(state.is_superseded(epoch));
+        assert!(!state.is_superseded(state.epoch()));
+        state.begin().unwrap();
+    }
+}
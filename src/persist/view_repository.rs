@@ -29,6 +29,103 @@ where
         view: V,
         context: ViewContext,
     ) -> impl Future<Output = Result<(), PersistenceError>> + Send;
+
+    /// Returns the view instances and contexts that exist for the provided view ids, used by the
+    /// `GenericQuery` when processing events in batches.
+    ///
+    /// The default implementation loads each view individually, repositories should override
+    /// this to load the views with as few round-trips as possible.
+    fn load_all_with_context(
+        &self,
+        view_ids: &[String],
+    ) -> impl Future<Output = Result<Vec<(V, ViewContext)>, PersistenceError>> + Send {
+        async move {
+            let mut views = Vec::with_capacity(view_ids.len());
+            for view_id in view_ids {
+                if let Some(view) = self.load_with_context(view_id).await? {
+                    views.push(view);
+                }
+            }
+            Ok(views)
+        }
+    }
+
+    /// Updates many view instances, used by the `GenericQuery` when processing events in batches.
+    /// An `OptimisticLockError` is returned if any of the views has been modified since it was
+    /// loaded, in which case some of the other views may have been written. The `GenericQuery`
+    /// reloads the views following a conflict and only reapplies events to those that were not
+    /// written.
+    ///
+    /// The default implementation updates each view individually, repositories should override
+    /// this to write the views with as few round-trips as possible.
+    fn update_all(
+        &self,
+        views: Vec<(V, ViewContext)>,
+    ) -> impl Future<Output = Result<(), PersistenceError>> + Send {
+        async move {
+            for (view, context) in views {
+                self.update_view(view, context).await?;
+            }
+            Ok(())
+        }
+    }
+
+    /// Deletes the view instance, used by the `GenericQuery` to remove views that have been
+    /// marked as deleted. An `OptimisticLockError` is returned if the view has been modified
+    /// since it was loaded with the provided context.
+    ///
+    /// No record of the view is kept, including the sequence of the last event applied, see
+    /// [`View::is_deleted`](../trait.View.html#method.is_deleted).
+    ///
+    /// The default implementation returns an error, repositories backing views that can be
+    /// marked as deleted must override this.
+    fn delete_view(
+        &self,
+        context: ViewContext,
+    ) -> impl Future<Output = Result<(), PersistenceError>> + Send {
+        async move {
+            Err(PersistenceError::UnknownError(
+                format!(
+                    "view {} cannot be deleted, deleting views is not supported by this repository",
+                    context.view_instance_id
+                )
+                .into(),
+            ))
+        }
+    }
+
+    /// Deletes every view instance held by this repository, e.g., before rebuilding the views.
+    ///
+    /// The default implementation returns an error, repositories must override this to support
+    /// rebuilding their views.
+    fn clear_all(&self) -> impl Future<Output = Result<(), PersistenceError>> + Send {
+        async {
+            Err(PersistenceError::UnknownError(
+                "clearing views is not supported by this repository".into(),
+            ))
+        }
+    }
+}
+
+/// A `ViewRepository` that can rebuild its views into a shadow location and then atomically
+/// switch readers over to them, used by a [`ViewRebuild`](struct.ViewRebuild.html) so that
+/// views can be rebuilt without disturbing the live views.
+pub trait ShadowViewRepository<V, A>: ViewRepository<V, A>
+where
+    V: View<A>,
+    A: Aggregate,
+{
+    /// The repository used to write rebuilt views into the shadow location.
+    type Shadow: ViewRepository<V, A>;
+
+    /// Removes any views remaining in the shadow location and returns a repository that will
+    /// write rebuilt views there.
+    fn prepare_shadow(&self)
+        -> impl Future<Output = Result<Self::Shadow, PersistenceError>> + Send;
+
+    /// Atomically replaces the live views with those in the shadow location.
+    /// The previously live views become the shadow location for the next rebuild.
+    fn promote_shadow(&self) -> impl Future<Output = Result<(), PersistenceError>> + Send;
 }
 
 /// A data structure maintaining context when updating views.
@@ -37,6 +134,12 @@ pub struct ViewContext {
     pub view_instance_id: String,
     /// The current version of the view instance, used for optimistic locking.
     pub version: i64,
+    /// The sequence of the last event applied from the aggregate instance sharing this view's id,
+    /// used to ignore duplicate or out-of-order events. Zero if no events have been tracked.
+    ///
+    /// Events from other aggregate instances, e.g., those routed to the view by a view id
+    /// mapper, are not tracked and are applied again if they are redelivered.
+    pub last_sequence: usize,
 }
 
 impl ViewContext {
@@ -45,6 +148,16 @@ impl ViewContext {
         Self {
             view_instance_id,
             version,
+            last_sequence: 0,
+        }
+    }
+
+    /// Sets the sequence of the last event applied to the view instance.
+    #[must_use]
+    pub fn with_last_sequence(self, last_sequence: usize) -> Self {
+        Self {
+            last_sequence,
+            ..self
         }
     }
 }
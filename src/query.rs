@@ -18,6 +18,18 @@ use crate::event::EventEnvelope;
 pub trait Query<A: Aggregate>: Send + Sync {
     /// Events will be dispatched here immediately after being committed.
     async fn dispatch(&self, aggregate_id: &str, events: &[EventEnvelope<A>]);
+
+    /// Dispatches a batch of events that may belong to many aggregate instances, used when
+    /// replaying events in batches.
+    ///
+    /// By default, each run of consecutive events from the same aggregate instance is passed to
+    /// `dispatch`. Queries may override this to process the batch more efficiently.
+    async fn dispatch_batch(&self, events: &[EventEnvelope<A>]) {
+        for aggregate_events in events.chunk_by(|a, b| a.aggregate_id == b.aggregate_id) {
+            let aggregate_id = &aggregate_events[0].aggregate_id;
+            self.dispatch(aggregate_id, aggregate_events).await;
+        }
+    }
 }
 
 /// A `View` represents a materialized view, generally serialized for persistence, that is updated by a query.
@@ -27,4 +39,19 @@ pub trait View<A: Aggregate>: Debug + Default + Serialize + DeserializeOwned + S
     /// Each implemented view is responsible for updating its state based on events passed via
     /// this method.
     fn update(&mut self, event: &EventEnvelope<A>);
+
+    /// Signals that the events applied have ended the life of this view, e.g., an account
+    /// has been closed. A `GenericQuery` will delete a view that returns `true` rather than
+    /// persisting it.
+    ///
+    /// _Note: deleting a view also removes the sequence of the last event applied to it, so
+    /// deletes are not idempotent under redelivery. An event of the aggregate instance that is
+    /// delivered again after the view has been deleted is applied to a new default view,
+    /// recreating it. Views that may receive redelivered events should instead be kept and
+    /// marked as closed within their own state._
+    ///
+    /// By default views are never deleted.
+    fn is_deleted(&self) -> bool {
+        false
+    }
 }
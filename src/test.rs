@@ -20,10 +20,50 @@
 //!         }]);
 //! # }
 //! ```
+//!
+//! Views are tested in the same way with a `ViewTestFramework`, events are provided to the view
+//! in envelopes and the resulting view is verified.
+//!
+//! ```rust
+//! # use cqrs_es::test::ViewTestFramework;
+//! # use cqrs_es::doc::{Customer, CustomerEvent, CustomerView};
+//! # fn test() {
+//! type CustomerViewTestFramework = ViewTestFramework<CustomerView, Customer>;
+//!
+//! CustomerViewTestFramework::with_aggregate_id("customer-1")
+//!     .given_no_previous_events()
+//!     .when(vec![CustomerEvent::NameAdded{
+//!             name: "John Doe".to_string()
+//!         }])
+//!     .then_expect_view_matching(|view| view.name == "John Doe");
+//! # }
+//! ```
+#[cfg(feature = "conformance")]
+mod conformance;
 mod executor;
+#[cfg(feature = "fault-injection")]
+mod fault_injection;
 mod framework;
+#[cfg(feature = "proptest")]
+mod property;
+mod scenario;
+mod schema;
 mod validator;
+mod view_executor;
+mod view_framework;
+mod view_validator;
 
+#[cfg(feature = "conformance")]
+pub use crate::test::conformance::*;
 pub use crate::test::executor::*;
+#[cfg(feature = "fault-injection")]
+pub use crate::test::fault_injection::*;
 pub use crate::test::framework::*;
+#[cfg(feature = "proptest")]
+pub use crate::test::property::*;
+pub use crate::test::scenario::*;
+pub use crate::test::schema::*;
 pub use crate::test::validator::*;
+pub use crate::test::view_executor::*;
+pub use crate::test::view_framework::*;
+pub use crate::test::view_validator::*;
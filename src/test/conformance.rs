@@ -0,0 +1,795 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::aggregate::Aggregate;
+use crate::event::{DomainEvent, EventEnvelope};
+use crate::event_sink::EventSink;
+use crate::persist::{
+    PersistedEventRepository, PersistenceError, ReplayFilter, ReplayStream, SerializedEvent,
+    ViewContext, ViewRepository,
+};
+use crate::query::View;
+
+const DEFAULT_LARGE_PAYLOAD_SIZE: usize = 128 * 1024;
+const SUBSCRIPTION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The aggregate used by the conformance suites, events are committed under the aggregate
+/// type `ConformanceAggregate`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ConformanceAggregate {
+    /// The entries added to the aggregate.
+    pub entries: Vec<String>,
+}
+
+/// The commands of the `ConformanceAggregate`, none are needed by the conformance suites.
+pub enum ConformanceCommand {}
+
+/// The events of the `ConformanceAggregate`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ConformanceEvent {
+    /// An entry was added.
+    EntryAdded {
+        /// The entry that was added.
+        entry: String,
+    },
+    /// An entry was removed.
+    EntryRemoved {
+        /// The entry that was removed.
+        entry: String,
+    },
+}
+
+/// The error of the `ConformanceAggregate`, no commands are handled so this is never returned.
+#[derive(Debug, thiserror::Error)]
+#[error("conformance aggregate error")]
+pub struct ConformanceError;
+
+impl Aggregate for ConformanceAggregate {
+    const TYPE: &'static str = "ConformanceAggregate";
+    type Command = ConformanceCommand;
+    type Event = ConformanceEvent;
+    type Error = ConformanceError;
+    type Services = ();
+
+    async fn handle(
+        &mut self,
+        command: Self::Command,
+        _service: &Self::Services,
+        _sink: &EventSink<Self>,
+    ) -> Result<(), Self::Error> {
+        match command {}
+    }
+
+    fn apply(&mut self, event: Self::Event) {
+        match event {
+            ConformanceEvent::EntryAdded { entry } => self.entries.push(entry),
+            ConformanceEvent::EntryRemoved { entry } => self.entries.retain(|e| e != &entry),
+        }
+    }
+}
+
+impl DomainEvent for ConformanceEvent {
+    fn event_type(&self) -> String {
+        match self {
+            Self::EntryAdded { .. } => "EntryAdded".to_string(),
+            Self::EntryRemoved { .. } => "EntryRemoved".to_string(),
+        }
+    }
+
+    fn event_version(&self) -> String {
+        "1.0".to_string()
+    }
+}
+
+/// The view used by the view repository conformance suite.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConformanceView {
+    /// The entries currently held by the aggregate.
+    pub entries: Vec<String>,
+}
+
+impl View<ConformanceAggregate> for ConformanceView {
+    fn update(&mut self, event: &EventEnvelope<ConformanceAggregate>) {
+        match &event.payload {
+            ConformanceEvent::EntryAdded { entry } => self.entries.push(entry.clone()),
+            ConformanceEvent::EntryRemoved { entry } => self.entries.retain(|e| e != entry),
+        }
+    }
+}
+
+/// A conformance suite for implementations of `PersistedEventRepository`, available with the
+/// `conformance` feature.
+///
+/// The suite commits events for the `ConformanceAggregate` and verifies:
+/// - the behavior of an aggregate instance without events or a snapshot,
+/// - events are returned in sequence order, with their payload and metadata intact,
+/// - conflicting events are rejected with an `OptimisticLockError` and nothing is committed,
+/// - snapshots are only updated to the next snapshot version,
+/// - the replay streams, filtered streams and subscriptions,
+/// - large event and snapshot payloads are stored intact.
+///
+/// Every repository returned by the factory must share the same storage. Each check uses new
+/// aggregate ids so the suite can be run against a database that holds other events.
+///
+/// ```
+/// use cqrs_es::persist::MemEventRepository;
+/// use cqrs_es::test::EventRepositoryConformance;
+///
+/// # async fn test() {
+/// let repo = MemEventRepository::default();
+/// EventRepositoryConformance::with(move || repo.clone())
+///     .verify()
+///     .await;
+/// # }
+/// ```
+pub struct EventRepositoryConformance<F> {
+    factory: F,
+    large_payload_size: usize,
+    subscriptions: bool,
+}
+
+impl<F, R> EventRepositoryConformance<F>
+where
+    F: Fn() -> R,
+    R: PersistedEventRepository,
+{
+    /// Create a conformance suite using a factory for the repository under test.
+    pub fn with(factory: F) -> Self {
+        Self {
+            factory,
+            large_payload_size: DEFAULT_LARGE_PAYLOAD_SIZE,
+            subscriptions: true,
+        }
+    }
+
+    /// Configures the size in bytes of the large payloads that are committed, the default
+    /// is 128 KiB.
+    #[must_use]
+    pub fn with_large_payload_size(self, large_payload_size: usize) -> Self {
+        Self {
+            large_payload_size,
+            ..self
+        }
+    }
+
+    /// Skips the subscription check, for repositories that rely on the default
+    /// `subscribe_all_events` and do not support subscriptions.
+    #[must_use]
+    pub fn without_subscriptions(self) -> Self {
+        Self {
+            subscriptions: false,
+            ..self
+        }
+    }
+
+    /// Runs every check against the repository, panicking on the first failure.
+    pub async fn verify(self) {
+        self.verify_empty_aggregate().await;
+        self.verify_ordering().await;
+        self.verify_optimistic_locking().await;
+        self.verify_snapshot_versioning().await;
+        self.verify_streaming().await;
+        if self.subscriptions {
+            self.verify_subscription().await;
+        }
+        self.verify_large_payloads().await;
+    }
+
+    async fn verify_empty_aggregate(&self) {
+        let repo = (self.factory)();
+        let id = unique_id("empty");
+        let events = repo.get_events::<ConformanceAggregate>(&id).await;
+        assert_eq!(
+            Vec::<SerializedEvent>::new(),
+            events.expect("get_events failed for an aggregate without events"),
+            "empty aggregate: get_events returned events"
+        );
+        let events = repo.get_last_events::<ConformanceAggregate>(&id, 0).await;
+        assert_eq!(
+            Vec::<SerializedEvent>::new(),
+            events.expect("get_last_events failed for an aggregate without events"),
+            "empty aggregate: get_last_events returned events"
+        );
+        let snapshot = repo.get_snapshot::<ConformanceAggregate>(&id).await;
+        assert_eq!(
+            None,
+            snapshot.expect("get_snapshot failed for an aggregate without a snapshot"),
+            "empty aggregate: get_snapshot returned a snapshot"
+        );
+        let stream = repo.stream_events::<ConformanceAggregate>(&id).await;
+        let events = drain(stream, "empty aggregate: stream_events").await;
+        assert!(
+            events.is_empty(),
+            "empty aggregate: stream_events returned events"
+        );
+    }
+
+    async fn verify_ordering(&self) {
+        let repo = (self.factory)();
+        let id = unique_id("ordering");
+        let other_id = unique_id("ordering");
+        let events: Vec<SerializedEvent> = (1..=6).map(|sequence| event(&id, sequence)).collect();
+        let other_events: Vec<SerializedEvent> =
+            (1..=3).map(|sequence| event(&other_id, sequence)).collect();
+        // commits are interleaved with those of another aggregate instance
+        commit(&repo, &events[..2], "ordering").await;
+        commit(&repo, &other_events[..2], "ordering").await;
+        commit(&repo, &events[2..5], "ordering").await;
+        commit(&repo, &other_events[2..], "ordering").await;
+        commit(&repo, &events[5..], "ordering").await;
+
+        // the events must be visible to a new repository
+        let repo = (self.factory)();
+        let found = repo.get_events::<ConformanceAggregate>(&id).await;
+        assert_eq!(
+            events,
+            found.expect("ordering: get_events failed"),
+            "ordering: get_events did not return the committed events in sequence order"
+        );
+        let found = repo.get_last_events::<ConformanceAggregate>(&id, 4).await;
+        assert_eq!(
+            events[4..].to_vec(),
+            found.expect("ordering: get_last_events failed"),
+            "ordering: get_last_events did not return the events after sequence 4"
+        );
+        let found = repo.get_events::<ConformanceAggregate>(&other_id).await;
+        assert_eq!(
+            other_events,
+            found.expect("ordering: get_events failed"),
+            "ordering: get_events did not return the committed events in sequence order"
+        );
+    }
+
+    async fn verify_optimistic_locking(&self) {
+        let repo = (self.factory)();
+        let id = unique_id("locking");
+        commit(&repo, &[event(&id, 1), event(&id, 2)], "optimistic locking").await;
+
+        let result = repo
+            .persist::<ConformanceAggregate>(&[event(&id, 2)], None)
+            .await;
+        assert_optimistic_lock(
+            result,
+            "optimistic locking: committing an existing sequence",
+        );
+        // nothing from a conflicting commit is written
+        let result = repo
+            .persist::<ConformanceAggregate>(&[event(&id, 3), event(&id, 2)], None)
+            .await;
+        assert_optimistic_lock(result, "optimistic locking: committing a partial conflict");
+        let found = repo.get_events::<ConformanceAggregate>(&id).await;
+        assert_eq!(
+            vec![event(&id, 1), event(&id, 2)],
+            found.expect("optimistic locking: get_events failed"),
+            "optimistic locking: events from a conflicting commit were written"
+        );
+
+        commit(&repo, &[event(&id, 3)], "optimistic locking").await;
+    }
+
+    async fn verify_snapshot_versioning(&self) {
+        let repo = (self.factory)();
+        let id = unique_id("snapshot");
+        let snapshot = |entries: usize| {
+            let entries: Vec<String> = (1..=entries)
+                .map(|entry| format!("entry {entry}"))
+                .collect();
+            json!({ "entries": entries })
+        };
+
+        let result = repo
+            .persist::<ConformanceAggregate>(
+                &[event(&id, 1), event(&id, 2)],
+                Some((id.clone(), snapshot(2), 1)),
+            )
+            .await;
+        result.expect("snapshot versioning: committing the first snapshot failed");
+        self.assert_snapshot(&id, 2, 1, &snapshot(2)).await;
+
+        let result = repo
+            .persist::<ConformanceAggregate>(&[event(&id, 3)], Some((id.clone(), snapshot(3), 2)))
+            .await;
+        result.expect("snapshot versioning: committing the next snapshot failed");
+        self.assert_snapshot(&id, 3, 2, &snapshot(3)).await;
+
+        // a stale snapshot is rejected along with its events
+        let result = repo
+            .persist::<ConformanceAggregate>(&[event(&id, 4)], Some((id.clone(), snapshot(4), 2)))
+            .await;
+        assert_optimistic_lock(result, "snapshot versioning: committing a stale snapshot");
+        self.assert_snapshot(&id, 3, 2, &snapshot(3)).await;
+        let found = repo.get_events::<ConformanceAggregate>(&id).await;
+        assert_eq!(
+            3,
+            found.expect("snapshot versioning: get_events failed").len(),
+            "snapshot versioning: events committed with a stale snapshot were written"
+        );
+    }
+
+    async fn assert_snapshot(
+        &self,
+        id: &str,
+        current_sequence: usize,
+        current_snapshot: usize,
+        aggregate: &Value,
+    ) {
+        let repo = (self.factory)();
+        let snapshot = repo
+            .get_snapshot::<ConformanceAggregate>(id)
+            .await
+            .expect("snapshot versioning: get_snapshot failed")
+            .expect("snapshot versioning: no snapshot was found");
+        assert_eq!(
+            (current_sequence, current_snapshot, aggregate),
+            (
+                snapshot.current_sequence,
+                snapshot.current_snapshot,
+                &snapshot.aggregate
+            ),
+            "snapshot versioning: unexpected (current_sequence, current_snapshot, aggregate)"
+        );
+    }
+
+    async fn verify_streaming(&self) {
+        let repo = (self.factory)();
+        let id = unique_id("streaming");
+        let other_id = unique_id("streaming");
+        let events: Vec<SerializedEvent> = (1..=5).map(|sequence| event(&id, sequence)).collect();
+        commit(&repo, &events, "streaming").await;
+        commit(&repo, &[event(&other_id, 1)], "streaming").await;
+
+        let stream = repo.stream_events::<ConformanceAggregate>(&id).await;
+        let found = drain(stream, "streaming: stream_events").await;
+        assert_eq!(
+            events, found,
+            "streaming: stream_events did not return the committed events in sequence order"
+        );
+
+        let stream = repo.stream_all_events::<ConformanceAggregate>().await;
+        let found = drain(stream, "streaming: stream_all_events").await;
+        let found: Vec<SerializedEvent> = found
+            .into_iter()
+            .filter(|event| event.aggregate_id == id || event.aggregate_id == other_id)
+            .collect();
+        assert_eq!(
+            events,
+            found
+                .iter()
+                .filter(|event| event.aggregate_id == id)
+                .cloned()
+                .collect::<Vec<_>>(),
+            "streaming: stream_all_events did not return the committed events in sequence order"
+        );
+        assert_eq!(
+            1,
+            found.len() - events.len(),
+            "streaming: stream_all_events did not return the events of every aggregate instance"
+        );
+
+        let filter = ReplayFilter::default()
+            .with_aggregate_ids(vec![id.clone()])
+            .with_event_types(vec!["EntryRemoved".to_string()])
+            .with_sequences(2..=4);
+        let stream = repo
+            .stream_filtered_events::<ConformanceAggregate>(&filter)
+            .await;
+        let found = drain(stream, "streaming: stream_filtered_events").await;
+        assert_eq!(
+            vec![events[1].clone(), events[3].clone()],
+            found,
+            "streaming: stream_filtered_events did not return the matching events"
+        );
+    }
+
+    async fn verify_subscription(&self) {
+        let repo = (self.factory)();
+        let id = unique_id("subscription");
+        commit(&repo, &[event(&id, 1)], "subscription").await;
+        let mut stream = repo
+            .subscribe_all_events::<ConformanceAggregate>()
+            .await
+            .expect("subscription: subscribe_all_events failed");
+        commit(&repo, &[event(&id, 2), event(&id, 3)], "subscription").await;
+
+        // committed events are replayed, followed by the events committed after subscribing
+        let mut found = Vec::new();
+        while found.len() < 3 {
+            let next = tokio::time::timeout(SUBSCRIPTION_TIMEOUT, stream.next_serialized())
+                .await
+                .expect("subscription: timed out waiting for committed events")
+                .expect("subscription: the stream ended")
+                .expect("subscription: the stream returned an error");
+            if next.aggregate_id == id {
+                found.push(next);
+            }
+        }
+        assert_eq!(
+            vec![event(&id, 1), event(&id, 2), event(&id, 3)],
+            found,
+            "subscription: subscribe_all_events did not return the committed events in sequence order"
+        );
+    }
+
+    async fn verify_large_payloads(&self) {
+        let repo = (self.factory)();
+        let id = unique_id("large");
+        let entry = large_entry(self.large_payload_size);
+        let large_event = SerializedEvent {
+            payload: json!(ConformanceEvent::EntryAdded {
+                entry: entry.clone()
+            }),
+            ..event(&id, 1)
+        };
+        let aggregate = json!({ "entries": [entry] });
+        let result = repo
+            .persist::<ConformanceAggregate>(
+                std::slice::from_ref(&large_event),
+                Some((id.clone(), aggregate.clone(), 1)),
+            )
+            .await;
+        result.expect("large payloads: committing a large event and snapshot failed");
+
+        let found = repo.get_events::<ConformanceAggregate>(&id).await;
+        assert!(
+            vec![large_event.clone()] == found.expect("large payloads: get_events failed"),
+            "large payloads: get_events did not return the large event intact"
+        );
+        let stream = repo.stream_events::<ConformanceAggregate>(&id).await;
+        let found = drain(stream, "large payloads: stream_events").await;
+        assert!(
+            vec![large_event] == found,
+            "large payloads: stream_events did not return the large event intact"
+        );
+        let snapshot = repo
+            .get_snapshot::<ConformanceAggregate>(&id)
+            .await
+            .expect("large payloads: get_snapshot failed")
+            .expect("large payloads: no snapshot was found");
+        assert!(
+            aggregate == snapshot.aggregate,
+            "large payloads: get_snapshot did not return the large snapshot intact"
+        );
+    }
+}
+
+/// A conformance suite for implementations of `ViewRepository`, available with the
+/// `conformance` feature.
+///
+/// The suite writes `ConformanceView` instances and verifies:
+/// - the behavior of a view instance that does not exist,
+/// - views are stored with their version and last applied sequence,
+/// - stale updates and deletes are rejected with an `OptimisticLockError`,
+/// - batch updates are written together or, on a conflict, not at all,
+/// - large views are stored intact,
+/// - every view is removed by `clear_all`.
+///
+/// Every repository returned by the factory must share the same storage. This storage should
+/// be dedicated to the suite since its views are cleared.
+///
+/// ```
+/// use cqrs_es::persist::MemViewRepository;
+/// use cqrs_es::test::ViewRepositoryConformance;
+///
+/// # async fn test() {
+/// let repo = MemViewRepository::default();
+/// ViewRepositoryConformance::with(move || repo.clone())
+///     .verify()
+///     .await;
+/// # }
+/// ```
+pub struct ViewRepositoryConformance<F> {
+    factory: F,
+    large_payload_size: usize,
+}
+
+impl<F, R> ViewRepositoryConformance<F>
+where
+    F: Fn() -> R,
+    R: ViewRepository<ConformanceView, ConformanceAggregate>,
+{
+    /// Create a conformance suite using a factory for the repository under test.
+    pub fn with(factory: F) -> Self {
+        Self {
+            factory,
+            large_payload_size: DEFAULT_LARGE_PAYLOAD_SIZE,
+        }
+    }
+
+    /// Configures the size in bytes of the large views that are written, the default
+    /// is 128 KiB.
+    #[must_use]
+    pub fn with_large_payload_size(self, large_payload_size: usize) -> Self {
+        Self {
+            large_payload_size,
+            ..self
+        }
+    }
+
+    /// Runs every check against the repository, panicking on the first failure.
+    pub async fn verify(self) {
+        self.verify_missing_view().await;
+        self.verify_versioning().await;
+        self.verify_batches().await;
+        self.verify_deletion().await;
+        self.verify_large_payloads().await;
+        self.verify_clear_all().await;
+    }
+
+    async fn verify_missing_view(&self) {
+        let repo = (self.factory)();
+        let id = unique_id("missing");
+        let view = repo.load(&id).await.expect("missing view: load failed");
+        assert_eq!(None, view, "missing view: load returned a view");
+        let view = repo
+            .load_with_context(&id)
+            .await
+            .expect("missing view: load_with_context failed");
+        assert!(
+            view.is_none(),
+            "missing view: load_with_context returned a view"
+        );
+        let views = repo
+            .load_all_with_context(std::slice::from_ref(&id))
+            .await
+            .expect("missing view: load_all_with_context failed");
+        assert!(
+            views.is_empty(),
+            "missing view: load_all_with_context returned a view"
+        );
+        let result = repo.delete_view(ViewContext::new(id, 1)).await;
+        assert_optimistic_lock(result, "missing view: deleting a view that does not exist");
+    }
+
+    async fn verify_versioning(&self) {
+        let repo = (self.factory)();
+        let id = unique_id("versioning");
+        let context = ViewContext::new(id.clone(), 0).with_last_sequence(3);
+        let result = repo.update_view(view(&["entry 1"]), context).await;
+        result.expect("versioning: writing a new view failed");
+        self.assert_view(&id, &view(&["entry 1"]), 1, 3, "versioning")
+            .await;
+
+        let context = ViewContext::new(id.clone(), 1).with_last_sequence(4);
+        let result = repo
+            .update_view(view(&["entry 1", "entry 2"]), context)
+            .await;
+        result.expect("versioning: updating a view failed");
+        self.assert_view(&id, &view(&["entry 1", "entry 2"]), 2, 4, "versioning")
+            .await;
+
+        let result = repo
+            .update_view(view(&["stale"]), ViewContext::new(id.clone(), 1))
+            .await;
+        assert_optimistic_lock(result, "versioning: updating a view with a stale version");
+        let result = repo
+            .update_view(view(&["stale"]), ViewContext::new(id.clone(), 0))
+            .await;
+        assert_optimistic_lock(result, "versioning: writing a new view that already exists");
+        self.assert_view(&id, &view(&["entry 1", "entry 2"]), 2, 4, "versioning")
+            .await;
+    }
+
+    async fn verify_batches(&self) {
+        let repo = (self.factory)();
+        let first_id = unique_id("batch");
+        let second_id = unique_id("batch");
+        let stale_id = unique_id("batch");
+        let result = repo
+            .update_view(view(&["entry 1"]), ViewContext::new(stale_id.clone(), 0))
+            .await;
+        result.expect("batches: writing a view failed");
+
+        // nothing from a conflicting batch is written
+        let result = repo
+            .update_all(vec![
+                (view(&["first"]), ViewContext::new(first_id.clone(), 0)),
+                (view(&["stale"]), ViewContext::new(stale_id.clone(), 0)),
+            ])
+            .await;
+        assert_optimistic_lock(result, "batches: writing a batch with a stale view");
+        let first = repo.load(&first_id).await.expect("batches: load failed");
+        assert_eq!(
+            None, first,
+            "batches: views from a conflicting batch were written"
+        );
+
+        let result = repo
+            .update_all(vec![
+                (
+                    view(&["first"]),
+                    ViewContext::new(first_id.clone(), 0).with_last_sequence(1),
+                ),
+                (
+                    view(&["second"]),
+                    ViewContext::new(second_id.clone(), 0).with_last_sequence(2),
+                ),
+                (
+                    view(&["entry 1", "entry 2"]),
+                    ViewContext::new(stale_id.clone(), 1).with_last_sequence(3),
+                ),
+            ])
+            .await;
+        result.expect("batches: writing a batch failed");
+
+        let ids = vec![
+            first_id.clone(),
+            unique_id("missing"),
+            second_id.clone(),
+            stale_id.clone(),
+        ];
+        let mut found: Vec<(String, ConformanceView, i64, usize)> = repo
+            .load_all_with_context(&ids)
+            .await
+            .expect("batches: load_all_with_context failed")
+            .into_iter()
+            .map(|(view, context)| {
+                (
+                    context.view_instance_id,
+                    view,
+                    context.version,
+                    context.last_sequence,
+                )
+            })
+            .collect();
+        found.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut expected = vec![
+            (first_id, view(&["first"]), 1, 1),
+            (second_id, view(&["second"]), 1, 2),
+            (stale_id, view(&["entry 1", "entry 2"]), 2, 3),
+        ];
+        expected.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            expected, found,
+            "batches: load_all_with_context did not return the written views"
+        );
+    }
+
+    async fn verify_deletion(&self) {
+        let repo = (self.factory)();
+        let id = unique_id("deletion");
+        let result = repo
+            .update_view(view(&["entry 1"]), ViewContext::new(id.clone(), 0))
+            .await;
+        result.expect("deletion: writing a view failed");
+        let result = repo
+            .update_view(
+                view(&["entry 1", "entry 2"]),
+                ViewContext::new(id.clone(), 1),
+            )
+            .await;
+        result.expect("deletion: updating a view failed");
+
+        let result = repo.delete_view(ViewContext::new(id.clone(), 1)).await;
+        assert_optimistic_lock(result, "deletion: deleting a view with a stale version");
+        self.assert_view(&id, &view(&["entry 1", "entry 2"]), 2, 0, "deletion")
+            .await;
+
+        let result = repo.delete_view(ViewContext::new(id.clone(), 2)).await;
+        result.expect("deletion: deleting a view failed");
+        let found = repo.load(&id).await.expect("deletion: load failed");
+        assert_eq!(None, found, "deletion: the deleted view was loaded");
+    }
+
+    async fn verify_large_payloads(&self) {
+        let repo = (self.factory)();
+        let id = unique_id("large");
+        let entry = large_entry(self.large_payload_size);
+        let large_view = view(&[entry.as_str()]);
+        let result = repo
+            .update_view(large_view.clone(), ViewContext::new(id.clone(), 0))
+            .await;
+        result.expect("large payloads: writing a large view failed");
+        let found = repo.load(&id).await.expect("large payloads: load failed");
+        assert!(
+            Some(large_view) == found,
+            "large payloads: load did not return the large view intact"
+        );
+    }
+
+    async fn verify_clear_all(&self) {
+        let repo = (self.factory)();
+        let ids = vec![unique_id("clear"), unique_id("clear")];
+        for id in &ids {
+            let result = repo
+                .update_view(view(&["entry 1"]), ViewContext::new(id.clone(), 0))
+                .await;
+            result.expect("clear all: writing a view failed");
+        }
+        repo.clear_all().await.expect("clear all: clear_all failed");
+        let found = repo
+            .load_all_with_context(&ids)
+            .await
+            .expect("clear all: load_all_with_context failed");
+        assert!(found.is_empty(), "clear all: views remain after clear_all");
+    }
+
+    async fn assert_view(
+        &self,
+        id: &str,
+        expected: &ConformanceView,
+        version: i64,
+        last_sequence: usize,
+        check: &str,
+    ) {
+        let repo = (self.factory)();
+        let (found, context) = repo
+            .load_with_context(id)
+            .await
+            .unwrap_or_else(|err| panic!("{check}: load_with_context failed: {err}"))
+            .unwrap_or_else(|| panic!("{check}: the view was not found"));
+        assert_eq!(
+            (expected, version, last_sequence),
+            (&found, context.version, context.last_sequence),
+            "{check}: unexpected (view, version, last_sequence)"
+        );
+    }
+}
+
+// Ids are unique across runs so that the suites can use a database holding earlier results.
+fn unique_id(check: &str) -> String {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos());
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+    format!("conformance-{check}-{nanos}-{count}")
+}
+
+// Even sequences remove the entry added by the previous sequence.
+fn event(aggregate_id: &str, sequence: usize) -> SerializedEvent {
+    let entry = format!("entry {}", sequence.div_ceil(2));
+    let payload = if sequence.is_multiple_of(2) {
+        ConformanceEvent::EntryRemoved { entry }
+    } else {
+        ConformanceEvent::EntryAdded { entry }
+    };
+    SerializedEvent::new(
+        aggregate_id.to_string(),
+        sequence,
+        ConformanceAggregate::TYPE.to_string(),
+        payload.event_type(),
+        payload.event_version(),
+        json!(payload),
+        json!({ "sequence": sequence.to_string() }),
+    )
+}
+
+fn view(entries: &[&str]) -> ConformanceView {
+    ConformanceView {
+        entries: entries.iter().map(ToString::to_string).collect(),
+    }
+}
+
+// Includes multibyte characters, which must survive any encoding used by the store.
+fn large_entry(size: usize) -> String {
+    let pattern = "large payload ü ✓ ";
+    pattern.repeat(size / pattern.len() + 1)
+}
+
+async fn commit<R: PersistedEventRepository>(repo: &R, events: &[SerializedEvent], check: &str) {
+    let result = repo.persist::<ConformanceAggregate>(events, None).await;
+    result.unwrap_or_else(|err| panic!("{check}: committing events failed: {err}"));
+}
+
+async fn drain(
+    stream: Result<ReplayStream, PersistenceError>,
+    check: &str,
+) -> Vec<SerializedEvent> {
+    let mut stream = stream.unwrap_or_else(|err| panic!("{check} failed: {err}"));
+    let mut events = Vec::new();
+    while let Some(event) = stream.next_serialized().await {
+        events.push(event.unwrap_or_else(|err| panic!("{check} returned an error: {err}")));
+    }
+    assert!(stream.is_complete(), "{check} did not complete");
+    events
+}
+
+fn assert_optimistic_lock(result: Result<(), PersistenceError>, check: &str) {
+    assert!(
+        matches!(result, Err(PersistenceError::OptimisticLockError)),
+        "{check} did not return an OptimisticLockError, found: {result:?}"
+    );
+}
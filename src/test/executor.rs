@@ -29,8 +29,8 @@ where
     ///
     /// For `async` tests use `when_async` instead.
     pub fn when(self, command: A::Command) -> AggregateResultValidator<A> {
-        let result = when::<A>(self.events, command, self.service);
-        AggregateResultValidator::new(result)
+        let (result, aggregate) = when::<A>(self.events, command, self.service);
+        AggregateResultValidator::new(result, aggregate)
     }
 
     /// Consumes a command in an `async` test and provides a validator object
@@ -55,8 +55,8 @@ where
         }
         let sink: EventSink<A> = Default::default();
         match aggregate.handle(command, &self.service, &sink).await {
-            Ok(_) => AggregateResultValidator::new(Ok(sink.collect().await)),
-            Err(e) => AggregateResultValidator::new(Err(e)),
+            Ok(_) => AggregateResultValidator::new(Ok(sink.collect().await), aggregate),
+            Err(e) => AggregateResultValidator::new(Err(e), aggregate),
         }
     }
 
@@ -88,12 +88,14 @@ async fn when<A: Aggregate>(
     events: Vec<A::Event>,
     command: A::Command,
     service: A::Services,
-) -> Result<Vec<A::Event>, A::Error> {
+) -> (Result<Vec<A::Event>, A::Error>, A) {
     let mut aggregate = A::default();
     for event in events {
         aggregate.apply(event);
     }
     let sink: EventSink<A> = Default::default();
-    aggregate.handle(command, &service, &sink).await?;
-    Ok(sink.collect().await)
+    match aggregate.handle(command, &service, &sink).await {
+        Ok(()) => (Ok(sink.collect().await), aggregate),
+        Err(err) => (Err(err), aggregate),
+    }
 }
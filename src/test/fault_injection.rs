@@ -0,0 +1,558 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde_json::Value;
+
+use crate::persist::{
+    PersistedEventRepository, PersistenceError, ReplayFilter, ReplayStream, SerializedEvent,
+    SerializedSnapshot,
+};
+use crate::Aggregate;
+
+const FAULTY_STREAM_CHANNEL_SIZE: usize = 200;
+
+/// The operations of a `PersistedEventRepository` that faults can be injected into.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RepositoryOperation {
+    /// `PersistedEventRepository::get_events`
+    GetEvents,
+    /// `PersistedEventRepository::get_last_events`
+    GetLastEvents,
+    /// `PersistedEventRepository::get_snapshot`
+    GetSnapshot,
+    /// `PersistedEventRepository::persist`
+    Persist,
+    /// `PersistedEventRepository::stream_events`
+    StreamEvents,
+    /// `PersistedEventRepository::stream_all_events`
+    StreamAllEvents,
+    /// `PersistedEventRepository::stream_filtered_events`
+    StreamFilteredEvents,
+    /// `PersistedEventRepository::subscribe_all_events`
+    SubscribeAllEvents,
+}
+
+/// A fault injected by a `FaultInjectingEventRepository`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Fault {
+    /// The operation fails with a `PersistenceError::ConnectionError`.
+    ConnectionError,
+    /// The operation fails with a `PersistenceError::OptimisticLockError`, nothing is committed.
+    OptimisticLock,
+    /// The operation is delayed before it is passed on to the decorated repository.
+    Delay(Duration),
+    /// The returned stream yields an error after the provided number of events, or once the
+    /// events run out if there are fewer. Other operations fail with a
+    /// `PersistenceError::ConnectionError`.
+    StreamError {
+        /// The number of events that are received before the error.
+        after: usize,
+    },
+}
+
+// Determines the fault, if any, for each call of an operation.
+enum FaultRule {
+    Always(Fault),
+    Probability(Fault, f64),
+    Schedule(VecDeque<Option<Fault>>),
+}
+
+struct FaultState {
+    rules: HashMap<RepositoryOperation, Vec<FaultRule>>,
+    calls: HashMap<RepositoryOperation, usize>,
+    injected: Vec<(RepositoryOperation, Fault)>,
+    random: SplitMix64,
+}
+
+/// Decorates a `PersistedEventRepository` with faults, allowing the handling of connection
+/// errors, optimistic lock failures, slow responses and failed streams to be tested.
+///
+/// Faults are configured for each operation: on every call, with a probability, or from a
+/// scripted schedule. Probabilities are drawn from a seeded generator so that a test injects
+/// the same faults each time it runs. When several rules are configured for an operation they
+/// are evaluated in the order they were added and the first fault found is injected. Calls
+/// without a fault are passed on to the decorated repository.
+///
+/// Clones of the repository share the fault configuration and the record of calls.
+///
+/// ```
+/// # use cqrs_es::doc::MyAggregate;
+/// use cqrs_es::persist::{MemEventRepository, PersistedEventRepository, PersistenceError};
+/// use cqrs_es::test::{Fault, FaultInjectingEventRepository, RepositoryOperation};
+///
+/// # async fn test() {
+/// let repo = FaultInjectingEventRepository::new(MemEventRepository::default())
+///     .with_fault_schedule(
+///         RepositoryOperation::GetEvents,
+///         vec![Some(Fault::ConnectionError), None],
+///     );
+///
+/// let result = repo.get_events::<MyAggregate>("agg-1").await;
+/// assert!(matches!(result, Err(PersistenceError::ConnectionError(_))));
+/// let result = repo.get_events::<MyAggregate>("agg-1").await;
+/// assert!(result.unwrap().is_empty());
+/// assert_eq!(2, repo.call_count(RepositoryOperation::GetEvents));
+/// # }
+/// ```
+pub struct FaultInjectingEventRepository<R> {
+    repository: R,
+    state: Arc<Mutex<FaultState>>,
+}
+
+impl<R: Clone> Clone for FaultInjectingEventRepository<R> {
+    fn clone(&self) -> Self {
+        Self {
+            repository: self.repository.clone(),
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<R> FaultInjectingEventRepository<R>
+where
+    R: PersistedEventRepository,
+{
+    /// Decorates the provided repository, no faults are injected until they are configured.
+    pub fn new(repository: R) -> Self {
+        let state = FaultState {
+            rules: HashMap::new(),
+            calls: HashMap::new(),
+            injected: Vec::new(),
+            random: SplitMix64(0),
+        };
+        Self {
+            repository,
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+
+    /// Injects the fault on every call of the operation.
+    #[must_use]
+    pub fn with_fault(self, operation: RepositoryOperation, fault: Fault) -> Self {
+        self.add_rule(operation, FaultRule::Always(fault))
+    }
+
+    /// Injects the fault on calls of the operation with the provided probability, between
+    /// 0.0 and 1.0.
+    ///
+    /// ```
+    /// use cqrs_es::persist::MemEventRepository;
+    /// use cqrs_es::test::{Fault, FaultInjectingEventRepository, RepositoryOperation};
+    ///
+    /// let repo = FaultInjectingEventRepository::new(MemEventRepository::default())
+    ///     .with_fault_probability(RepositoryOperation::Persist, Fault::OptimisticLock, 0.2)
+    ///     .with_seed(42);
+    /// ```
+    #[must_use]
+    pub fn with_fault_probability(
+        self,
+        operation: RepositoryOperation,
+        fault: Fault,
+        probability: f64,
+    ) -> Self {
+        self.add_rule(operation, FaultRule::Probability(fault, probability))
+    }
+
+    /// Injects faults from a schedule, each call of the operation takes the next entry and
+    /// `None` passes the call on without a fault. Calls after the end of the schedule are not
+    /// affected by it.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use cqrs_es::persist::MemEventRepository;
+    /// use cqrs_es::test::{Fault, FaultInjectingEventRepository, RepositoryOperation};
+    ///
+    /// let repo = FaultInjectingEventRepository::new(MemEventRepository::default())
+    ///     .with_fault_schedule(
+    ///         RepositoryOperation::StreamAllEvents,
+    ///         vec![
+    ///             Some(Fault::Delay(Duration::from_millis(50))),
+    ///             Some(Fault::StreamError { after: 10 }),
+    ///         ],
+    ///     );
+    /// ```
+    #[must_use]
+    pub fn with_fault_schedule(
+        self,
+        operation: RepositoryOperation,
+        schedule: Vec<Option<Fault>>,
+    ) -> Self {
+        self.add_rule(operation, FaultRule::Schedule(schedule.into()))
+    }
+
+    /// Seeds the generator used for fault probabilities, the default seed is zero.
+    #[must_use]
+    pub fn with_seed(self, seed: u64) -> Self {
+        self.state.lock().unwrap().random = SplitMix64(seed);
+        self
+    }
+
+    /// The number of calls made to the operation, including those that were given a fault.
+    pub fn call_count(&self, operation: RepositoryOperation) -> usize {
+        // uninteresting unwrap: this is not a struct for production use
+        let state = self.state.lock().unwrap();
+        state.calls.get(&operation).copied().unwrap_or_default()
+    }
+
+    /// Every fault that has been injected, in the order they were injected.
+    pub fn injected_faults(&self) -> Vec<(RepositoryOperation, Fault)> {
+        self.state.lock().unwrap().injected.clone()
+    }
+
+    /// The decorated repository, e.g., to verify the events that were committed.
+    pub fn inner(&self) -> &R {
+        &self.repository
+    }
+
+    fn add_rule(self, operation: RepositoryOperation, rule: FaultRule) -> Self {
+        let mut state = self.state.lock().unwrap();
+        state.rules.entry(operation).or_default().push(rule);
+        drop(state);
+        self
+    }
+
+    // Records the call and determines its fault, every schedule advances on each call.
+    fn next_fault(&self, operation: RepositoryOperation) -> Option<Fault> {
+        let mut state = self.state.lock().unwrap();
+        *state.calls.entry(operation).or_default() += 1;
+        let FaultState {
+            rules,
+            injected,
+            random,
+            ..
+        } = &mut *state;
+        let mut fault = None;
+        for rule in rules.get_mut(&operation).into_iter().flatten() {
+            let next = match rule {
+                FaultRule::Always(fault) => Some(fault.clone()),
+                FaultRule::Probability(fault, probability) => {
+                    (random.next_f64() < *probability).then(|| fault.clone())
+                }
+                FaultRule::Schedule(schedule) => schedule.pop_front().flatten(),
+            };
+            fault = fault.or(next);
+        }
+        if let Some(fault) = &fault {
+            injected.push((operation, fault.clone()));
+        }
+        fault
+    }
+
+    // Applies any fault for the call, returning the number of events a stream may yield
+    // before it fails.
+    async fn inject(
+        &self,
+        operation: RepositoryOperation,
+    ) -> Result<Option<usize>, PersistenceError> {
+        match self.next_fault(operation) {
+            None => Ok(None),
+            Some(Fault::ConnectionError) => Err(connection_error(operation)),
+            Some(Fault::OptimisticLock) => Err(PersistenceError::OptimisticLockError),
+            Some(Fault::Delay(delay)) => {
+                tokio::time::sleep(delay).await;
+                Ok(None)
+            }
+            Some(Fault::StreamError { after }) => match operation {
+                RepositoryOperation::StreamEvents
+                | RepositoryOperation::StreamAllEvents
+                | RepositoryOperation::StreamFilteredEvents
+                | RepositoryOperation::SubscribeAllEvents => Ok(Some(after)),
+                _ => Err(connection_error(operation)),
+            },
+        }
+    }
+}
+
+impl<R> PersistedEventRepository for FaultInjectingEventRepository<R>
+where
+    R: PersistedEventRepository,
+{
+    async fn get_events<A: Aggregate>(
+        &self,
+        aggregate_id: &str,
+    ) -> Result<Vec<SerializedEvent>, PersistenceError> {
+        self.inject(RepositoryOperation::GetEvents).await?;
+        self.repository.get_events::<A>(aggregate_id).await
+    }
+
+    async fn get_last_events<A: Aggregate>(
+        &self,
+        aggregate_id: &str,
+        last_sequence: usize,
+    ) -> Result<Vec<SerializedEvent>, PersistenceError> {
+        self.inject(RepositoryOperation::GetLastEvents).await?;
+        self.repository
+            .get_last_events::<A>(aggregate_id, last_sequence)
+            .await
+    }
+
+    async fn get_snapshot<A: Aggregate>(
+        &self,
+        aggregate_id: &str,
+    ) -> Result<Option<SerializedSnapshot>, PersistenceError> {
+        self.inject(RepositoryOperation::GetSnapshot).await?;
+        self.repository.get_snapshot::<A>(aggregate_id).await
+    }
+
+    async fn persist<A: Aggregate>(
+        &self,
+        events: &[SerializedEvent],
+        snapshot_update: Option<(String, Value, usize)>,
+    ) -> Result<(), PersistenceError> {
+        self.inject(RepositoryOperation::Persist).await?;
+        self.repository.persist::<A>(events, snapshot_update).await
+    }
+
+    async fn stream_events<A: Aggregate>(
+        &self,
+        aggregate_id: &str,
+    ) -> Result<ReplayStream, PersistenceError> {
+        let fail_after = self.inject(RepositoryOperation::StreamEvents).await?;
+        let stream = self.repository.stream_events::<A>(aggregate_id).await?;
+        Ok(fail_stream(
+            stream,
+            fail_after,
+            RepositoryOperation::StreamEvents,
+        ))
+    }
+
+    async fn stream_all_events<A: Aggregate>(&self) -> Result<ReplayStream, PersistenceError> {
+        let fail_after = self.inject(RepositoryOperation::StreamAllEvents).await?;
+        let stream = self.repository.stream_all_events::<A>().await?;
+        Ok(fail_stream(
+            stream,
+            fail_after,
+            RepositoryOperation::StreamAllEvents,
+        ))
+    }
+
+    async fn stream_filtered_events<A: Aggregate>(
+        &self,
+        filter: &ReplayFilter,
+    ) -> Result<ReplayStream, PersistenceError> {
+        let fail_after = self
+            .inject(RepositoryOperation::StreamFilteredEvents)
+            .await?;
+        let stream = self.repository.stream_filtered_events::<A>(filter).await?;
+        Ok(fail_stream(
+            stream,
+            fail_after,
+            RepositoryOperation::StreamFilteredEvents,
+        ))
+    }
+
+    async fn subscribe_all_events<A: Aggregate>(&self) -> Result<ReplayStream, PersistenceError> {
+        let fail_after = self.inject(RepositoryOperation::SubscribeAllEvents).await?;
+        let stream = self.repository.subscribe_all_events::<A>().await?;
+        Ok(fail_stream(
+            stream,
+            fail_after,
+            RepositoryOperation::SubscribeAllEvents,
+        ))
+    }
+}
+
+// Forwards events from the stream until the limit is reached, and then fails the stream.
+fn fail_stream(
+    mut stream: ReplayStream,
+    fail_after: Option<usize>,
+    operation: RepositoryOperation,
+) -> ReplayStream {
+    let Some(fail_after) = fail_after else {
+        return stream;
+    };
+    let (mut feed, faulty_stream) = ReplayStream::new(FAULTY_STREAM_CHANNEL_SIZE);
+    tokio::spawn(async move {
+        for _ in 0..fail_after {
+            let next = tokio::select! {
+                () = feed.closed() => return,
+                next = stream.next_serialized() => next,
+            };
+            match next {
+                Some(Ok(event)) => {
+                    if feed.push(Ok(event)).await.is_err() {
+                        return;
+                    }
+                }
+                Some(Err(err)) => {
+                    let _ = feed.push(Err(err)).await;
+                    return;
+                }
+                None => break,
+            }
+        }
+        let _ = feed.push(Err(connection_error(operation))).await;
+    });
+    faulty_stream
+}
+
+fn connection_error(operation: RepositoryOperation) -> PersistenceError {
+    PersistenceError::ConnectionError(format!("injected connection error in {operation:?}").into())
+}
+
+// A small, seedable generator so that probabilities inject the same faults on every run.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_f64(&mut self) -> f64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        // the upper 53 bits give a uniform value in [0, 1)
+        (z >> 11) as f64 / (1_u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use serde_json::json;
+
+    use crate::doc::MyAggregate;
+    use crate::persist::{
+        MemEventRepository, PersistedEventRepository, PersistenceError, SerializedEvent,
+    };
+    use crate::test::{Fault, FaultInjectingEventRepository, RepositoryOperation};
+
+    fn event(aggregate_id: &str, sequence: usize) -> SerializedEvent {
+        SerializedEvent::new(
+            aggregate_id.to_string(),
+            sequence,
+            "MyAggregate".to_string(),
+            "SomethingWasDone".to_string(),
+            "0.1.0".to_string(),
+            json!("SomethingWasDone"),
+            json!({}),
+        )
+    }
+
+    fn seeded_repo() -> FaultInjectingEventRepository<MemEventRepository> {
+        let repo = MemEventRepository::default();
+        repo.insert_events::<MyAggregate>(&[
+            event("agg-1", 1),
+            event("agg-1", 2),
+            event("agg-1", 3),
+        ])
+        .unwrap();
+        FaultInjectingEventRepository::new(repo)
+    }
+
+    #[tokio::test]
+    async fn faults_by_operation() {
+        let repo = seeded_repo()
+            .with_fault(RepositoryOperation::GetSnapshot, Fault::ConnectionError)
+            .with_fault(RepositoryOperation::Persist, Fault::OptimisticLock);
+
+        let result = repo.get_snapshot::<MyAggregate>("agg-1").await;
+        assert!(matches!(result, Err(PersistenceError::ConnectionError(_))));
+        let result = repo
+            .persist::<MyAggregate>(&[event("agg-1", 4)], None)
+            .await;
+        assert!(matches!(result, Err(PersistenceError::OptimisticLockError)));
+        let events = repo.get_events::<MyAggregate>("agg-1").await.unwrap();
+        assert_eq!(3, events.len());
+
+        // a rejected commit does not reach the decorated repository
+        assert_eq!(3, repo.inner().event_count());
+        assert_eq!(
+            vec![
+                (RepositoryOperation::GetSnapshot, Fault::ConnectionError),
+                (RepositoryOperation::Persist, Fault::OptimisticLock),
+            ],
+            repo.injected_faults()
+        );
+    }
+
+    #[tokio::test]
+    async fn faults_by_schedule() {
+        let repo = seeded_repo().with_fault_schedule(
+            RepositoryOperation::Persist,
+            vec![
+                Some(Fault::OptimisticLock),
+                None,
+                Some(Fault::ConnectionError),
+            ],
+        );
+        let result = repo
+            .persist::<MyAggregate>(&[event("agg-1", 4)], None)
+            .await;
+        assert!(matches!(result, Err(PersistenceError::OptimisticLockError)));
+        repo.persist::<MyAggregate>(&[event("agg-1", 4)], None)
+            .await
+            .unwrap();
+        let result = repo
+            .persist::<MyAggregate>(&[event("agg-1", 5)], None)
+            .await;
+        assert!(matches!(result, Err(PersistenceError::ConnectionError(_))));
+        repo.persist::<MyAggregate>(&[event("agg-1", 5)], None)
+            .await
+            .unwrap();
+
+        assert_eq!(4, repo.call_count(RepositoryOperation::Persist));
+        assert_eq!(5, repo.inner().event_count());
+    }
+
+    #[tokio::test]
+    async fn faults_by_probability_are_deterministic() {
+        let injected = |seed| async move {
+            let repo = seeded_repo()
+                .with_fault_probability(RepositoryOperation::GetEvents, Fault::ConnectionError, 0.5)
+                .with_seed(seed);
+            let mut failed = Vec::new();
+            for _ in 0..20 {
+                failed.push(repo.get_events::<MyAggregate>("agg-1").await.is_err());
+            }
+            failed
+        };
+        let first = injected(7).await;
+        assert_eq!(first, injected(7).await);
+        assert_ne!(first, injected(8).await);
+        assert!(first.contains(&true) && first.contains(&false));
+    }
+
+    #[tokio::test]
+    async fn stream_errors_midway() {
+        let repo = seeded_repo()
+            .with_fault(
+                RepositoryOperation::StreamEvents,
+                Fault::StreamError { after: 2 },
+            )
+            .with_fault(
+                RepositoryOperation::StreamAllEvents,
+                Fault::StreamError { after: 5 },
+            );
+
+        let mut stream = repo.stream_events::<MyAggregate>("agg-1").await.unwrap();
+        assert_eq!(1, stream.next_serialized().await.unwrap().unwrap().sequence);
+        assert_eq!(2, stream.next_serialized().await.unwrap().unwrap().sequence);
+        let result = stream.next_serialized().await.unwrap();
+        assert!(matches!(result, Err(PersistenceError::ConnectionError(_))));
+        assert!(stream.next_serialized().await.is_none());
+        assert!(!stream.is_complete());
+
+        // a stream with fewer events fails once they run out
+        let mut stream = repo.stream_all_events::<MyAggregate>().await.unwrap();
+        let mut received = 0;
+        while let Some(Ok(_)) = stream.next_serialized().await {
+            received += 1;
+        }
+        assert_eq!(3, received);
+        assert!(!stream.is_complete());
+    }
+
+    #[tokio::test]
+    async fn delays() {
+        let repo = seeded_repo().with_fault(
+            RepositoryOperation::GetEvents,
+            Fault::Delay(Duration::from_millis(50)),
+        );
+        let started = Instant::now();
+        let events = repo.get_events::<MyAggregate>("agg-1").await.unwrap();
+        assert_eq!(3, events.len());
+        assert!(started.elapsed() >= Duration::from_millis(50));
+    }
+}
@@ -0,0 +1,219 @@
+use std::fmt::Debug;
+
+use proptest::collection::vec;
+use proptest::strategy::Strategy;
+use proptest::test_runner::{Config, TestCaseError, TestError, TestRunner};
+use serde_json::Value;
+use tokio::runtime::{Builder, Handle, RuntimeFlavor};
+use tokio::task::block_in_place;
+
+use crate::aggregate::Aggregate;
+use crate::event_sink::EventSink;
+
+const DEFAULT_MAX_COMMANDS: usize = 20;
+
+type Invariant<A> = (String, Box<dyn Fn(&A) -> bool>);
+
+/// A framework for property-based testing of an aggregate, available with the `proptest`
+/// feature.
+///
+/// Random sequences of commands are handled by an aggregate and, after every command, the
+/// framework verifies that:
+/// - every configured invariant holds,
+/// - applying the events produced so far to a new aggregate reproduces the aggregate built
+///   while handling the commands,
+/// - the produced events and the aggregate survive a serde round trip.
+///
+/// Commands that are rejected with an error produce no events, as with a `CqrsFramework`.
+/// A failing sequence of commands is shrunk to a minimal reproduction before being reported.
+///
+/// ***Runtimes:*** `check` blocks while the commands are handled. Called outside of a Tokio
+/// runtime, e.g., from a `#[test]`, it runs the commands on its own runtime. Within a
+/// multi-threaded runtime, e.g., `#[tokio::test(flavor = "multi_thread")]`, it runs them on the
+/// current runtime. It panics if called within a current thread runtime, which is the default
+/// for `#[tokio::test]`, as that runtime cannot be blocked.
+///
+/// ```
+/// # use cqrs_es::doc::{Customer, CustomerCommand, CustomerService};
+/// use cqrs_es::test::PropertyTestFramework;
+/// use proptest::prelude::*;
+///
+/// let commands = prop_oneof![
+///     "[a-z]{1,8}".prop_map(|name| CustomerCommand::AddCustomerName { name }),
+///     "[a-z]{1,8}".prop_map(|new_email| CustomerCommand::UpdateEmail { new_email }),
+/// ];
+/// PropertyTestFramework::<Customer>::with(CustomerService)
+///     .with_invariant("populated customers have a name", |customer| {
+///         !customer.data_populated || !customer.name.is_empty()
+///     })
+///     .check(commands);
+/// ```
+pub struct PropertyTestFramework<A>
+where
+    A: Aggregate,
+{
+    service: A::Services,
+    invariants: Vec<Invariant<A>>,
+    max_commands: usize,
+    config: Config,
+}
+
+impl<A> PropertyTestFramework<A>
+where
+    A: Aggregate,
+{
+    /// Create a property test framework using the provided service.
+    pub fn with(service: A::Services) -> Self {
+        Self {
+            service,
+            invariants: Vec::new(),
+            max_commands: DEFAULT_MAX_COMMANDS,
+            config: Config::default(),
+        }
+    }
+
+    /// Adds an invariant that must hold for the aggregate after every command, the name is
+    /// used to report a violation.
+    #[must_use]
+    pub fn with_invariant(mut self, name: &str, invariant: impl Fn(&A) -> bool + 'static) -> Self {
+        self.invariants
+            .push((name.to_string(), Box::new(invariant)));
+        self
+    }
+
+    /// Configures the maximum number of commands in a generated sequence, the default is 20.
+    #[must_use]
+    pub fn with_max_commands(self, max_commands: usize) -> Self {
+        Self {
+            max_commands,
+            ..self
+        }
+    }
+
+    /// Configures the proptest runner, e.g., to change the number of sequences generated.
+    #[must_use]
+    pub fn with_config(self, config: Config) -> Self {
+        Self { config, ..self }
+    }
+
+    /// Generates sequences of commands from the provided strategy and verifies each of them,
+    /// panicking with the minimal failing sequence if any verification fails.
+    ///
+    /// Panics if called within a current thread Tokio runtime.
+    pub fn check<S>(self, commands: S)
+    where
+        S: Strategy<Value = A::Command>,
+        A::Command: Debug,
+    {
+        // a runtime is only built when not called within one
+        let runtime = match Handle::try_current() {
+            Ok(handle) => {
+                assert!(
+                    handle.runtime_flavor() == RuntimeFlavor::MultiThread,
+                    "a property test cannot block a current thread runtime, use `#[test]` or `#[tokio::test(flavor = \"multi_thread\")]`"
+                );
+                None
+            }
+            Err(_) => Some(
+                Builder::new_current_thread()
+                    .build()
+                    .expect("unable to build a runtime for the property test"),
+            ),
+        };
+        let mut runner = TestRunner::new(self.config.clone());
+        let result = runner.run(
+            &vec(commands, 1..=self.max_commands),
+            |commands| match &runtime {
+                Some(runtime) => runtime.block_on(self.verify(commands)),
+                None => block_in_place(|| Handle::current().block_on(self.verify(commands))),
+            },
+        );
+        match result {
+            Ok(()) => {}
+            Err(TestError::Fail(reason, commands)) => {
+                panic!("{reason}\n  minimal failing commands: {commands:#?}");
+            }
+            Err(TestError::Abort(reason)) => {
+                panic!("property test aborted: {reason}");
+            }
+        }
+    }
+
+    async fn verify(&self, commands: Vec<A::Command>) -> Result<(), TestCaseError> {
+        let mut aggregate = A::default();
+        let mut events: Vec<A::Event> = Vec::new();
+        for (position, command) in commands.into_iter().enumerate() {
+            let step = position + 1;
+            let sink = EventSink::default();
+            match aggregate.handle(command, &self.service, &sink).await {
+                Ok(()) => {
+                    let produced = sink.collect().await;
+                    for event in &produced {
+                        verify_event_round_trip(event, step)?;
+                    }
+                    events.extend(produced);
+                }
+                // events written before the error are not committed, the aggregate is rebuilt
+                // from the committed events
+                Err(_) => aggregate = replay(&events),
+            }
+            let state = to_json(&aggregate, "aggregate")?;
+            for (name, invariant) in &self.invariants {
+                if !invariant(&aggregate) {
+                    return Err(TestCaseError::fail(format!(
+                        "invariant '{name}' violated after command {step}, aggregate: {state}"
+                    )));
+                }
+            }
+            let replayed = to_json(&replay::<A>(&events), "aggregate")?;
+            if replayed != state {
+                return Err(TestCaseError::fail(format!(
+                    "applying the events does not reproduce the aggregate after command {step}\n  handled:  {state}\n  replayed: {replayed}"
+                )));
+            }
+            let reloaded: A = serde_json::from_value(state.clone()).map_err(|err| {
+                TestCaseError::fail(format!(
+                    "aggregate could not be deserialized after command {step}: '{err}'"
+                ))
+            })?;
+            if to_json(&reloaded, "aggregate")? != state {
+                return Err(TestCaseError::fail(format!(
+                    "aggregate was changed by a serde round trip after command {step}: {state}"
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn replay<A: Aggregate>(events: &[A::Event]) -> A {
+    let mut aggregate = A::default();
+    for event in events {
+        aggregate.apply(event.clone());
+    }
+    aggregate
+}
+
+fn verify_event_round_trip<E>(event: &E, step: usize) -> Result<(), TestCaseError>
+where
+    E: serde::Serialize + serde::de::DeserializeOwned + PartialEq + Debug,
+{
+    let payload = to_json(event, "event")?;
+    let reloaded: E = serde_json::from_value(payload.clone()).map_err(|err| {
+        TestCaseError::fail(format!(
+            "event could not be deserialized after command {step}: '{err}'\n  payload: {payload}"
+        ))
+    })?;
+    if &reloaded != event {
+        return Err(TestCaseError::fail(format!(
+            "event was changed by a serde round trip after command {step}\n  produced: {event:?}\n  reloaded: {reloaded:?}"
+        )));
+    }
+    Ok(())
+}
+
+fn to_json<T: serde::Serialize>(value: &T, description: &str) -> Result<Value, TestCaseError> {
+    serde_json::to_value(value).map_err(|err| {
+        TestCaseError::fail(format!("{description} could not be serialized: '{err}'"))
+    })
+}
@@ -0,0 +1,206 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+
+use crate::aggregate::Aggregate;
+use crate::cqrs::CqrsFramework;
+use crate::event::EventEnvelope;
+use crate::mem_store::MemStore;
+use crate::persist::{GenericQuery, MemViewRepository};
+use crate::query::{Query, View};
+use crate::AggregateError;
+
+type DispatchLog<A> = Arc<Mutex<Vec<EventEnvelope<A>>>>;
+
+/// A framework for testing a sequence of commands end to end, through a `CqrsFramework` backed
+/// by a `MemStore`.
+///
+/// Unlike the `TestFramework` this covers the event sequencing, metadata and dispatch to
+/// queries that are handled by the `CqrsFramework`. Each query is given a name and the events
+/// dispatched to it are recorded for verification.
+///
+/// ```
+/// # use cqrs_es::doc::{Customer, CustomerCommand, CustomerEvent, CustomerService, CustomerView};
+/// use cqrs_es::persist::MemViewRepository;
+/// use cqrs_es::test::ScenarioTestFramework;
+///
+/// # async fn test() {
+/// let customers = MemViewRepository::<CustomerView, Customer>::default();
+/// let scenario = ScenarioTestFramework::<Customer>::with(CustomerService)
+///     .with_view("customers", customers.clone())
+///     .start();
+///
+/// scenario
+///     .execute("customer-1", CustomerCommand::AddCustomerName { name: "John Doe".to_string() })
+///     .await
+///     .unwrap();
+///
+/// assert_eq!(1, scenario.committed_events_for("customer-1").len());
+/// assert_eq!(1, scenario.dispatched_events("customers").len());
+/// let (view, _) = customers.view("customer-1").unwrap();
+/// assert_eq!("John Doe", view.name);
+/// # }
+/// ```
+pub struct ScenarioTestFramework<A>
+where
+    A: Aggregate,
+{
+    service: A::Services,
+    queries: Vec<Box<dyn Query<A>>>,
+    dispatched: HashMap<String, DispatchLog<A>>,
+}
+
+impl<A> ScenarioTestFramework<A>
+where
+    A: Aggregate + 'static,
+{
+    /// Create a scenario test framework using the provided service.
+    pub fn with(service: A::Services) -> Self {
+        Self {
+            service,
+            queries: Vec::new(),
+            dispatched: HashMap::new(),
+        }
+    }
+
+    /// Adds a query to the framework, the events dispatched to it are recorded under the
+    /// provided name.
+    ///
+    /// ```
+    /// # use cqrs_es::doc::{MyAggregate, MyQuery, MyService};
+    /// use cqrs_es::test::ScenarioTestFramework;
+    ///
+    /// let framework = ScenarioTestFramework::<MyAggregate>::with(MyService)
+    ///     .with_query("my_query", Box::new(MyQuery));
+    /// ```
+    #[must_use]
+    pub fn with_query(self, name: &str, query: Box<dyn Query<A>>) -> Self {
+        self.record(name, query)
+    }
+
+    /// Adds a `GenericQuery` that maintains views in the provided repository, the events
+    /// dispatched to it are recorded under the provided name. A clone of the repository can be
+    /// held to verify the resulting views.
+    ///
+    /// ```
+    /// # use cqrs_es::doc::{Customer, CustomerService, CustomerView};
+    /// use cqrs_es::persist::MemViewRepository;
+    /// use cqrs_es::test::ScenarioTestFramework;
+    ///
+    /// let customers = MemViewRepository::<CustomerView, Customer>::default();
+    /// let framework = ScenarioTestFramework::<Customer>::with(CustomerService)
+    ///     .with_view("customers", customers.clone());
+    /// ```
+    #[must_use]
+    pub fn with_view<V>(self, name: &str, view_repository: MemViewRepository<V, A>) -> Self
+    where
+        V: View<A> + 'static,
+    {
+        let query = GenericQuery::new(Arc::new(view_repository));
+        self.record(name, Box::new(query))
+    }
+
+    /// Builds the `CqrsFramework` and begins the scenario.
+    #[must_use]
+    pub fn start(self) -> Scenario<A> {
+        let committed = DispatchLog::default();
+        let mut queries: Vec<Box<dyn Query<A>>> = vec![Box::new(RecordingQuery {
+            log: committed.clone(),
+            query: None,
+        })];
+        queries.extend(self.queries);
+        Scenario {
+            cqrs: CqrsFramework::new(MemStore::default(), queries, self.service),
+            committed,
+            dispatched: self.dispatched,
+        }
+    }
+
+    fn record(mut self, name: &str, query: Box<dyn Query<A>>) -> Self {
+        let log = DispatchLog::default();
+        self.dispatched.insert(name.to_string(), log.clone());
+        self.queries.push(Box::new(RecordingQuery {
+            log,
+            query: Some(query),
+        }));
+        self
+    }
+}
+
+/// A running scenario, accepts commands and records the resulting events.
+pub struct Scenario<A>
+where
+    A: Aggregate,
+{
+    cqrs: CqrsFramework<A, MemStore<A>>,
+    committed: DispatchLog<A>,
+    dispatched: HashMap<String, DispatchLog<A>>,
+}
+
+impl<A> Scenario<A>
+where
+    A: Aggregate,
+{
+    /// Executes a command against the aggregate instance, see `CqrsFramework::execute`.
+    pub async fn execute(
+        &self,
+        aggregate_id: &str,
+        command: A::Command,
+    ) -> Result<(), AggregateError<A::Error>> {
+        self.cqrs.execute(aggregate_id, command).await
+    }
+
+    /// Executes a command against the aggregate instance with the provided metadata, see
+    /// `CqrsFramework::execute_with_metadata`.
+    pub async fn execute_with_metadata(
+        &self,
+        aggregate_id: &str,
+        command: A::Command,
+        metadata: HashMap<String, String>,
+    ) -> Result<(), AggregateError<A::Error>> {
+        self.cqrs
+            .execute_with_metadata(aggregate_id, command, metadata)
+            .await
+    }
+
+    /// Every event committed during the scenario, in the order that they were committed.
+    pub fn committed_events(&self) -> Vec<EventEnvelope<A>> {
+        // uninteresting unwrap: this is not a struct for production use
+        self.committed.lock().unwrap().clone()
+    }
+
+    /// The events committed for a single aggregate instance, in sequence order.
+    pub fn committed_events_for(&self, aggregate_id: &str) -> Vec<EventEnvelope<A>> {
+        self.committed_events()
+            .into_iter()
+            .filter(|event| event.aggregate_id == aggregate_id)
+            .collect()
+    }
+
+    /// The events dispatched to the named query, in the order that they were dispatched.
+    pub fn dispatched_events(&self, query_name: &str) -> Vec<EventEnvelope<A>> {
+        let Some(log) = self.dispatched.get(query_name) else {
+            let mut names: Vec<&String> = self.dispatched.keys().collect();
+            names.sort();
+            panic!("no query named '{query_name}', configured queries: {names:?}");
+        };
+        log.lock().unwrap().clone()
+    }
+}
+
+// Records the events dispatched to a query before passing them on to it.
+struct RecordingQuery<A: Aggregate> {
+    log: DispatchLog<A>,
+    query: Option<Box<dyn Query<A>>>,
+}
+
+#[async_trait]
+impl<A: Aggregate> Query<A> for RecordingQuery<A> {
+    async fn dispatch(&self, aggregate_id: &str, events: &[EventEnvelope<A>]) {
+        self.log.lock().unwrap().extend_from_slice(events);
+        if let Some(query) = &self.query {
+            query.dispatch(aggregate_id, events).await;
+        }
+    }
+}
@@ -0,0 +1,203 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+
+use crate::aggregate::Aggregate;
+use crate::event::{DomainEvent, EventEnvelope};
+use crate::persist::{EventUpcaster, SerializedEvent};
+
+/// The environment variable that, when set, records the current samples in the golden files.
+pub const BLESS_EVENT_SCHEMAS: &str = "CQRS_BLESS_EVENT_SCHEMAS";
+
+// Recorded event payloads by event type and then event version.
+type GoldenSamples = BTreeMap<String, BTreeMap<String, Vec<Value>>>;
+
+/// A framework for verifying that stored events remain readable as the events of an
+/// aggregate change.
+///
+/// The serialized form of sample events is recorded in a golden file, named for the aggregate
+/// type, within the configured directory. Verification fails if any recorded sample can no
+/// longer be deserialized, after applying the configured upcasters, or if the current samples
+/// differ from those recorded for their event type and version.
+///
+/// Intentional changes are recorded by running the tests with the `CQRS_BLESS_EVENT_SCHEMAS`
+/// environment variable set:
+/// ```shell
+/// CQRS_BLESS_EVENT_SCHEMAS=1 cargo test
+/// ```
+/// This replaces the samples recorded for each current event type and version, samples of
+/// earlier event versions are kept and must remain readable. A sample that should no longer be
+/// supported is removed by editing the golden file.
+///
+/// ```
+/// # use cqrs_es::doc::{Customer, CustomerEvent};
+/// use cqrs_es::test::EventSchemaTestFramework;
+///
+/// # fn test() {
+/// EventSchemaTestFramework::<Customer>::with_directory("tests/event_schemas")
+///     .verify(vec![
+///         CustomerEvent::NameAdded { name: "John Doe".to_string() },
+///         CustomerEvent::EmailUpdated { new_email: "john@example.com".to_string() },
+///         CustomerEvent::CustomerDataPopulated,
+///     ]);
+/// # }
+/// ```
+pub struct EventSchemaTestFramework<A>
+where
+    A: Aggregate,
+{
+    directory: PathBuf,
+    upcasters: Vec<Box<dyn EventUpcaster>>,
+    bless: bool,
+    _phantom: PhantomData<A>,
+}
+
+impl<A> EventSchemaTestFramework<A>
+where
+    A: Aggregate,
+{
+    /// Create an event schema test framework that keeps its golden files in the provided
+    /// directory, relative paths are resolved from the package root when run by `cargo test`.
+    pub fn with_directory(directory: impl AsRef<Path>) -> Self {
+        Self {
+            directory: directory.as_ref().to_path_buf(),
+            upcasters: Vec::new(),
+            bless: std::env::var_os(BLESS_EVENT_SCHEMAS).is_some(),
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Configures the upcasters that are applied to recorded samples before they are
+    /// deserialized, these should be the upcasters configured on the event store.
+    #[must_use]
+    pub fn with_upcasters(self, upcasters: Vec<Box<dyn EventUpcaster>>) -> Self {
+        Self { upcasters, ..self }
+    }
+
+    /// Configures whether the current samples are recorded, overriding the
+    /// `CQRS_BLESS_EVENT_SCHEMAS` environment variable.
+    #[must_use]
+    pub fn with_bless(self, bless: bool) -> Self {
+        Self { bless, ..self }
+    }
+
+    /// The path of the golden file for this aggregate.
+    pub fn golden_file(&self) -> PathBuf {
+        self.directory.join(format!("{}.json", A::TYPE))
+    }
+
+    /// Verifies the recorded samples and the provided current samples against the golden file,
+    /// panicking with every problem found.
+    pub fn verify(self, samples: Vec<A::Event>) {
+        let path = self.golden_file();
+        let mut recorded = self.read_golden_file(&path);
+        let current = Self::current_samples(&samples);
+        let mut problems = String::new();
+        if self.bless {
+            for (event_type, versions) in current {
+                recorded.entry(event_type).or_default().extend(versions);
+            }
+            self.write_golden_file(&path, &recorded);
+        } else {
+            Self::compare(&recorded, &current, &mut problems);
+        }
+        self.deserialize_recorded(&recorded, &mut problems);
+        if !problems.is_empty() {
+            panic!(
+                "event schemas do not match the golden file '{}':{problems}\n\
+                 run with `{BLESS_EVENT_SCHEMAS}=1 cargo test` to record intentional changes",
+                path.display()
+            );
+        }
+    }
+
+    fn current_samples(samples: &[A::Event]) -> GoldenSamples {
+        let mut current = GoldenSamples::new();
+        for sample in samples {
+            let payload = serde_json::to_value(sample)
+                .unwrap_or_else(|err| panic!("sample event could not be serialized: '{err}'"));
+            current
+                .entry(sample.event_type())
+                .or_default()
+                .entry(sample.event_version())
+                .or_default()
+                .push(payload);
+        }
+        current
+    }
+
+    // Every current sample must have been recorded for its event type and version.
+    fn compare(recorded: &GoldenSamples, current: &GoldenSamples, problems: &mut String) {
+        for (event_type, versions) in current {
+            for (event_version, payloads) in versions {
+                let recorded_payloads = recorded
+                    .get(event_type)
+                    .and_then(|versions| versions.get(event_version));
+                let Some(recorded_payloads) = recorded_payloads else {
+                    let _ = write!(
+                        problems,
+                        "\n  {event_type} {event_version}: no samples have been recorded"
+                    );
+                    continue;
+                };
+                for payload in payloads {
+                    if !recorded_payloads.contains(payload) {
+                        let _ = write!(
+                            problems,
+                            "\n  {event_type} {event_version}: sample has not been recorded: {payload}"
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    fn deserialize_recorded(&self, recorded: &GoldenSamples, problems: &mut String) {
+        for (event_type, versions) in recorded {
+            for (event_version, payloads) in versions {
+                for payload in payloads {
+                    let event = SerializedEvent::new(
+                        String::new(),
+                        1,
+                        A::TYPE.to_string(),
+                        event_type.clone(),
+                        event_version.clone(),
+                        payload.clone(),
+                        Value::Object(serde_json::Map::new()),
+                    )
+                    .upcast(&self.upcasters);
+                    if let Err(err) = EventEnvelope::<A>::try_from(event) {
+                        let _ = write!(
+                            problems,
+                            "\n  {event_type} {event_version}: recorded sample can no longer be deserialized: '{err}'\n    sample: {payload}"
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    fn read_golden_file(&self, path: &Path) -> GoldenSamples {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|err| {
+                panic!("golden file '{}' is not valid: '{err}'", path.display())
+            }),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => GoldenSamples::new(),
+            Err(err) => panic!("unable to read golden file '{}': '{err}'", path.display()),
+        }
+    }
+
+    fn write_golden_file(&self, path: &Path, recorded: &GoldenSamples) {
+        let mut contents = serde_json::to_string_pretty(recorded)
+            .unwrap_or_else(|err| panic!("unable to serialize golden file: '{err}'"));
+        contents.push('\n');
+        std::fs::create_dir_all(&self.directory)
+            .and_then(|()| std::fs::write(path, contents))
+            .unwrap_or_else(|err| {
+                panic!("unable to write golden file '{}': '{err}'", path.display())
+            });
+    }
+}
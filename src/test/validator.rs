@@ -1,3 +1,5 @@
+use std::fmt::{Debug, Write};
+
 use crate::aggregate::Aggregate;
 
 /// Validation object for the `TestFramework` package.
@@ -6,6 +8,7 @@ where
     A: Aggregate,
 {
     result: Result<Vec<A::Event>, A::Error>,
+    aggregate: A,
 }
 
 impl<A: Aggregate> AggregateResultValidator<A> {
@@ -23,11 +26,61 @@ impl<A: Aggregate> AggregateResultValidator<A> {
     /// validator.then_expect_events(vec![MyEvents::SomethingWasDone]);
     /// # }
     /// ```
+    ///
+    /// On failure only the events that differ are reported, along with their position.
     pub fn then_expect_events(self, expected_events: Vec<A::Event>) {
         let events = self.result.unwrap_or_else(|err| {
             panic!("expected success, received aggregate error: '{err}'");
         });
-        assert_eq!(events, expected_events);
+        assert_events_eq(&events, &expected_events);
+    }
+
+    /// Verifies that the expected events have been produced by the command and that the
+    /// aggregate, with these events applied, satisfies the provided predicate.
+    ///
+    /// ```
+    /// # use cqrs_es::doc::{Customer, CustomerCommand, CustomerEvent, CustomerService};
+    /// use cqrs_es::test::TestFramework;
+    ///
+    /// let validator = TestFramework::<Customer>::with(CustomerService)
+    ///     .given_no_previous_events()
+    ///     .when(CustomerCommand::AddCustomerName { name: "John Doe".to_string() });
+    ///
+    /// validator.then_expect_events_and_state(
+    ///     vec![CustomerEvent::NameAdded { name: "John Doe".to_string() }],
+    ///     |customer| customer.name == "John Doe",
+    /// );
+    /// ```
+    pub fn then_expect_events_and_state(
+        self,
+        expected_events: Vec<A::Event>,
+        predicate: impl FnOnce(&A) -> bool,
+    ) {
+        let events = self.result.unwrap_or_else(|err| {
+            panic!("expected success, received aggregate error: '{err}'");
+        });
+        assert_events_eq(&events, &expected_events);
+        assert_state(&self.aggregate, predicate);
+    }
+
+    /// Verifies that the command succeeded and that the aggregate, with the produced events
+    /// applied, satisfies the provided predicate.
+    ///
+    /// ```
+    /// # use cqrs_es::doc::{Customer, CustomerCommand, CustomerEvent, CustomerService};
+    /// use cqrs_es::test::TestFramework;
+    ///
+    /// let validator = TestFramework::<Customer>::with(CustomerService)
+    ///     .given(vec![CustomerEvent::NameAdded { name: "John Doe".to_string() }])
+    ///     .when(CustomerCommand::UpdateEmail { new_email: "john@example.com".to_string() });
+    ///
+    /// validator.then_expect_state(|customer| customer.data_populated);
+    /// ```
+    pub fn then_expect_state(self, predicate: impl FnOnce(&A) -> bool) {
+        if let Err(err) = self.result {
+            panic!("expected success, received aggregate error: '{err}'");
+        }
+        assert_state(&self.aggregate, predicate);
     }
 
     /// Verifies that the individual expected events have been produced by the command.
@@ -92,6 +145,28 @@ impl<A: Aggregate> AggregateResultValidator<A> {
         }
     }
 
+    /// Verifies that the result is an error that satisfies the provided predicate, useful for
+    /// matching a variant of an error enum without comparing every field.
+    ///
+    /// ```
+    /// # use cqrs_es::doc::{MyAggregate, MyCommands, MyEvents, MyService};
+    /// use cqrs_es::test::TestFramework;
+    ///
+    /// let validator = TestFramework::<MyAggregate>::with(MyService)
+    ///     .given_no_previous_events()
+    ///     .when(MyCommands::BadCommand);
+    ///
+    /// validator.then_expect_error_matching(|err| err.0.starts_with("the expected"));
+    /// ```
+    pub fn then_expect_error_matching(self, predicate: impl FnOnce(&A::Error) -> bool) {
+        match self.result {
+            Ok(events) => {
+                panic!("expected error, received events: '{events:?}'");
+            }
+            Err(err) => assert!(predicate(&err), "error did not match: '{err:?}'"),
+        }
+    }
+
     /// Returns the internal error payload for validation by the user.
     ///
     /// ```
@@ -109,8 +184,47 @@ impl<A: Aggregate> AggregateResultValidator<A> {
         self.result
     }
 
-    pub(crate) fn new(result: Result<Vec<A::Event>, A::Error>) -> Self {
-        Self { result }
+    pub(crate) fn new(result: Result<Vec<A::Event>, A::Error>, aggregate: A) -> Self {
+        Self { result, aggregate }
+    }
+}
+
+// Compares events position by position so that a failure with many events only reports
+// those that differ.
+fn assert_events_eq<E: Debug + PartialEq>(events: &[E], expected_events: &[E]) {
+    if events == expected_events {
+        return;
+    }
+    let mut report = format!(
+        "expected {} events, found {}",
+        expected_events.len(),
+        events.len()
+    );
+    for position in 0..events.len().max(expected_events.len()) {
+        match (expected_events.get(position), events.get(position)) {
+            (Some(expected), Some(found)) if expected == found => {}
+            (expected, found) => {
+                let _ = write!(
+                    report,
+                    "\n  event {position}:\n    expected: {}\n    found:    {}",
+                    describe(expected),
+                    describe(found)
+                );
+            }
+        }
+    }
+    panic!("{report}");
+}
+
+fn describe<E: Debug>(event: Option<&E>) -> String {
+    event.map_or_else(|| "<none>".to_string(), |event| format!("{event:?}"))
+}
+
+fn assert_state<A: Aggregate>(aggregate: &A, predicate: impl FnOnce(&A) -> bool) {
+    if !predicate(aggregate) {
+        let state = serde_json::to_string_pretty(aggregate)
+            .unwrap_or_else(|err| format!("<unable to serialize aggregate: {err}>"));
+        panic!("aggregate state did not match:\n{state}");
     }
 }
 impl<A> AggregateResultValidator<A>
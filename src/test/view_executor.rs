@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+use crate::aggregate::Aggregate;
+use crate::event::EventEnvelope;
+use crate::query::View;
+use crate::test::ViewResultValidator;
+
+/// Holds the state of a view built from the previous events and accepts new events.
+pub struct ViewTestExecutor<V, A>
+where
+    V: View<A>,
+    A: Aggregate,
+{
+    aggregate_id: String,
+    metadata: HashMap<String, String>,
+    view: V,
+    sequence: usize,
+    _phantom: PhantomData<A>,
+}
+
+impl<V, A> ViewTestExecutor<V, A>
+where
+    V: View<A>,
+    A: Aggregate,
+{
+    /// Applies the events to the view and provides a validator object to test against.
+    ///
+    /// Before the events are applied the view is serialized and deserialized, as it would be
+    /// when stored and then loaded by a `ViewRepository`.
+    ///
+    /// ```
+    /// # use cqrs_es::doc::{Customer, CustomerEvent, CustomerView};
+    /// use cqrs_es::test::ViewTestFramework;
+    ///
+    /// let executor = ViewTestFramework::<CustomerView, Customer>::with_aggregate_id("customer-1")
+    ///     .given(vec![CustomerEvent::NameAdded { name: "John Doe".to_string() }]);
+    ///
+    /// let validator = executor.when(vec![CustomerEvent::EmailUpdated {
+    ///     new_email: "john@example.com".to_string(),
+    /// }]);
+    /// ```
+    pub fn when(self, events: Vec<A::Event>) -> ViewResultValidator<V, A> {
+        let executor = Self {
+            view: round_trip(&self.view),
+            ..self
+        }
+        .and(events);
+        ViewResultValidator::new(round_trip(&executor.view))
+    }
+
+    /// Adds additional events to a view test.
+    ///
+    /// ```
+    /// # use cqrs_es::doc::{Customer, CustomerEvent, CustomerView};
+    /// use cqrs_es::test::ViewTestFramework;
+    ///
+    /// let executor = ViewTestFramework::<CustomerView, Customer>::with_aggregate_id("customer-1")
+    ///     .given(vec![CustomerEvent::NameAdded { name: "John Doe".to_string() }])
+    ///     .and(vec![CustomerEvent::CustomerDataPopulated]);
+    /// ```
+    #[must_use]
+    pub fn and(self, events: Vec<A::Event>) -> Self {
+        let mut view = self.view;
+        let mut sequence = self.sequence;
+        for payload in events {
+            sequence += 1;
+            view.update(&EventEnvelope {
+                aggregate_id: self.aggregate_id.clone(),
+                sequence,
+                payload,
+                metadata: self.metadata.clone(),
+            });
+        }
+        Self {
+            view,
+            sequence,
+            ..self
+        }
+    }
+
+    pub(crate) fn new(aggregate_id: String, metadata: HashMap<String, String>) -> Self {
+        Self {
+            aggregate_id,
+            metadata,
+            view: V::default(),
+            sequence: 0,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+// Serializes and deserializes the view in the same way that the persisted view repositories do,
+// a view that does not survive this unchanged will not survive being stored.
+fn round_trip<V: View<A>, A: Aggregate>(view: &V) -> V {
+    let payload = serde_json::to_value(view)
+        .unwrap_or_else(|err| panic!("view could not be serialized: '{err}'"));
+    let reloaded: V = serde_json::from_value(payload.clone()).unwrap_or_else(|err| {
+        panic!("serialized view could not be deserialized: '{err}'\n  payload: '{payload}'")
+    });
+    let reserialized = serde_json::to_value(&reloaded)
+        .unwrap_or_else(|err| panic!("view could not be serialized: '{err}'"));
+    assert_eq!(
+        payload, reserialized,
+        "view was changed by serializing and deserializing it"
+    );
+    reloaded
+}
@@ -0,0 +1,78 @@
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+use crate::aggregate::Aggregate;
+use crate::query::View;
+use crate::test::ViewTestExecutor;
+
+/// A framework for testing the logic of a view, providing events to the view in the same
+/// `EventEnvelope`s that a query would receive.
+pub struct ViewTestFramework<V, A>
+where
+    V: View<A>,
+    A: Aggregate,
+{
+    aggregate_id: String,
+    metadata: HashMap<String, String>,
+    _phantom: PhantomData<(V, A)>,
+}
+
+impl<V, A> ViewTestFramework<V, A>
+where
+    V: View<A>,
+    A: Aggregate,
+{
+    /// Create a view test framework for the events of the provided aggregate instance.
+    pub fn with_aggregate_id(aggregate_id: &str) -> Self {
+        Self {
+            aggregate_id: aggregate_id.to_string(),
+            metadata: HashMap::new(),
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Adds metadata to the envelope of every event provided to the view.
+    ///
+    /// ```
+    /// # use std::collections::HashMap;
+    /// # use cqrs_es::doc::{Customer, CustomerView};
+    /// use cqrs_es::test::ViewTestFramework;
+    ///
+    /// let metadata = HashMap::from([("user".to_string(), "test_user".to_string())]);
+    /// let framework = ViewTestFramework::<CustomerView, Customer>::with_aggregate_id("customer-1")
+    ///     .with_metadata(metadata);
+    /// ```
+    #[must_use]
+    pub fn with_metadata(self, metadata: HashMap<String, String>) -> Self {
+        Self { metadata, ..self }
+    }
+
+    /// Initiates a view test with no previous events.
+    ///
+    /// ```
+    /// # use cqrs_es::doc::{Customer, CustomerView};
+    /// use cqrs_es::test::ViewTestFramework;
+    ///
+    /// let executor = ViewTestFramework::<CustomerView, Customer>::with_aggregate_id("customer-1")
+    ///     .given_no_previous_events();
+    /// ```
+    #[must_use]
+    pub fn given_no_previous_events(self) -> ViewTestExecutor<V, A> {
+        ViewTestExecutor::new(self.aggregate_id, self.metadata)
+    }
+
+    /// Initiates a view test with a collection of previous events, these are applied to a
+    /// default view starting with sequence 1.
+    ///
+    /// ```
+    /// # use cqrs_es::doc::{Customer, CustomerEvent, CustomerView};
+    /// use cqrs_es::test::ViewTestFramework;
+    ///
+    /// let executor = ViewTestFramework::<CustomerView, Customer>::with_aggregate_id("customer-1")
+    ///     .given(vec![CustomerEvent::NameAdded { name: "John Doe".to_string() }]);
+    /// ```
+    #[must_use]
+    pub fn given(self, events: Vec<A::Event>) -> ViewTestExecutor<V, A> {
+        self.given_no_previous_events().and(events)
+    }
+}
@@ -0,0 +1,133 @@
+use std::marker::PhantomData;
+
+use crate::aggregate::Aggregate;
+use crate::query::View;
+
+/// Validation object for the `ViewTestFramework` package.
+pub struct ViewResultValidator<V, A>
+where
+    V: View<A>,
+    A: Aggregate,
+{
+    view: V,
+    _phantom: PhantomData<A>,
+}
+
+impl<V, A> ViewResultValidator<V, A>
+where
+    V: View<A>,
+    A: Aggregate,
+{
+    /// Verifies that the view satisfies the provided predicate.
+    ///
+    /// ```
+    /// # use cqrs_es::doc::{Customer, CustomerEvent, CustomerView};
+    /// use cqrs_es::test::ViewTestFramework;
+    ///
+    /// let validator = ViewTestFramework::<CustomerView, Customer>::with_aggregate_id("customer-1")
+    ///     .given_no_previous_events()
+    ///     .when(vec![CustomerEvent::NameAdded { name: "John Doe".to_string() }]);
+    ///
+    /// validator.then_expect_view_matching(|view| view.name == "John Doe");
+    /// ```
+    pub fn then_expect_view_matching(self, predicate: impl FnOnce(&V) -> bool) {
+        let view = self.view;
+        assert!(predicate(&view), "view did not match: '{view:?}'");
+    }
+
+    /// Verifies that the events have ended the life of the view, see `View::is_deleted`.
+    ///
+    /// ```
+    /// # use cqrs_es::doc::{Customer, CustomerEvent};
+    /// # use serde::{Deserialize, Serialize};
+    /// use cqrs_es::test::ViewTestFramework;
+    /// use cqrs_es::{EventEnvelope, View};
+    ///
+    /// // Customers whose data has not yet been populated.
+    /// #[derive(Debug, Default, Serialize, Deserialize)]
+    /// struct PendingCustomerView {
+    ///     populated: bool,
+    /// }
+    ///
+    /// impl View<Customer> for PendingCustomerView {
+    ///     fn update(&mut self, event: &EventEnvelope<Customer>) {
+    ///         if let CustomerEvent::CustomerDataPopulated = event.payload {
+    ///             self.populated = true;
+    ///         }
+    ///     }
+    ///
+    ///     fn is_deleted(&self) -> bool {
+    ///         self.populated
+    ///     }
+    /// }
+    ///
+    /// let validator = ViewTestFramework::<PendingCustomerView, Customer>::with_aggregate_id("customer-1")
+    ///     .given(vec![CustomerEvent::NameAdded { name: "John Doe".to_string() }])
+    ///     .when(vec![CustomerEvent::CustomerDataPopulated]);
+    ///
+    /// validator.then_expect_deleted();
+    /// ```
+    pub fn then_expect_deleted(self) {
+        let view = self.view;
+        assert!(
+            view.is_deleted(),
+            "expected a deleted view, found: '{view:?}'"
+        );
+    }
+
+    /// Returns the view for validation by the user.
+    ///
+    /// ```
+    /// # use cqrs_es::doc::{Customer, CustomerEvent, CustomerView};
+    /// use cqrs_es::test::ViewTestFramework;
+    ///
+    /// let validator = ViewTestFramework::<CustomerView, Customer>::with_aggregate_id("customer-1")
+    ///     .given_no_previous_events()
+    ///     .when(vec![CustomerEvent::NameAdded { name: "John Doe".to_string() }]);
+    ///
+    /// let view = validator.inspect_view();
+    /// assert_eq!("customer-1", view.customer_id);
+    /// assert_eq!(1, view.updates);
+    /// ```
+    pub fn inspect_view(self) -> V {
+        self.view
+    }
+
+    pub(crate) fn new(view: V) -> Self {
+        Self {
+            view,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<V, A> ViewResultValidator<V, A>
+where
+    V: View<A> + PartialEq,
+    A: Aggregate,
+{
+    /// Verifies that the events have produced the expected view.
+    ///
+    /// > Note that the view *must* implement `std::cmp::PartialEq`.
+    ///
+    /// ```
+    /// # use cqrs_es::doc::{Customer, CustomerEvent, CustomerView};
+    /// use cqrs_es::test::ViewTestFramework;
+    ///
+    /// let validator = ViewTestFramework::<CustomerView, Customer>::with_aggregate_id("customer-1")
+    ///     .given(vec![CustomerEvent::NameAdded { name: "John Doe".to_string() }])
+    ///     .when(vec![CustomerEvent::EmailUpdated {
+    ///         new_email: "john@example.com".to_string(),
+    ///     }]);
+    ///
+    /// validator.then_expect_view(CustomerView {
+    ///     customer_id: "customer-1".to_string(),
+    ///     name: "John Doe".to_string(),
+    ///     email: "john@example.com".to_string(),
+    ///     updates: 2,
+    /// });
+    /// ```
+    pub fn then_expect_view(self, expected_view: V) {
+        assert_eq!(self.view, expected_view);
+    }
+}
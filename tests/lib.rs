@@ -6,9 +6,15 @@ use serde::{Deserialize, Serialize};
 
 use cqrs_es::event_sink::EventSink;
 use cqrs_es::mem_store::MemStore;
-use cqrs_es::test::TestFramework;
-use cqrs_es::Query;
+use cqrs_es::persist::{
+    MemEventRepository, MemViewRepository, PersistedEventRepository, PersistedEventStore,
+    SemanticVersionEventUpcaster, SerializedEvent,
+};
+use cqrs_es::test::{
+    EventSchemaTestFramework, ScenarioTestFramework, TestFramework, ViewTestFramework,
+};
 use cqrs_es::{Aggregate, AggregateError, CqrsFramework, DomainEvent, EventEnvelope, EventStore};
+use cqrs_es::{Query, View};
 
 #[derive(Debug, Serialize, Deserialize, Default, Clone, PartialEq)]
 pub struct TestAggregate {
@@ -132,20 +138,24 @@ impl DomainEvent for TestEvent {
     }
 }
 
+#[derive(Debug)]
 pub enum TestCommand {
     CreateTest(CreateTest),
     ConfirmTest(ConfirmTest),
     DoSomethingElse(DoSomethingElse),
 }
 
+#[derive(Debug)]
 pub struct CreateTest {
     pub id: String,
 }
 
+#[derive(Debug)]
 pub struct ConfirmTest {
     pub test_name: String,
 }
 
+#[derive(Debug)]
 pub struct DoSomethingElse {
     pub description: String,
 }
@@ -235,6 +245,55 @@ async fn test_mem_store() {
     );
 }
 
+#[tokio::test]
+async fn test_mem_store_conflict() {
+    let event_store = MemStore::<TestAggregate>::default();
+    let id = "test_id_D";
+    let first_context = event_store.load_aggregate(id).await.unwrap();
+    let second_context = event_store.load_aggregate(id).await.unwrap();
+    let created = TestEvent::Created(Created { id: id.to_string() });
+    event_store
+        .commit(vec![created.clone()], first_context, metadata())
+        .await
+        .unwrap();
+    let err = event_store
+        .commit(vec![created], second_context, metadata())
+        .await
+        .unwrap_err();
+    assert!(matches!(err, AggregateError::AggregateConflict));
+    assert_eq!(1, event_store.load_events(id).await.unwrap().len());
+}
+
+#[tokio::test]
+async fn test_mem_store_subscription() {
+    let event_store = MemStore::<TestAggregate>::default();
+    let id = "test_id_B";
+    let created = TestEvent::Created(Created {
+        id: "test_event_B".to_string(),
+    });
+    let agg_context = event_store.load_aggregate(id).await.unwrap();
+    event_store
+        .commit(vec![created.clone()], agg_context, metadata())
+        .await
+        .unwrap();
+
+    // committed events are replayed before following new events
+    let mut subscription = event_store.subscribe();
+    let agg_context = event_store.load_aggregate(id).await.unwrap();
+    let tested = TestEvent::Tested(Tested {
+        test_name: "test A".to_string(),
+    });
+    event_store
+        .commit(vec![tested.clone()], agg_context, metadata())
+        .await
+        .unwrap();
+
+    let first = subscription.next().await.unwrap();
+    assert_eq!((1, created), (first.sequence, first.payload));
+    let second = subscription.next().await.unwrap();
+    assert_eq!((2, tested), (second.sequence, second.payload));
+}
+
 type ThisTestFramework = TestFramework<TestAggregate>;
 
 #[test]
@@ -294,6 +353,458 @@ fn test_framework_failure_test_b() {
         .then_expect_error_message("some error message");
 }
 
+#[test]
+fn test_framework_state_test() {
+    ThisTestFramework::with(TestService)
+        .given(vec![TestEvent::Created(Created {
+            id: "test_id_A".to_string(),
+        })])
+        .when(TestCommand::ConfirmTest(ConfirmTest {
+            test_name: "test A".to_string(),
+        }))
+        .then_expect_events_and_state(
+            vec![TestEvent::Tested(Tested {
+                test_name: "test A".to_string(),
+            })],
+            |aggregate| aggregate.id == "test_id_A" && aggregate.tests == vec!["test A"],
+        );
+
+    ThisTestFramework::with(TestService)
+        .given_no_previous_events()
+        .when(TestCommand::DoSomethingElse(DoSomethingElse {
+            description: "something else".to_string(),
+        }))
+        .then_expect_state(|aggregate| aggregate.description == "something else");
+
+    ThisTestFramework::with(TestService)
+        .given(vec![TestEvent::Tested(Tested {
+            test_name: "test A".to_string(),
+        })])
+        .when(TestCommand::ConfirmTest(ConfirmTest {
+            test_name: "test A".to_string(),
+        }))
+        .then_expect_error_matching(|err| err.0.contains("already performed"));
+}
+
+#[test]
+#[should_panic(expected = "event 1:")]
+fn test_framework_event_difference_test() {
+    ThisTestFramework::with(TestService)
+        .given_no_previous_events()
+        .when(TestCommand::CreateTest(CreateTest {
+            id: "test_id_A".to_string(),
+        }))
+        .then_expect_events(vec![
+            TestEvent::Created(Created {
+                id: "test_id_A".to_string(),
+            }),
+            TestEvent::Tested(Tested {
+                test_name: "test A".to_string(),
+            }),
+        ]);
+}
+
+#[test]
+#[should_panic(expected = "aggregate state did not match")]
+fn test_framework_state_failure_test() {
+    ThisTestFramework::with(TestService)
+        .given_no_previous_events()
+        .when(TestCommand::CreateTest(CreateTest {
+            id: "test_id_A".to_string(),
+        }))
+        .then_expect_state(|aggregate| aggregate.id == "test_id_B");
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq)]
+struct TestHistoryView {
+    id: String,
+    tests: Vec<(usize, String)>,
+    last_user: Option<String>,
+    #[serde(skip)]
+    events_applied: usize,
+}
+
+impl View<TestAggregate> for TestHistoryView {
+    fn update(&mut self, event: &EventEnvelope<TestAggregate>) {
+        match &event.payload {
+            TestEvent::Created(e) => self.id.clone_from(&e.id),
+            TestEvent::Tested(e) => self.tests.push((event.sequence, e.test_name.clone())),
+            TestEvent::SomethingElse(_) => {}
+        }
+        self.last_user = event.metadata.get("user").cloned();
+        self.events_applied += 1;
+    }
+}
+
+type ThisViewTestFramework = ViewTestFramework<TestHistoryView, TestAggregate>;
+
+#[test]
+fn test_view_framework_test() {
+    let metadata = HashMap::from([("user".to_string(), "test_user".to_string())]);
+
+    ThisViewTestFramework::with_aggregate_id("test_id_A")
+        .with_metadata(metadata)
+        .given(vec![TestEvent::Created(Created {
+            id: "test_id_A".to_string(),
+        })])
+        .when(vec![TestEvent::Tested(Tested {
+            test_name: "test A".to_string(),
+        })])
+        .then_expect_view_matching(|view| {
+            view.id == "test_id_A"
+                && view.tests == vec![(2, "test A".to_string())]
+                && view.last_user.as_deref() == Some("test_user")
+        });
+}
+
+// Fields that are not serialized are lost when the view is stored and reloaded.
+#[test]
+#[should_panic]
+fn test_view_framework_failure_test() {
+    ThisViewTestFramework::with_aggregate_id("test_id_A")
+        .given(vec![TestEvent::Created(Created {
+            id: "test_id_A".to_string(),
+        })])
+        .when(vec![TestEvent::Tested(Tested {
+            test_name: "test A".to_string(),
+        })])
+        .then_expect_view_matching(|view| view.events_applied == 2);
+}
+
+#[tokio::test]
+async fn test_scenario_framework() {
+    let delivered_events = Arc::new(RwLock::new(Vec::new()));
+    let histories = MemViewRepository::<TestHistoryView, TestAggregate>::default();
+    let scenario = ScenarioTestFramework::<TestAggregate>::with(TestService)
+        .with_query(
+            "delivered",
+            Box::new(TestView::new(delivered_events.clone())),
+        )
+        .with_view("histories", histories.clone())
+        .start();
+
+    for id in ["test_id_A", "test_id_B"] {
+        scenario
+            .execute_with_metadata(
+                id,
+                TestCommand::CreateTest(CreateTest { id: id.to_string() }),
+                metadata(),
+            )
+            .await
+            .unwrap();
+    }
+    scenario
+        .execute(
+            "test_id_A",
+            TestCommand::ConfirmTest(ConfirmTest {
+                test_name: "test A".to_string(),
+            }),
+        )
+        .await
+        .unwrap();
+    let result = scenario
+        .execute(
+            "test_id_A",
+            TestCommand::ConfirmTest(ConfirmTest {
+                test_name: "test A".to_string(),
+            }),
+        )
+        .await;
+    assert!(matches!(result, Err(AggregateError::UserError(_))));
+
+    let committed: Vec<(String, usize)> = scenario
+        .committed_events()
+        .into_iter()
+        .map(|event| (event.aggregate_id, event.sequence))
+        .collect();
+    assert_eq!(
+        vec![
+            ("test_id_A".to_string(), 1),
+            ("test_id_B".to_string(), 1),
+            ("test_id_A".to_string(), 2)
+        ],
+        committed
+    );
+    let events = scenario.committed_events_for("test_id_A");
+    assert_eq!(metadata(), events[0].metadata);
+    assert!(events[1].metadata.is_empty());
+
+    assert_eq!(3, scenario.dispatched_events("delivered").len());
+    assert_eq!(3, delivered_events.read().unwrap().len());
+    assert_eq!(3, scenario.dispatched_events("histories").len());
+    let (view, context) = histories.view("test_id_A").unwrap();
+    assert_eq!(vec![(2, "test A".to_string())], view.tests);
+    assert_eq!(2, context.last_sequence);
+}
+
+#[cfg(feature = "proptest")]
+fn test_commands() -> impl proptest::strategy::Strategy<Value = TestCommand> {
+    use proptest::prelude::*;
+    prop_oneof![
+        "[a-c]".prop_map(|test_name| TestCommand::ConfirmTest(ConfirmTest { test_name })),
+        "[a-z]{0,5}".prop_map(|description| {
+            TestCommand::DoSomethingElse(DoSomethingElse { description })
+        }),
+    ]
+}
+
+#[cfg(feature = "proptest")]
+#[test]
+fn test_property_framework() {
+    cqrs_es::test::PropertyTestFramework::<TestAggregate>::with(TestService)
+        .with_invariant("tests are not repeated", |aggregate| {
+            let mut tests = aggregate.tests.clone();
+            tests.sort();
+            tests.dedup();
+            tests.len() == aggregate.tests.len()
+        })
+        .check(test_commands());
+}
+
+#[cfg(feature = "proptest")]
+#[tokio::test(flavor = "multi_thread")]
+async fn test_property_framework_within_runtime() {
+    cqrs_es::test::PropertyTestFramework::<TestAggregate>::with(TestService)
+        .with_max_commands(5)
+        .check(test_commands());
+}
+
+#[cfg(feature = "proptest")]
+#[tokio::test]
+#[should_panic(expected = "a property test cannot block a current thread runtime")]
+async fn test_property_framework_current_thread_runtime() {
+    cqrs_es::test::PropertyTestFramework::<TestAggregate>::with(TestService).check(test_commands());
+}
+
+// Shrinking reduces a failing sequence to the minimal number of commands.
+#[cfg(feature = "proptest")]
+#[test]
+#[should_panic(expected = "invariant 'at most two tests' violated after command 3")]
+fn test_property_framework_failure_test() {
+    cqrs_es::test::PropertyTestFramework::<TestAggregate>::with(TestService)
+        .with_invariant("at most two tests", |aggregate| aggregate.tests.len() <= 2)
+        .check(test_commands());
+}
+
+#[cfg(feature = "conformance")]
+#[tokio::test]
+async fn test_mem_event_repository_conformance() {
+    let repo = MemEventRepository::default();
+    cqrs_es::test::EventRepositoryConformance::with(move || repo.clone())
+        .verify()
+        .await;
+}
+
+#[cfg(feature = "conformance")]
+#[tokio::test]
+async fn test_mem_view_repository_conformance() {
+    let repo = MemViewRepository::default();
+    cqrs_es::test::ViewRepositoryConformance::with(move || repo.clone())
+        .verify()
+        .await;
+}
+
+// Renames the `name` field of `Tested` events prior to version 1.0.
+fn tested_upcaster() -> SemanticVersionEventUpcaster {
+    SemanticVersionEventUpcaster::new(
+        "Tested",
+        "1.0.0",
+        Box::new(|mut payload: serde_json::Value| {
+            let name = payload["Tested"]["name"].take();
+            serde_json::json!({ "Tested": { "test_name": name } })
+        }),
+    )
+}
+
+fn schema_directory() -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("cqrs-es-{}", uuid::Uuid::new_v4()))
+}
+
+fn schema_samples() -> Vec<TestEvent> {
+    vec![
+        TestEvent::Created(Created {
+            id: "test_id_A".to_string(),
+        }),
+        TestEvent::Tested(Tested {
+            test_name: "test A".to_string(),
+        }),
+    ]
+}
+
+// Records a sample of `Tested` from before the `name` field was renamed.
+fn schema_directory_with_old_sample() -> std::path::PathBuf {
+    let directory = schema_directory();
+    EventSchemaTestFramework::<TestAggregate>::with_directory(&directory)
+        .with_bless(true)
+        .verify(schema_samples());
+    let golden_file = directory.join("TestAggregate.json");
+    let mut recorded: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&golden_file).unwrap()).unwrap();
+    recorded["Tested"]["0.9.0"] = serde_json::json!([{ "Tested": { "name": "old test" } }]);
+    std::fs::write(&golden_file, recorded.to_string()).unwrap();
+    directory
+}
+
+#[test]
+fn test_schema_framework() {
+    let directory = schema_directory();
+    EventSchemaTestFramework::<TestAggregate>::with_directory(&directory)
+        .with_bless(true)
+        .verify(schema_samples());
+    assert!(directory.join("TestAggregate.json").exists());
+
+    EventSchemaTestFramework::<TestAggregate>::with_directory(&directory)
+        .with_bless(false)
+        .verify(schema_samples());
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+#[should_panic(expected = "Tested 1.0: sample has not been recorded")]
+fn test_schema_framework_unrecorded_sample() {
+    let directory = schema_directory();
+    EventSchemaTestFramework::<TestAggregate>::with_directory(&directory)
+        .with_bless(true)
+        .verify(schema_samples());
+    EventSchemaTestFramework::<TestAggregate>::with_directory(&directory)
+        .with_bless(false)
+        .verify(vec![TestEvent::Tested(Tested {
+            test_name: "test B".to_string(),
+        })]);
+}
+
+#[test]
+#[should_panic(expected = "Tested 0.9.0: recorded sample can no longer be deserialized")]
+fn test_schema_framework_incompatible_sample() {
+    EventSchemaTestFramework::<TestAggregate>::with_directory(schema_directory_with_old_sample())
+        .with_bless(false)
+        .verify(schema_samples());
+}
+
+#[test]
+fn test_schema_framework_upcast_sample() {
+    let directory = schema_directory_with_old_sample();
+    EventSchemaTestFramework::<TestAggregate>::with_directory(&directory)
+        .with_bless(false)
+        .with_upcasters(vec![Box::new(tested_upcaster())])
+        .verify(schema_samples());
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[tokio::test]
+async fn persisted_framework_test() {
+    let repo = MemEventRepository::default();
+    let id = uuid::Uuid::new_v4().to_string();
+    repo.insert_events::<TestAggregate>(&[SerializedEvent::new(
+        id.clone(),
+        1,
+        TestAggregate::TYPE.to_string(),
+        "Tested".to_string(),
+        "0.9.0".to_string(),
+        serde_json::json!({ "Tested": { "name": "old test" } }),
+        serde_json::json!({}),
+    )])
+    .unwrap();
+    let event_store = PersistedEventStore::<MemEventRepository, TestAggregate>::new_snapshot_store(
+        repo.clone(),
+        2,
+    )
+    .with_upcasters(vec![Box::new(tested_upcaster())]);
+    let cqrs = CqrsFramework::new(event_store, vec![], TestService);
+
+    // the upcast event is applied when loading the aggregate
+    let err = cqrs
+        .execute(
+            &id,
+            TestCommand::ConfirmTest(ConfirmTest {
+                test_name: "old test".to_string(),
+            }),
+        )
+        .await
+        .unwrap_err();
+    assert!(matches!(err, AggregateError::UserError(_)));
+
+    for test_name in ["test A", "test B", "test C"] {
+        cqrs.execute(
+            &id,
+            TestCommand::ConfirmTest(ConfirmTest {
+                test_name: test_name.to_string(),
+            }),
+        )
+        .await
+        .unwrap();
+    }
+    assert_eq!(4, repo.event_count());
+    let snapshot = repo
+        .get_snapshot::<TestAggregate>(&id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        (4, 2),
+        (snapshot.current_sequence, snapshot.current_snapshot)
+    );
+}
+
+#[tokio::test]
+async fn persisted_store_conflict_test() {
+    let event_store = PersistedEventStore::<MemEventRepository, TestAggregate>::new_event_store(
+        MemEventRepository::default(),
+    );
+    let id = "test_id_C";
+    let first_context = event_store.load_aggregate(id).await.unwrap();
+    let second_context = event_store.load_aggregate(id).await.unwrap();
+    let created = TestEvent::Created(Created { id: id.to_string() });
+    event_store
+        .commit(vec![created.clone()], first_context, metadata())
+        .await
+        .unwrap();
+    let err = event_store
+        .commit(vec![created], second_context, metadata())
+        .await
+        .unwrap_err();
+    assert!(matches!(err, AggregateError::AggregateConflict));
+}
+
+#[cfg(feature = "fault-injection")]
+#[tokio::test]
+async fn fault_injection_test() {
+    use cqrs_es::persist::{QueryReplay, ReplayErrorPolicy};
+    use cqrs_es::test::{Fault, FaultInjectingEventRepository, RepositoryOperation};
+
+    let repo = FaultInjectingEventRepository::new(MemEventRepository::default())
+        .with_fault_schedule(
+            RepositoryOperation::Persist,
+            vec![Some(Fault::OptimisticLock)],
+        )
+        .with_fault(
+            RepositoryOperation::StreamAllEvents,
+            Fault::StreamError { after: 1 },
+        );
+    let event_store = PersistedEventStore::<_, TestAggregate>::new_event_store(repo.clone());
+    let cqrs = CqrsFramework::new(event_store, vec![], TestService);
+    let command = || {
+        TestCommand::ConfirmTest(ConfirmTest {
+            test_name: "test A".to_string(),
+        })
+    };
+
+    // a conflicting commit is rejected and can be retried
+    let err = cqrs.execute("test_id_A", command()).await.unwrap_err();
+    assert!(matches!(err, AggregateError::AggregateConflict));
+    cqrs.execute("test_id_A", command()).await.unwrap();
+    cqrs.execute("test_id_B", command()).await.unwrap();
+    assert_eq!(3, repo.call_count(RepositoryOperation::Persist));
+
+    // a replay halts when the stream fails midway
+    let delivered_events = Arc::default();
+    let mut replay = QueryReplay::new(repo, TestView::new(Arc::clone(&delivered_events)));
+    replay.use_error_policy(ReplayErrorPolicy::FailFast);
+    let err = replay.replay_all().await.unwrap_err();
+    assert!(matches!(err, AggregateError::DatabaseConnectionError(_)));
+    assert_eq!(1, delivered_events.read().unwrap().len());
+}
+
 #[tokio::test]
 async fn framework_test() {
     let event_store = MemStore::<TestAggregate>::default();